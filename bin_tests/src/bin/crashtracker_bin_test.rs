@@ -12,7 +12,7 @@ fn main() -> anyhow::Result<()> {
 #[cfg(unix)]
 mod unix {
     use anyhow::Context;
-    use bin_tests::ReceiverType;
+    use bin_tests::{CrashType, ReceiverType};
     use std::{env, fs::File, str::FromStr, time::Duration};
 
     use datadog_crashtracker::{
@@ -20,10 +20,60 @@ mod unix {
         CrashtrackerReceiverConfig,
     };
     use ddcommon::tag;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Exit code of the process when the crash handler touches the heap.
+    const HEAP_USED_EXIT_CODE: i32 = 42;
+
+    /// Once poisoned, any use of the heap kills the process with [`HEAP_USED_EXIT_CODE`], instead of
+    /// letting the crash reach the default handler. Used to check that the crash handler doesn't
+    /// allocate.
+    struct PoisonableAllocator;
+
+    static POISONED: AtomicBool = AtomicBool::new(false);
+
+    impl PoisonableAllocator {
+        fn check_poisoned(&self) {
+            if POISONED.load(Ordering::SeqCst) {
+                const MESSAGE: &[u8] = b"heap used after the allocator was poisoned\n";
+                unsafe {
+                    libc::write(2, MESSAGE.as_ptr() as *const libc::c_void, MESSAGE.len());
+                    libc::_exit(HEAP_USED_EXIT_CODE);
+                }
+            }
+        }
+    }
+
+    unsafe impl GlobalAlloc for PoisonableAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.check_poisoned();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.check_poisoned();
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            self.check_poisoned();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: PoisonableAllocator = PoisonableAllocator;
 
     #[inline(never)]
     unsafe fn deref_ptr(p: *mut u8) {
-        *std::hint::black_box(p) = std::hint::black_box(1);
+        // Write through `memset` rather than `*p = 1`: debug builds of recent toolchains check
+        // for null dereferences and panic, which would allocate instead of crashing.
+        libc::memset(
+            std::hint::black_box(p) as *mut libc::c_void,
+            std::hint::black_box(1),
+            1,
+        );
     }
 
     pub fn main() -> anyhow::Result<()> {
@@ -35,6 +85,7 @@ mod unix {
         let stderr_filename = args.next().context("Unexpected number of arguments")?;
         let stdout_filename = args.next().context("Unexpected number of arguments")?;
         let socket_path = args.next().context("Unexpected number of arguments")?;
+        let crash_type = args.next().context("Unexpected number of arguments")?;
         anyhow::ensure!(args.next().is_none(), "unexpected extra arguments");
 
         let timeout = Duration::from_secs(30);
@@ -50,12 +101,15 @@ mod unix {
         };
 
         let config = CrashtrackerConfiguration {
-            additional_files: vec![],
             create_alt_stack: true,
+            environment_variables: vec!["DD_CRASHTRACKER_TEST_VAR".to_owned()],
+            minidump: true,
+            report_panics: true,
             resolve_frames: crashtracker::StacktraceCollection::WithoutSymbols,
             endpoint,
             timeout,
             wait_for_receiver,
+            ..Default::default()
         };
 
         let metadata = CrashtrackerMetadata {
@@ -103,8 +157,18 @@ mod unix {
         }
 
         crashtracker::begin_profiling_op(crashtracker::ProfilingOpTypes::CollectingSample)?;
-        unsafe {
-            deref_ptr(std::ptr::null_mut::<u8>());
+        let crash_type = CrashType::from_str(&crash_type)?;
+        // Nothing may allocate from here on, until the crash is handled. The panic hook runs in a
+        // normal context, so it is allowed to.
+        if crash_type != CrashType::Panic {
+            POISONED.store(true, Ordering::SeqCst);
+        }
+        match crash_type {
+            CrashType::NullDeref => unsafe {
+                deref_ptr(std::ptr::null_mut::<u8>());
+            },
+            CrashType::Abort => std::process::abort(),
+            CrashType::Panic => panic!("Program panicked"),
         }
         crashtracker::end_profiling_op(crashtracker::ProfilingOpTypes::CollectingSample)?;
         Ok(())
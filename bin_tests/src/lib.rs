@@ -34,6 +34,17 @@ pub enum ReceiverType {
     UnixSocket,
 }
 
+/// How the crashtracker test binary crashes
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, EnumString, Display)]
+pub enum CrashType {
+    /// Dereference a null pointer, raising SIGSEGV
+    NullDeref,
+    /// Call `abort`, raising SIGABRT
+    Abort,
+    /// Panic, which is reported by the panic hook instead of a signal handler
+    Panic,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum BuildProfile {
     Debug,
@@ -5,40 +5,80 @@
 
 use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::os::unix::process::ExitStatusExt;
 use std::path::Path;
 use std::process;
 use std::{fs, path::PathBuf};
 
 use anyhow::Context;
-use bin_tests::{build_artifacts, ArtifactType, ArtifactsBuild, BuildProfile, ReceiverType};
+use bin_tests::{
+    build_artifacts, ArtifactType, ArtifactsBuild, BuildProfile, CrashType, ReceiverType,
+};
 
 #[test]
 #[cfg_attr(miri, ignore)]
 fn test_crash_tracking_bin_debug_stdin() {
-    test_crash_tracking_bin(BuildProfile::Debug, ReceiverType::ChildProcessStdin);
+    test_crash_tracking_bin(
+        BuildProfile::Debug,
+        ReceiverType::ChildProcessStdin,
+        CrashType::NullDeref,
+    );
 }
 
 #[test]
 #[cfg_attr(miri, ignore)]
 fn test_crash_tracking_bin_debug_unix_socket() {
-    test_crash_tracking_bin(BuildProfile::Debug, ReceiverType::UnixSocket);
+    test_crash_tracking_bin(
+        BuildProfile::Debug,
+        ReceiverType::UnixSocket,
+        CrashType::NullDeref,
+    );
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_crash_tracking_bin_debug_stdin_abort() {
+    test_crash_tracking_bin(
+        BuildProfile::Debug,
+        ReceiverType::ChildProcessStdin,
+        CrashType::Abort,
+    );
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_crash_tracking_bin_debug_stdin_panic() {
+    test_crash_tracking_bin(
+        BuildProfile::Debug,
+        ReceiverType::ChildProcessStdin,
+        CrashType::Panic,
+    );
 }
 
 #[test]
 #[ignore] // This test is slow, only run it if explicitly opted in
 fn test_crash_tracking_bin_release_stdin() {
-    test_crash_tracking_bin(BuildProfile::Release, ReceiverType::ChildProcessStdin);
+    test_crash_tracking_bin(
+        BuildProfile::Release,
+        ReceiverType::ChildProcessStdin,
+        CrashType::NullDeref,
+    );
 }
 
 #[test]
 #[ignore] // This test is slow, only run it if explicitly opted in
 fn test_crash_tracking_bin_release_unix_socket() {
-    test_crash_tracking_bin(BuildProfile::Release, ReceiverType::UnixSocket);
+    test_crash_tracking_bin(
+        BuildProfile::Release,
+        ReceiverType::UnixSocket,
+        CrashType::NullDeref,
+    );
 }
 
 fn test_crash_tracking_bin(
     crash_tracking_receiver_profile: BuildProfile,
     receiver_type: ReceiverType,
+    crash_type: CrashType,
 ) {
     let (crashtracker_bin, crashtracker_receiver, crashtracker_unix_socket_receiver) =
         setup_crashtracking_crates(crash_tracking_receiver_profile);
@@ -56,13 +96,26 @@ fn test_crash_tracking_bin(
         .arg(&fixtures.stderr_path)
         .arg(&fixtures.stdout_path)
         .arg(&fixtures.unix_socket_path)
+        .arg(crash_type.to_string())
+        .env("DD_CRASHTRACKER_TEST_VAR", "allowed")
+        .env("DD_CRASHTRACKER_SECRET_VAR", "not allowed")
         .spawn()
         .unwrap();
     let exit_status = bin_tests::timeit!("exit after signal", {
         eprintln!("Waiting for exit");
         p.wait().unwrap()
     });
-    assert!(!exit_status.success());
+    if crash_type == CrashType::Panic {
+        // The panic hook chains to the default one, and the panic unwinds out of `main`
+        assert_eq!(exit_status.code(), Some(101), "{exit_status}");
+    } else {
+        // The process exits with a specific code instead of being killed by the signal if the
+        // crash handler uses the heap
+        assert!(
+            exit_status.signal().is_some(),
+            "Expected the process to be killed by a signal: {exit_status}"
+        );
+    }
     // Sadly this is necessary because in case of partial crash the tracked process
     // doesn't wait for the crahtracker receiver which causes races, with the test
     // running before the receiver has a chance to send the report.
@@ -86,7 +139,7 @@ fn test_crash_tracking_bin(
     assert_eq!(Ok(""), String::from_utf8(stdout).as_deref());
 
     // Check the crash data
-    let crash_profile = fs::read(fixtures.crash_profile_path)
+    let crash_profile = fs::read(&fixtures.crash_profile_path)
         .context("reading crashtracker profiling payload")
         .unwrap();
     let crash_payload = serde_json::from_slice::<serde_json::Value>(&crash_profile)
@@ -101,21 +154,87 @@ fn test_crash_tracking_bin(
         }),
         crash_payload["counters"],
     );
-    assert_eq!(
-        serde_json::json!({
-          "signum": 11,
-          "signame": "SIGSEGV"
-        }),
-        crash_payload["siginfo"]
-    );
+    let siginfo = &crash_payload["siginfo"];
+    match crash_type {
+        CrashType::Panic => {
+            assert_eq!(crash_payload["kind"], "Panic");
+            assert!(siginfo.is_null());
+            let panic_info = &crash_payload["panic_info"];
+            assert_eq!(panic_info["message"], "Program panicked");
+            assert!(panic_info["location"]
+                .as_str()
+                .is_some_and(|l| l.contains("crashtracker_bin_test.rs")));
+            assert!(!crash_payload["stacktrace"]
+                .as_array()
+                .is_some_and(|s| s.is_empty()));
+        }
+        CrashType::NullDeref => {
+            assert_eq!(siginfo["signum"], 11);
+            assert_eq!(siginfo["signame"], "SIGSEGV");
+            // SEGV_MAPERR, at the dereferenced null pointer
+            #[cfg(target_os = "linux")]
+            assert_eq!(siginfo["si_code"], 1);
+            assert_eq!(siginfo["si_addr"], "0x0");
+        }
+        CrashType::Abort => {
+            assert_eq!(siginfo["signum"], 6);
+            assert_eq!(siginfo["signame"], "SIGABRT");
+            // `abort` raises the signal on the crashing process itself
+            assert_eq!(siginfo["si_pid"], crash_payload["proc_info"]["pid"]);
+            assert!(siginfo.get("si_addr").is_none());
+        }
+    }
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    if crash_type != CrashType::Panic {
+        assert!(siginfo["registers"]
+            .as_object()
+            .is_some_and(|r| !r.is_empty()));
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let runtime_context = &crash_payload["runtime_context"];
+        assert_eq!(
+            runtime_context["thread_id"],
+            crash_payload["proc_info"]["tid"]
+        );
+        assert!(runtime_context["thread_name"].is_string());
+        assert!(runtime_context["cmdline"][0]
+            .as_str()
+            .is_some_and(|arg| arg.ends_with("crashtracker_bin_test")));
+        assert_eq!(
+            runtime_context["environment"],
+            serde_json::json!({"DD_CRASHTRACKER_TEST_VAR": "allowed"})
+        );
+        assert!(runtime_context["rss_bytes"]
+            .as_u64()
+            .is_some_and(|rss| rss > 0));
+        assert!(runtime_context["open_fds"]
+            .as_u64()
+            .is_some_and(|fds| fds > 0));
+        assert!(runtime_context["limits"]["max_open_files"]["soft"].is_u64());
+        assert!(runtime_context["uptime_ms"].is_u64());
+    }
+
+    // The minidump is written next to the report
+    #[cfg(target_os = "linux")]
+    {
+        let minidump = fs::read(fixtures.crash_profile_path.with_extension("dmp"))
+            .context("reading crashtracker minidump")
+            .unwrap();
+        assert!(minidump.starts_with(b"MDMP"));
+    }
 
     let crash_telemetry = fs::read(fixtures.crash_telemetry_path)
         .context("reading crashtracker telemetry payload")
         .unwrap();
-    assert_telemetry_message(&crash_telemetry);
+    assert_telemetry_message(&crash_telemetry, crash_type);
 }
 
-fn assert_telemetry_message(crash_telemetry: &[u8]) {
+fn assert_telemetry_message(crash_telemetry: &[u8], crash_type: CrashType) {
     let telemetry_payload: serde_json::Value =
         serde_json::from_slice::<serde_json::Value>(crash_telemetry)
             .context("deserializing crashtracker telemetry payload to json")
@@ -139,17 +258,18 @@ fn assert_telemetry_message(crash_telemetry: &[u8]) {
         .split(',')
         .filter(|t| !t.starts_with("uuid:"))
         .collect::<std::collections::HashSet<_>>();
-    assert_eq!(
-        std::collections::HashSet::from_iter([
-            "signum:11",
-            "signame:SIGSEGV",
-            "collecting_sample:1",
-            "not_profiling:0",
-            "serializing:0",
-            "unwinding:0",
-        ]),
-        tags
-    );
+    let mut expected_tags = std::collections::HashSet::from_iter([
+        "collecting_sample:1",
+        "not_profiling:0",
+        "serializing:0",
+        "unwinding:0",
+    ]);
+    expected_tags.extend(match crash_type {
+        CrashType::NullDeref => vec!["signum:11", "signame:SIGSEGV"],
+        CrashType::Abort => vec!["signum:6", "signame:SIGABRT"],
+        CrashType::Panic => vec!["kind:panic"],
+    });
+    assert_eq!(expected_tags, tags);
     assert_eq!(telemetry_payload["payload"][0]["is_sensitive"], true);
 }
 
@@ -191,6 +311,7 @@ fn crash_tracking_empty_endpoint_inner(receiver_type: ReceiverType) {
         .arg(&fixtures.stderr_path)
         .arg(&fixtures.stdout_path)
         .arg(&fixtures.unix_socket_path)
+        .arg(CrashType::NullDeref.to_string())
         .env(
             "DD_TRACE_AGENT_URL",
             format!("unix://{}", socket_path.display()),
@@ -208,7 +329,7 @@ fn crash_tracking_empty_endpoint_inner(receiver_type: ReceiverType) {
     let resp = String::from_utf8_lossy(&out[..read]);
     let pos = resp.find("\r\n\r\n").unwrap();
     let body = &resp[pos + 4..];
-    assert_telemetry_message(body.as_bytes());
+    assert_telemetry_message(body.as_bytes(), CrashType::NullDeref);
 }
 
 struct TestFixtures<'a> {
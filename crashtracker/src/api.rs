@@ -6,13 +6,30 @@ use crate::{
     configuration::CrashtrackerReceiverConfig,
     counters::reset_counters,
     crash_handler::{
-        ensure_receiver, ensure_socket, register_crash_handlers, restore_old_handlers,
-        shutdown_receiver, update_receiver_after_fork,
+        ensure_receiver, ensure_socket, register_crash_handlers, register_panic_hook,
+        restore_old_handlers, shutdown_receiver, update_receiver_after_fork,
     },
     crash_info::CrashtrackerMetadata,
+    spool::upload_spooled_reports,
     update_config, update_metadata, CrashtrackerConfiguration,
 };
 
+/// Upload, from a background thread, the crash reports a previous process left in the spool
+/// directory because it couldn't upload them.
+fn spawn_spooled_reports_upload(config: &CrashtrackerConfiguration) -> anyhow::Result<()> {
+    if config.spool_dir.is_none() {
+        return Ok(());
+    }
+    let config = config.clone();
+    std::thread::Builder::new()
+        .name("dd-crashtracker-spool".to_string())
+        .spawn(move || {
+            // Reports which can't be uploaded are kept for the next start, nothing else to do
+            let _ = upload_spooled_reports(&config);
+        })?;
+    Ok(())
+}
+
 /// Cleans up after the crash-tracker:
 /// Unregister the crash handler, restore the previous handler (if any), and
 /// shut down the receiver.  Note that the use of this function is optional:
@@ -38,10 +55,9 @@ pub fn shutdown_crash_handler() -> anyhow::Result<()> {
 /// This should be one of the first things done after a fork, to minimize the
 /// chance that a crash occurs between the fork, and this call.
 /// In particular, reset the counters that track the profiler state machine,
-/// and start a new receiver to collect data from this fork.
-/// NOTE: An alternative design would be to have a 1:many sidecar listening on a
-/// socket instead of 1:1 receiver listening on a pipe, but the only real
-/// advantage would be to have fewer processes in `ps -a`.
+/// and start a new receiver to collect data from this fork, unless the crash-tracker was
+/// initialized with a unix socket, in which case the receiver listening on it, e.g. the
+/// sidecar's, also receives the reports of this fork.
 ///
 /// PRECONDITIONS:
 ///     This function assumes that the crash-tracker has previously been
@@ -72,6 +88,8 @@ pub fn on_fork(
 }
 
 /// Initialize the crash-tracking infrastructure.
+/// If `config.report_panics` is set, the first Rust panic is reported too, by a panic hook which
+/// chains to the previous one.
 ///
 /// PRECONDITIONS:
 ///     None.
@@ -89,10 +107,16 @@ pub fn init_with_receiver(
     // Setup the receiver first, so that if there is a crash detected it has
     // somewhere to go.
     let create_alt_stack = config.create_alt_stack;
+    let signals = config.signals.clone();
+    let report_panics = config.report_panics;
+    spawn_spooled_reports_upload(&config)?;
     update_metadata(metadata)?;
     update_config(config)?;
     ensure_receiver(&receiver_config)?;
-    register_crash_handlers(create_alt_stack)?;
+    register_crash_handlers(create_alt_stack, &signals)?;
+    if report_panics {
+        register_panic_hook(receiver_config);
+    }
     Ok(())
 }
 
@@ -114,10 +138,12 @@ pub fn init_with_unix_socket(
     // Setup the receiver first, so that if there is a crash detected it has
     // somewhere to go.
     let create_alt_stack = config.create_alt_stack;
+    let signals = config.signals.clone();
+    spawn_spooled_reports_upload(&config)?;
     update_metadata(metadata)?;
     update_config(config)?;
     ensure_socket(socket_path)?;
-    register_crash_handlers(create_alt_stack)?;
+    register_crash_handlers(create_alt_stack, &signals)?;
     Ok(())
 }
 
@@ -161,14 +187,15 @@ fn test_crash() -> anyhow::Result<()> {
         stderr_filename,
         stdout_filename,
     )?;
-    let config = CrashtrackerConfiguration::new(
-        vec![],
+    let config = CrashtrackerConfiguration {
         create_alt_stack,
         endpoint,
         resolve_frames,
         timeout,
         wait_for_receiver,
-    )?;
+        ..Default::default()
+    }
+    .validate()?;
     let metadata = CrashtrackerMetadata::new(
         "libname".to_string(),
         "version".to_string(),
@@ -2,96 +2,170 @@
 // SPDX-License-Identifier: Apache-2.0
 #![cfg(unix)]
 
-use anyhow::Context;
+use super::constants::*;
+use super::signal_safe::SignalSafeWriter;
+use libc::c_void;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
 
-use crate::StacktraceCollection;
+/// Upper bound on the frames walked per stack, in case the frame pointers of a corrupted stack
+/// form a very long chain.
+const MAX_FRAMES: usize = 512;
 
-use super::constants::*;
-use std::{
-    fs::File,
-    io::{Read, Write},
-};
+/// Frame pointers further than this from the stack pointer are considered corrupted.
+const MAX_STACK_SIZE: u64 = 64 << 20;
+
+/// Walk a stack by following the chain of frame pointers, starting from the registers of the
+/// innermost frame. `visit` is called with the instruction pointer and stack pointer of each
+/// frame, and `read_word` is used to read the frame records, the walk stops at the first one it
+/// can't read.
+///
+/// Frames of code compiled without frame pointers are skipped.
+pub fn walk_frame_pointers<E>(
+    (ip, sp, mut fp): (u64, u64, u64),
+    read_word: impl Fn(u64) -> Option<u64>,
+    mut visit: impl FnMut(u64, u64) -> Result<(), E>,
+) -> Result<(), E> {
+    visit(ip, sp)?;
+    let mut frames = 1;
+    while frames < MAX_FRAMES && fp % 8 == 0 && fp >= sp && fp - sp < MAX_STACK_SIZE {
+        // A frame record holds the frame pointer of the caller, followed by the return address
+        let (Some(caller_fp), Some(return_address)) = (read_word(fp), read_word(fp + 8)) else {
+            break;
+        };
+        if return_address == 0 {
+            break;
+        }
+        visit(return_address, fp + 16)?;
+        frames += 1;
+        // The stack grows down, the frames of the callers are at higher addresses
+        if caller_fp <= fp {
+            break;
+        }
+        fp = caller_fp;
+    }
+    Ok(())
+}
+
+/// The instruction pointer, stack pointer and frame pointer saved in a signal handler's ucontext.
+/// SAFETY:
+///     `ucontext` must be the non-null context received by the signal handler.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn frame_registers(ucontext: *const c_void) -> Option<(u64, u64, u64)> {
+    let gregs = &(*(ucontext as *const libc::ucontext_t)).uc_mcontext.gregs;
+    Some((
+        gregs[libc::REG_RIP as usize] as u64,
+        gregs[libc::REG_RSP as usize] as u64,
+        gregs[libc::REG_RBP as usize] as u64,
+    ))
+}
+
+/// The instruction pointer, stack pointer and frame pointer saved in a signal handler's ucontext.
+/// SAFETY:
+///     `ucontext` must be the non-null context received by the signal handler.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+unsafe fn frame_registers(ucontext: *const c_void) -> Option<(u64, u64, u64)> {
+    let mcontext = &(*(ucontext as *const libc::ucontext_t)).uc_mcontext;
+    // x29 is the frame pointer
+    Some((mcontext.pc, mcontext.sp, mcontext.regs[29]))
+}
+
+/// The instruction pointer, stack pointer and frame pointer saved in a signal handler's ucontext.
+/// SAFETY:
+///     `ucontext` must be the non-null context received by the signal handler.
+#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+unsafe fn frame_registers(ucontext: *const c_void) -> Option<(u64, u64, u64)> {
+    let mcontext = (*(ucontext as *const libc::ucontext_t))
+        .uc_mcontext
+        .as_ref()?;
+    Some((
+        mcontext.__ss.__rip,
+        mcontext.__ss.__rsp,
+        mcontext.__ss.__rbp,
+    ))
+}
+
+/// The instruction pointer, stack pointer and frame pointer saved in a signal handler's ucontext.
+/// SAFETY:
+///     `ucontext` must be the non-null context received by the signal handler.
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+unsafe fn frame_registers(ucontext: *const c_void) -> Option<(u64, u64, u64)> {
+    let mcontext = (*(ucontext as *const libc::ucontext_t))
+        .uc_mcontext
+        .as_ref()?;
+    Some((mcontext.__ss.__pc, mcontext.__ss.__sp, mcontext.__ss.__fp))
+}
 
-/// Emit a stacktrace onto the given handle as formatted json.
+#[cfg(not(any(
+    all(target_os = "linux", target_arch = "x86_64"),
+    all(target_os = "linux", target_arch = "aarch64"),
+    all(target_os = "macos", target_arch = "x86_64"),
+    all(target_os = "macos", target_arch = "aarch64"),
+)))]
+unsafe fn frame_registers(_ucontext: *const c_void) -> Option<(u64, u64, u64)> {
+    None
+}
+
+/// Read a word of the memory of this process, returns None if it isn't mapped.
+#[cfg(target_os = "linux")]
+fn read_word(address: u64) -> Option<u64> {
+    // Unlike a plain read, `process_vm_readv` reports unmapped memory with EFAULT instead of
+    // faulting
+    let mut word = 0u64;
+    let local = libc::iovec {
+        iov_base: &mut word as *mut u64 as *mut c_void,
+        iov_len: std::mem::size_of::<u64>(),
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut c_void,
+        iov_len: std::mem::size_of::<u64>(),
+    };
+    let read = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    (read == std::mem::size_of::<u64>() as isize).then_some(word)
+}
+
+/// Read a word of the memory of this process.
+#[cfg(not(target_os = "linux"))]
+fn read_word(address: u64) -> Option<u64> {
+    // There is no cheap way to probe the memory, rely on the bounds checked by the walk
+    Some(unsafe { std::ptr::read_volatile(address as *const u64) })
+}
+
+/// Emit the stacktrace of the crashing thread onto the given handle as formatted json, one frame
+/// per line. The stack is walked from the registers saved in `ucontext`, so it starts at the
+/// crashing instruction rather than in the signal handler.
 /// SAFETY:
+///     `ucontext` must be either null, or the context received by the signal handler.
 ///     Crash-tracking functions are not reentrant.
 ///     No other crash-handler functions should be called concurrently.
 /// ATOMICITY:
 ///     This function is not atomic. A crash during its execution may lead to
 ///     unexpected crash-handling behaviour.
 /// SIGNAL SAFETY:
-///     Getting a backtrace on rust is not guaranteed to be signal safe.
-///     https://github.com/rust-lang/backtrace-rs/issues/414
-///     Calculating the `ip` of the frames seems safe, but resolving the frames
-///     sometimes crashes.
-pub unsafe fn emit_backtrace_by_frames(
-    w: &mut impl Write,
-    resolve_frames: StacktraceCollection,
-) -> anyhow::Result<()> {
-    // https://docs.rs/backtrace/latest/backtrace/index.html
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_STACKTRACE}")?;
-    backtrace::trace_unsynchronized(|frame| {
-        // Write the values we can get without resolving, since these seem to
-        // be crash safe in my experiments.
-        write! {w, "{{"}.unwrap();
-        write!(w, "\"ip\": \"{:?}\", ", frame.ip()).unwrap();
-        if let Some(module_base_address) = frame.module_base_address() {
-            write!(w, "\"module_base_address\": \"{module_base_address:?}\", ",).unwrap();
-        }
-        write!(w, "\"sp\": \"{:?}\", ", frame.sp()).unwrap();
-        write!(w, "\"symbol_address\": \"{:?}\"", frame.symbol_address()).unwrap();
-        if resolve_frames == StacktraceCollection::EnabledWithInprocessSymbols {
-            write!(w, ", \"names\": [").unwrap();
-
-            let mut first = true;
-            // This can give multiple answers in the case of inlined functions
-            // https://docs.rs/backtrace/latest/backtrace/fn.resolve.html
-            // Store them all into an array of names
-            unsafe {
-                backtrace::resolve_frame_unsynchronized(frame, |symbol| {
-                    if !first {
-                        write!(w, ", ").unwrap();
-                    }
-                    write!(w, "{{").unwrap();
-                    let mut comma_needed = false;
-                    if let Some(name) = symbol.name() {
-                        write!(w, "\"name\": \"{}\"", name).unwrap();
-                        comma_needed = true;
-                    }
-                    if let Some(filename) = symbol.filename() {
-                        if comma_needed {
-                            write!(w, ", ").unwrap();
-                        }
-                        write!(w, "\"filename\": {:?}", filename).unwrap();
-                        comma_needed = true;
-                    }
-                    if let Some(colno) = symbol.colno() {
-                        if comma_needed {
-                            write!(w, ", ").unwrap();
-                        }
-                        write!(w, "\"colno\": {}", colno).unwrap();
-                        comma_needed = true;
-                    }
-
-                    if let Some(lineno) = symbol.lineno() {
-                        if comma_needed {
-                            write!(w, ", ").unwrap();
-                        }
-                        write!(w, "\"lineno\": {}", lineno).unwrap();
-                    }
-
-                    write!(w, "}}").unwrap();
-
-                    first = false;
-                });
-            }
-            write!(w, "]").unwrap();
-        }
-        writeln!(w, "}}").unwrap();
-        true // keep going to the next frame
-    });
-    writeln! {w, "{DD_CRASHTRACK_END_STACKTRACE}"}.unwrap();
-    Ok(())
+///     This function doesn't allocate nor take locks. On Linux, the stack is read with
+///     `process_vm_readv`, so a corrupted frame pointer ends the stacktrace instead of faulting.
+pub unsafe fn emit_backtrace_by_frame_pointers(
+    w: &mut SignalSafeWriter<impl Write>,
+    ucontext: *const c_void,
+) -> io::Result<()> {
+    w.write_line(DD_CRASHTRACK_BEGIN_STACKTRACE)?;
+    let registers = if ucontext.is_null() {
+        None
+    } else {
+        frame_registers(ucontext)
+    };
+    if let Some(registers) = registers {
+        walk_frame_pointers(registers, read_word, |ip, sp| {
+            w.write_str("{\"ip\": \"")?;
+            w.write_hex(ip)?;
+            w.write_str("\", \"sp\": \"")?;
+            w.write_hex(sp)?;
+            w.write_line("\"}")
+        })?;
+    }
+    w.write_line(DD_CRASHTRACK_END_STACKTRACE)
 }
 
 /// Emit a file onto the given handle.
@@ -113,11 +187,15 @@ pub unsafe fn emit_backtrace_by_frames(
 /// SIGNAL SAFETY:
 ///     This function is careful to only write to the handle, without doing any
 ///     unnecessary mutexes or memory allocation.
-#[allow(dead_code)]
-pub fn emit_text_file(w: &mut impl Write, path: &str) -> anyhow::Result<()> {
-    // open is signal safe
+pub fn emit_text_file(w: &mut SignalSafeWriter<impl Write>, path: &CStr) -> io::Result<()> {
+    // open is signal safe, and taking a `CStr` avoids allocating to add the nul terminator
     // https://man7.org/linux/man-pages/man7/signal-safety.7.html
-    let mut file = File::open(path).with_context(|| path.to_string())?;
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the file descriptor was just opened, the file closes it when dropped
+    let mut file = unsafe { File::from_raw_fd(fd) };
 
     // Reading the file into a fixed buffer is signal safe.
     // Doing anything more complicated may involve allocation which is not.
@@ -125,25 +203,92 @@ pub fn emit_text_file(w: &mut impl Write, path: &str) -> anyhow::Result<()> {
     const BUFFER_LEN: usize = 512;
     let mut buffer = [0u8; BUFFER_LEN];
 
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_FILE} {path}")?;
+    w.write_str(DD_CRASHTRACK_BEGIN_FILE)?;
+    w.write_str(" ")?;
+    w.write_bytes(path.to_bytes())?;
+    w.write_str("\n")?;
 
     loop {
-        let read_count = file.read(&mut buffer)?;
-        w.write_all(&buffer[..read_count])?;
-        if read_count == 0 {
-            break;
-        }
+        let read_count = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read_count) => read_count,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        w.write_bytes(&buffer[..read_count])?;
     }
-    writeln!(w, "\n{DD_CRASHTRACK_END_FILE} \"{path}\"")?;
-    w.flush()?;
-    Ok(())
+    w.write_str("\n")?;
+    w.write_str(DD_CRASHTRACK_END_FILE)?;
+    w.write_str(" \"")?;
+    w.write_bytes(path.to_bytes())?;
+    w.write_line("\"")?;
+    w.flush()
 }
 
 #[cfg(target_os = "linux")]
 /// `/proc/self/maps` is very useful for debugging, and difficult to get from
 /// the child process (permissions issues on Linux).  Emit it directly onto the
 /// pipe to get around this.
-pub fn emit_proc_self_maps(w: &mut impl Write) -> anyhow::Result<()> {
-    emit_text_file(w, "/proc/self/maps")?;
-    Ok(())
+pub fn emit_proc_self_maps(w: &mut SignalSafeWriter<impl Write>) -> io::Result<()> {
+    let path = CStr::from_bytes_with_nul(b"/proc/self/maps\0")
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    emit_text_file(w, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_frame_pointers() {
+        // A fake stack of 3 frame records, the outermost one ends the chain with a null frame
+        // pointer
+        let stack: [u64; 6] = [0x1010, 0xa1, 0x1020, 0xa2, 0, 0xa3];
+        let read_word = |address: u64| {
+            let index = (address.checked_sub(0x1000)? / 8) as usize;
+            stack.get(index).copied()
+        };
+        let mut frames = vec![];
+        walk_frame_pointers((0xa0, 0xff0, 0x1000), read_word, |ip, sp| {
+            frames.push((ip, sp));
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                (0xa0, 0xff0),
+                (0xa1, 0x1010),
+                (0xa2, 0x1020),
+                (0xa3, 0x1030)
+            ]
+        );
+
+        // A frame pointer below the stack pointer is corrupted
+        let mut frames = vec![];
+        walk_frame_pointers((0xa0, 0x2000, 0x1000), read_word, |ip, sp| {
+            frames.push((ip, sp));
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+        assert_eq!(frames, vec![(0xa0, 0x2000)]);
+    }
+
+    #[test]
+    fn test_emit_text_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"line 1\nline 2").unwrap();
+        let path = std::ffi::CString::new(file.path().to_str().unwrap()).unwrap();
+
+        let mut output = vec![];
+        let mut buffer = [0u8; 16];
+        emit_text_file(&mut SignalSafeWriter::new(&mut output, &mut buffer), &path).unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{DD_CRASHTRACK_BEGIN_FILE} {path}\nline 1\nline 2\n{DD_CRASHTRACK_END_FILE} \"{path}\"\n"
+            )
+        );
+    }
 }
@@ -1,7 +1,10 @@
 // Copyright 2023-Present Datadog, Inc. https://www.datadoghq.com/
 // SPDX-License-Identifier: Apache-2.0
+use anyhow::Context;
 use ddcommon::Endpoint;
+use nix::sys::signal::Signal;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::time::Duration;
 
 /// Stacktrace collection occurs in the context of a crashing process.
@@ -15,17 +18,80 @@ pub enum StacktraceCollection {
     /// Stacktrace collection occurs in the
     Disabled,
     WithoutSymbols,
+    /// The signal handler can't safely resolve symbols, so they are resolved by the receiver as
+    /// with `EnabledWithSymbolsInReceiver`.
     EnabledWithInprocessSymbols,
     EnabledWithSymbolsInReceiver,
 }
 
+/// Signals handled by the crashtracker when the configuration doesn't specify any.
+pub const DEFAULT_SIGNALS: [libc::c_int; 6] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGABRT,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGSYS,
+];
+
+/// Directories searched for separate debug files when the configuration doesn't specify any.
+pub const DEFAULT_DEBUG_DIRS: [&str; 1] = ["/usr/lib/debug"];
+
+/// Time the crashing process waits for the receiver when the configuration doesn't specify it.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The cache directory of the user, `$XDG_CACHE_HOME` or `~/.cache`, where the receiver keeps
+/// its state. Unlike the temporary directory, other users can't write to it.
+pub(crate) fn user_cache_dir() -> anyhow::Result<PathBuf> {
+    let non_empty = |var| std::env::var_os(var).filter(|value| !value.is_empty());
+    match non_empty("XDG_CACHE_HOME") {
+        Some(cache_home) => Ok(PathBuf::from(cache_home)),
+        None => {
+            let home = non_empty("HOME").context("Neither XDG_CACHE_HOME nor HOME is set")?;
+            Ok(PathBuf::from(home).join(".cache"))
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CrashtrackerConfiguration {
     // Paths to any additional files to track, if any
     pub additional_files: Vec<String>,
     pub create_alt_stack: bool,
+    // Directories searched by the receiver for debug files, under `.build-id/xx/yyyy.debug`
+    #[serde(default)]
+    pub debug_dirs: Vec<String>,
+    // Where the debug files downloaded from debuginfod are cached, defaults to
+    // dd-crashtracker-debuginfod in $XDG_CACHE_HOME or ~/.cache
+    #[serde(default)]
+    pub debuginfod_cache_dir: Option<String>,
+    // debuginfod server queried by the receiver for the debug files it can't find locally, if any
+    #[serde(default)]
+    pub debuginfod_url: Option<String>,
     pub endpoint: Option<Endpoint>,
+    // Environment variables of the crashed process included in the report, by name. Others are
+    // left out, as they may hold secrets
+    #[serde(default)]
+    pub environment_variables: Vec<String>,
+    // Upper bound on the reports of the same crash, by fingerprint, uploaded per hour by the
+    // receivers of this user on this host, if any
+    #[serde(default)]
+    pub max_reports_per_hour: Option<u32>,
+    // Whether the receiver also attaches a minidump of the crashed process to the report, on
+    // Linux. For file endpoints, it is also written next to the report
+    #[serde(default)]
+    pub minidump: bool,
+    // Whether `init_with_receiver` installs a panic hook reporting Rust panics as crashes. Only
+    // the first panic of the process is reported
+    #[serde(default)]
+    pub report_panics: bool,
     pub resolve_frames: StacktraceCollection,
+    // Signals to handle, the previous handler of each of them is chained after the crash report
+    #[serde(default)]
+    pub signals: Vec<libc::c_int>,
+    // Directory where crash reports are kept until they are uploaded, if any
+    #[serde(default)]
+    pub spool_dir: Option<String>,
     pub timeout: Duration,
     pub wait_for_receiver: bool,
 }
@@ -68,22 +134,90 @@ impl CrashtrackerReceiverConfig {
     }
 }
 
+impl Default for CrashtrackerConfiguration {
+    fn default() -> Self {
+        Self {
+            additional_files: vec![],
+            create_alt_stack: false,
+            debug_dirs: DEFAULT_DEBUG_DIRS.map(String::from).to_vec(),
+            debuginfod_cache_dir: None,
+            debuginfod_url: None,
+            endpoint: None,
+            environment_variables: vec![],
+            max_reports_per_hour: None,
+            minidump: false,
+            report_panics: false,
+            resolve_frames: StacktraceCollection::EnabledWithSymbolsInReceiver,
+            signals: DEFAULT_SIGNALS.to_vec(),
+            spool_dir: None,
+            timeout: DEFAULT_TIMEOUT,
+            wait_for_receiver: false,
+        }
+    }
+}
+
 impl CrashtrackerConfiguration {
-    pub fn new(
-        additional_files: Vec<String>,
-        create_alt_stack: bool,
-        endpoint: Option<Endpoint>,
-        resolve_frames: StacktraceCollection,
-        timeout: Duration,
-        wait_for_receiver: bool,
-    ) -> anyhow::Result<Self> {
+    /// Check a configuration built with the struct update syntax from the default one, and use
+    /// the default signals and debug directories if none are given.
+    pub fn validate(self) -> anyhow::Result<Self> {
+        // Use the default signals if none are given
+        let signals = if self.signals.is_empty() {
+            DEFAULT_SIGNALS.to_vec()
+        } else {
+            self.signals
+        };
+        for signum in &signals {
+            let signal = Signal::try_from(*signum)?;
+            anyhow::ensure!(
+                signal != Signal::SIGKILL && signal != Signal::SIGSTOP,
+                "{signal} can't be handled by the crashtracker"
+            );
+        }
+        let signals = signals.into_iter().fold(vec![], |mut signals, signum| {
+            if !signals.contains(&signum) {
+                signals.push(signum);
+            }
+            signals
+        });
+
+        // Use the default debug directories if none are given
+        let debug_dirs = if self.debug_dirs.is_empty() {
+            DEFAULT_DEBUG_DIRS.map(String::from).to_vec()
+        } else {
+            self.debug_dirs
+        };
+
         Ok(Self {
-            additional_files,
-            create_alt_stack,
-            endpoint,
-            resolve_frames,
-            timeout,
-            wait_for_receiver,
+            debug_dirs,
+            signals,
+            ..self
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_signals(signals: Vec<libc::c_int>) -> anyhow::Result<CrashtrackerConfiguration> {
+        CrashtrackerConfiguration {
+            signals,
+            ..Default::default()
+        }
+        .validate()
+    }
+
+    #[test]
+    fn test_signals() {
+        let config = config_with_signals(vec![]).unwrap();
+        assert_eq!(config.signals, DEFAULT_SIGNALS);
+
+        let config =
+            config_with_signals(vec![libc::SIGABRT, libc::SIGSEGV, libc::SIGABRT]).unwrap();
+        assert_eq!(config.signals, vec![libc::SIGABRT, libc::SIGSEGV]);
+
+        assert!(config_with_signals(vec![libc::SIGKILL]).is_err());
+        assert!(config_with_signals(vec![libc::SIGSTOP]).is_err());
+        assert!(config_with_signals(vec![-1]).is_err());
+    }
+}
@@ -4,7 +4,9 @@
 use std::sync::atomic::{AtomicI64, Ordering::SeqCst};
 
 #[cfg(unix)]
-use std::io::Write;
+use crate::signal_safe::SignalSafeWriter;
+#[cfg(unix)]
+use std::io::{self, Write};
 
 /// This enum represents operations a profiler might be engaged in.
 /// The idea is that if a crash consistently occurs while a particular operation
@@ -94,20 +96,20 @@ pub fn end_profiling_op(op: ProfilingOpTypes) -> anyhow::Result<()> {
 ///     This function is careful to only write to the handle, without doing any
 ///     unnecessary mutexes or memory allocation.
 #[cfg(unix)]
-pub fn emit_counters(w: &mut impl Write) -> anyhow::Result<()> {
+pub fn emit_counters(w: &mut SignalSafeWriter<impl Write>) -> io::Result<()> {
     use super::constants::*;
 
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_COUNTERS}")?;
+    w.write_line(DD_CRASHTRACK_BEGIN_COUNTERS)?;
     for (i, c) in PROFILING_OP_COUNTERS.iter().enumerate() {
-        writeln!(
-            w,
-            "{{\"{}\": {}}}",
-            ProfilingOpTypes::name(i)?,
-            c.load(SeqCst)
-        )?;
+        // The index is always valid, so the allocating error path isn't taken
+        let name = ProfilingOpTypes::name(i).unwrap_or("unknown");
+        w.write_str("{\"")?;
+        w.write_str(name)?;
+        w.write_str("\": ")?;
+        w.write_i64(c.load(SeqCst))?;
+        w.write_line("}")?;
     }
-    writeln!(w, "{DD_CRASHTRACK_END_COUNTERS}")?;
-    Ok(())
+    w.write_line(DD_CRASHTRACK_END_COUNTERS)
 }
 
 /// Resets all counters to 0.
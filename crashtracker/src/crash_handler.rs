@@ -5,13 +5,15 @@
 
 use crate::configuration::CrashtrackerReceiverConfig;
 
-use super::collectors::emit_backtrace_by_frames;
+use super::collectors::emit_backtrace_by_frame_pointers;
 #[cfg(target_os = "linux")]
 use super::collectors::emit_proc_self_maps;
-use super::configuration::{CrashtrackerConfiguration, StacktraceCollection};
+use super::configuration::{CrashtrackerConfiguration, StacktraceCollection, DEFAULT_SIGNALS};
 use super::constants::*;
 use super::counters::emit_counters;
-use super::crash_info::CrashtrackerMetadata;
+use super::crash_info::{CrashtrackerMetadata, PanicInfo};
+use super::receiver::unix_socket_addr;
+use super::signal_safe::SignalSafeWriter;
 use anyhow::Context;
 use libc::{
     c_void, mmap, sigaltstack, siginfo_t, MAP_ANON, MAP_FAILED, MAP_PRIVATE, PROT_NONE, PROT_READ,
@@ -19,19 +21,31 @@ use libc::{
 };
 use nix::sys::signal;
 use nix::sys::signal::{SaFlags, SigAction, SigHandler};
+use std::cell::{Cell, UnsafeCell};
 use std::fs::File;
-use std::io::Read;
-use std::io::Write;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
 use std::os::unix::net::UnixStream;
 use std::process::{Command, Stdio};
 use std::ptr;
 use std::sync::atomic::Ordering::SeqCst;
-use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64};
+use std::sync::atomic::{compiler_fence, AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
+use std::time::{Duration, Instant};
 
+/// The handlers which were registered before the crashtracker, for each handled signal
 #[derive(Debug)]
 struct OldHandlers {
-    pub sigbus: SigAction,
-    pub sigsegv: SigAction,
+    pub handlers: Vec<(signal::Signal, SigAction)>,
+}
+
+impl OldHandlers {
+    fn get(&self, signum: i32) -> Option<&SigAction> {
+        self.handlers
+            .iter()
+            .find(|(signal, _)| *signal as i32 == signum)
+            .map(|(_, action)| action)
+    }
 }
 
 enum ReceiverType {
@@ -50,6 +64,9 @@ static OLD_HANDLERS: AtomicPtr<OldHandlers> = AtomicPtr::new(ptr::null_mut());
 static RECEIVER: AtomicPtr<ReceiverType> = AtomicPtr::new(ptr::null_mut());
 static METADATA: AtomicPtr<(CrashtrackerMetadata, String)> = AtomicPtr::new(ptr::null_mut());
 static CONFIG: AtomicPtr<(CrashtrackerConfiguration, String)> = AtomicPtr::new(ptr::null_mut());
+// The thread whose panic was reported when built with panic=abort, so that the abort following
+// the panic on that thread isn't reported as a second crash. 0 if there is none.
+static PANIC_REPORTED_THREAD: AtomicUsize = AtomicUsize::new(0);
 
 fn make_receiver(config: &CrashtrackerReceiverConfig) -> anyhow::Result<std::process::Child> {
     // TODO: currently create the file in write mode.  Would append make more sense?
@@ -189,6 +206,7 @@ unsafe fn cleanup_receiver(receiver: *mut ReceiverType) -> anyhow::Result<()> {
 
 pub fn ensure_socket(socket_path: &str) -> anyhow::Result<()> {
     anyhow::ensure!(socket_is_writable(socket_path));
+    unix_socket_addr(socket_path).with_context(|| format!("Invalid socket path {socket_path}"))?;
     let socket_path_ptr =
         Box::into_raw(Box::new(ReceiverType::UnixSocket(socket_path.to_string())));
     let old = RECEIVER.swap(socket_path_ptr, SeqCst);
@@ -199,6 +217,8 @@ pub fn ensure_socket(socket_path: &str) -> anyhow::Result<()> {
 
 /// Each fork needs its own receiver.  This function should run in the child
 /// after a fork to spawn a new receiver for the child.
+/// A receiver listening on a unix socket, such as the sidecar's, accepts the reports of any number
+/// of processes, so the child keeps using it.
 /// PRECONDITIONS:
 ///     None
 /// SAFETY:
@@ -209,6 +229,11 @@ pub fn ensure_socket(socket_path: &str) -> anyhow::Result<()> {
 ///     If two simultaneous calls to this function occur, the first will win,
 ///     and the second will cleanup the redundant receiver.
 pub fn update_receiver_after_fork(config: &CrashtrackerReceiverConfig) -> anyhow::Result<()> {
+    // Safety: the receiver is only replaced by the crash-handler functions, which aren't called
+    // concurrently with this one
+    if let Some(ReceiverType::UnixSocket(_)) = unsafe { RECEIVER.load(SeqCst).as_ref() } {
+        return Ok(());
+    }
     let new_receiver = Box::into_raw(Box::new(ReceiverType::ForkedProcess(make_receiver(
         config,
     )?)));
@@ -260,21 +285,24 @@ pub fn shutdown_receiver() -> anyhow::Result<()> {
 extern "C" fn handle_posix_sigaction(signum: i32, sig_info: *mut siginfo_t, ucontext: *mut c_void) {
     // Handle the signal.  Note this has a guard to ensure that we only generate
     // one crash report per process.
-    let _ = handle_posix_signal_impl(signum);
+    let _ = handle_posix_signal_impl(signum, sig_info, ucontext);
 
     // Once we've handled the signal, chain to any previous handlers.
     // SAFETY: This was created by [register_crash_handlers].  There is a tiny
     // instant of time between when the handlers are registered, and the
     // `OLD_HANDLERS` are set.  This should be very short, but is hard to fully
     // eliminate given the existing POSIX APIs.
-    let old_handlers = unsafe { &*OLD_HANDLERS.load(SeqCst) };
-    let old_sigaction = if signum == libc::SIGSEGV {
-        old_handlers.sigsegv
-    } else if signum == libc::SIGBUS {
-        old_handlers.sigbus
-    } else {
-        unreachable!("The only signals we're registered for are SEGV and BUS")
-    };
+    let old_handlers = unsafe { OLD_HANDLERS.load(SeqCst).as_ref() };
+    // If the handlers have been restored concurrently, behave as if the previous handler was the
+    // default one.
+    let default_sigaction = SigAction::new(
+        SigHandler::SigDfl,
+        SaFlags::empty(),
+        signal::SigSet::empty(),
+    );
+    let old_sigaction = old_handlers
+        .and_then(|old_handlers| old_handlers.get(signum))
+        .unwrap_or(&default_sigaction);
 
     // How we chain depends on what kind of handler we're chaining to.
     // https://www.gnu.org/software/libc/manual/html_node/Signal-Handling.html
@@ -286,14 +314,8 @@ extern "C" fn handle_posix_sigaction(signum: i32, sig_info: *mut siginfo_t, ucon
             // In the case of a default handler, we want to invoke it so that
             // the core-dump can be generated.  Restoring the handler then
             // re-raising the signal accomplishes that.
-            let signal = if signum == libc::SIGSEGV {
-                signal::SIGSEGV
-            } else if signum == libc::SIGBUS {
-                signal::SIGBUS
-            } else {
-                unreachable!("The only signals we're registered for are SEGV and BUS")
-            };
-            unsafe { signal::sigaction(signal, &old_sigaction) }
+            let signal = signal::Signal::try_from(signum).unwrap_or_else(|_| std::process::abort());
+            unsafe { signal::sigaction(signal, old_sigaction) }
                 .unwrap_or_else(|_| std::process::abort());
             // Signals are only delivered once.
             // In the case where we were invoked because of a crash, returning
@@ -309,146 +331,646 @@ extern "C" fn handle_posix_sigaction(signum: i32, sig_info: *mut siginfo_t, ucon
     };
 }
 
-fn emit_config(w: &mut impl Write, config_str: &str) -> anyhow::Result<()> {
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_CONFIG}")?;
-    writeln!(w, "{}", config_str)?;
-    writeln!(w, "{DD_CRASHTRACK_END_CONFIG}")?;
-    Ok(())
+fn emit_config(w: &mut SignalSafeWriter<impl Write>, config_str: &str) -> io::Result<()> {
+    w.write_line(DD_CRASHTRACK_BEGIN_CONFIG)?;
+    w.write_line(config_str)?;
+    w.write_line(DD_CRASHTRACK_END_CONFIG)
 }
 
-fn emit_metadata(w: &mut impl Write, metadata_str: &str) -> anyhow::Result<()> {
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_METADATA}")?;
-    writeln!(w, "{}", metadata_str)?;
-    writeln!(w, "{DD_CRASHTRACK_END_METADATA}")?;
-    Ok(())
+fn emit_metadata(w: &mut SignalSafeWriter<impl Write>, metadata_str: &str) -> io::Result<()> {
+    w.write_line(DD_CRASHTRACK_BEGIN_METADATA)?;
+    w.write_line(metadata_str)?;
+    w.write_line(DD_CRASHTRACK_END_METADATA)
+}
+
+fn emit_procinfo(w: &mut SignalSafeWriter<impl Write>) -> io::Result<()> {
+    w.write_line(DD_CRASHTRACK_BEGIN_PROCINFO)?;
+    w.write_str("{\"pid\": ")?;
+    w.write_i64(nix::unistd::getpid().as_raw().into())?;
+    #[cfg(target_os = "linux")]
+    {
+        w.write_str(", \"tid\": ")?;
+        w.write_i64(nix::unistd::gettid().as_raw().into())?;
+    }
+    w.write_line(" }")?;
+    w.write_line(DD_CRASHTRACK_END_PROCINFO)
+}
+
+/// Whether the signal was sent by a process, through `kill`, `sigqueue`, `tgkill`..., rather than
+/// raised by the kernel because of a fault.
+#[cfg(target_os = "linux")]
+fn is_user_sent(si_code: i32) -> bool {
+    // SI_USER is 0, and every other user code (SI_QUEUE, SI_TKILL, ...) is negative
+    si_code <= 0
+}
+
+#[cfg(target_os = "macos")]
+fn is_user_sent(si_code: i32) -> bool {
+    // SI_USER and SI_QUEUE, some versions of macOS also report 0 for `kill`
+    matches!(si_code, 0 | 0x10001 | 0x10002)
 }
 
-fn emit_procinfo(w: &mut impl Write) -> anyhow::Result<()> {
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_PROCINFO}")?;
-    let pid = nix::unistd::getpid();
-    writeln!(w, "{{\"pid\": {pid} }}")?;
-    writeln!(w, "{DD_CRASHTRACK_END_PROCINFO}")?;
+/// Whether the kernel fills `si_addr` with the faulting address for this signal.
+fn has_fault_address(signum: i32) -> bool {
+    matches!(
+        signum,
+        libc::SIGSEGV | libc::SIGBUS | libc::SIGILL | libc::SIGFPE | libc::SIGTRAP
+    )
+}
+
+/// Emit a `"name": "0x..."` json field.
+fn emit_register(w: &mut SignalSafeWriter<impl Write>, name: &str, value: u64) -> io::Result<()> {
+    w.write_str("\"")?;
+    w.write_str(name)?;
+    w.write_str("\": \"")?;
+    w.write_hex(value)?;
+    w.write_str("\"")
+}
+
+/// Emit the general purpose registers saved in the ucontext as a `"registers"` json field.
+/// SAFETY:
+///     `ucontext` must be the non-null context received by the signal handler.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+unsafe fn emit_registers(
+    w: &mut SignalSafeWriter<impl Write>,
+    ucontext: *const c_void,
+) -> io::Result<()> {
+    const REGISTERS: [(&str, libc::c_int); 18] = [
+        ("rax", libc::REG_RAX),
+        ("rbx", libc::REG_RBX),
+        ("rcx", libc::REG_RCX),
+        ("rdx", libc::REG_RDX),
+        ("rsi", libc::REG_RSI),
+        ("rdi", libc::REG_RDI),
+        ("rbp", libc::REG_RBP),
+        ("rsp", libc::REG_RSP),
+        ("r8", libc::REG_R8),
+        ("r9", libc::REG_R9),
+        ("r10", libc::REG_R10),
+        ("r11", libc::REG_R11),
+        ("r12", libc::REG_R12),
+        ("r13", libc::REG_R13),
+        ("r14", libc::REG_R14),
+        ("r15", libc::REG_R15),
+        ("rip", libc::REG_RIP),
+        ("eflags", libc::REG_EFL),
+    ];
+    let gregs = &(*(ucontext as *const libc::ucontext_t)).uc_mcontext.gregs;
+    w.write_str(", \"registers\": {")?;
+    for (i, (name, reg)) in REGISTERS.iter().enumerate() {
+        if i > 0 {
+            w.write_str(", ")?;
+        }
+        emit_register(w, name, gregs[*reg as usize] as u64)?;
+    }
+    w.write_str("}")
+}
+
+/// Emit the general purpose registers saved in the ucontext as a `"registers"` json field.
+/// SAFETY:
+///     `ucontext` must be the non-null context received by the signal handler.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+unsafe fn emit_registers(
+    w: &mut SignalSafeWriter<impl Write>,
+    ucontext: *const c_void,
+) -> io::Result<()> {
+    let mcontext = &(*(ucontext as *const libc::ucontext_t)).uc_mcontext;
+    w.write_str(", \"registers\": {")?;
+    for (i, reg) in mcontext.regs.iter().enumerate() {
+        w.write_str("\"x")?;
+        w.write_u64(i as u64)?;
+        w.write_str("\": \"")?;
+        w.write_hex(*reg)?;
+        w.write_str("\", ")?;
+    }
+    emit_register(w, "sp", mcontext.sp)?;
+    w.write_str(", ")?;
+    emit_register(w, "pc", mcontext.pc)?;
+    w.write_str(", ")?;
+    emit_register(w, "pstate", mcontext.pstate)?;
+    w.write_str("}")
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+unsafe fn emit_registers(
+    _w: &mut SignalSafeWriter<impl Write>,
+    _ucontext: *const c_void,
+) -> io::Result<()> {
     Ok(())
 }
 
-fn emit_siginfo(w: &mut impl Write, signum: i32) -> anyhow::Result<()> {
-    let signame = if signum == libc::SIGSEGV {
-        "SIGSEGV"
-    } else if signum == libc::SIGBUS {
-        "SIGBUS"
-    } else {
-        "UNKNOWN"
+/// Emit the signal information, and the registers of the crashing thread, as formatted json.
+/// SAFETY:
+///     `sig_info` and `ucontext` must be either null, or the pointers received by the signal
+///     handler for `signum`.
+/// SIGNAL SAFETY:
+///     Only reads from the given pointers and formats integers, nothing is allocated.
+unsafe fn emit_siginfo(
+    w: &mut SignalSafeWriter<impl Write>,
+    signum: i32,
+    sig_info: *const siginfo_t,
+    ucontext: *const c_void,
+) -> io::Result<()> {
+    let signame = signal::Signal::try_from(signum).map_or("UNKNOWN", |signal| signal.as_str());
+
+    w.write_line(DD_CRASHTRACK_BEGIN_SIGINFO)?;
+    w.write_str("{\"signum\": ")?;
+    w.write_i64(signum.into())?;
+    w.write_str(", \"signame\": \"")?;
+    w.write_str(signame)?;
+    w.write_str("\"")?;
+    if let Some(sig_info) = sig_info.as_ref() {
+        let si_code = sig_info.si_code;
+        w.write_str(", \"si_code\": ")?;
+        w.write_i64(si_code.into())?;
+        // The fields of the siginfo union are only valid depending on who raised the signal
+        if is_user_sent(si_code) {
+            w.write_str(", \"si_pid\": ")?;
+            w.write_i64(sig_info.si_pid().into())?;
+        } else if has_fault_address(signum) {
+            w.write_str(", ")?;
+            emit_register(w, "si_addr", sig_info.si_addr() as u64)?;
+        }
+    }
+    if !ucontext.is_null() {
+        emit_registers(w, ucontext)?;
+    }
+    w.write_line("}")?;
+    w.write_line(DD_CRASHTRACK_END_SIGINFO)
+}
+
+/// Size of the buffer the crash report is formatted into before being written to the receiver.
+const CRASH_REPORT_BUFFER_SIZE: usize = 4096;
+
+/// The signal handler can't allocate, so the crash report buffer is allocated statically.
+struct CrashReportBuffer(UnsafeCell<[u8; CRASH_REPORT_BUFFER_SIZE]>);
+
+// SAFETY: the buffer is only used by the crash handler, which only runs once.
+unsafe impl Sync for CrashReportBuffer {}
+
+static CRASH_REPORT_BUFFER: CrashReportBuffer =
+    CrashReportBuffer(UnsafeCell::new([0; CRASH_REPORT_BUFFER_SIZE]));
+
+/// Where the crash report is written. Raw handles are used so that a nested crash handler can
+/// complete the report, see [`handle_nested_fault`].
+#[derive(Clone, Copy)]
+struct ReportSink {
+    fd: RawFd,
+    /// The pid of the forked receiver, `None` when the report is sent over a unix socket.
+    receiver_pid: Option<libc::pid_t>,
+}
+
+impl ReportSink {
+    /// A handle writing to the sink, which doesn't close it when dropped.
+    fn writer(&self) -> ManuallyDrop<File> {
+        // SAFETY: the fd stays open until the report is finished.
+        ManuallyDrop::new(unsafe { File::from_raw_fd(self.fd) })
+    }
+
+    /// Let the receiver know that the report is complete, then wait for it to process the report,
+    /// at most `config.timeout`, if `config.wait_for_receiver` is set.
+    /// SIGNAL SAFETY:
+    ///     Only calls `close`, `shutdown`, `waitpid`, `nanosleep`, `clock_gettime` and `poll`.
+    fn finish(&self, config: &CrashtrackerConfiguration) -> io::Result<()> {
+        match self.receiver_pid {
+            Some(pid) => {
+                // Closing the pipe lets the receiver know no more data is coming, which avoids a
+                // deadlock where it waits for input while we wait for it to exit.
+                unsafe { libc::close(self.fd) };
+                if config.wait_for_receiver {
+                    wait_for_child(pid, config.timeout)?;
+                }
+                Ok(())
+            }
+            None => {
+                unsafe { libc::shutdown(self.fd, libc::SHUT_WR) };
+                // The receiver can signal completion by either writing at least one byte,
+                // or by closing the stream.
+                let res = if config.wait_for_receiver {
+                    wait_for_readable(self.fd, config.timeout)
+                } else {
+                    Ok(())
+                };
+                unsafe { libc::close(self.fd) };
+                res
+            }
+        }
+    }
+}
+
+/// Wait for the child to exit, at most `timeout`. The child is left running if it doesn't exit
+/// in time, it may still manage to send the report.
+fn wait_for_child(pid: libc::pid_t, timeout: Duration) -> io::Result<()> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+    // `Instant::now` and `sleep` are thin wrappers over `clock_gettime` and `nanosleep`, which are
+    // signal safe.
+    let start = Instant::now();
+    loop {
+        match unsafe { libc::waitpid(pid, ptr::null_mut(), libc::WNOHANG) } {
+            0 => {}
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            -1 => return Err(io::Error::last_os_error()),
+            _ => return Ok(()),
+        }
+        if start.elapsed() >= timeout {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Wait for `fd` to be readable or closed by the peer, at most `timeout`.
+fn wait_for_readable(fd: RawFd, timeout: Duration) -> io::Result<()> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
     };
+    let timeout_ms = timeout.as_millis().try_into().unwrap_or(libc::c_int::MAX);
+    match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+        0 => Err(io::ErrorKind::TimedOut.into()),
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
 
-    writeln!(w, "{DD_CRASHTRACK_BEGIN_SIGINFO}")?;
-    writeln!(w, "{{\"signum\": {signum}, \"signame\": \"{signame}\"}}")?;
-    writeln!(w, "{DD_CRASHTRACK_END_SIGINFO}")?;
-    Ok(())
+/// The crash report being emitted by the crash handler, so that a nested crash handler can
+/// complete it if emitting it faults. Lives on the stack of the crash handler emitting the report.
+struct ReportInProgress {
+    thread: libc::pthread_t,
+    sink: ReportSink,
+    config: &'static CrashtrackerConfiguration,
+    /// The begin marker of the section being emitted, if any.
+    section: Cell<Option<&'static str>>,
 }
 
-fn emit_crashreport(
-    pipe: &mut impl Write,
-    config: &CrashtrackerConfiguration,
+impl ReportInProgress {
+    /// Emit a section of the report, and flush it so that a fault only loses the section it
+    /// happens in.
+    fn emit_section<W: Write>(
+        &self,
+        w: &mut SignalSafeWriter<W>,
+        section: &'static str,
+        emit: impl FnOnce(&mut SignalSafeWriter<W>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        self.set_section(Some(section));
+        emit(w)?;
+        w.flush()?;
+        self.set_section(None);
+        Ok(())
+    }
+
+    fn set_section(&self, section: Option<&'static str>) {
+        self.section.set(section);
+        // The section is read by a nested signal handler on this thread, so the compiler must not
+        // move the write past the code of the section.
+        compiler_fence(SeqCst);
+    }
+}
+
+static REPORT_IN_PROGRESS: AtomicPtr<ReportInProgress> = AtomicPtr::new(ptr::null_mut());
+
+/// Emit the crash report onto the sink of `report`.
+/// SAFETY:
+///     Can only be called once, from the signal handler, as it uses the static report buffer.
+///     `sig_info` and `ucontext` must be the pointers received by the signal handler.
+/// SIGNAL SAFETY:
+///     Nothing is allocated and no lock is taken, the report is formatted by hand into the static
+///     buffer.
+unsafe fn emit_crashreport(
+    report: &ReportInProgress,
     config_str: &str,
     metadata_string: &str,
     signum: i32,
-) -> anyhow::Result<()> {
-    emit_metadata(pipe, metadata_string)?;
-    emit_config(pipe, config_str)?;
-    emit_siginfo(pipe, signum)?;
-    emit_procinfo(pipe)?;
-    pipe.flush()?;
-    emit_counters(pipe)?;
-    pipe.flush()?;
+    sig_info: *const siginfo_t,
+    ucontext: *const c_void,
+) -> io::Result<()> {
+    let mut pipe = report.sink.writer();
+    let buffer = &mut *CRASH_REPORT_BUFFER.0.get();
+    let w = &mut SignalSafeWriter::new(&mut *pipe, buffer);
+    report.emit_section(w, DD_CRASHTRACK_BEGIN_METADATA, |w| {
+        emit_metadata(w, metadata_string)
+    })?;
+    report.emit_section(w, DD_CRASHTRACK_BEGIN_CONFIG, |w| {
+        emit_config(w, config_str)
+    })?;
+    report.emit_section(w, DD_CRASHTRACK_BEGIN_SIGINFO, |w| {
+        emit_siginfo(w, signum, sig_info, ucontext)
+    })?;
+    report.emit_section(w, DD_CRASHTRACK_BEGIN_PROCINFO, emit_procinfo)?;
+    report.emit_section(w, DD_CRASHTRACK_BEGIN_COUNTERS, emit_counters)?;
 
     #[cfg(target_os = "linux")]
-    emit_proc_self_maps(pipe)?;
+    report.emit_section(w, DD_CRASHTRACK_BEGIN_FILE, emit_proc_self_maps)?;
 
-    // Getting a backtrace on rust is not guaranteed to be signal safe
-    // https://github.com/rust-lang/backtrace-rs/issues/414
-    // let current_backtrace = backtrace::Backtrace::new();
-    // In fact, if we look into the code here, we see mallocs.
-    // https://doc.rust-lang.org/src/std/backtrace.rs.html#332
+    // Unwinders such as the `backtrace` crate, or the one of the standard library, allocate and
+    // take locks, so the stack is walked with the frame pointers instead. Symbols, if requested,
+    // are resolved by the receiver.
     // Do this last, so even if it crashes, we still get the other info.
-    if config.resolve_frames != StacktraceCollection::Disabled {
-        unsafe { emit_backtrace_by_frames(pipe, config.resolve_frames)? };
+    if report.config.resolve_frames != StacktraceCollection::Disabled {
+        report.emit_section(w, DD_CRASHTRACK_BEGIN_STACKTRACE, |w| {
+            emit_backtrace_by_frame_pointers(w, ucontext)
+        })?;
     }
-    writeln!(pipe, "{DD_CRASHTRACK_DONE}")?;
-    pipe.flush()?;
+    w.write_line(DD_CRASHTRACK_DONE)?;
+    w.flush()
+}
 
-    Ok(())
+/// Called when the crash handler faults while emitting the crash report: report the section which
+/// was lost, then complete the report as usual instead of losing the rest of it.
+/// SIGNAL SAFETY:
+///     Formats into a buffer on the stack, the static report buffer may be in an invalid state.
+fn handle_nested_fault() -> io::Result<()> {
+    // SAFETY: the report is only published while the crash handler emitting it is on the stack.
+    let report = unsafe { REPORT_IN_PROGRESS.load(SeqCst).as_ref() };
+    // A crash of another thread while the report is emitted is ignored, as the report can't be
+    // written to concurrently.
+    let Some(report) = report.filter(|report| report.thread == unsafe { libc::pthread_self() })
+    else {
+        return Ok(());
+    };
+    // Don't try again if completing the report faults too
+    REPORT_IN_PROGRESS.store(ptr::null_mut(), SeqCst);
+
+    let mut pipe = report.sink.writer();
+    let mut buffer = [0u8; 256];
+    let w = &mut SignalSafeWriter::new(&mut *pipe, &mut buffer);
+    // The marker must start a line, the receiver drops the one cut short by the fault.
+    w.write_str("\n")?;
+    w.write_str(DD_CRASHTRACK_NESTED_FAULT)?;
+    if let Some(section) = report.section.get() {
+        w.write_str(" ")?;
+        w.write_str(section)?;
+    }
+    w.write_line("")?;
+    w.write_line(DD_CRASHTRACK_DONE)?;
+    w.flush()?;
+    report.sink.finish(report.config)
+}
+
+/// Allow the receiver to ptrace this process, so that it can capture the stacks of the other
+/// threads. With Yama's `ptrace_scope` set to 1, only ancestors could trace the process otherwise.
+/// Failing is fine, e.g. when Yama isn't enabled there is nothing to allow.
+#[cfg(target_os = "linux")]
+fn allow_ptrace_from(config: &CrashtrackerConfiguration, receiver_pid: Option<u32>) {
+    if config.resolve_frames == StacktraceCollection::Disabled {
+        return;
+    }
+    if let Some(receiver_pid) = receiver_pid {
+        unsafe { libc::prctl(libc::PR_SET_PTRACER, receiver_pid as libc::c_ulong, 0, 0, 0) };
+    }
 }
 
-fn handle_posix_signal_impl(signum: i32) -> anyhow::Result<()> {
+/// The pid of the process at the other end of the socket.
+#[cfg(target_os = "linux")]
+fn peer_pid(stream: &UnixStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut c_void,
+            &mut len,
+        )
+    };
+    (res == 0 && cred.pid > 0).then_some(cred.pid as u32)
+}
+
+/// Errors of the signal handler carry no message, as building one would allocate. The handler's
+/// result is ignored anyway.
+fn handler_error() -> io::Error {
+    io::ErrorKind::Other.into()
+}
+
+fn handle_posix_signal_impl(
+    signum: i32,
+    sig_info: *const siginfo_t,
+    ucontext: *const c_void,
+) -> io::Result<()> {
+    if signum == libc::SIGABRT && PANIC_REPORTED_THREAD.load(SeqCst) == current_thread() {
+        // The panic which led to the abort was already reported
+        return Ok(());
+    }
     static NUM_TIMES_CALLED: AtomicU64 = AtomicU64::new(0);
     if NUM_TIMES_CALLED.fetch_add(1, SeqCst) > 0 {
         // In the case where some lower-level signal handler recovered the error
         // we don't want to spam the system with calls.  Make this one shot.
-        return Ok(());
+        // This is also where a fault while emitting the crash report lands.
+        return handle_nested_fault();
     }
-    eprintln!("start");
 
     // Leak receiver to avoid calling 'drop' during a crash
-    let receiver = RECEIVER.swap(ptr::null_mut(), SeqCst);
-    anyhow::ensure!(!receiver.is_null(), "No crashtracking receiver");
+    let receiver = unsafe { RECEIVER.swap(ptr::null_mut(), SeqCst).as_mut() };
+    let receiver = receiver.ok_or_else(handler_error)?;
 
     // Leak config, and metadata to avoid calling 'drop' during a crash
-    let config = CONFIG.swap(ptr::null_mut(), SeqCst);
-    anyhow::ensure!(!config.is_null(), "No crashtracking config");
-    let (config, config_str) = unsafe { config.as_ref().context("No crashtracking receiver")? };
+    let config = unsafe { CONFIG.swap(ptr::null_mut(), SeqCst).as_ref() };
+    let (config, config_str) = config.ok_or_else(handler_error)?;
 
-    let metadata_ptr = METADATA.swap(ptr::null_mut(), SeqCst);
-    anyhow::ensure!(!metadata_ptr.is_null(), "No crashtracking metadata");
-    let (_metadata, metadata_string) = unsafe { metadata_ptr.as_ref().context("metadata ptr")? };
+    let metadata = unsafe { METADATA.swap(ptr::null_mut(), SeqCst).as_ref() };
+    let (_metadata, metadata_string) = metadata.ok_or_else(handler_error)?;
 
-    match unsafe { receiver.as_mut().context("No crashtracking receiver")? } {
+    let sink = match receiver {
         ReceiverType::ForkedProcess(child) => {
-            let pipe = child
-                .stdin
-                .as_mut()
-                .context("Crashtracker: Can't get pipe")?;
-            let res = emit_crashreport(pipe, config, config_str, metadata_string, signum);
-            let _ = pipe.flush();
-            if config.wait_for_receiver {
-                // https://doc.rust-lang.org/std/process/struct.Child.html#method.wait
-                // The stdin handle to the child process, if any, will be closed before waiting.
-                // This helps avoid deadlock: it ensures that the child does not block waiting
-                // for input from the parent, while the parent waits for the child to exit.
-                // TODO, use a polling mechanism that could recover from a crashing child
-                child.wait()?;
-            } else {
-                // Dropping the handle closes it.
-                drop(child.stdin.take())
+            #[cfg(target_os = "linux")]
+            allow_ptrace_from(config, Some(child.id()));
+            let pipe = child.stdin.take().ok_or_else(handler_error)?;
+            ReportSink {
+                fd: pipe.into_raw_fd(),
+                receiver_pid: Some(child.id() as libc::pid_t),
             }
-            res
         }
         ReceiverType::UnixSocket(path) => {
-            let mut unix_stream = UnixStream::connect(path)?;
-            let res = emit_crashreport(
-                &mut unix_stream,
-                config,
-                config_str,
-                metadata_string,
-                signum,
-            );
-            let _ = unix_stream.flush();
-            unix_stream
-                .shutdown(std::net::Shutdown::Write)
-                .context("Could not shutdown writing on the stream")?;
-            if config.wait_for_receiver {
-                let mut buf = [0; 1];
-                // The receiver can signal completion by either writing at least one byte,
-                // or by closing the stream.
-                let _ = unix_stream.read_exact(&mut buf[..]);
+            // Resolving the address doesn't allocate, unlike `UnixStream::connect`
+            let unix_stream = UnixStream::connect_addr(&unix_socket_addr(path)?)?;
+            #[cfg(target_os = "linux")]
+            allow_ptrace_from(config, peer_pid(&unix_stream));
+            ReportSink {
+                fd: unix_stream.into_raw_fd(),
+                receiver_pid: None,
             }
-            res
         }
-    }
+    };
+
+    let report = ReportInProgress {
+        thread: unsafe { libc::pthread_self() },
+        sink,
+        config,
+        section: Cell::new(None),
+    };
+    REPORT_IN_PROGRESS.store(&report as *const ReportInProgress as *mut _, SeqCst);
+    let res = unsafe {
+        emit_crashreport(
+            &report,
+            config_str,
+            metadata_string,
+            signum,
+            sig_info,
+            ucontext,
+        )
+    };
+    REPORT_IN_PROGRESS.store(ptr::null_mut(), SeqCst);
+    sink.finish(config)?;
+    res
     // Calling "free" in a signal handler is dangerous, so we just leak the
     // objects we took (receiver, metadata, config, etc)
 }
 
+/// Return an identifier of the calling thread, never 0.
+fn current_thread() -> usize {
+    unsafe { libc::pthread_self() as usize }
+}
+
+/// Registers a panic hook which reports Rust panics to a new receiver, then calls the previous
+/// hook. Only the first panic of the process is reported, so that a panic which is caught and
+/// repeated doesn't start a receiver each time. When built with panic=abort, the signal handler
+/// then ignores the SIGABRT raised by the panicking thread once the hook returns.
+/// This function can be called multiple times, the hook is only registered once.
+/// PRECONDITIONS:
+///     The config and metadata should be set, panics are ignored otherwise.
+pub fn register_panic_hook(receiver_config: CrashtrackerReceiverConfig) {
+    static REGISTERED: AtomicBool = AtomicBool::new(false);
+    if REGISTERED.swap(true, SeqCst) {
+        return;
+    }
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        static REPORTED: AtomicBool = AtomicBool::new(false);
+        // Marks the frame of the hook on the stack, see `emit_panic_backtrace`
+        let hook_frame = 0u8;
+        if !REPORTED.swap(true, SeqCst) {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned());
+            let panic_info = PanicInfo {
+                message,
+                location: info.location().map(|location| location.to_string()),
+            };
+            match report_panic(&receiver_config, &panic_info, &hook_frame) {
+                Ok(()) if cfg!(panic = "abort") => {
+                    PANIC_REPORTED_THREAD.store(current_thread(), SeqCst)
+                }
+                Ok(()) => {}
+                Err(e) => eprintln!("Unable to report the panic to the crashtracker: {e}"),
+            }
+        }
+        previous_hook(info);
+    }));
+}
+
+/// Send the report of a panic to a new receiver, so that the receiver of the signal handler is
+/// still there if the process crashes later on. Unlike the signal handler, the panic hook runs in
+/// a normal context, where allocating and taking locks is fine.
+fn report_panic(
+    receiver_config: &CrashtrackerReceiverConfig,
+    panic_info: &PanicInfo,
+    hook_frame: *const u8,
+) -> anyhow::Result<()> {
+    // SAFETY: the config and metadata are only freed when they are updated, and crash-tracking
+    // functions aren't expected to be called concurrently with a panic.
+    let config = unsafe { CONFIG.load(SeqCst).as_ref() };
+    let (config, config_str) = config.context("Missing crashtracker configuration")?;
+    let metadata = unsafe { METADATA.load(SeqCst).as_ref() };
+    let (_metadata, metadata_string) = metadata.context("Missing crashtracker metadata")?;
+
+    let mut receiver = make_receiver(receiver_config)?;
+    let pipe = receiver.stdin.take().context("Missing receiver stdin")?;
+    let sink = ReportSink {
+        fd: pipe.into_raw_fd(),
+        receiver_pid: Some(receiver.id() as libc::pid_t),
+    };
+    let mut pipe = sink.writer();
+    let mut buffer = vec![0; CRASH_REPORT_BUFFER_SIZE];
+    let res = emit_panic_report(
+        &mut SignalSafeWriter::new(&mut *pipe, &mut buffer),
+        config,
+        config_str,
+        metadata_string,
+        panic_info,
+        hook_frame,
+    );
+    sink.finish(config)?;
+    res
+}
+
+fn emit_panic_report(
+    w: &mut SignalSafeWriter<impl Write>,
+    config: &CrashtrackerConfiguration,
+    config_str: &str,
+    metadata_string: &str,
+    panic_info: &PanicInfo,
+    hook_frame: *const u8,
+) -> anyhow::Result<()> {
+    emit_metadata(w, metadata_string)?;
+    emit_config(w, config_str)?;
+    w.write_line(DD_CRASHTRACK_BEGIN_PANIC)?;
+    w.write_line(&serde_json::to_string(panic_info)?)?;
+    w.write_line(DD_CRASHTRACK_END_PANIC)?;
+    emit_procinfo(w)?;
+    emit_counters(w)?;
+    #[cfg(target_os = "linux")]
+    emit_proc_self_maps(w)?;
+    if config.resolve_frames != StacktraceCollection::Disabled {
+        emit_panic_backtrace(w, hook_frame)?;
+    }
+    w.write_line(DD_CRASHTRACK_DONE)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Emit the stacktrace of the panicking thread, starting at the caller of the panic hook, so that
+/// the innermost frames, used for the fingerprint, are those of the panic. Symbols, if requested,
+/// are resolved by the receiver as for the crashes reported by the signal handler.
+///
+/// `hook_frame` is the address of a local of the hook. The stack growing down, the frames called
+/// by the hook are those whose stack pointer is below it, and the next frame is the hook's.
+fn emit_panic_backtrace(
+    w: &mut SignalSafeWriter<impl Write>,
+    hook_frame: *const u8,
+) -> io::Result<()> {
+    w.write_line(DD_CRASHTRACK_BEGIN_STACKTRACE)?;
+    let mut frames = vec![];
+    backtrace::trace(|frame| {
+        frames.push((frame.ip(), frame.sp(), frame.symbol_address()));
+        true
+    });
+    // Backends which don't know the stack pointers report null ones, all frames are kept then
+    let hook_callees = frames
+        .iter()
+        .take_while(|(_, sp, _)| !sp.is_null() && *sp as usize <= hook_frame as usize)
+        .count();
+    let skipped = if hook_callees > 0 {
+        hook_callees + 1
+    } else {
+        0
+    };
+    for (ip, sp, symbol_address) in frames.into_iter().skip(skipped) {
+        w.write_str("{\"ip\": \"")?;
+        w.write_hex(ip as usize as u64)?;
+        w.write_str("\", \"sp\": \"")?;
+        w.write_hex(sp as usize as u64)?;
+        w.write_str("\", \"symbol_address\": \"")?;
+        w.write_hex(symbol_address as usize as u64)?;
+        w.write_line("\"}")?;
+    }
+    w.write_line(DD_CRASHTRACK_END_STACKTRACE)
+}
+
 /// Registers UNIX signal handlers to detect program crashes.
+/// The default signals are handled if `signals` is empty.
 /// This function can be called multiple times and will be idempotent: it will
 /// only create and set the handlers once.
 /// However, note the restriction below:
@@ -467,18 +989,39 @@ fn handle_posix_signal_impl(signum: i32) -> anyhow::Result<()> {
 ///     will not yet be stored.  This would lead to unexpected behaviour for the
 ///     user.  This should only matter if something crashes concurrently with
 ///     this function executing.
-pub fn register_crash_handlers(create_alt_stack: bool) -> anyhow::Result<()> {
+pub fn register_crash_handlers(create_alt_stack: bool, signals: &[i32]) -> anyhow::Result<()> {
     if !OLD_HANDLERS.load(SeqCst).is_null() {
         return Ok(());
     }
 
+    let signals = if signals.is_empty() {
+        &DEFAULT_SIGNALS[..]
+    } else {
+        signals
+    };
+    let signals = signals
+        .iter()
+        .map(|signum| Ok(signal::Signal::try_from(*signum)?))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
     unsafe {
         if create_alt_stack {
             set_alt_stack()?;
         }
-        let sigbus = register_signal_handler(signal::SIGBUS)?;
-        let sigsegv = register_signal_handler(signal::SIGSEGV)?;
-        let boxed_ptr = Box::into_raw(Box::new(OldHandlers { sigbus, sigsegv }));
+        let mut handlers = Vec::with_capacity(signals.len());
+        for signal in signals {
+            match register_signal_handler(signal) {
+                Ok(old_handler) => handlers.push((signal, old_handler)),
+                Err(err) => {
+                    // Don't leave the handlers registered so far without a way to chain them
+                    for (signal, old_handler) in &handlers {
+                        let _ = signal::sigaction(*signal, old_handler);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        let boxed_ptr = Box::into_raw(Box::new(OldHandlers { handlers }));
 
         let res = OLD_HANDLERS.compare_exchange(ptr::null_mut(), boxed_ptr, SeqCst, SeqCst);
         anyhow::ensure!(
@@ -514,9 +1057,10 @@ pub fn restore_old_handlers(inside_signal_handler: bool) -> anyhow::Result<()> {
     anyhow::ensure!(!prev.is_null(), "No crashtracking previous signal handlers");
     // Safety: The only nonnull pointer stored here comes from Box::into_raw()
     let prev = unsafe { Box::from_raw(prev) };
-    // Safety: The value restored here was returned from a previous sigaction call
-    unsafe { signal::sigaction(signal::SIGBUS, &prev.sigbus)? };
-    unsafe { signal::sigaction(signal::SIGSEGV, &prev.sigsegv)? };
+    for (signal, old_handler) in &prev.handlers {
+        // Safety: The value restored here was returned from a previous sigaction call
+        unsafe { signal::sigaction(*signal, old_handler)? };
+    }
     // We want to avoid freeing memory inside the handler, so just leak it
     // This is fine since we're crashing anyway at this point
     if inside_signal_handler {
@@ -562,3 +1106,164 @@ unsafe fn set_alt_stack() -> anyhow::Result<()> {
     ALTSTACK_INIT.store(true, SeqCst);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SigInfo;
+    use std::os::fd::AsRawFd;
+
+    fn parse_siginfo(
+        emit: impl FnOnce(&mut SignalSafeWriter<Vec<u8>>) -> io::Result<()>,
+    ) -> SigInfo {
+        let mut output = vec![];
+        let mut buffer = [0u8; 64];
+        let mut w = SignalSafeWriter::new(&mut output, &mut buffer);
+        emit(&mut w).unwrap();
+        w.flush().unwrap();
+        let report = std::str::from_utf8(&output).unwrap();
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some(DD_CRASHTRACK_BEGIN_SIGINFO));
+        let siginfo = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(lines.next(), Some(DD_CRASHTRACK_END_SIGINFO));
+        siginfo
+    }
+
+    #[test]
+    fn test_emit_siginfo_without_context() {
+        let siginfo =
+            parse_siginfo(|w| unsafe { emit_siginfo(w, libc::SIGSEGV, ptr::null(), ptr::null()) });
+        assert_eq!(siginfo.signum, libc::SIGSEGV as u64);
+        assert_eq!(siginfo.signame.as_deref(), Some("SIGSEGV"));
+        assert_eq!(siginfo.si_code, None);
+        assert!(siginfo.registers.is_empty());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_emit_siginfo_user_sent() {
+        // A zeroed siginfo has an si_code of SI_USER, as sent by `kill`
+        let sig_info: siginfo_t = unsafe { std::mem::zeroed() };
+        let ucontext: libc::ucontext_t = unsafe { std::mem::zeroed() };
+        let siginfo = parse_siginfo(|w| unsafe {
+            emit_siginfo(
+                w,
+                libc::SIGABRT,
+                &sig_info,
+                &ucontext as *const libc::ucontext_t as *const c_void,
+            )
+        });
+        assert_eq!(siginfo.signame.as_deref(), Some("SIGABRT"));
+        assert_eq!(siginfo.si_code, Some(0));
+        assert_eq!(siginfo.si_pid, Some(0));
+        assert_eq!(siginfo.si_addr, None);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            siginfo.registers.get("rip").map(String::as_str),
+            Some("0x0")
+        );
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(siginfo.registers.get("pc").map(String::as_str), Some("0x0"));
+    }
+
+    #[inline(never)]
+    fn panic_in_callee() {
+        panic!("test panic");
+    }
+
+    #[inline(never)]
+    fn first_caller() {
+        panic_in_callee()
+    }
+
+    #[inline(never)]
+    fn second_caller() {
+        panic_in_callee()
+    }
+
+    /// Panic from `caller` on a new thread, and return the stacktrace emitted by a panic hook.
+    fn panic_stacktrace(caller: fn()) -> Vec<crate::StackFrame> {
+        const THREAD_NAME: &str = "panic-stacktrace-test";
+        static OUTPUT: std::sync::Mutex<Vec<u8>> = std::sync::Mutex::new(vec![]);
+        static HOOK: std::sync::Once = std::sync::Once::new();
+        HOOK.call_once(|| {
+            let previous_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                if std::thread::current().name() != Some(THREAD_NAME) {
+                    return previous_hook(info);
+                }
+                let hook_frame = 0u8;
+                let mut output = OUTPUT.lock().unwrap();
+                let mut buffer = [0u8; 256];
+                let mut w = SignalSafeWriter::new(&mut *output, &mut buffer);
+                emit_panic_backtrace(&mut w, &hook_frame).unwrap();
+                w.flush().unwrap();
+            }));
+        });
+
+        std::thread::Builder::new()
+            .name(THREAD_NAME.to_string())
+            .spawn(move || std::panic::catch_unwind(caller))
+            .unwrap()
+            .join()
+            .unwrap()
+            .unwrap_err();
+        let output = std::mem::take(&mut *OUTPUT.lock().unwrap());
+        let report = String::from_utf8(output).unwrap();
+        let mut lines = report.lines();
+        assert_eq!(lines.next(), Some(DD_CRASHTRACK_BEGIN_STACKTRACE));
+        lines
+            .take_while(|line| *line != DD_CRASHTRACK_END_STACKTRACE)
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_panics_with_different_callers_have_different_fingerprints() {
+        let fingerprint = |caller| {
+            let mut crash_info = crate::CrashInfo::new();
+            crash_info
+                .set_panic_info(PanicInfo {
+                    message: Some("test panic".to_string()),
+                    location: Some("src/crash_handler.rs:1:1".to_string()),
+                })
+                .unwrap();
+            crash_info
+                .set_stacktrace(None, panic_stacktrace(caller))
+                .unwrap();
+            crate::fingerprint::fingerprint(&crash_info)
+        };
+        assert_eq!(fingerprint(first_caller), fingerprint(first_caller));
+        assert_ne!(fingerprint(first_caller), fingerprint(second_caller));
+    }
+
+    #[test]
+    fn test_abort_after_reported_panic_is_ignored() {
+        PANIC_REPORTED_THREAD.store(current_thread(), SeqCst);
+        // Without a receiver, the crash would fail to be reported
+        let res = handle_posix_signal_impl(libc::SIGABRT, ptr::null(), ptr::null());
+        PANIC_REPORTED_THREAD.store(0, SeqCst);
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_wait_for_child_timeout() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        let res = wait_for_child(pid, Duration::from_millis(50));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_readable_timeout() {
+        let (local, mut remote) = UnixStream::pair().unwrap();
+        let res = wait_for_readable(local.as_raw_fd(), Duration::from_millis(50));
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        remote.write_all(b"x").unwrap();
+        wait_for_readable(local.as_raw_fd(), Duration::from_secs(10)).unwrap();
+    }
+}
@@ -1,10 +1,15 @@
 // Copyright 2023-Present Datadog, Inc. https://www.datadoghq.com/
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(unix)]
+use crate::debug_files::DebugFileFinder;
 use crate::stacktrace::StackFrame;
+#[cfg(unix)]
+use crate::stacktrace::{NormalizedAddress, NormalizedAddressMeta};
 use crate::telemetry::TelemetryCrashUploader;
 use crate::CrashtrackerConfiguration;
 use anyhow::Context;
+use base64::Engine;
 use chrono::{DateTime, Utc};
 use ddcommon::tag::Tag;
 use serde::{Deserialize, Serialize};
@@ -37,21 +42,118 @@ impl CrashtrackerMetadata {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct SigInfo {
     pub signum: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub signame: Option<String>,
+    /// The `si_code` of the signal, e.g. SEGV_MAPERR vs SEGV_ACCERR, or SI_USER for a `kill`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub si_code: Option<i32>,
+    /// The faulting address, as a hex string, for signals raised by a memory fault.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub si_addr: Option<String>,
+    /// The pid of the process which sent the signal, for signals sent by `kill` and friends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub si_pid: Option<u32>,
+    /// General purpose registers at the time of the crash, as hex strings keyed by their name.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    pub registers: HashMap<String, String>,
+}
+
+/// What the crash report is about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CrashKind {
+    /// The process received one of the signals handled by the crashtracker.
+    #[default]
+    UnixSignal,
+    /// A Rust panic, reported by the panic hook. The process may have recovered from it.
+    Panic,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PanicInfo {
+    /// The panic message, if the payload of the panic is a string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Where the panic happened, as `file:line:column`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub location: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProcessInfo {
     pub pid: u32,
+    /// The id of the crashing thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub tid: Option<u32>,
+}
+
+/// A resource limit of the crashed process, in the units of `/proc/<pid>/limits`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceLimit {
+    /// `None` when unlimited.
+    pub soft: Option<u64>,
+    /// `None` when unlimited.
+    pub hard: Option<u64>,
+}
+
+/// The state of the crashed process, read by the receiver while the process is kept alive. Each
+/// field is best effort, and left empty if it couldn't be read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeContext {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub cmdline: Vec<String>,
+    /// The environment variables allow-listed by the configuration, as the process was started
+    /// with them.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// Resource limits, keyed by their name in snake case, e.g. `max_open_files`.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    pub limits: HashMap<String, ResourceLimit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub open_fds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub peak_rss_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub rss_bytes: Option<u64>,
+    /// The id of the crashing thread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub thread_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub thread_name: Option<String>,
+    /// Time since the process started, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub uptime_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub vm_size_bytes: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CrashInfo {
+    /// Registers of the innermost frame of the additional stacktraces, as hex strings keyed by
+    /// their name, keyed by thread like the stacktraces.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    pub additional_registers: HashMap<String, HashMap<String, String>>,
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     pub additional_stacktraces: HashMap<String, Vec<StackFrame>>,
@@ -61,15 +163,36 @@ pub struct CrashInfo {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     pub files: HashMap<String, Vec<String>>,
+    /// Identifies the same crash across processes and hosts, unlike the uuid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    #[serde(default)]
+    pub kind: CrashKind,
+    /// Sections of the crash report which were lost because the crash handler faulted while
+    /// emitting them, identified by their begin marker.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub lost_sections: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     pub metadata: Option<CrashtrackerMetadata>,
+    /// Minidump of the crashed process, base64 encoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub minidump: Option<String>,
     pub os_info: os_info::Info,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
+    pub panic_info: Option<PanicInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
     pub proc_info: Option<ProcessInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
+    pub runtime_context: Option<RuntimeContext>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
     pub siginfo: Option<SigInfo>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     #[serde(default)]
@@ -88,7 +211,14 @@ pub struct CrashInfo {
 /// Getters and predicates
 impl CrashInfo {
     pub fn crash_seen(&self) -> bool {
-        self.siginfo.is_some()
+        self.siginfo.is_some() || self.panic_info.is_some()
+    }
+
+    /// The frames of the crashing thread, followed by the frames of the other threads.
+    fn all_frames_mut(&mut self) -> impl Iterator<Item = &mut StackFrame> {
+        self.stacktrace
+            .iter_mut()
+            .chain(self.additional_stacktraces.values_mut().flatten())
     }
 }
 
@@ -103,7 +233,7 @@ impl CrashInfo {
     pub fn normalize_ips(&mut self, pid: u32) -> anyhow::Result<()> {
         let normalizer = blazesym::normalize::Normalizer::new();
         let pid = pid.into();
-        self.stacktrace.iter_mut().for_each(|frame| {
+        self.all_frames_mut().for_each(|frame| {
             frame
                 .normalize_ip(&normalizer, pid)
                 .unwrap_or_else(|err| eprintln!("Error resolving name {err}"))
@@ -113,7 +243,7 @@ impl CrashInfo {
 
     pub fn resolve_names(&mut self, src: &blazesym::symbolize::Source) -> anyhow::Result<()> {
         let symbolizer = blazesym::symbolize::Symbolizer::new();
-        for frame in &mut self.stacktrace {
+        for frame in self.all_frames_mut() {
             // Resolving names is best effort, just print the error and continue
             frame
                 .resolve_names(src, &symbolizer)
@@ -122,6 +252,45 @@ impl CrashInfo {
         Ok(())
     }
 
+    /// Resolve the names of the frames which are still unresolved, from the debug files of their
+    /// binaries. Their addresses must have been normalized beforehand.
+    pub(crate) fn resolve_names_from_debug_files(
+        &mut self,
+        finder: &DebugFileFinder,
+    ) -> anyhow::Result<()> {
+        let symbolizer = blazesym::symbolize::Symbolizer::new();
+        let mut debug_files: HashMap<Vec<u8>, Option<std::path::PathBuf>> = HashMap::new();
+        for frame in self.all_frames_mut().filter(|frame| frame.names.is_none()) {
+            let Some(NormalizedAddress {
+                meta:
+                    NormalizedAddressMeta::Elf {
+                        build_id: Some(build_id),
+                        ..
+                    },
+                ..
+            }) = &frame.normalized_ip
+            else {
+                continue;
+            };
+            let debug_file = debug_files.entry(build_id.clone()).or_insert_with(|| {
+                finder.find(build_id).unwrap_or_else(|err| {
+                    eprintln!("Error looking up the debug file of {build_id:02x?}: {err}");
+                    None
+                })
+            });
+            if let Some(debug_file) = debug_file {
+                let src = blazesym::symbolize::Source::Elf(blazesym::symbolize::Elf::new(
+                    debug_file.clone(),
+                ));
+                // Resolving names is best effort, just print the error and continue
+                frame
+                    .resolve_names_from_normalized_ip(&src, &symbolizer)
+                    .unwrap_or_else(|err| eprintln!("Error resolving name {err}"));
+            }
+        }
+        Ok(())
+    }
+
     pub fn resolve_names_from_process(&mut self, pid: u32) -> anyhow::Result<()> {
         let mut process = blazesym::symbolize::Process::new(pid.into());
         // https://github.com/libbpf/blazesym/issues/518
@@ -137,13 +306,20 @@ impl CrashInfo {
         let os_info = os_info::get();
         let uuid = Uuid::new_v4();
         Self {
+            additional_registers: HashMap::new(),
             additional_stacktraces: HashMap::new(),
             counters: HashMap::new(),
             files: HashMap::new(),
+            fingerprint: None,
             incomplete: false,
+            kind: CrashKind::default(),
+            lost_sections: vec![],
             metadata: None,
+            minidump: None,
             os_info,
+            panic_info: None,
             proc_info: None,
+            runtime_context: None,
             siginfo: None,
             stacktrace: vec![],
             tags: HashMap::new(),
@@ -187,6 +363,16 @@ impl CrashInfo {
         Ok(())
     }
 
+    pub fn add_lost_section(&mut self, section: &str) -> anyhow::Result<()> {
+        self.lost_sections.push(section.to_string());
+        Ok(())
+    }
+
+    pub fn set_fingerprint(&mut self, fingerprint: Option<String>) -> anyhow::Result<()> {
+        self.fingerprint = fingerprint;
+        Ok(())
+    }
+
     pub fn set_incomplete(&mut self, incomplete: bool) -> anyhow::Result<()> {
         self.incomplete = incomplete;
         Ok(())
@@ -198,12 +384,41 @@ impl CrashInfo {
         Ok(())
     }
 
+    pub fn set_minidump(&mut self, minidump: &[u8]) -> anyhow::Result<()> {
+        anyhow::ensure!(self.minidump.is_none());
+        self.minidump = Some(base64::engine::general_purpose::STANDARD.encode(minidump));
+        Ok(())
+    }
+
+    pub fn set_panic_info(&mut self, panic_info: PanicInfo) -> anyhow::Result<()> {
+        anyhow::ensure!(self.panic_info.is_none() && self.siginfo.is_none());
+        self.kind = CrashKind::Panic;
+        self.panic_info = Some(panic_info);
+        Ok(())
+    }
+
     pub fn set_procinfo(&mut self, proc_info: ProcessInfo) -> anyhow::Result<()> {
         anyhow::ensure!(self.proc_info.is_none());
         self.proc_info = Some(proc_info);
         Ok(())
     }
 
+    pub fn set_registers(
+        &mut self,
+        thread_id: String,
+        registers: HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.additional_registers.contains_key(&thread_id));
+        self.additional_registers.insert(thread_id, registers);
+        Ok(())
+    }
+
+    pub fn set_runtime_context(&mut self, runtime_context: RuntimeContext) -> anyhow::Result<()> {
+        anyhow::ensure!(self.runtime_context.is_none());
+        self.runtime_context = Some(runtime_context);
+        Ok(())
+    }
+
     pub fn set_siginfo(&mut self, siginfo: SigInfo) -> anyhow::Result<()> {
         anyhow::ensure!(self.siginfo.is_none());
         self.siginfo = Some(siginfo);
@@ -252,13 +467,18 @@ impl CrashInfo {
         // If we're debugging to a file, dump the actual crashinfo into a json
         if let Some(endpoint) = &config.endpoint {
             if Some("file") == endpoint.url.scheme_str() {
-                self.to_file(
-                    endpoint
-                        .url
-                        .path_and_query()
-                        .ok_or_else(|| anyhow::format_err!("empty path for upload to file"))?
-                        .as_str(),
-                )?;
+                let path = endpoint
+                    .url
+                    .path_and_query()
+                    .ok_or_else(|| anyhow::format_err!("empty path for upload to file"))?
+                    .as_str();
+                self.to_file(path)?;
+                if let Some(minidump) = &self.minidump {
+                    let path = format!("{path}.dmp");
+                    let minidump = base64::engine::general_purpose::STANDARD.decode(minidump)?;
+                    std::fs::write(&path, minidump)
+                        .with_context(|| format!("Unable to write {path}"))?;
+                }
             }
         }
         self.upload_to_telemetry(config)
@@ -0,0 +1,308 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(unix)]
+
+//! Lookup of the separate debug files of stripped binaries, by build id.
+//!
+//! Debug files are first searched in the debug directories of the configuration, with the
+//! `.build-id/xx/yyyy.debug` layout used by distributions, then downloaded from the debuginfod
+//! server of the configuration, if any. Downloaded files are cached on disk with the layout of the
+//! debuginfod client cache, `<cache dir>/<build id>/debuginfo`, so a binary's debug file is only
+//! downloaded once. The cache defaults to a directory of the user's cache dir, `$XDG_CACHE_HOME` or
+//! `~/.cache`, which other users can't write to.
+
+use crate::configuration::user_cache_dir;
+use crate::CrashtrackerConfiguration;
+use anyhow::Context;
+use std::fmt::Write;
+use std::fs;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub struct DebugFileFinder {
+    debug_dirs: Vec<PathBuf>,
+    debuginfod: Option<Debuginfod>,
+}
+
+struct Debuginfod {
+    url: String,
+    cache_dir: PathBuf,
+    timeout: Duration,
+    rt: tokio::runtime::Runtime,
+}
+
+impl DebugFileFinder {
+    pub fn new(config: &CrashtrackerConfiguration) -> anyhow::Result<Self> {
+        let debuginfod = match &config.debuginfod_url {
+            Some(url) => Some(Debuginfod {
+                url: url.trim_end_matches('/').to_string(),
+                cache_dir: match &config.debuginfod_cache_dir {
+                    Some(cache_dir) => PathBuf::from(cache_dir),
+                    None => user_cache_dir()
+                        .context("The debuginfod cache dir must be configured")?
+                        .join("dd-crashtracker-debuginfod"),
+                },
+                timeout: config.timeout,
+                rt: tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?,
+            }),
+            None => None,
+        };
+        Ok(Self {
+            debug_dirs: config.debug_dirs.iter().map(PathBuf::from).collect(),
+            debuginfod,
+        })
+    }
+
+    /// Find the debug file of the binary with the given build id, returns `None` if there is none.
+    pub fn find(&self, build_id: &[u8]) -> anyhow::Result<Option<PathBuf>> {
+        anyhow::ensure!(build_id.len() > 1, "Invalid build id {build_id:?}");
+        let build_id = build_id.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        });
+
+        let (prefix, suffix) = build_id.split_at(2);
+        for debug_dir in &self.debug_dirs {
+            let path = debug_dir
+                .join(".build-id")
+                .join(prefix)
+                .join(format!("{suffix}.debug"));
+            if path.is_file() {
+                return Ok(Some(path));
+            }
+        }
+
+        match &self.debuginfod {
+            Some(debuginfod) => debuginfod.fetch(&build_id),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Debuginfod {
+    fn fetch(&self, build_id: &str) -> anyhow::Result<Option<PathBuf>> {
+        let dir = self.cache_dir.join(build_id);
+        let path = dir.join("debuginfo");
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+
+        let url = format!("{}/buildid/{build_id}/debuginfo", self.url);
+        let Some(debuginfo) = self.download(&url)? else {
+            return Ok(None);
+        };
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("Unable to create {dir:?}"))?;
+        // Receivers may share the cache, only make complete files visible
+        let tmp_path = dir.join(format!("debuginfo.{}.tmp", std::process::id()));
+        fs::write(&tmp_path, debuginfo)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(Some(path))
+    }
+
+    /// Download `url`, returns `None` if the server doesn't have it.
+    fn download(&self, url: &str) -> anyhow::Result<Option<hyper::body::Bytes>> {
+        let uri = ddcommon::parse_uri(url)?;
+        let client: hyper::Client<_, hyper::Body> =
+            hyper::Client::builder().build(ddcommon::connector::Connector::default());
+        self.rt.block_on(async {
+            let response = tokio::time::timeout(self.timeout, client.get(uri))
+                .await
+                .with_context(|| format!("Timed out requesting {url}"))??;
+            if response.status() == hyper::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            anyhow::ensure!(
+                response.status().is_success(),
+                "Unable to download {url}: {}",
+                response.status()
+            );
+            let body = tokio::time::timeout(self.timeout, hyper::body::to_bytes(response))
+                .await
+                .with_context(|| format!("Timed out downloading {url}"))??;
+            Ok(Some(body))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CrashInfo, NormalizedAddressMeta, StackFrame, StacktraceCollection};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::process::Command;
+
+    const BUILD_ID: [u8; 4] = [0xab, 0xcd, 0xef, 0x01];
+
+    fn test_config(
+        debug_dirs: Vec<String>,
+        debuginfod_cache_dir: &Path,
+        debuginfod_url: Option<String>,
+    ) -> CrashtrackerConfiguration {
+        CrashtrackerConfiguration {
+            debug_dirs,
+            debuginfod_cache_dir: Some(debuginfod_cache_dir.to_str().unwrap().to_string()),
+            debuginfod_url,
+            resolve_frames: StacktraceCollection::EnabledWithSymbolsInReceiver,
+            timeout: Duration::from_secs(10),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    /// Serve a single HTTP request with the given status and body, returns the server's url and
+    /// a handle returning the request.
+    fn serve_once(
+        status: &'static str,
+        body: &'static [u8],
+    ) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_find_in_debug_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let debug_dir = tmp.path().join("debug");
+        let path = debug_dir.join(".build-id/ab/cdef01.debug");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "debuginfo").unwrap();
+
+        let config = test_config(
+            vec![
+                tmp.path().join("missing").to_str().unwrap().to_string(),
+                debug_dir.to_str().unwrap().to_string(),
+            ],
+            &tmp.path().join("cache"),
+            None,
+        );
+        let finder = DebugFileFinder::new(&config).unwrap();
+        assert_eq!(finder.find(&BUILD_ID).unwrap(), Some(path));
+        assert_eq!(finder.find(&[0x12, 0x34]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_find_with_debuginfod() {
+        let tmp = tempfile::tempdir().unwrap();
+        let cache_dir = tmp.path().join("cache");
+        let (url, server) = serve_once("200 OK", b"debuginfo");
+        let config = test_config(vec![], &cache_dir, Some(format!("{url}/")));
+        let finder = DebugFileFinder::new(&config).unwrap();
+
+        let path = finder.find(&BUILD_ID).unwrap().unwrap();
+        assert_eq!(path, cache_dir.join("abcdef01/debuginfo"));
+        assert_eq!(fs::read(&path).unwrap(), b"debuginfo");
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /buildid/abcdef01/debuginfo HTTP/1.1\r\n"));
+
+        // The server is gone, the cached file is used
+        assert_eq!(finder.find(&BUILD_ID).unwrap(), Some(path));
+    }
+
+    #[test]
+    fn test_find_missing_from_debuginfod() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (url, server) = serve_once("404 Not Found", b"");
+        let config = test_config(vec![], &tmp.path().join("cache"), Some(url));
+        let finder = DebugFileFinder::new(&config).unwrap();
+
+        assert_eq!(finder.find(&BUILD_ID).unwrap(), None);
+        server.join().unwrap();
+    }
+
+    #[inline(never)]
+    fn symbolized_from_debug_file() {}
+
+    fn run(command: &str, args: &[&Path]) {
+        let status = Command::new(command).args(args).status().unwrap();
+        assert!(status.success(), "{command} {args:?} failed: {status}");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_resolve_names_from_debug_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut crash_info = CrashInfo::new();
+        crash_info.stacktrace.push(StackFrame {
+            ip: Some(format!(
+                "{:#x}",
+                symbolized_from_debug_file as *const () as usize
+            )),
+            module_base_address: None,
+            names: None,
+            normalized_ip: None,
+            sp: None,
+            symbol_address: None,
+        });
+        crash_info.normalize_ips(std::process::id()).unwrap();
+
+        // Point the frame at a stripped copy of the test binary, and put the debug file of the
+        // binary in a debug dir
+        let Some(NormalizedAddressMeta::Elf {
+            path,
+            build_id: Some(build_id),
+        }) = crash_info.stacktrace[0]
+            .normalized_ip
+            .as_mut()
+            .map(|normalized_ip| &mut normalized_ip.meta)
+        else {
+            panic!("Unexpected frame {:?}", crash_info.stacktrace[0]);
+        };
+        let exe = std::env::current_exe().unwrap();
+        let stripped = tmp.path().join("stripped");
+        run("strip", &[Path::new("-o"), &stripped, &exe]);
+        *path = stripped;
+        let build_id: String = build_id.iter().map(|byte| format!("{byte:02x}")).collect();
+        let debug_file = tmp
+            .path()
+            .join(".build-id")
+            .join(&build_id[..2])
+            .join(format!("{}.debug", &build_id[2..]));
+        fs::create_dir_all(debug_file.parent().unwrap()).unwrap();
+        run(
+            "objcopy",
+            &[Path::new("--only-keep-debug"), &exe, &debug_file],
+        );
+
+        let config = test_config(
+            vec![tmp.path().to_str().unwrap().to_string()],
+            &tmp.path().join("cache"),
+            None,
+        );
+        let finder = DebugFileFinder::new(&config).unwrap();
+        crash_info.resolve_names_from_debug_files(&finder).unwrap();
+        let names = crash_info.stacktrace[0].names.as_ref().unwrap();
+        assert!(names[0]
+            .name
+            .as_ref()
+            .unwrap()
+            .contains("symbolized_from_debug_file"));
+    }
+}
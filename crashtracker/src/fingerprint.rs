@@ -0,0 +1,312 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(unix)]
+
+//! Identification of the same crash across processes and hosts.
+//!
+//! The fingerprint of a crash is a hash of what caused it, the signal or the location of the
+//! panic, and of the innermost frames of the crashing thread. Frames are identified by the build
+//! id of their binary and their offset in it, or by their symbol name, so the fingerprint doesn't
+//! depend on where the binaries were loaded. Without either, frames are identified by the path of
+//! their binary and their offset in it, and as a last resort by their address.
+//!
+//! The receiver uses the fingerprint to limit how many reports of the same crash are uploaded per
+//! hour, so that a crash-looping process doesn't flood the backend with identical reports.
+
+use crate::stacktrace::{NormalizedAddress, NormalizedAddressMeta};
+use crate::{CrashInfo, CrashKind};
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Number of innermost frames of the crashing thread which are part of the fingerprint.
+const FINGERPRINT_FRAMES: usize = 8;
+
+/// The window of the rate limit on the reports with the same fingerprint.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// 64 bits FNV-1a, which unlike the hashers of the standard library is guaranteed to be stable
+/// across versions and platforms.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// The fingerprint of the crash, as 16 hex digits. If the stacktrace wasn't collected, it only
+/// identifies the signal or the location of the panic, and is shared by unrelated crashes.
+pub fn fingerprint(crash_info: &CrashInfo) -> String {
+    let mut hasher = Fnv1a::new();
+    match crash_info.kind {
+        CrashKind::UnixSignal => {
+            hasher.write(b"signal");
+            let signum = crash_info
+                .siginfo
+                .as_ref()
+                .map_or(0, |siginfo| siginfo.signum);
+            hasher.write(&signum.to_le_bytes());
+        }
+        CrashKind::Panic => {
+            hasher.write(b"panic");
+            let location = crash_info
+                .panic_info
+                .as_ref()
+                .and_then(|panic_info| panic_info.location.as_deref());
+            hasher.write(location.unwrap_or_default().as_bytes());
+        }
+    }
+
+    for frame in crash_info.stacktrace.iter().take(FINGERPRINT_FRAMES) {
+        let name = frame
+            .names
+            .as_ref()
+            .and_then(|names| names.first())
+            .and_then(|names| names.name.as_deref());
+        match (&frame.normalized_ip, name) {
+            (
+                Some(NormalizedAddress {
+                    file_offset,
+                    meta:
+                        NormalizedAddressMeta::Elf {
+                            build_id: Some(build_id),
+                            ..
+                        },
+                }),
+                _,
+            ) => {
+                hasher.write(b"\0elf");
+                hasher.write(build_id);
+                hasher.write(&file_offset.to_le_bytes());
+            }
+            (_, Some(name)) => {
+                hasher.write(b"\0name");
+                hasher.write(name.as_bytes());
+            }
+            (
+                Some(NormalizedAddress {
+                    file_offset,
+                    meta: NormalizedAddressMeta::Apk(path) | NormalizedAddressMeta::Elf { path, .. },
+                }),
+                _,
+            ) => {
+                hasher.write(b"\0path");
+                hasher.write(path.as_os_str().as_bytes());
+                hasher.write(&file_offset.to_le_bytes());
+            }
+            // Addresses change with the load address of the binaries, but still identify the
+            // crashes of the same process
+            _ => {
+                hasher.write(b"\0ip");
+                hasher.write(frame.ip.as_deref().unwrap_or_default().as_bytes());
+            }
+        }
+    }
+    format!("{:016x}", hasher.0)
+}
+
+/// Limits the reports with the same fingerprint to `max_reports` per hour, across the receivers
+/// sharing `dir`. The times of the reports of each fingerprint are kept in `<dir>/<fingerprint>`.
+pub struct RateLimiter {
+    dir: PathBuf,
+    max_reports: u32,
+}
+
+impl RateLimiter {
+    pub fn new(dir: PathBuf, max_reports: u32) -> Self {
+        Self { dir, max_reports }
+    }
+
+    /// Record a report of the crash with the given fingerprint, returns false, without recording
+    /// it, if `max_reports` were already recorded in the last hour.
+    pub fn try_acquire(&self, fingerprint: &str, now: SystemTime) -> anyhow::Result<bool> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(self.dir.join(fingerprint))?;
+        lock(&file)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let now = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+        let mut reports: Vec<u64> = contents
+            .lines()
+            .filter_map(|line| line.parse().ok())
+            .filter(|time| now.saturating_sub(*time) < RATE_LIMIT_WINDOW.as_secs())
+            .collect();
+        if reports.len() >= self.max_reports as usize {
+            return Ok(false);
+        }
+
+        reports.push(now);
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        for time in reports {
+            writeln!(file, "{time}")?;
+        }
+        Ok(true)
+    }
+}
+
+/// Take an exclusive lock on `file`, released when it is closed.
+fn lock(file: &File) -> anyhow::Result<()> {
+    // SAFETY: the file descriptor is valid for the lifetime of `file`
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) };
+    anyhow::ensure!(res == 0, std::io::Error::last_os_error());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PanicInfo, SigInfo, StackFrame, StackFrameNames};
+
+    fn frame(ip: &str, build_id: Option<Vec<u8>>, name: Option<&str>) -> StackFrame {
+        StackFrame {
+            ip: Some(ip.to_string()),
+            module_base_address: None,
+            names: name.map(|name| {
+                vec![StackFrameNames {
+                    colno: None,
+                    filename: None,
+                    lineno: None,
+                    name: Some(name.to_string()),
+                }]
+            }),
+            normalized_ip: build_id.map(|build_id| NormalizedAddress {
+                file_offset: 0x1234,
+                meta: NormalizedAddressMeta::Elf {
+                    path: PathBuf::from("/usr/lib/libfoo.so"),
+                    build_id: Some(build_id),
+                },
+            }),
+            sp: None,
+            symbol_address: None,
+        }
+    }
+
+    fn segfault(stacktrace: Vec<StackFrame>) -> CrashInfo {
+        let mut crash_info = CrashInfo::new();
+        crash_info
+            .set_siginfo(SigInfo {
+                signum: 11,
+                ..Default::default()
+            })
+            .unwrap();
+        crash_info.set_stacktrace(None, stacktrace).unwrap();
+        crash_info
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let crash = segfault(vec![
+            frame("0x7f0001234", Some(vec![1, 2, 3]), None),
+            frame("0x7f0005678", None, Some("main")),
+        ]);
+        let fingerprint = super::fingerprint(&crash);
+        assert_eq!(fingerprint.len(), 16);
+
+        // The same crash with the binaries loaded elsewhere
+        let same_crash = segfault(vec![
+            frame("0x7e0001234", Some(vec![1, 2, 3]), None),
+            frame("0x7e0005678", None, Some("main")),
+        ]);
+        assert_eq!(super::fingerprint(&same_crash), fingerprint);
+
+        let other_binary = segfault(vec![
+            frame("0x7f0001234", Some(vec![1, 2, 4]), None),
+            frame("0x7f0005678", None, Some("main")),
+        ]);
+        assert_ne!(super::fingerprint(&other_binary), fingerprint);
+
+        let mut panic = CrashInfo::new();
+        panic
+            .set_panic_info(PanicInfo {
+                message: None,
+                location: Some("src/lib.rs:1:2".to_string()),
+            })
+            .unwrap();
+        panic
+            .set_stacktrace(None, crash.stacktrace.clone())
+            .unwrap();
+        assert_ne!(super::fingerprint(&panic), fingerprint);
+    }
+
+    #[test]
+    fn test_fingerprint_fallbacks() {
+        // Binaries without a build id are identified by their path
+        let without_build_id = |ip: &str, path: &str| {
+            let mut frame = frame(ip, None, None);
+            frame.normalized_ip = Some(NormalizedAddress {
+                file_offset: 0x1234,
+                meta: NormalizedAddressMeta::Elf {
+                    path: PathBuf::from(path),
+                    build_id: None,
+                },
+            });
+            segfault(vec![frame])
+        };
+        assert_eq!(
+            super::fingerprint(&without_build_id("0x7f0001234", "/usr/bin/foo")),
+            super::fingerprint(&without_build_id("0x7e0001234", "/usr/bin/foo"))
+        );
+        assert_ne!(
+            super::fingerprint(&without_build_id("0x7f0001234", "/usr/bin/foo")),
+            super::fingerprint(&without_build_id("0x7f0001234", "/usr/bin/bar"))
+        );
+
+        // Then by their address
+        let unnormalized = |ip| segfault(vec![frame(ip, None, None)]);
+        assert_eq!(
+            super::fingerprint(&unnormalized("0x7f0001234")),
+            super::fingerprint(&unnormalized("0x7f0001234"))
+        );
+        assert_ne!(
+            super::fingerprint(&unnormalized("0x7f0001234")),
+            super::fingerprint(&unnormalized("0x7f0005678"))
+        );
+
+        // Without a stacktrace, only the signal is left
+        assert_eq!(
+            super::fingerprint(&segfault(vec![])),
+            super::fingerprint(&segfault(vec![]))
+        );
+        assert_ne!(
+            super::fingerprint(&segfault(vec![])),
+            super::fingerprint(&unnormalized("0x7f0001234"))
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let tmp = tempfile::tempdir().unwrap();
+        let limiter = RateLimiter::new(tmp.path().join("rate_limit"), 2);
+        let now = SystemTime::now();
+
+        assert!(limiter.try_acquire("abcd", now).unwrap());
+        assert!(limiter.try_acquire("abcd", now).unwrap());
+        assert!(!limiter.try_acquire("abcd", now).unwrap());
+        // Fingerprints are limited independently
+        assert!(limiter.try_acquire("ef01", now).unwrap());
+        // The reports older than an hour don't count anymore
+        let later = now + RATE_LIMIT_WINDOW;
+        assert!(limiter.try_acquire("abcd", later).unwrap());
+    }
+}
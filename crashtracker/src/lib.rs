@@ -5,21 +5,24 @@
 //! uploading the result to the backend.
 //!
 //! Architecturally, it consists of two parts:
-//! 1. A signal handler, which catches a UNIX signal (SIGSEGV, SIGBUS, SIGABRT)
-//!    associated with a crash, and and collects information about the state of
+//! 1. A signal handler, which catches a UNIX signal (SIGSEGV, SIGBUS, SIGABRT, SIGILL, SIGFPE,
+//!    SIGSYS by default) associated with a crash, and and collects information about the state of
 //!    the program at crash time.  The signal handler runs under a constrained
 //!    environment where many standard operations are illegal.
 //!    https://man7.org/linux/man-pages/man7/signal-safety.7.html
 //!    In particular, memory allocation, and synchronization such as mutexes are
-//!    potentially UB.  The signal handler therefore does as little as possible
+//!    potentially UB.  The signal handler formats the report by hand into a
+//!    preallocated buffer, without allocating.  It does as little as possible
 //!    in process, and instead writes data across a pipe to a separate receiver
 //!    process.
+//!    If the signal handler faults while collecting a section of the report, it
+//!    reports the lost section and completes the rest of the report.
 //!    The signal handler then restores the previous signal handler, and waits
-//!    for the receiver process to exit.  Keeping the crashing process alive
-//!    until the receiver has completed increases the chances that the container
-//!    will survive long enough to upload the report; otherwise, there is a
-//!    chance that the container will be killed when the crashing process dies
-//!    and no telemetry will get out.
+//!    for the receiver process to exit, at most for the configured timeout.
+//!    Keeping the crashing process alive until the receiver has completed
+//!    increases the chances that the container will survive long enough to
+//!    upload the report; otherwise, there is a chance that the container will
+//!    be killed when the crashing process dies and no telemetry will get out.
 //!    Once the receiver has completed, the crash-handler returns, allowing the
 //!    previous crash handler (if any) to execute, maintaining the customer
 //!    experience as much as possible.
@@ -28,20 +31,43 @@
 //!    the pipe, adds additional data about the system state (e.g. /proc/cpuinfo and /proc/meminfo),
 //!    formats it into a crash report, uploads it to the backend, and then exits. The receiver also
 //!    exits if the pipe is closed without a crash report, to avoid leaving a zombie process if the
-//!    parent exits normally.
+//!    parent exits normally. If a spool directory is configured, the report is written there before
+//!    being uploaded, and reports which couldn't be uploaded are retried when the crashtracker is
+//!    next initialized.
+//!    Alternatively, the receiver can listen on a unix socket, and receive the reports of any
+//!    number of processes, e.g. all the workers of a pre-fork pool. The sidecar hosts such a
+//!    receiver for the processes connected to it.
+//!
+//! Optionally, Rust panics are reported too, by a panic hook which sends the panic message,
+//! location and stacktrace to a new receiver through the same protocol, then calls the previous
+//! panic hook. Such reports have the `Panic` kind instead of `UnixSignal`.
 //!
 //! Data collected:
 //! 1. The data collected by the crash-handler includes:
 //!    1. The signal type leading to the crash
-//!    2. The stacktrace at time of crash (for the crashing thread). Depending on a flag, this can
-//!       either be resolved, or raw addresses. Resolving addresses provide more data, but sometimes
-//!       crashes the crash handler (ironic).
+//!    2. The stacktrace at time of crash (for the crashing thread), walked through the frame
+//!       pointers from the registers of the crashing instruction. The signal handler only emits raw
+//!       addresses; depending on a flag, the receiver resolves them into symbols.
 //!    3. System level info (e.g. /proc/self/maps).
 //!    4. The result of counters describing the current state of the profiler.
 //! 2. Data augmented by the receiver includes:
 //!    1. Metadata provided by the caller (e.g. library & profiler versions).
 //!    2. System info: OS version, /proc/cpuinfo /proc/meminfo, etc.
 //!    3. A timestamp and GUID for tracking the crash report.
+//!    4. On Linux, the stacktraces of the other threads of the crashed process, captured with
+//!       ptrace while the crash handler waits for the receiver.
+//!    5. The names of the frames, resolved from the crashed process, or for stripped binaries
+//!       from their debug files, found by build id in local debug directories or downloaded
+//!       from a debuginfod server.
+//!    6. A fingerprint identifying the same crash across processes and hosts, from the signal and
+//!       the innermost frames. The receiver can be configured to upload at most a given number of
+//!       reports with the same fingerprint per hour.
+//!    7. Optionally on Linux, a minidump of the crashed process, with the registers and stacks of
+//!       its threads and the build ids of its modules, for debuggers and `minidump_stackwalk`. It
+//!       is attached to the report, and also written next to it for file endpoints.
+//!    8. On Linux, the runtime context of the crashed process: the crashing thread's name, uptime,
+//!       memory usage, open file descriptors, resource limits, command line and the environment
+//!       variables allow-listed by the configuration.
 //!
 //! Handling of forks
 //! Safety issues
@@ -53,14 +79,22 @@ mod constants;
 mod counters;
 mod crash_handler;
 mod crash_info;
+mod debug_files;
+mod fingerprint;
+mod minidump;
 mod receiver;
+mod runtime_context;
+mod signal_safe;
+mod spool;
 mod stacktrace;
 mod telemetry;
+mod thread_stacks;
 
 #[cfg(unix)]
 pub use api::*;
 pub use configuration::{
     CrashtrackerConfiguration, CrashtrackerReceiverConfig, StacktraceCollection,
+    DEFAULT_DEBUG_DIRS, DEFAULT_SIGNALS,
 };
 pub use constants::*;
 pub use counters::{begin_profiling_op, end_profiling_op, reset_counters, ProfilingOpTypes};
@@ -68,5 +102,10 @@ pub use counters::{begin_profiling_op, end_profiling_op, reset_counters, Profili
 pub use crash_handler::{update_config, update_metadata};
 pub use crash_info::*;
 #[cfg(unix)]
-pub use receiver::{receiver_entry_point_stdin, reciever_entry_point_unix_socket};
+pub use receiver::{
+    async_receiver_entry_point_unix_listener, get_unix_socket, receiver_entry_point_stdin,
+    reciever_entry_point_unix_socket,
+};
+#[cfg(unix)]
+pub use spool::upload_spooled_reports;
 pub use stacktrace::{NormalizedAddress, NormalizedAddressMeta, StackFrame, StackFrameNames};
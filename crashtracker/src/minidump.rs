@@ -0,0 +1,715 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(target_os = "linux")]
+
+//! Minidumps of crashed processes, in the Breakpad flavor of the format, which tools such as
+//! `minidump_stackwalk` or lldb can load.
+//!
+//! The minidump is built by the receiver, from the crash report and from the crashed process
+//! itself, which is kept alive by its crash handler:
+//! - the threads, with the registers reported by the crash handler for the crashing thread, and
+//!   the instruction, stack and frame pointers captured by the receiver for the other threads.
+//!   The memory of their
+//!   stacks is read from `/proc/<pid>/mem`, so that debuggers can unwind them.
+//! - the modules, from the process maps of the report, with the build ids read from their files.
+//! - the signal, as the exception of the crashing thread.
+//! - the process maps, and a description of the system.
+
+use crate::{CrashInfo, CrashKind};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+const MINIDUMP_SIGNATURE: u32 = 0x504d444d; // "MDMP"
+const MINIDUMP_VERSION: u32 = 0xa793;
+const HEADER_SIZE: usize = 32;
+const DIRECTORY_ENTRY_SIZE: usize = 12;
+
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const EXCEPTION_STREAM: u32 = 6;
+const SYSTEM_INFO_STREAM: u32 = 7;
+const LINUX_MAPS_STREAM: u32 = 0x47670009;
+
+const PLATFORM_ID_LINUX: u32 = 0x8201;
+/// Signature of the Breakpad CodeView record holding the build id of an ELF module.
+const CV_SIGNATURE_ELF: u32 = 0x4270454c; // "BpEL"
+
+/// Upper bound on the stack memory saved per thread, starting from its stack pointer.
+const MAX_STACK_SIZE: u64 = 32 << 10;
+/// Memory below the stack pointer which leaf functions may use, on x86_64.
+const RED_ZONE_SIZE: u64 = 128;
+
+#[cfg(target_arch = "x86_64")]
+mod context {
+    use std::collections::HashMap;
+
+    pub const PROCESSOR_ARCHITECTURE: u16 = 9; // AMD64
+    pub const IP_REGISTER: &str = "rip";
+    pub const SP_REGISTER: &str = "rsp";
+    const CONTEXT_SIZE: usize = 0x4d0;
+    const CONTEXT_FLAGS: u32 = 0x00100000 | 0x1 | 0x2; // AMD64 | CONTROL | INTEGER
+    const REGISTER_OFFSETS: [(&str, usize); 17] = [
+        ("rax", 0x78),
+        ("rcx", 0x80),
+        ("rdx", 0x88),
+        ("rbx", 0x90),
+        ("rsp", 0x98),
+        ("rbp", 0xa0),
+        ("rsi", 0xa8),
+        ("rdi", 0xb0),
+        ("r8", 0xb8),
+        ("r9", 0xc0),
+        ("r10", 0xc8),
+        ("r11", 0xd0),
+        ("r12", 0xd8),
+        ("r13", 0xe0),
+        ("r14", 0xe8),
+        ("r15", 0xf0),
+        ("rip", 0xf8),
+    ];
+
+    /// A `CONTEXT_AMD64` with the given registers, the missing ones are zero.
+    pub fn context(registers: &HashMap<&str, u64>) -> Vec<u8> {
+        let mut context = vec![0; CONTEXT_SIZE];
+        context[0x30..0x34].copy_from_slice(&CONTEXT_FLAGS.to_le_bytes());
+        if let Some(eflags) = registers.get("eflags") {
+            context[0x44..0x48].copy_from_slice(&(*eflags as u32).to_le_bytes());
+        }
+        for (name, offset) in REGISTER_OFFSETS {
+            if let Some(value) = registers.get(name) {
+                context[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        context
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod context {
+    use std::collections::HashMap;
+
+    pub const PROCESSOR_ARCHITECTURE: u16 = 12; // ARM64
+    pub const IP_REGISTER: &str = "pc";
+    pub const SP_REGISTER: &str = "sp";
+    const CONTEXT_SIZE: usize = 0x390;
+    const CONTEXT_FLAGS: u32 = 0x00400000 | 0x1 | 0x2; // ARM64 | CONTROL | INTEGER
+
+    /// A `CONTEXT_ARM64` with the given registers, the missing ones are zero.
+    pub fn context(registers: &HashMap<&str, u64>) -> Vec<u8> {
+        let mut context = vec![0; CONTEXT_SIZE];
+        context[0..4].copy_from_slice(&CONTEXT_FLAGS.to_le_bytes());
+        if let Some(pstate) = registers.get("pstate") {
+            context[4..8].copy_from_slice(&(*pstate as u32).to_le_bytes());
+        }
+        // x0 to x30, followed by sp and pc
+        for i in 0..33 {
+            let value = match i {
+                31 => registers.get("sp"),
+                32 => registers.get("pc"),
+                _ => registers.get(format!("x{i}").as_str()),
+            };
+            if let Some(value) = value {
+                let offset = 8 + i * 8;
+                context[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        context
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod context {
+    use std::collections::HashMap;
+
+    pub const PROCESSOR_ARCHITECTURE: u16 = 0xffff; // Unknown
+    pub const IP_REGISTER: &str = "pc";
+    pub const SP_REGISTER: &str = "sp";
+
+    pub fn context(_registers: &HashMap<&str, u64>) -> Vec<u8> {
+        vec![]
+    }
+}
+
+/// The `MINIDUMP_LOCATION_DESCRIPTOR` of some data of the minidump.
+#[derive(Clone, Copy, Default)]
+struct Location {
+    data_size: u32,
+    rva: u32,
+}
+
+/// Serializes the little-endian structures of the minidump.
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn location(&mut self, location: Location) {
+        self.u32(location.data_size);
+        self.u32(location.rva);
+    }
+
+    fn rva(&self) -> u32 {
+        self.buf.len() as u32
+    }
+
+    /// Append `bytes`, aligned on 8 bytes, returns where they are.
+    fn append(&mut self, bytes: &[u8]) -> Location {
+        self.buf.resize(align_up(self.buf.len(), 8), 0);
+        let rva = self.rva();
+        self.buf.extend_from_slice(bytes);
+        Location {
+            data_size: bytes.len() as u32,
+            rva,
+        }
+    }
+
+    /// Append a `MINIDUMP_STRING`, returns its rva.
+    fn string(&mut self, s: &str) -> u32 {
+        let utf16: Vec<u16> = s.encode_utf16().chain([0]).collect();
+        let mut bytes = ((utf16.len() as u32 - 1) * 2).to_le_bytes().to_vec();
+        bytes.extend(utf16.iter().flat_map(|c| c.to_le_bytes()));
+        self.append(&bytes).rva
+    }
+}
+
+struct Thread {
+    tid: u32,
+    context: Location,
+    stack_start: u64,
+    stack: Location,
+}
+
+struct Module {
+    base: u64,
+    size: u64,
+    path: String,
+}
+
+/// Build the minidump of the crash. `crash_info.proc_info` must be set, and the crashed process
+/// still alive for the stacks to be saved.
+pub fn build_minidump(crash_info: &CrashInfo) -> anyhow::Result<Vec<u8>> {
+    let proc_info = crash_info
+        .proc_info
+        .as_ref()
+        .context("Unable to build the minidump: No PID specified")?;
+    let pid = proc_info.pid;
+    let crashing_tid = proc_info.tid.unwrap_or(pid);
+    let maps: Vec<String> = match crash_info.files.get("/proc/self/maps") {
+        Some(maps) => maps.clone(),
+        None => std::fs::read_to_string(format!("/proc/{pid}/maps"))
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect(),
+    };
+    // The memory of the crashed process can't be read if it exited already, the minidump is still
+    // useful without the stacks.
+    let mem = File::open(format!("/proc/{pid}/mem")).ok();
+
+    let is_signal = crash_info.kind == CrashKind::UnixSignal && crash_info.siginfo.is_some();
+    let mut streams = vec![SYSTEM_INFO_STREAM, THREAD_LIST_STREAM, MODULE_LIST_STREAM];
+    if is_signal {
+        streams.push(EXCEPTION_STREAM);
+    }
+    streams.push(LINUX_MAPS_STREAM);
+
+    let mut w = Writer {
+        buf: vec![0; HEADER_SIZE + streams.len() * DIRECTORY_ENTRY_SIZE],
+    };
+    let mut directory = vec![];
+
+    // The registers of the crashing thread come from the crash handler, only the frame registers
+    // captured by the receiver are known for the others.
+    let crashing_registers: HashMap<&str, u64> = match &crash_info.siginfo {
+        Some(siginfo) if !siginfo.registers.is_empty() => parse_registers(&siginfo.registers),
+        _ => innermost_frame_registers(&crash_info.stacktrace),
+    };
+    let mut threads = vec![];
+    let crashing_context = w.append(&context::context(&crashing_registers));
+    threads.push(write_thread(
+        &mut w,
+        mem.as_ref(),
+        &maps,
+        crashing_tid,
+        crashing_context,
+        &crashing_registers,
+    ));
+    let mut other_threads: Vec<_> = crash_info.additional_stacktraces.iter().collect();
+    other_threads.sort_by_key(|(thread, _)| *thread);
+    for (thread, stacktrace) in other_threads {
+        // Threads are keyed by "<tid>:<name>"
+        let Some(tid) = thread
+            .split(':')
+            .next()
+            .and_then(|tid| tid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let registers = match crash_info.additional_registers.get(thread) {
+            Some(registers) => parse_registers(registers),
+            None => innermost_frame_registers(stacktrace),
+        };
+        let context = w.append(&context::context(&registers));
+        threads.push(write_thread(
+            &mut w,
+            mem.as_ref(),
+            &maps,
+            tid,
+            context,
+            &registers,
+        ));
+    }
+
+    // System info
+    let csd_version = w.string(&os_description());
+    let (major, minor, build) = kernel_version();
+    let rva = w.append(&[]).rva;
+    w.u16(context::PROCESSOR_ARCHITECTURE);
+    w.u16(0); // processor level
+    w.u16(0); // processor revision
+    w.u8(std::thread::available_parallelism().map_or(0, |n| n.get().min(255) as u8));
+    w.u8(0); // product type
+    w.u32(major);
+    w.u32(minor);
+    w.u32(build);
+    w.u32(PLATFORM_ID_LINUX);
+    w.u32(csd_version);
+    w.u16(0); // suite mask
+    w.u16(0); // reserved
+    w.buf.extend_from_slice(&[0; 24]); // cpu information
+    directory.push((SYSTEM_INFO_STREAM, location_since(&w, rva)));
+
+    // Threads
+    let rva = w.append(&[]).rva;
+    w.u32(threads.len() as u32);
+    for thread in &threads {
+        w.u32(thread.tid);
+        w.u32(0); // suspend count
+        w.u32(0); // priority class
+        w.u32(0); // priority
+        w.u64(0); // teb
+        w.u64(thread.stack_start);
+        w.location(thread.stack);
+        w.location(thread.context);
+    }
+    directory.push((THREAD_LIST_STREAM, location_since(&w, rva)));
+
+    // Modules
+    let modules = parse_modules(&maps);
+    let mut module_records = vec![];
+    for module in &modules {
+        let name = w.string(&module.path);
+        let cv_record = match read_build_id(&module.path) {
+            Ok(Some(build_id)) => {
+                let mut record = CV_SIGNATURE_ELF.to_le_bytes().to_vec();
+                record.extend_from_slice(&build_id);
+                w.append(&record)
+            }
+            _ => Location::default(),
+        };
+        module_records.push((name, cv_record));
+    }
+    let rva = w.append(&[]).rva;
+    w.u32(modules.len() as u32);
+    for (module, (name, cv_record)) in modules.iter().zip(module_records) {
+        w.u64(module.base);
+        w.u32(module.size as u32);
+        w.u32(0); // checksum
+        w.u32(0); // time date stamp
+        w.u32(name);
+        w.buf.extend_from_slice(&[0; 52]); // version info
+        w.location(cv_record);
+        w.location(Location::default()); // misc record
+        w.u64(0); // reserved
+        w.u64(0); // reserved
+    }
+    directory.push((MODULE_LIST_STREAM, location_since(&w, rva)));
+
+    // Exception
+    if let (true, Some(siginfo)) = (is_signal, &crash_info.siginfo) {
+        let rva = w.append(&[]).rva;
+        w.u32(crashing_tid);
+        w.u32(0); // alignment
+        w.u32(siginfo.signum as u32);
+        w.u32(siginfo.si_code.unwrap_or_default() as u32);
+        w.u64(0); // nested exception record
+        w.u64(
+            siginfo
+                .si_addr
+                .as_deref()
+                .and_then(parse_hex)
+                .unwrap_or_default(),
+        );
+        w.u32(0); // number of parameters
+        w.u32(0); // alignment
+        w.buf.extend_from_slice(&[0; 15 * 8]); // parameters
+        w.location(crashing_context);
+        directory.push((EXCEPTION_STREAM, location_since(&w, rva)));
+    }
+
+    // Process maps
+    let mut maps_text = maps.join("\n");
+    maps_text.push('\n');
+    let maps_location = w.append(maps_text.as_bytes());
+    directory.push((LINUX_MAPS_STREAM, maps_location));
+
+    // Header and directory, at the start of the minidump
+    let mut header = Writer { buf: vec![] };
+    header.u32(MINIDUMP_SIGNATURE);
+    header.u32(MINIDUMP_VERSION);
+    header.u32(directory.len() as u32);
+    header.u32(HEADER_SIZE as u32);
+    header.u32(0); // checksum
+    header.u32(crash_info.timestamp.map_or(0, |ts| ts.timestamp() as u32));
+    header.u64(0); // flags
+    for (stream_type, location) in directory {
+        header.u32(stream_type);
+        header.location(location);
+    }
+    w.buf[..header.buf.len()].copy_from_slice(&header.buf);
+    Ok(w.buf)
+}
+
+fn location_since(w: &Writer, rva: u32) -> Location {
+    Location {
+        data_size: w.rva() - rva,
+        rva,
+    }
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+fn parse_registers(registers: &HashMap<String, String>) -> HashMap<&str, u64> {
+    registers
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), parse_hex(value)?)))
+        .collect()
+}
+
+fn innermost_frame_registers(stacktrace: &[crate::StackFrame]) -> HashMap<&'static str, u64> {
+    let mut registers = HashMap::new();
+    if let Some(frame) = stacktrace.first() {
+        if let Some(ip) = frame.ip.as_deref().and_then(parse_hex) {
+            registers.insert(context::IP_REGISTER, ip);
+        }
+        if let Some(sp) = frame.sp.as_deref().and_then(parse_hex) {
+            registers.insert(context::SP_REGISTER, sp);
+        }
+    }
+    registers
+}
+
+/// Save the stack of the thread, from its stack pointer to the end of its mapping, at most
+/// [`MAX_STACK_SIZE`].
+fn write_thread(
+    w: &mut Writer,
+    mem: Option<&File>,
+    maps: &[String],
+    tid: u32,
+    context: Location,
+    registers: &HashMap<&str, u64>,
+) -> Thread {
+    let mut thread = Thread {
+        tid,
+        context,
+        stack_start: 0,
+        stack: Location::default(),
+    };
+    let (Some(mem), Some(sp)) = (mem, registers.get(context::SP_REGISTER)) else {
+        return thread;
+    };
+    let Some((_, mapping_end)) = maps
+        .iter()
+        .filter_map(|line| parse_mapping(line))
+        .map(|(start, end, _, _)| (start, end))
+        .find(|(start, end)| (*start..*end).contains(sp))
+    else {
+        return thread;
+    };
+    let start = sp.saturating_sub(RED_ZONE_SIZE) & !0xf;
+    let end = mapping_end.min(start + MAX_STACK_SIZE);
+    let mut stack = vec![0; (end - start) as usize];
+    if mem.read_exact_at(&mut stack, start).is_ok() {
+        thread.stack_start = start;
+        thread.stack = w.append(&stack);
+    }
+    thread
+}
+
+/// Parse a line of `/proc/<pid>/maps` into its start and end addresses, file offset and path.
+fn parse_mapping(line: &str) -> Option<(u64, u64, u64, &str)> {
+    let mut fields = line.splitn(6, ' ');
+    let (start, end) = fields.next()?.split_once('-')?;
+    let _perms = fields.next()?;
+    let offset = fields.next()?;
+    let path = fields.nth(2).unwrap_or_default().trim_start();
+    Some((
+        u64::from_str_radix(start, 16).ok()?,
+        u64::from_str_radix(end, 16).ok()?,
+        u64::from_str_radix(offset, 16).ok()?,
+        path,
+    ))
+}
+
+/// The files mapped in the process, from their mapping at offset 0 to the end of their last one.
+fn parse_modules(maps: &[String]) -> Vec<Module> {
+    let mut modules: Vec<Module> = vec![];
+    for (start, end, offset, path) in maps.iter().filter_map(|line| parse_mapping(line)) {
+        if !path.starts_with('/') {
+            continue;
+        }
+        match modules.iter_mut().rev().find(|module| module.path == path) {
+            Some(module) if offset != 0 => module.size = end.saturating_sub(module.base),
+            _ if offset == 0 => modules.push(Module {
+                base: start,
+                size: end - start,
+                path: path.to_string(),
+            }),
+            _ => {}
+        }
+    }
+    modules
+}
+
+/// Read the GNU build id from the notes of an ELF file.
+fn read_build_id(path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    const PT_NOTE: u32 = 4;
+    const NT_GNU_BUILD_ID: u32 = 3;
+
+    let file = File::open(path)?;
+    let mut header = [0u8; 64];
+    file.read_exact_at(&mut header, 0)?;
+    // 64 bits, little-endian
+    anyhow::ensure!(
+        &header[..6] == b"\x7fELF\x02\x01",
+        "Unsupported ELF file {path}"
+    );
+    let u16_at = |bytes: &[u8], at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at =
+        |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let u64_at =
+        |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+
+    let phoff = u64_at(&header, 0x20);
+    let phentsize = u16_at(&header, 0x36) as u64;
+    let phnum = u16_at(&header, 0x38) as u64;
+    anyhow::ensure!(phentsize >= 0x38, "Invalid ELF program header size");
+    for i in 0..phnum {
+        let mut phdr = [0u8; 0x38];
+        file.read_exact_at(&mut phdr, phoff + i * phentsize)?;
+        if u32_at(&phdr, 0) != PT_NOTE {
+            continue;
+        }
+        let size = u64_at(&phdr, 0x20).min(1 << 16) as usize;
+        let mut notes = vec![0u8; size];
+        file.read_exact_at(&mut notes, u64_at(&phdr, 0x8))?;
+        let mut at = 0;
+        while at + 12 <= notes.len() {
+            let name_size = u32_at(&notes, at) as usize;
+            let desc_size = u32_at(&notes, at + 4) as usize;
+            let note_type = u32_at(&notes, at + 8);
+            let name_start = at + 12;
+            let desc_start = name_start + align_up(name_size, 4);
+            let desc_end = desc_start + desc_size;
+            if desc_end > notes.len() {
+                break;
+            }
+            if note_type == NT_GNU_BUILD_ID
+                && &notes[name_start..name_start + name_size] == b"GNU\0"
+            {
+                return Ok(Some(notes[desc_start..desc_end].to_vec()));
+            }
+            at = desc_start + align_up(desc_size, 4);
+        }
+    }
+    Ok(None)
+}
+
+fn uname() -> Option<libc::utsname> {
+    // SAFETY: utsname is plain arrays of chars, for which zero is a valid value
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    (unsafe { libc::uname(&mut uts) } == 0).then_some(uts)
+}
+
+fn uts_field(field: &[libc::c_char]) -> String {
+    let bytes: Vec<u8> = field
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The kernel version as major, minor and patch numbers.
+fn kernel_version() -> (u32, u32, u32) {
+    let Some(uts) = uname() else {
+        return (0, 0, 0);
+    };
+    let release = uts_field(&uts.release);
+    let mut numbers = release
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse().unwrap_or_default());
+    (
+        numbers.next().unwrap_or_default(),
+        numbers.next().unwrap_or_default(),
+        numbers.next().unwrap_or_default(),
+    )
+}
+
+/// `uname -srvm`, as reported by Breakpad.
+fn os_description() -> String {
+    let Some(uts) = uname() else {
+        return String::new();
+    };
+    format!(
+        "{} {} {} {}",
+        uts_field(&uts.sysname),
+        uts_field(&uts.release),
+        uts_field(&uts.version),
+        uts_field(&uts.machine)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProcessInfo, SigInfo};
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn stream(minidump: &[u8], stream_type: u32) -> Option<&[u8]> {
+        let count = read_u32(minidump, 8) as usize;
+        let directory = read_u32(minidump, 12) as usize;
+        (0..count).find_map(|i| {
+            let entry = directory + i * DIRECTORY_ENTRY_SIZE;
+            (read_u32(minidump, entry) == stream_type).then(|| {
+                let size = read_u32(minidump, entry + 4) as usize;
+                let rva = read_u32(minidump, entry + 8) as usize;
+                &minidump[rva..rva + size]
+            })
+        })
+    }
+
+    #[test]
+    fn test_build_minidump() {
+        let pid = std::process::id();
+        let local = 0u64;
+        let sp = &local as *const u64 as u64;
+        let mut crash_info = CrashInfo::new();
+        crash_info
+            .set_procinfo(ProcessInfo {
+                pid,
+                tid: Some(pid),
+            })
+            .unwrap();
+        crash_info
+            .set_siginfo(SigInfo {
+                signum: 11,
+                si_code: Some(1),
+                si_addr: Some("0x0".to_string()),
+                registers: HashMap::from([
+                    (context::IP_REGISTER.to_string(), "0x1234".to_string()),
+                    (context::SP_REGISTER.to_string(), format!("{sp:#x}")),
+                ]),
+                ..Default::default()
+            })
+            .unwrap();
+        crash_info
+            .add_file_with_contents(
+                "/proc/self/maps",
+                std::fs::read_to_string("/proc/self/maps")
+                    .unwrap()
+                    .lines()
+                    .map(String::from)
+                    .collect(),
+            )
+            .unwrap();
+
+        let minidump = build_minidump(&crash_info).unwrap();
+        assert_eq!(&minidump[..4], b"MDMP");
+
+        let threads = stream(&minidump, THREAD_LIST_STREAM).unwrap();
+        assert_eq!(read_u32(threads, 0), 1);
+        assert_eq!(read_u32(threads, 4), pid);
+        // The stack memory was saved
+        assert!(read_u32(threads, 4 + 32) > 0);
+
+        let exception = stream(&minidump, EXCEPTION_STREAM).unwrap();
+        assert_eq!(read_u32(exception, 8), 11);
+
+        let modules = stream(&minidump, MODULE_LIST_STREAM).unwrap();
+        assert!(read_u32(modules, 0) > 0);
+        // The test binary is one of the modules
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.to_str().unwrap();
+        let module_list = parse_modules(crash_info.files.get("/proc/self/maps").unwrap());
+        assert!(module_list.iter().any(|module| module.path == exe));
+        read_build_id(exe).unwrap();
+
+        assert!(stream(&minidump, LINUX_MAPS_STREAM).is_some());
+        assert!(stream(&minidump, SYSTEM_INFO_STREAM).is_some());
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_other_threads_have_frame_pointer() {
+        let pid = std::process::id();
+        let mut crash_info = CrashInfo::new();
+        crash_info
+            .set_procinfo(ProcessInfo {
+                pid,
+                tid: Some(pid),
+            })
+            .unwrap();
+        let thread = "42:worker".to_string();
+        crash_info
+            .set_stacktrace(Some(thread.clone()), vec![])
+            .unwrap();
+        crash_info
+            .set_registers(
+                thread,
+                HashMap::from([
+                    ("rip".to_string(), "0x1234".to_string()),
+                    ("rsp".to_string(), "0x7ff0".to_string()),
+                    ("rbp".to_string(), "0x7ff8".to_string()),
+                ]),
+            )
+            .unwrap();
+
+        let minidump = build_minidump(&crash_info).unwrap();
+        let threads = stream(&minidump, THREAD_LIST_STREAM).unwrap();
+        assert_eq!(read_u32(threads, 0), 2);
+        // Each thread is 48 bytes, ending with the location of its context
+        let other = 4 + 48;
+        assert_eq!(read_u32(threads, other), 42);
+        let context = read_u32(threads, other + 44) as usize;
+        let rbp = u64::from_le_bytes(minidump[context + 0xa0..context + 0xa8].try_into().unwrap());
+        assert_eq!(rbp, 0x7ff8);
+    }
+}
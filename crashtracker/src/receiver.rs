@@ -3,32 +3,116 @@
 #![cfg(unix)]
 
 use super::*;
+use crate::debug_files::DebugFileFinder;
+use crate::fingerprint::{fingerprint, RateLimiter};
 use anyhow::Context;
-use std::{io::BufReader, os::unix::net::UnixListener};
+use std::{
+    io::BufReader,
+    os::unix::net::{SocketAddr, UnixListener},
+};
 
 pub fn resolve_frames(
     config: &CrashtrackerConfiguration,
     crash_info: &mut CrashInfo,
 ) -> anyhow::Result<()> {
-    if config.resolve_frames == StacktraceCollection::EnabledWithSymbolsInReceiver {
-        let proc_info = crash_info
+    if matches!(
+        config.resolve_frames,
+        StacktraceCollection::EnabledWithInprocessSymbols
+            | StacktraceCollection::EnabledWithSymbolsInReceiver
+    ) {
+        let pid = crash_info
             .proc_info
             .as_ref()
-            .context("Unable to resolve frames: No PID specified")?;
-        crash_info.resolve_names_from_process(proc_info.pid)?
+            .context("Unable to resolve frames: No PID specified")?
+            .pid;
+        // Normalize while the crashed process is still alive, the debug files of the stripped
+        // binaries are found from the build ids.
+        crash_info
+            .normalize_ips(pid)
+            .unwrap_or_else(|e| eprintln!("Unable to normalize the frames: {e}"));
+        crash_info.resolve_names_from_process(pid)?;
+        let finder = DebugFileFinder::new(config)?;
+        crash_info.resolve_names_from_debug_files(&finder)?;
     }
     Ok(())
 }
 
+/// Capture the stacks of the other threads of the crashed process, which is kept alive until the
+/// receiver is done. This is best effort, failures are logged and the report is sent without them.
+#[cfg(target_os = "linux")]
+pub fn collect_thread_stacks(config: &CrashtrackerConfiguration, crash_info: &mut CrashInfo) {
+    // A panicking process keeps running, stopping its threads would disturb it
+    if config.resolve_frames == StacktraceCollection::Disabled
+        || crash_info.kind == CrashKind::Panic
+    {
+        return;
+    }
+    let Some(proc_info) = crash_info.proc_info.clone() else {
+        return;
+    };
+    match crate::thread_stacks::collect_thread_stacks(proc_info.pid, proc_info.tid) {
+        Ok(stacks) => {
+            for stack in stacks {
+                crash_info
+                    .set_registers(stack.thread.clone(), stack.registers)
+                    .and_then(|_| crash_info.set_stacktrace(Some(stack.thread), stack.frames))
+                    .unwrap_or_else(|e| eprintln!("Unable to add thread stacktrace: {e}"));
+            }
+        }
+        Err(e) => eprintln!("Unable to collect the stacks of the other threads: {e}"),
+    }
+}
+
+/// Add the state of the crashed process, read from `/proc` while it is kept alive by the crash
+/// handler. This is best effort, the report is sent without it if it can't be read.
+#[cfg(target_os = "linux")]
+fn add_runtime_context(config: &CrashtrackerConfiguration, crash_info: &mut CrashInfo) {
+    let Some(proc_info) = &crash_info.proc_info else {
+        return;
+    };
+    let runtime_context = crate::runtime_context::collect_runtime_context(
+        proc_info.pid,
+        proc_info.tid,
+        &config.environment_variables,
+    );
+    crash_info
+        .set_runtime_context(runtime_context)
+        .unwrap_or_else(|e| eprintln!("Unable to add the runtime context: {e}"));
+}
+
+#[cfg(not(target_os = "linux"))]
+fn add_runtime_context(_config: &CrashtrackerConfiguration, _crash_info: &mut CrashInfo) {}
+
+#[cfg(not(target_os = "linux"))]
+pub fn collect_thread_stacks(_config: &CrashtrackerConfiguration, _crash_info: &mut CrashInfo) {}
+
+/// The address of the unix socket at `socket_path`. On Linux, a path which doesn't start with `/`
+/// or `.` is the name of an abstract socket, as used by the sidecar.
+pub fn unix_socket_addr(socket_path: &str) -> std::io::Result<SocketAddr> {
+    #[cfg(target_os = "linux")]
+    if !socket_path.starts_with(['/', '.']) {
+        use std::os::linux::net::SocketAddrExt;
+        return SocketAddr::from_abstract_name(socket_path);
+    }
+    SocketAddr::from_pathname(socket_path)
+}
+
+/// Bind a unix socket at `socket_path`. Fails if another receiver already listens on it, a socket
+/// left over by a receiver which exited is replaced.
 pub fn get_unix_socket(socket_path: impl AsRef<str>) -> anyhow::Result<UnixListener> {
     let socket_path = socket_path.as_ref();
-    if std::fs::metadata(socket_path).is_ok() {
+    let addr = unix_socket_addr(socket_path)?;
+    if addr.as_pathname().is_some() && std::fs::metadata(socket_path).is_ok() {
+        anyhow::ensure!(
+            std::os::unix::net::UnixStream::connect(socket_path).is_err(),
+            "another receiver already listens on {socket_path:?}"
+        );
         std::fs::remove_file(socket_path)
             .with_context(|| format!("could not delete previous socket at {:?}", socket_path))?;
     }
 
     let unix_listener =
-        UnixListener::bind(socket_path).context("Could not create the unix socket")?;
+        UnixListener::bind_addr(&addr).context("Could not create the unix socket")?;
     Ok(unix_listener)
 }
 
@@ -36,13 +120,53 @@ pub fn reciever_entry_point_unix_socket(socket_path: impl AsRef<str>) -> anyhow:
     let listener = get_unix_socket(socket_path)?;
     let (unix_stream, _) = listener.accept()?;
     let stream = BufReader::new(unix_stream);
-    receiver_entry_point(stream)
+    receiver_entry_point(stream, None)
     // Dropping the stream closes it, allowing the collector to exit if it was waiting.
 }
 
+/// Receives the crash reports of any number of processes connecting to `listener`, e.g. all the
+/// workers of a pre-fork pool, so that they share a receiver instead of each running its own.
+/// Each report is received and processed on a blocking thread of the current tokio runtime, so a
+/// slow upload doesn't hold back the other reports. Only returns if accepting connections fails.
+///
+/// As the receiver reads the state of the crashed processes and traces them, only the processes
+/// of the same user are accepted, and the pid of a crashed process is the one of the peer of the
+/// socket rather than the one in its report.
+pub async fn async_receiver_entry_point_unix_listener(
+    listener: &tokio::net::UnixListener,
+) -> anyhow::Result<()> {
+    // SAFETY: geteuid can't fail
+    let euid = unsafe { libc::geteuid() };
+    loop {
+        let (unix_stream, _) = listener.accept().await?;
+        let peer_pid = match unix_stream.peer_cred() {
+            Ok(cred) if cred.uid() == euid => cred.pid().and_then(|pid| u32::try_from(pid).ok()),
+            Ok(cred) => {
+                eprintln!(
+                    "Rejected the crash report of a process of user {}",
+                    cred.uid()
+                );
+                continue;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Rejected a crash report, the credentials of its process are unknown: {e}"
+                );
+                continue;
+            }
+        };
+        let unix_stream = unix_stream.into_std()?;
+        unix_stream.set_nonblocking(false)?;
+        tokio::task::spawn_blocking(move || {
+            receiver_entry_point(BufReader::new(unix_stream), peer_pid)
+                .unwrap_or_else(|e| eprintln!("Failed to process the crash report: {e}"));
+        });
+    }
+}
+
 pub fn receiver_entry_point_stdin() -> anyhow::Result<()> {
     let stream = std::io::stdin().lock();
-    receiver_entry_point(stream)
+    receiver_entry_point(stream, None)
 }
 
 /// Receives data from a crash collector via a pipe on `stdin`, formats it into
@@ -54,19 +178,113 @@ pub fn receiver_entry_point_stdin() -> anyhow::Result<()> {
 ///
 /// See comments in [profiling/crashtracker/mod.rs] for a full architecture
 /// description.
-fn receiver_entry_point(stream: impl std::io::BufRead) -> anyhow::Result<()> {
-    match receive_report(stream)? {
-        CrashReportStatus::NoCrash => Ok(()),
-        CrashReportStatus::CrashReport(config, mut crash_info) => {
-            resolve_frames(&config, &mut crash_info)?;
-            crash_info.upload_to_endpoint(&config)
-        }
-        CrashReportStatus::PartialCrashReport(config, mut crash_info, stdin_state) => {
+///
+/// The stream is only closed once the report is completed, as the crash handler keeps the crashed
+/// process alive until then, for its state and the stacks of its threads to be collected. It is
+/// closed before the upload, so that the crashed process doesn't wait for it.
+///
+/// `peer_pid`, if known, is the pid of the process which sent the report, which replaces the one
+/// in the report.
+fn receiver_entry_point(
+    mut stream: impl std::io::BufRead,
+    peer_pid: Option<u32>,
+) -> anyhow::Result<()> {
+    let (config, mut crash_info) = match receive_report(&mut stream)? {
+        CrashReportStatus::NoCrash => return Ok(()),
+        CrashReportStatus::CrashReport(config, crash_info) => (config, crash_info),
+        CrashReportStatus::PartialCrashReport(config, crash_info, stdin_state) => {
             eprintln!("Failed to fully receive crash.  Exit state was: {stdin_state:?}");
-            resolve_frames(&config, &mut crash_info)?;
-            crash_info.upload_to_endpoint(&config)
+            (config, crash_info)
         }
+    };
+    if let Some(pid) = peer_pid {
+        set_peer_pid(&mut crash_info, pid);
     }
+    process_report(&config, crash_info, || drop(stream))
+}
+
+/// Replace the pid of the report by the one of the process which sent it. The id of the crashing
+/// thread is only kept if it is a thread of that process.
+fn set_peer_pid(crash_info: &mut CrashInfo, pid: u32) {
+    let tid = crash_info
+        .proc_info
+        .as_ref()
+        .and_then(|proc_info| proc_info.tid)
+        .filter(|tid| std::path::Path::new(&format!("/proc/{pid}/task/{tid}")).exists());
+    crash_info.proc_info = Some(ProcessInfo { pid, tid });
+}
+
+/// Spool the report if configured, so it isn't lost if the receiver gets killed or the upload
+/// fails, then complete and upload it. `release_process` is called once the crashed process isn't
+/// needed anymore, before the upload.
+fn process_report(
+    config: &CrashtrackerConfiguration,
+    mut crash_info: CrashInfo,
+    release_process: impl FnOnce(),
+) -> anyhow::Result<()> {
+    let spool = |crash_info: &CrashInfo| {
+        let spool_dir = config.spool_dir.as_ref()?;
+        spool::spool_report(spool_dir, crash_info)
+            .map_err(|e| eprintln!("Unable to spool the crash report: {e}"))
+            .ok()
+    };
+    let spooled = spool(&crash_info);
+    add_runtime_context(config, &mut crash_info);
+    collect_thread_stacks(config, &mut crash_info);
+    resolve_frames(config, &mut crash_info)?;
+    crash_info.set_fingerprint(Some(fingerprint(&crash_info)))?;
+    if !within_rate_limit(config, &crash_info) {
+        eprintln!(
+            "Not uploading the crash report, the same crash was reported too often in the last hour"
+        );
+        if let Some(spooled) = spooled {
+            spooled.remove()?;
+        }
+        return Ok(());
+    }
+    add_minidump(config, &mut crash_info);
+    release_process();
+    // Replace the spooled report by the complete one
+    let spooled = spool(&crash_info).or(spooled);
+    crash_info.upload_to_endpoint(config)?;
+    if let Some(spooled) = spooled {
+        spooled.remove()?;
+    }
+    Ok(())
+}
+
+/// Attach a minidump of the crash to the report, if requested. This is best effort, failures are
+/// logged and the report is sent without it.
+#[cfg(target_os = "linux")]
+fn add_minidump(config: &CrashtrackerConfiguration, crash_info: &mut CrashInfo) {
+    if !config.minidump {
+        return;
+    }
+    crate::minidump::build_minidump(crash_info)
+        .and_then(|minidump| crash_info.set_minidump(&minidump))
+        .unwrap_or_else(|e| eprintln!("Unable to add the minidump: {e}"));
+}
+
+#[cfg(not(target_os = "linux"))]
+fn add_minidump(_config: &CrashtrackerConfiguration, _crash_info: &mut CrashInfo) {}
+
+/// Whether the report is within `config.max_reports_per_hour` for its fingerprint, counting the
+/// reports of the receivers of the user. The report is uploaded if the limit can't be checked.
+fn within_rate_limit(config: &CrashtrackerConfiguration, crash_info: &CrashInfo) -> bool {
+    let (Some(max_reports), Some(fingerprint)) =
+        (config.max_reports_per_hour, &crash_info.fingerprint)
+    else {
+        return true;
+    };
+    crate::configuration::user_cache_dir()
+        .and_then(|dir| {
+            RateLimiter::new(dir.join("dd-crashtracker-rate-limit"), max_reports)
+                .try_acquire(fingerprint, std::time::SystemTime::now())
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("Unable to check the rate limit of the crash report: {e}");
+            true
+        })
 }
 
 /// The crashtracker collector sends data in blocks.
@@ -81,6 +299,7 @@ enum StdinState {
     File(String, Vec<String>),
     InternalError(String),
     Metadata,
+    Panic,
     ProcInfo,
     SigInfo,
     StackTrace(Vec<StackFrame>),
@@ -97,6 +316,20 @@ fn process_line(
     line: String,
     state: StdinState,
 ) -> anyhow::Result<StdinState> {
+    // The crash handler faulted while emitting a section, and skipped to the end of the report
+    if line.starts_with(DD_CRASHTRACK_NESTED_FAULT) {
+        if let Some((_, section)) = line.split_once(' ') {
+            eprintln!("Crash handler faulted while emitting {section}");
+            crashinfo.add_lost_section(section)?;
+            crashinfo.set_incomplete(true)?;
+        }
+        // The frames received before the fault are still useful
+        if let StdinState::StackTrace(stacktrace) = state {
+            crashinfo.set_stacktrace(None, stacktrace)?;
+        }
+        return Ok(StdinState::Waiting);
+    }
+
     let next = match state {
         StdinState::Config if line.starts_with(DD_CRASHTRACK_END_CONFIG) => StdinState::Waiting,
         StdinState::Config => {
@@ -145,6 +378,14 @@ fn process_line(
             StdinState::Metadata
         }
 
+        StdinState::Panic if line.starts_with(DD_CRASHTRACK_END_PANIC) => StdinState::Waiting,
+        StdinState::Panic => {
+            let panic_info = serde_json::from_str(&line)?;
+            crashinfo.set_panic_info(panic_info)?;
+            crashinfo.set_timestamp_to_now()?;
+            StdinState::Panic
+        }
+
         StdinState::ProcInfo if line.starts_with(DD_CRASHTRACK_END_PROCINFO) => StdinState::Waiting,
         StdinState::ProcInfo => {
             let proc_info = serde_json::from_str(&line)?;
@@ -181,6 +422,7 @@ fn process_line(
         StdinState::Waiting if line.starts_with(DD_CRASHTRACK_BEGIN_METADATA) => {
             StdinState::Metadata
         }
+        StdinState::Waiting if line.starts_with(DD_CRASHTRACK_BEGIN_PANIC) => StdinState::Panic,
         StdinState::Waiting if line.starts_with(DD_CRASHTRACK_BEGIN_PROCINFO) => {
             StdinState::ProcInfo
         }
@@ -210,14 +452,19 @@ enum CrashReportStatus {
 /// In the case where the parent failed to transfer a full crash-report
 /// (for instance if it crashed while calculating the crash-report), we return
 /// a PartialCrashReport.
-fn receive_report(stream: impl std::io::BufRead) -> anyhow::Result<CrashReportStatus> {
+fn receive_report(stream: &mut impl std::io::BufRead) -> anyhow::Result<CrashReportStatus> {
     let mut crashinfo = CrashInfo::new();
     let mut stdin_state = StdinState::Waiting;
     let mut config = None;
 
     //TODO: This assumes that the input is valid UTF-8.
-    for line in stream.lines() {
+    let mut lines = std::io::BufRead::lines(stream).peekable();
+    while let Some(line) = lines.next() {
         let line = line?;
+        // A nested fault is reported on a new line, the line before it was cut short by the fault
+        if matches!(lines.peek(), Some(Ok(next)) if next.starts_with(DD_CRASHTRACK_NESTED_FAULT)) {
+            continue;
+        }
         match process_line(&mut crashinfo, &mut config, line, stdin_state) {
             Ok(next_state) => stdin_state = next_state,
             Err(e) => {
@@ -252,3 +499,216 @@ fn receive_report(stream: impl std::io::BufRead) -> anyhow::Result<CrashReportSt
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(target_os = "linux")]
+    use nix::sys::wait::{waitpid, WaitStatus};
+
+    fn test_config() -> CrashtrackerConfiguration {
+        CrashtrackerConfiguration {
+            resolve_frames: StacktraceCollection::WithoutSymbols,
+            timeout: std::time::Duration::from_secs(1),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_receive_report_with_nested_fault() {
+        let report = format!(
+            "{DD_CRASHTRACK_BEGIN_CONFIG}\n{}\n{DD_CRASHTRACK_END_CONFIG}\n\
+             {DD_CRASHTRACK_BEGIN_SIGINFO}\n{{\"signum\": 11, \"signame\": \"SIGSEGV\"}}\n\
+             {DD_CRASHTRACK_END_SIGINFO}\n\
+             {DD_CRASHTRACK_BEGIN_STACKTRACE}\n{{\"ip\": \"0x1\", \"sp\": \"0x2\"}}\n{{\"ip\": \"0x\n\
+             {DD_CRASHTRACK_NESTED_FAULT} {DD_CRASHTRACK_BEGIN_STACKTRACE}\n{DD_CRASHTRACK_DONE}\n",
+            serde_json::to_string(&test_config()).unwrap()
+        );
+
+        let CrashReportStatus::CrashReport(_, crash_info) =
+            receive_report(&mut report.as_bytes()).unwrap()
+        else {
+            panic!("Expected a complete crash report");
+        };
+        assert_eq!(
+            crash_info.lost_sections,
+            vec![DD_CRASHTRACK_BEGIN_STACKTRACE]
+        );
+        assert!(crash_info.incomplete);
+        // The frames received before the fault are kept, the one cut short is dropped
+        assert_eq!(crash_info.stacktrace.len(), 1);
+        assert_eq!(crash_info.stacktrace[0].ip.as_deref(), Some("0x1"));
+    }
+
+    #[test]
+    fn test_receive_panic_report() {
+        let report = format!(
+            "{DD_CRASHTRACK_BEGIN_CONFIG}\n{}\n{DD_CRASHTRACK_END_CONFIG}\n\
+             {DD_CRASHTRACK_BEGIN_PANIC}\n{{\"message\": \"oops\", \"location\": \"src/lib.rs:1:2\"}}\n\
+             {DD_CRASHTRACK_END_PANIC}\n{DD_CRASHTRACK_DONE}\n",
+            serde_json::to_string(&test_config()).unwrap()
+        );
+
+        let CrashReportStatus::CrashReport(_, crash_info) =
+            receive_report(&mut report.as_bytes()).unwrap()
+        else {
+            panic!("Expected a complete crash report");
+        };
+        assert_eq!(crash_info.kind, CrashKind::Panic);
+        assert_eq!(crash_info.siginfo, None);
+        assert_eq!(
+            crash_info.panic_info,
+            Some(PanicInfo {
+                message: Some("oops".to_string()),
+                location: Some("src/lib.rs:1:2".to_string()),
+            })
+        );
+        assert!(crash_info.timestamp.is_some());
+        assert!(!crash_info.incomplete);
+    }
+
+    /// Spawns a receiver listening on a socket in `dir`, returns the path of the socket.
+    fn spawn_listener(dir: &std::path::Path) -> std::path::PathBuf {
+        let socket_path = dir.join("crashtracker.sock");
+        let listener = get_unix_socket(socket_path.to_str().unwrap()).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let listener = tokio::net::UnixListener::from_std(listener).unwrap();
+        tokio::spawn(async move { async_receiver_entry_point_unix_listener(&listener).await });
+        socket_path
+    }
+
+    #[test]
+    fn test_live_socket_is_not_replaced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let socket_path = tmp.path().join("crashtracker.sock");
+        let socket_path = socket_path.to_str().unwrap();
+        let listener = get_unix_socket(socket_path).unwrap();
+        assert!(get_unix_socket(socket_path).is_err());
+        // The socket of a receiver which exited is replaced
+        drop(listener);
+        get_unix_socket(socket_path).unwrap();
+    }
+
+    fn file_config(output: &std::path::Path) -> CrashtrackerConfiguration {
+        let mut config = test_config();
+        config.endpoint = Some(ddcommon::Endpoint {
+            url: ddcommon::parse_uri(&format!("file://{}", output.display())).unwrap(),
+            api_key: None,
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn test_receive_reports_of_several_processes() {
+        use std::io::Write;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let socket_path = spawn_listener(tmp.path());
+
+        let outputs = vec![
+            tmp.path().join("report1.json"),
+            tmp.path().join("report2.json"),
+        ];
+        for output in &outputs {
+            let config = file_config(output);
+            let report = format!(
+                "{DD_CRASHTRACK_BEGIN_CONFIG}\n{}\n{DD_CRASHTRACK_END_CONFIG}\n\
+                 {DD_CRASHTRACK_BEGIN_SIGINFO}\n{{\"signum\": 11, \"signame\": \"SIGSEGV\"}}\n\
+                 {DD_CRASHTRACK_END_SIGINFO}\n{DD_CRASHTRACK_DONE}\n",
+                serde_json::to_string(&config).unwrap()
+            );
+            let mut stream = std::os::unix::net::UnixStream::connect(&socket_path).unwrap();
+            stream.write_all(report.as_bytes()).unwrap();
+        }
+
+        // Both reports are processed, while the receiver keeps listening
+        let uploaded = tokio::task::spawn_blocking(move || {
+            for _ in 0..100 {
+                let reports: Vec<CrashInfo> = outputs
+                    .iter()
+                    .filter_map(|output| std::fs::read(output).ok())
+                    .filter_map(|report| serde_json::from_slice(&report).ok())
+                    .collect();
+                if reports.len() == outputs.len() {
+                    return reports;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            panic!("The crash reports weren't received");
+        })
+        .await
+        .unwrap();
+        for crash_info in uploaded {
+            assert_eq!(crash_info.siginfo.unwrap().signum, 11);
+        }
+    }
+
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn test_crashed_process_is_kept_alive_until_the_report_is_completed() {
+        use std::io::{Read, Write};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let socket_path = spawn_listener(tmp.path());
+        let output = tmp.path().join("report.json");
+        // The pid of the report is replaced by the one of the peer of the socket
+        let report = format!(
+            "{DD_CRASHTRACK_BEGIN_CONFIG}\n{}\n{DD_CRASHTRACK_END_CONFIG}\n\
+             {DD_CRASHTRACK_BEGIN_SIGINFO}\n{{\"signum\": 11, \"signame\": \"SIGSEGV\"}}\n\
+             {DD_CRASHTRACK_END_SIGINFO}\n\
+             {DD_CRASHTRACK_BEGIN_PROCINFO}\n{{\"pid\": 1, \"tid\": 1}}\n\
+             {DD_CRASHTRACK_END_PROCINFO}\n{DD_CRASHTRACK_DONE}\n",
+            serde_json::to_string(&file_config(&output)).unwrap(),
+        );
+
+        // Like the crash handler, the crashed process sends its report and waits for the receiver
+        // to close the stream before exiting
+        // SAFETY: the child only sends the report and exits
+        let crashed = match unsafe { libc::fork() } {
+            0 => {
+                let sent = std::os::unix::net::UnixStream::connect(&socket_path)
+                    .and_then(|mut stream| {
+                        stream.write_all(report.as_bytes())?;
+                        stream.shutdown(std::net::Shutdown::Write)?;
+                        stream.read_to_end(&mut vec![])
+                    })
+                    .is_ok();
+                unsafe { libc::_exit(if sent { 0 } else { 1 }) };
+            }
+            pid => nix::unistd::Pid::from_raw(pid),
+        };
+        // Waiting for the child before the report is written would race with the receiver, which
+        // waits for it to stop when capturing its stack
+        let crash_info: CrashInfo = tokio::task::spawn_blocking(move || {
+            for _ in 0..100 {
+                if let Some(crash_info) = std::fs::read(&output)
+                    .ok()
+                    .and_then(|report| serde_json::from_slice(&report).ok())
+                {
+                    assert_eq!(
+                        waitpid(crashed, None).unwrap(),
+                        WaitStatus::Exited(crashed, 0)
+                    );
+                    return crash_info;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            panic!("The crash report wasn't received");
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            crash_info.proc_info,
+            Some(ProcessInfo {
+                pid: crashed.as_raw() as u32,
+                tid: None
+            })
+        );
+        let runtime_context = crash_info.runtime_context.unwrap();
+        assert!(!runtime_context.cmdline.is_empty());
+        // The only thread of the process, as the crashing thread is unknown
+        assert_eq!(crash_info.additional_stacktraces.len(), 1);
+    }
+}
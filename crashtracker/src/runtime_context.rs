@@ -0,0 +1,176 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(target_os = "linux")]
+
+//! Collection of the state of the crashed process from `/proc/<pid>`: the crashing thread, memory
+//! usage, open file descriptors, resource limits, command line and allow-listed environment
+//! variables. The receiver reads it while the crashed process waits for it, so the values are
+//! those at the time of the crash, e.g. to tell whether it ran out of memory or file descriptors.
+
+use crate::{ResourceLimit, RuntimeContext};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Gather the runtime context of the process `pid`, whose thread `tid` crashed. Only the
+/// environment variables named in `environment_variables` are included, as the others may hold
+/// secrets. This is best effort, the parts which can't be read are left out.
+pub fn collect_runtime_context(
+    pid: u32,
+    tid: Option<u32>,
+    environment_variables: &[String],
+) -> RuntimeContext {
+    let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+    let status = fs::read_to_string(proc_dir.join("status")).unwrap_or_default();
+    let environment = read_nul_separated(&proc_dir.join("environ"))
+        .into_iter()
+        .filter_map(|var| {
+            let (name, value) = var.split_once('=')?;
+            environment_variables
+                .iter()
+                .any(|allowed| allowed == name)
+                .then(|| (name.to_string(), value.to_string()))
+        })
+        .collect();
+    let thread_name = tid
+        .and_then(|tid| fs::read_to_string(proc_dir.join(format!("task/{tid}/comm"))).ok())
+        .map(|name| name.trim_end().to_string());
+
+    RuntimeContext {
+        cmdline: read_nul_separated(&proc_dir.join("cmdline")),
+        environment,
+        limits: fs::read_to_string(proc_dir.join("limits"))
+            .map(|limits| parse_limits(&limits))
+            .unwrap_or_default(),
+        open_fds: fs::read_dir(proc_dir.join("fd"))
+            .ok()
+            .map(|entries| entries.count() as u64),
+        peak_rss_bytes: parse_status_bytes(&status, "VmHWM"),
+        rss_bytes: parse_status_bytes(&status, "VmRSS"),
+        thread_id: tid,
+        thread_name,
+        uptime_ms: uptime_ms(&proc_dir),
+        vm_size_bytes: parse_status_bytes(&status, "VmSize"),
+    }
+}
+
+/// The strings of a file holding nul terminated strings, such as `cmdline` and `environ`.
+fn read_nul_separated(path: &Path) -> Vec<String> {
+    fs::read(path)
+        .unwrap_or_default()
+        .split(|byte| *byte == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+/// A memory size of `/proc/<pid>/status`, e.g. `VmRSS:    12345 kB`, in bytes.
+fn parse_status_bytes(status: &str, field: &str) -> Option<u64> {
+    let kb = status.lines().find_map(|line| {
+        line.strip_prefix(field)?
+            .strip_prefix(':')?
+            .trim()
+            .strip_suffix("kB")
+    })?;
+    Some(kb.trim().parse::<u64>().ok()? * 1024)
+}
+
+/// The resource limits of `/proc/<pid>/limits`, keyed by their name in snake case.
+fn parse_limits(limits: &str) -> HashMap<String, ResourceLimit> {
+    // The kernel formats each limit as "%-25s %-20s %-20s %-10s", names have spaces but values
+    // don't
+    let parse_value = |value: &str| value.parse::<u64>().ok();
+    limits
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let name = line.get(..25)?.trim();
+            let mut values = line.get(25..)?.split_whitespace();
+            let (soft, hard) = (values.next()?, values.next()?);
+            let name = name.to_lowercase().replace(' ', "_");
+            let limit = ResourceLimit {
+                soft: parse_value(soft),
+                hard: parse_value(hard),
+            };
+            Some((name, limit))
+        })
+        .collect()
+}
+
+/// How long ago the process started, from its start time in `/proc/<pid>/stat`, in clock ticks
+/// since boot, and the time since boot in `/proc/uptime`.
+fn uptime_ms(proc_dir: &Path) -> Option<u64> {
+    let stat = fs::read_to_string(proc_dir.join("stat")).ok()?;
+    // The command name, in parentheses, may contain spaces. The start time is the 22nd field, the
+    // 20th after the name.
+    let start_ticks: u64 = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .nth(19)?
+        .parse()
+        .ok()?;
+    let boot_uptime_secs: f64 = fs::read_to_string("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_sec <= 0 {
+        return None;
+    }
+    let start_ms = start_ticks * 1000 / ticks_per_sec as u64;
+    Some(((boot_uptime_secs * 1000.0) as u64).saturating_sub(start_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = "\
+Limit                     Soft Limit           Hard Limit           Units     
+Max cpu time              unlimited            unlimited            seconds   
+Max open files            1024                 524288               files     
+Max pending signals       62935                62935                signals   
+";
+        let limits = parse_limits(limits);
+        assert_eq!(limits.len(), 3);
+        assert_eq!(
+            limits["max_cpu_time"],
+            ResourceLimit {
+                soft: None,
+                hard: None
+            }
+        );
+        assert_eq!(
+            limits["max_open_files"],
+            ResourceLimit {
+                soft: Some(1024),
+                hard: Some(524288)
+            }
+        );
+    }
+
+    #[test]
+    fn test_collect_runtime_context() {
+        let pid = std::process::id();
+        let tid = nix::unistd::gettid().as_raw() as u32;
+        let context = collect_runtime_context(
+            pid,
+            Some(tid),
+            &["PATH".to_string(), "DD_MISSING_VAR".to_string()],
+        );
+        assert!(context.thread_name.is_some());
+        assert_eq!(context.thread_id, Some(tid));
+        assert!(!context.cmdline.is_empty());
+        assert_eq!(context.environment.keys().collect::<Vec<_>>(), vec!["PATH"]);
+        assert!(context.rss_bytes.unwrap() > 0);
+        assert!(context.vm_size_bytes.unwrap() >= context.rss_bytes.unwrap());
+        assert!(context.peak_rss_bytes.unwrap() >= context.rss_bytes.unwrap());
+        assert!(context.open_fds.unwrap() > 0);
+        assert!(context.limits.contains_key("max_open_files"));
+        assert!(context.uptime_ms.is_some());
+    }
+}
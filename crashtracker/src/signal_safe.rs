@@ -0,0 +1,143 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(unix)]
+
+//! Allocation-free output for the crash handler.
+//!
+//! `write!` goes through `core::fmt`, which isn't guaranteed to be signal safe nor free of
+//! allocations. The crash handler instead writes through a [`SignalSafeWriter`], which formats
+//! integers by hand into a caller provided buffer, and only calls `write` on the underlying handle
+//! when the buffer is full or explicitly flushed.
+
+use std::io::{self, Write};
+
+/// Large enough for the longest integer we format: `u64::MAX` in decimal, or `0x` + 16 hex digits
+const MAX_INTEGER_LEN: usize = 20;
+
+pub struct SignalSafeWriter<'a, W: Write> {
+    inner: &'a mut W,
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a, W: Write> SignalSafeWriter<'a, W> {
+    /// Buffer the writes to `inner` into `buffer`.
+    pub fn new(inner: &'a mut W, buffer: &'a mut [u8]) -> Self {
+        Self {
+            inner,
+            buffer,
+            len: 0,
+        }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.len + bytes.len() > self.buffer.len() {
+            self.flush_buffer()?;
+        }
+        if bytes.len() > self.buffer.len() {
+            return self.inner.write_all(bytes);
+        }
+        self.buffer[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    pub fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.write_bytes(s.as_bytes())
+    }
+
+    /// Write `s` followed by a newline.
+    pub fn write_line(&mut self, s: &str) -> io::Result<()> {
+        self.write_str(s)?;
+        self.write_bytes(b"\n")
+    }
+
+    /// Write `value` in decimal.
+    pub fn write_u64(&mut self, mut value: u64) -> io::Result<()> {
+        let mut digits = [0u8; MAX_INTEGER_LEN];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (value % 10) as u8;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        self.write_bytes(&digits[start..])
+    }
+
+    /// Write `value` in decimal.
+    pub fn write_i64(&mut self, value: i64) -> io::Result<()> {
+        if value < 0 {
+            self.write_bytes(b"-")?;
+        }
+        self.write_u64(value.unsigned_abs())
+    }
+
+    /// Write `value` in lowercase hexadecimal prefixed by `0x`, like `{:#x}`.
+    pub fn write_hex(&mut self, mut value: u64) -> io::Result<()> {
+        const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut digits = [0u8; MAX_INTEGER_LEN];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = HEX_DIGITS[(value & 0xf) as usize];
+            value >>= 4;
+            if value == 0 {
+                break;
+            }
+        }
+        start -= 2;
+        digits[start..start + 2].copy_from_slice(b"0x");
+        self.write_bytes(&digits[start..])
+    }
+
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        let len = std::mem::take(&mut self.len);
+        self.inner.write_all(&self.buffer[..len])
+    }
+
+    /// Write the buffered data to the underlying handle, and flush it.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(f: impl FnOnce(&mut SignalSafeWriter<Vec<u8>>) -> io::Result<()>) -> String {
+        let mut output = vec![];
+        let mut buffer = [0u8; 8];
+        let mut writer = SignalSafeWriter::new(&mut output, &mut buffer);
+        f(&mut writer).unwrap();
+        writer.flush().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_integers() {
+        for value in [0, 7, 10, 1234567890, u64::MAX] {
+            assert_eq!(written(|w| w.write_u64(value)), value.to_string());
+            assert_eq!(written(|w| w.write_hex(value)), format!("{value:#x}"));
+        }
+        for value in [0, -1, 42, i64::MIN, i64::MAX] {
+            assert_eq!(written(|w| w.write_i64(value)), value.to_string());
+        }
+    }
+
+    #[test]
+    fn test_buffering() {
+        // Writes smaller and larger than the buffer are kept in order
+        let output = written(|w| {
+            w.write_str("abc")?;
+            w.write_line("defghijklmnop")?;
+            w.write_u64(12345)?;
+            w.write_str("xyz")
+        });
+        assert_eq!(output, "abcdefghijklmnop\n12345xyz");
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(unix)]
+
+//! Durable storage of crash reports until they are uploaded.
+//!
+//! When the configuration has a `spool_dir`, the receiver writes the crash report to it before
+//! trying to upload it, and deletes it once the upload succeeded. Reports left behind, because
+//! the upload failed or the receiver was killed, are uploaded by the next process initializing the
+//! crashtracker with the same spool directory.
+//!
+//! A report is locked with `flock` for as long as a process is uploading it, so that processes
+//! sharing a spool directory don't upload the same report twice.
+
+use crate::{CrashInfo, CrashtrackerConfiguration};
+use anyhow::Context;
+use std::fs::{self, File};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const REPORT_EXTENSION: &str = "json";
+const TMP_EXTENSION: &str = "tmp";
+const MAX_UPLOAD_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A crash report in the spool directory, locked by this process until this is dropped.
+pub struct SpooledReport {
+    path: PathBuf,
+    file: File,
+}
+
+impl SpooledReport {
+    /// Delete the report, once it has been uploaded.
+    pub fn remove(self) -> anyhow::Result<()> {
+        fs::remove_file(&self.path).with_context(|| format!("Unable to remove {:?}", self.path))
+    }
+}
+
+/// Try to take an exclusive lock on `file`, returns false if another process holds it.
+fn try_lock(file: &File) -> anyhow::Result<bool> {
+    // SAFETY: the file descriptor is valid for the lifetime of `file`
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if res == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(err.into())
+    }
+}
+
+/// Write the report to `<spool_dir>/<uuid>.json`, replacing any previous version of it.
+///
+/// The report is written to a temporary file which is renamed once synced, so that a crash of the
+/// receiver never leaves a truncated report behind.
+pub fn spool_report(spool_dir: &str, crash_info: &CrashInfo) -> anyhow::Result<SpooledReport> {
+    fs::create_dir_all(spool_dir).with_context(|| format!("Unable to create {spool_dir}"))?;
+    let path = Path::new(spool_dir).join(format!("{}.{REPORT_EXTENSION}", crash_info.uuid));
+    let tmp_path = path.with_extension(TMP_EXTENSION);
+    let mut file = File::create(&tmp_path)?;
+    // Lock before the report becomes visible under its final name
+    anyhow::ensure!(
+        try_lock(&file)?,
+        "{tmp_path:?} is locked by another process"
+    );
+    serde_json::to_writer(&mut file, crash_info)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(SpooledReport { path, file })
+}
+
+/// Upload the reports found in the spool directory of `config`, deleting them once uploaded.
+///
+/// Each report is retried with an exponential backoff, and kept for a later call if it still
+/// can't be uploaded. Reports locked by another process are skipped. This blocks until every
+/// report is handled, which can take several times the configured timeout.
+pub fn upload_spooled_reports(config: &CrashtrackerConfiguration) -> anyhow::Result<()> {
+    let Some(spool_dir) = &config.spool_dir else {
+        return Ok(());
+    };
+    let entries = match fs::read_dir(spool_dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        entries => entries.with_context(|| format!("Unable to list {spool_dir}"))?,
+    };
+    let mut failures = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == REPORT_EXTENSION)
+            && upload_spooled_report(config, &path).is_err()
+        {
+            failures += 1;
+        }
+    }
+    anyhow::ensure!(
+        failures == 0,
+        "Unable to upload {failures} spooled crash reports"
+    );
+    Ok(())
+}
+
+fn upload_spooled_report(config: &CrashtrackerConfiguration, path: &Path) -> anyhow::Result<()> {
+    let file = match File::open(path) {
+        // Uploaded by another process in the meantime
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        file => file?,
+    };
+    // The report was deleted or replaced by the process owning the lock before we got it
+    if !try_lock(&file)? || file.metadata()?.nlink() == 0 {
+        return Ok(());
+    }
+    let report = SpooledReport {
+        path: path.to_path_buf(),
+        file,
+    };
+    let crash_info: CrashInfo = match serde_json::from_reader(&report.file) {
+        Ok(crash_info) => crash_info,
+        Err(e) => {
+            // Retrying would never succeed
+            report.remove()?;
+            return Err(e).context("Invalid spooled crash report");
+        }
+    };
+
+    let mut attempt = 1;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match crash_info.upload_to_endpoint(config) {
+            Ok(()) => return report.remove(),
+            Err(e) if attempt == MAX_UPLOAD_ATTEMPTS => return Err(e),
+            Err(_) => {
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StacktraceCollection;
+    use ddcommon::Endpoint;
+
+    fn test_config(spool_dir: &Path, output: &Path) -> CrashtrackerConfiguration {
+        CrashtrackerConfiguration {
+            endpoint: Some(Endpoint {
+                url: ddcommon::parse_uri(&format!("file://{}", output.display())).unwrap(),
+                api_key: None,
+            }),
+            resolve_frames: StacktraceCollection::Disabled,
+            spool_dir: Some(spool_dir.to_str().unwrap().to_string()),
+            timeout: Duration::from_secs(1),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap()
+    }
+
+    fn spooled_files(spool_dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(spool_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    #[test]
+    fn test_spool_and_upload() {
+        let tmp = tempfile::tempdir().unwrap();
+        let spool_dir = tmp.path().join("spool");
+        let output = tmp.path().join("report.json");
+        let config = test_config(&spool_dir, &output);
+        let crash_info = CrashInfo::new();
+
+        let report = spool_report(config.spool_dir.as_ref().unwrap(), &crash_info).unwrap();
+        assert_eq!(
+            spooled_files(&spool_dir),
+            vec![spool_dir.join(format!("{}.json", crash_info.uuid))]
+        );
+
+        // The report is skipped while it is locked by the receiver
+        upload_spooled_reports(&config).unwrap();
+        assert!(!output.exists());
+        assert_eq!(spooled_files(&spool_dir).len(), 1);
+
+        drop(report);
+        upload_spooled_reports(&config).unwrap();
+        let uploaded: CrashInfo =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(uploaded.uuid, crash_info.uuid);
+        assert!(spooled_files(&spool_dir).is_empty());
+    }
+
+    #[test]
+    fn test_upload_without_spool_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config = test_config(&tmp.path().join("missing"), &tmp.path().join("report.json"));
+        upload_spooled_reports(&config).unwrap();
+        assert!(!tmp.path().join("report.json").exists());
+    }
+
+    #[test]
+    fn test_invalid_report_is_removed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let spool_dir = tmp.path().join("spool");
+        let output = tmp.path().join("report.json");
+        let config = test_config(&spool_dir, &output);
+        fs::create_dir(&spool_dir).unwrap();
+        fs::write(spool_dir.join("invalid.json"), "{").unwrap();
+
+        assert!(upload_spooled_reports(&config).is_err());
+        assert!(spooled_files(&spool_dir).is_empty());
+        assert!(!output.exists());
+    }
+}
@@ -131,5 +131,24 @@ mod unix {
             }
             Ok(())
         }
+
+        /// Resolve the names of the frame from its normalized address, with `src` pointing at the
+        /// binary the address belongs to or at its debug file.
+        pub fn resolve_names_from_normalized_ip(
+            &mut self,
+            src: &Source,
+            symbolizer: &Symbolizer,
+        ) -> anyhow::Result<()> {
+            if let Some(normalized_ip) = &self.normalized_ip {
+                let file_offset = normalized_ip.file_offset;
+                match symbolizer.symbolize_single(src, Input::FileOffset(file_offset))? {
+                    Symbolized::Sym(s) => self.names = Some(vec![s.into()]),
+                    Symbolized::Unknown(reason) => {
+                        anyhow::bail!("Couldn't symbolize file offset {file_offset:#x}: {reason}");
+                    }
+                }
+            }
+            Ok(())
+        }
     }
 }
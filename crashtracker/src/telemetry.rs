@@ -5,7 +5,10 @@ use std::collections::HashMap;
 use std::fmt::Write;
 use std::time::{self, SystemTime};
 
-use super::{CrashInfo, CrashtrackerConfiguration, CrashtrackerMetadata, StackFrame};
+use super::{
+    CrashInfo, CrashKind, CrashtrackerConfiguration, CrashtrackerMetadata, PanicInfo,
+    RuntimeContext, StackFrame,
+};
 use anyhow::Ok;
 use ddtelemetry::{
     build_host,
@@ -44,8 +47,16 @@ macro_rules! parse_tags {
 struct TelemetryCrashInfoMessage<'a> {
     pub additional_stacktraces: &'a HashMap<String, Vec<StackFrame>>,
     pub files: &'a HashMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<&'a str>,
     pub metadata: Option<&'a CrashtrackerMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minidump: Option<&'a str>,
     pub os_info: &'a os_info::Info,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panic_info: Option<&'a PanicInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime_context: Option<&'a RuntimeContext>,
     pub tags: &'a HashMap<String, String>,
 }
 
@@ -127,8 +138,12 @@ impl TelemetryCrashUploader {
         let message = serde_json::to_string(&TelemetryCrashInfoMessage {
             additional_stacktraces: &crash_info.additional_stacktraces,
             files: &crash_info.files,
+            fingerprint: crash_info.fingerprint.as_deref(),
             metadata: crash_info.metadata.as_ref(),
+            minidump: crash_info.minidump.as_deref(),
             os_info: &crash_info.os_info,
+            panic_info: crash_info.panic_info.as_ref(),
+            runtime_context: crash_info.runtime_context.as_ref(),
             tags: &crash_info.tags,
         })?;
 
@@ -167,8 +182,14 @@ impl TelemetryCrashUploader {
                 ddcommon::header::APPLICATION_JSON,
             )
             .body(serde_json::to_string(&payload)?.into())?;
-        self.rt
+        let response = self
+            .rt
             .block_on(async { tokio::time::timeout(timeout, client.request(req)).await })??;
+        anyhow::ensure!(
+            response.status().is_success(),
+            "Crash report rejected by the telemetry intake: {}",
+            response.status()
+        );
         Ok(())
     }
 }
@@ -176,6 +197,12 @@ impl TelemetryCrashUploader {
 fn extract_crash_info_tags(crash_info: &CrashInfo) -> anyhow::Result<String> {
     let mut tags = String::new();
     write!(&mut tags, "uuid:{}", crash_info.uuid)?;
+    if let Some(fingerprint) = &crash_info.fingerprint {
+        write!(&mut tags, ",fingerprint:{fingerprint}")?;
+    }
+    if crash_info.kind == CrashKind::Panic {
+        write!(&mut tags, ",kind:panic")?;
+    }
     if let Some(siginfo) = &crash_info.siginfo {
         write!(&mut tags, ",signum:{}", siginfo.signum)?;
         if let Some(signame) = &siginfo.signame {
@@ -204,7 +231,6 @@ mod tests {
         TelemetryCrashUploader::new(
             &new_test_prof_metadata(),
             &crate::CrashtrackerConfiguration {
-                additional_files: vec![],
                 create_alt_stack: true,
                 endpoint: Some(Endpoint {
                     url: hyper::Uri::from_static("http://localhost:8126/profiling/v1/input"),
@@ -213,6 +239,7 @@ mod tests {
                 resolve_frames: crate::StacktraceCollection::WithoutSymbols,
                 timeout: time::Duration::from_secs(30),
                 wait_for_receiver: true,
+                ..Default::default()
             },
         )
         .unwrap()
@@ -272,14 +299,22 @@ mod tests {
             &crate::CrashInfo {
                 counters,
                 files: HashMap::new(),
+                fingerprint: Some("0123456789abcdef".to_owned()),
+                kind: crate::CrashKind::UnixSignal,
+                lost_sections: vec![],
                 metadata: Some(new_test_prof_metadata()),
+                minidump: None,
                 os_info: os_info::Info::unknown(),
+                panic_info: None,
                 siginfo: Some(SigInfo {
                     signum: 11,
                     signame: Some("SIGSEGV".to_owned()),
+                    ..Default::default()
                 }),
                 proc_info: None,
+                runtime_context: None,
                 stacktrace: vec![],
+                additional_registers: HashMap::new(),
                 additional_stacktraces: HashMap::new(),
                 tags: HashMap::new(),
                 timestamp: DateTime::from_timestamp(1702465105, 0),
@@ -305,6 +340,7 @@ mod tests {
         assert_eq!(
             HashSet::from_iter([
                 "uuid:1d6b97cb-968c-40c9-af6e-e4b4d71e8781",
+                "fingerprint:0123456789abcdef",
                 "signum:11",
                 "signame:SIGSEGV",
                 "collecting_sample:1",
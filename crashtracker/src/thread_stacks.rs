@@ -0,0 +1,190 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+#![cfg(target_os = "linux")]
+
+//! Collection of the stacks of the threads of a crashed process, other than the crashing one.
+//!
+//! This runs in the receiver, while the crashed process is kept alive by its crash handler. Every
+//! thread listed in `/proc/<pid>/task` is attached with `PTRACE_SEIZE` and stopped with
+//! `PTRACE_INTERRUPT`, which doesn't deliver any signal to the process. Its stack is then walked by
+//! following the chain of frame pointers, so frames of code compiled without frame pointers are
+//! skipped. The thread is resumed as soon as its stack is captured.
+
+use crate::collectors::walk_frame_pointers;
+use crate::stacktrace::StackFrame;
+use anyhow::Context;
+use nix::sys::ptrace;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs;
+
+/// The stack of a thread, and the registers of its innermost frame as hex strings keyed by their
+/// name.
+pub struct ThreadStack {
+    /// `"<tid>:<thread name>"`
+    pub thread: String,
+    pub frames: Vec<StackFrame>,
+    pub registers: HashMap<String, String>,
+}
+
+/// Capture the stack of every thread of the process `pid`, except `crashing_tid` whose stack is
+/// reported by the crash handler itself.
+///
+/// Threads which can't be captured, e.g. because they exited in the meantime, are skipped, and
+/// nothing is returned if the process itself exited.
+pub fn collect_thread_stacks(
+    pid: u32,
+    crashing_tid: Option<u32>,
+) -> anyhow::Result<Vec<ThreadStack>> {
+    let task_dir = format!("/proc/{pid}/task");
+    let mut stacks = vec![];
+    let entries = match fs::read_dir(&task_dir) {
+        // The process already exited, e.g. when it didn't wait for the receiver
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stacks),
+        entries => entries.with_context(|| format!("Unable to list {task_dir}"))?,
+    };
+    for entry in entries {
+        let Ok(tid) = entry?.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        if Some(tid) == crashing_tid {
+            continue;
+        }
+        let name = fs::read_to_string(format!("{task_dir}/{tid}/comm")).unwrap_or_default();
+        match capture_thread_stack(Pid::from_raw(tid as i32)) {
+            Ok((frames, registers)) => stacks.push(ThreadStack {
+                thread: format!("{tid}:{}", name.trim_end()),
+                frames,
+                registers,
+            }),
+            Err(e) => eprintln!("Unable to capture the stack of thread {tid}: {e}"),
+        }
+    }
+    Ok(stacks)
+}
+
+fn capture_thread_stack(tid: Pid) -> anyhow::Result<(Vec<StackFrame>, HashMap<String, String>)> {
+    ptrace::seize(tid, ptrace::Options::empty())?;
+    let stack = ptrace::interrupt(tid)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+            WaitStatus::PtraceEvent(..) | WaitStatus::Stopped(..) => unwind_stopped_thread(tid),
+            status => anyhow::bail!("Thread didn't stop: {status:?}"),
+        });
+    // Detaching resumes the thread
+    let _ = ptrace::detach(tid, None);
+    stack
+}
+
+fn unwind_stopped_thread(tid: Pid) -> anyhow::Result<(Vec<StackFrame>, HashMap<String, String>)> {
+    let registers = get_frame_registers(tid)?;
+    let mut frames = vec![];
+    walk_frame_pointers(
+        registers,
+        |address| read_word(tid, address).ok(),
+        |ip, sp| {
+            frames.push(new_frame(ip, sp));
+            anyhow::Ok(())
+        },
+    )?;
+    let (ip, sp, fp) = registers;
+    let registers = FRAME_REGISTER_NAMES
+        .iter()
+        .zip([ip, sp, fp])
+        .map(|(name, value)| (name.to_string(), format!("{value:#x}")))
+        .collect();
+    Ok((frames, registers))
+}
+
+fn new_frame(ip: u64, sp: u64) -> StackFrame {
+    StackFrame {
+        ip: Some(format!("{ip:#x}")),
+        module_base_address: None,
+        names: None,
+        normalized_ip: None,
+        sp: Some(format!("{sp:#x}")),
+        symbol_address: None,
+    }
+}
+
+fn read_word(tid: Pid, address: u64) -> nix::Result<u64> {
+    ptrace::read(tid, address as ptrace::AddressType).map(|word| word as u64)
+}
+
+/// Names of the instruction pointer, stack pointer and frame pointer registers.
+#[cfg(target_arch = "x86_64")]
+const FRAME_REGISTER_NAMES: [&str; 3] = ["rip", "rsp", "rbp"];
+#[cfg(target_arch = "aarch64")]
+const FRAME_REGISTER_NAMES: [&str; 3] = ["pc", "sp", "x29"];
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const FRAME_REGISTER_NAMES: [&str; 3] = ["pc", "sp", "fp"];
+
+/// Returns the instruction pointer, stack pointer and frame pointer of a stopped thread.
+#[cfg(target_arch = "x86_64")]
+fn get_frame_registers(tid: Pid) -> anyhow::Result<(u64, u64, u64)> {
+    let regs = ptrace::getregs(tid)?;
+    Ok((regs.rip, regs.rsp, regs.rbp))
+}
+
+/// Returns the instruction pointer, stack pointer and frame pointer of a stopped thread.
+#[cfg(target_arch = "aarch64")]
+fn get_frame_registers(tid: Pid) -> anyhow::Result<(u64, u64, u64)> {
+    // SAFETY: the register set is plain integers, for which zero is a valid value
+    let mut regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: &mut regs as *mut libc::user_regs_struct as *mut libc::c_void,
+        iov_len: std::mem::size_of::<libc::user_regs_struct>(),
+    };
+    // SAFETY: the kernel writes at most `iov_len` bytes into `regs`
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETREGSET,
+            tid.as_raw(),
+            libc::NT_PRSTATUS,
+            &mut iov as *mut libc::iovec,
+        )
+    };
+    nix::errno::Errno::result(res)?;
+    // x29 is the frame pointer
+    Ok((regs.pc, regs.sp, regs.regs[29]))
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn get_frame_registers(_tid: Pid) -> anyhow::Result<(u64, u64, u64)> {
+    anyhow::bail!("Capturing the stacks of other threads isn't supported on this architecture")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_collect_thread_stacks() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id();
+        // Give the child the time to exec
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let stacks = collect_thread_stacks(pid, None);
+        // The main thread of the process has the tid of the process
+        let skipped = collect_thread_stacks(pid, Some(pid));
+        let _ = child.kill();
+        let _ = child.wait();
+
+        let stacks = stacks.unwrap();
+        assert_eq!(stacks.len(), 1);
+        let stack = &stacks[0];
+        assert_eq!(stack.thread, format!("{pid}:sleep"));
+        assert!(stack.frames[0]
+            .ip
+            .as_ref()
+            .is_some_and(|ip| ip.starts_with("0x")));
+        assert_eq!(stack.registers.len(), 3);
+        assert_eq!(
+            stack.frames[0].ip.as_ref(),
+            stack.registers.get(FRAME_REGISTER_NAMES[0])
+        );
+        assert!(skipped.unwrap().is_empty());
+    }
+}
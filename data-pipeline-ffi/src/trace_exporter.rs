@@ -2,14 +2,45 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use data_pipeline::trace_exporter::{
-    ResponseCallback, TraceExporter, TraceExporterInputFormat, TraceExporterOutputFormat,
+    ResponseCallback, TraceExporter, TraceExporterError, TraceExporterInputFormat,
+    TraceExporterOutputFormat,
 };
 use ddcommon_ffi::{
     slice::{AsBytes, ByteSlice},
-    CharSlice, MaybeError,
+    CharSlice, Error, MaybeError,
 };
 use std::{ffi::c_char, ptr::NonNull};
 
+/// Kind of error returned when sending traces, see `ddog_trace_exporter_send`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub enum TraceExporterErrorCode {
+    None,
+    Deserialization,
+    Serialization,
+    Network,
+    Timeout,
+    HttpStatus,
+    AgentRejected,
+    BufferFull,
+    BufferClosed,
+}
+
+impl From<&TraceExporterError> for TraceExporterErrorCode {
+    fn from(err: &TraceExporterError) -> Self {
+        match err {
+            TraceExporterError::Deserialization(_) => TraceExporterErrorCode::Deserialization,
+            TraceExporterError::Serialization(_) => TraceExporterErrorCode::Serialization,
+            TraceExporterError::Network(_) => TraceExporterErrorCode::Network,
+            TraceExporterError::Timeout => TraceExporterErrorCode::Timeout,
+            TraceExporterError::HttpStatus { .. } => TraceExporterErrorCode::HttpStatus,
+            TraceExporterError::AgentRejected { .. } => TraceExporterErrorCode::AgentRejected,
+            TraceExporterError::BufferFull => TraceExporterErrorCode::BufferFull,
+            TraceExporterError::BufferClosed => TraceExporterErrorCode::BufferClosed,
+        }
+    }
+}
+
 /// Create a new TraceExporter instance.
 ///
 /// # Arguments
@@ -26,7 +57,7 @@ use std::{ffi::c_char, ptr::NonNull};
 ///   Proxy input format, this should be set to format if the trace data that will be passed through
 ///   as is.
 /// * `agent_response_callback` - The callback into the client library that the TraceExporter uses
-///   for updated Agent JSON responses.
+///   for updated Agent JSON responses. The responses contain the `rate_by_service` sampling rates.
 #[no_mangle]
 pub unsafe extern "C" fn ddog_trace_exporter_new(
     out_handle: NonNull<Box<TraceExporter>>,
@@ -42,8 +73,7 @@ pub unsafe extern "C" fn ddog_trace_exporter_new(
     let callback_wrapper = ResponseCallbackWrapper {
         response_callback: agent_response_callback,
     };
-    // TODO - handle errors - https://datadoghq.atlassian.net/browse/APMSP-1095
-    let exporter = TraceExporter::builder()
+    let exporter = match TraceExporter::builder()
         .set_url(url.to_utf8_lossy().as_ref())
         .set_tracer_version(tracer_version.to_utf8_lossy().as_ref())
         .set_language(language.to_utf8_lossy().as_ref())
@@ -53,7 +83,10 @@ pub unsafe extern "C" fn ddog_trace_exporter_new(
         .set_output_format(output_format)
         .set_response_callback(Box::new(callback_wrapper))
         .build()
-        .unwrap();
+    {
+        Ok(exporter) => exporter,
+        Err(err) => return MaybeError::Some(Error::from(err)),
+    };
     out_handle.as_ptr().write(Box::new(exporter));
     MaybeError::None
 }
@@ -64,8 +97,10 @@ struct ResponseCallbackWrapper {
 
 impl ResponseCallback for ResponseCallbackWrapper {
     fn call(&self, response: &str) {
-        let c_response = std::ffi::CString::new(response).unwrap();
-        (self.response_callback)(c_response.as_ptr());
+        // A response containing a nul byte can't be passed as a C string and isn't valid JSON
+        if let Ok(c_response) = std::ffi::CString::new(response) {
+            (self.response_callback)(c_response.as_ptr());
+        }
     }
 }
 
@@ -87,15 +122,24 @@ pub unsafe extern "C" fn ddog_trace_exporter_free(handle: Box<TraceExporter>) {
 /// * `trace` - The traces to send to the Datadog Agent in the input format used to create the
 ///   TraceExporter.
 /// * `trace_count` - The number of traces to send to the Datadog Agent.
+/// * `out_error_code` - Optional pointer to write the kind of error to, set to `None` when the
+///   traces have been sent successfully.
 #[no_mangle]
 pub unsafe extern "C" fn ddog_trace_exporter_send(
     handle: &TraceExporter,
     trace: ByteSlice,
     trace_count: usize,
+    out_error_code: Option<NonNull<TraceExporterErrorCode>>,
 ) -> MaybeError {
-    // TODO - handle errors - https://datadoghq.atlassian.net/browse/APMSP-1095
-    handle
-        .send(trace.as_bytes(), trace_count)
-        .unwrap_or(String::from(""));
-    MaybeError::None
+    let (code, error) = match handle.send(trace.as_bytes(), trace_count) {
+        Ok(_) => (TraceExporterErrorCode::None, MaybeError::None),
+        Err(err) => (
+            TraceExporterErrorCode::from(&err),
+            MaybeError::Some(Error::from(err.to_string())),
+        ),
+    };
+    if let Some(out_error_code) = out_error_code {
+        out_error_code.as_ptr().write(code);
+    }
+    error
 }
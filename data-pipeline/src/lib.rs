@@ -1,4 +1,8 @@
 // Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod sampler;
+pub mod span_concentrator;
+pub mod stats_exporter;
+pub mod trace_buffer;
 pub mod trace_exporter;
@@ -0,0 +1,354 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! Priority sampling of traces.
+//!
+//! The sampling decision is made on the root span of each trace, using the first user-defined
+//! rule matching the trace, or the rates sent by the agent if no rule matches. The decision is
+//! recorded on the root span the same way the tracers do, so the agent and the backend handle
+//! traces sampled by the exporter like any other trace.
+mod rate_limiter;
+mod rules;
+
+pub use rules::SamplingRule;
+
+use datadog_trace_protobuf::span_bytes::SpanBytes;
+use datadog_trace_utils::trace_utils;
+use log::error;
+use rate_limiter::RateLimiter;
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+const TAG_SAMPLING_PRIORITY: &str = "_sampling_priority_v1";
+const TAG_DECISION_MAKER: &str = "_dd.p.dm";
+const TAG_RULE_RATE: &str = "_dd.rule_psr";
+const TAG_AGENT_RATE: &str = "_dd.agent_psr";
+const TAG_LIMIT_RATE: &str = "_dd.limit_psr";
+const TAG_ENV: &str = "env";
+
+/// Key of the default rate in the agent rates
+const DEFAULT_RATE_KEY: &str = "service:,env:";
+
+/// Factor used to hash trace ids, shared with the tracers and the agent so all of them make the
+/// same decision for a given trace id and rate.
+const KNUTH_FACTOR: u64 = 1111111111111111111;
+
+/// Sampling priorities set in the `_sampling_priority_v1` metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplingPriority {
+    UserReject = -1,
+    AutoReject = 0,
+    AutoKeep = 1,
+    UserKeep = 2,
+}
+
+impl SamplingPriority {
+    /// Return the priority matching a `_sampling_priority_v1` metric value
+    fn from_metric(value: f64) -> Self {
+        match value as i8 {
+            i8::MIN..=-1 => SamplingPriority::UserReject,
+            0 => SamplingPriority::AutoReject,
+            1 => SamplingPriority::AutoKeep,
+            _ => SamplingPriority::UserKeep,
+        }
+    }
+}
+
+/// Sampling mechanisms reported in the `_dd.p.dm` tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SamplingMechanism {
+    Default = 0,
+    AgentRate = 1,
+    Rule = 3,
+}
+
+struct RuleSampler {
+    rule: SamplingRule,
+    limiter: Option<Mutex<RateLimiter>>,
+}
+
+/// A sampler setting the sampling priority of traces.
+#[derive(Default)]
+pub struct PrioritySampler {
+    rules: Vec<RuleSampler>,
+    agent_rates: RwLock<HashMap<String, f64>>,
+}
+
+impl PrioritySampler {
+    /// Return a new PrioritySampler applying the given rules in order.
+    pub fn new(rules: Vec<SamplingRule>) -> Self {
+        let now = Instant::now();
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| RuleSampler {
+                    limiter: rule
+                        .rate_limit
+                        .map(|rate| Mutex::new(RateLimiter::new(rate, now))),
+                    rule,
+                })
+                .collect(),
+            agent_rates: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Replace the rates used for traces not matching any rule with the `rate_by_service` rates
+    /// sent by the agent.
+    pub fn update_agent_rates(&self, rates: &HashMap<String, f64>) {
+        rates.clone_into(&mut self.agent_rates.write().unwrap());
+    }
+
+    /// Make a sampling decision for the trace and record it on its root span.
+    ///
+    /// Traces which already have a sampling priority, set by the tracer or by the user, are left
+    /// untouched. Return the sampling priority of the trace, or None if the trace is empty.
+    pub fn sample(&self, trace: &mut [SpanBytes]) -> Option<SamplingPriority> {
+        let root_span_index = match trace_utils::get_root_span_index_bytes(trace) {
+            Ok(index) => index,
+            Err(err) => {
+                error!("Error sampling trace: {err}");
+                return None;
+            }
+        };
+        let root_span = &mut trace[root_span_index];
+        if let Some(priority) = root_span.metrics.get(TAG_SAMPLING_PRIORITY) {
+            return Some(SamplingPriority::from_metric(*priority));
+        }
+
+        let priority = match self.rules.iter().find(|r| r.rule.matches(root_span)) {
+            Some(rule_sampler) => self.sample_with_rule(rule_sampler, root_span),
+            None => self.sample_with_agent_rates(root_span),
+        };
+        root_span
+            .metrics
+            .insert(TAG_SAMPLING_PRIORITY.into(), priority as i8 as f64);
+        Some(priority)
+    }
+
+    fn sample_with_rule(
+        &self,
+        rule_sampler: &RuleSampler,
+        root_span: &mut SpanBytes,
+    ) -> SamplingPriority {
+        let rate = rule_sampler.rule.sample_rate;
+        root_span.metrics.insert(TAG_RULE_RATE.into(), rate);
+        if !sampled_by_rate(root_span.trace_id, rate) {
+            return SamplingPriority::UserReject;
+        }
+        if let Some(limiter) = &rule_sampler.limiter {
+            let mut limiter = limiter.lock().unwrap();
+            let allowed = limiter.is_allowed(Instant::now());
+            root_span
+                .metrics
+                .insert(TAG_LIMIT_RATE.into(), limiter.effective_rate());
+            if !allowed {
+                return SamplingPriority::UserReject;
+            }
+        }
+        set_decision_maker(root_span, SamplingMechanism::Rule);
+        SamplingPriority::UserKeep
+    }
+
+    fn sample_with_agent_rates(&self, root_span: &mut SpanBytes) -> SamplingPriority {
+        let agent_rates = self.agent_rates.read().unwrap();
+        let env = root_span
+            .meta
+            .get(TAG_ENV)
+            .map(|env| env.as_str())
+            .unwrap_or("");
+        let key = format!("service:{},env:{env}", root_span.service);
+        let (rate, mechanism) = match agent_rates
+            .get(&key)
+            .or_else(|| agent_rates.get(DEFAULT_RATE_KEY))
+        {
+            Some(rate) => (*rate, SamplingMechanism::AgentRate),
+            // Keep everything until the agent sends rates
+            None => (1.0, SamplingMechanism::Default),
+        };
+        drop(agent_rates);
+
+        if mechanism == SamplingMechanism::AgentRate {
+            root_span.metrics.insert(TAG_AGENT_RATE.into(), rate);
+        }
+        if sampled_by_rate(root_span.trace_id, rate) {
+            set_decision_maker(root_span, mechanism);
+            SamplingPriority::AutoKeep
+        } else {
+            SamplingPriority::AutoReject
+        }
+    }
+}
+
+/// Return true if the trace is kept with the given rate, deterministically for a trace id.
+fn sampled_by_rate(trace_id: u64, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    trace_id.wrapping_mul(KNUTH_FACTOR) < (rate * u64::MAX as f64) as u64
+}
+
+/// Set the decision maker tag, only set on kept traces
+fn set_decision_maker(root_span: &mut SpanBytes, mechanism: SamplingMechanism) {
+    root_span.meta.insert(
+        TAG_DECISION_MAKER.into(),
+        format!("-{}", mechanism as u8).into(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_trace(trace_id: u64) -> Vec<SpanBytes> {
+        let mut root = SpanBytes {
+            service: "web".into(),
+            name: "http.request".into(),
+            resource: "GET /".into(),
+            trace_id,
+            span_id: 1,
+            ..Default::default()
+        };
+        root.meta.insert(TAG_ENV.into(), "prod".into());
+        vec![
+            SpanBytes {
+                service: "web".into(),
+                name: "db.query".into(),
+                trace_id,
+                span_id: 2,
+                parent_id: 1,
+                ..Default::default()
+            },
+            root,
+        ]
+    }
+
+    fn root_span(trace: &[SpanBytes]) -> &SpanBytes {
+        trace.iter().find(|span| span.parent_id == 0).unwrap()
+    }
+
+    #[test]
+    fn test_sampled_by_rate() {
+        assert!(sampled_by_rate(1, 1.0));
+        assert!(!sampled_by_rate(1, 0.0));
+        let kept = (1..10001).filter(|id| sampled_by_rate(*id, 0.3)).count();
+        assert!((2500..3500).contains(&kept), "kept {kept} traces");
+    }
+
+    #[test]
+    fn test_default_keeps_everything() {
+        let sampler = PrioritySampler::default();
+        let mut trace = test_trace(42);
+        assert_eq!(sampler.sample(&mut trace), Some(SamplingPriority::AutoKeep));
+
+        let root = root_span(&trace);
+        assert_eq!(root.metrics[TAG_SAMPLING_PRIORITY], 1.0);
+        assert_eq!(root.meta[TAG_DECISION_MAKER], "-0");
+        assert!(!root.metrics.contains_key(TAG_AGENT_RATE));
+        // Only the root span is tagged
+        assert!(trace[0].metrics.is_empty());
+    }
+
+    #[test]
+    fn test_agent_rates() {
+        let sampler = PrioritySampler::default();
+        sampler.update_agent_rates(&HashMap::from([
+            ("service:web,env:prod".to_string(), 0.0),
+            (DEFAULT_RATE_KEY.to_string(), 1.0),
+        ]));
+
+        let mut trace = test_trace(42);
+        assert_eq!(
+            sampler.sample(&mut trace),
+            Some(SamplingPriority::AutoReject)
+        );
+        let root = root_span(&trace);
+        assert_eq!(root.metrics[TAG_AGENT_RATE], 0.0);
+        assert!(!root.meta.contains_key(TAG_DECISION_MAKER));
+
+        // Other services use the default rate
+        let mut trace = test_trace(42);
+        trace[1].service = "other".into();
+        assert_eq!(sampler.sample(&mut trace), Some(SamplingPriority::AutoKeep));
+        let root = root_span(&trace);
+        assert_eq!(root.metrics[TAG_AGENT_RATE], 1.0);
+        assert_eq!(root.meta[TAG_DECISION_MAKER], "-1");
+    }
+
+    #[test]
+    fn test_rules() {
+        let sampler = PrioritySampler::new(vec![
+            SamplingRule {
+                resource: Some("GET /health".to_string()),
+                sample_rate: 0.0,
+                ..Default::default()
+            },
+            SamplingRule {
+                service: Some("web".to_string()),
+                sample_rate: 1.0,
+                ..Default::default()
+            },
+        ]);
+        // Rules take precedence over agent rates
+        sampler.update_agent_rates(&HashMap::from([(DEFAULT_RATE_KEY.to_string(), 0.0)]));
+
+        let mut trace = test_trace(42);
+        assert_eq!(sampler.sample(&mut trace), Some(SamplingPriority::UserKeep));
+        let root = root_span(&trace);
+        assert_eq!(root.metrics[TAG_SAMPLING_PRIORITY], 2.0);
+        assert_eq!(root.metrics[TAG_RULE_RATE], 1.0);
+        assert_eq!(root.meta[TAG_DECISION_MAKER], "-3");
+
+        // The first matching rule is used
+        let mut trace = test_trace(42);
+        trace[1].resource = "GET /health".into();
+        assert_eq!(
+            sampler.sample(&mut trace),
+            Some(SamplingPriority::UserReject)
+        );
+        assert_eq!(root_span(&trace).metrics[TAG_SAMPLING_PRIORITY], -1.0);
+    }
+
+    #[test]
+    fn test_rule_rate_limit() {
+        let sampler = PrioritySampler::new(vec![SamplingRule {
+            sample_rate: 1.0,
+            rate_limit: Some(1.0),
+            ..Default::default()
+        }]);
+
+        let mut trace = test_trace(1);
+        assert_eq!(sampler.sample(&mut trace), Some(SamplingPriority::UserKeep));
+        assert_eq!(root_span(&trace).metrics[TAG_LIMIT_RATE], 1.0);
+
+        let mut trace = test_trace(2);
+        assert_eq!(
+            sampler.sample(&mut trace),
+            Some(SamplingPriority::UserReject)
+        );
+        assert_eq!(root_span(&trace).metrics[TAG_LIMIT_RATE], 0.5);
+    }
+
+    #[test]
+    fn test_existing_priority_is_kept() {
+        let sampler = PrioritySampler::new(vec![SamplingRule {
+            sample_rate: 0.0,
+            ..Default::default()
+        }]);
+        let mut trace = test_trace(42);
+        trace[1].metrics.insert(TAG_SAMPLING_PRIORITY.into(), 2.0);
+        assert_eq!(sampler.sample(&mut trace), Some(SamplingPriority::UserKeep));
+        let root = root_span(&trace);
+        assert_eq!(root.metrics[TAG_SAMPLING_PRIORITY], 2.0);
+        assert!(!root.metrics.contains_key(TAG_RULE_RATE));
+    }
+
+    #[test]
+    fn test_empty_trace() {
+        let sampler = PrioritySampler::default();
+        assert_eq!(sampler.sample(&mut []), None);
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// A token bucket limiting the number of traces kept per second.
+///
+/// The bucket is refilled continuously with `rate` tokens per second and holds at most `rate`
+/// tokens, or a single one for rates lower than one. The limiter also tracks the ratio of allowed
+/// traces over the current and previous one second windows, which is reported as the effective
+/// rate of the limiter.
+#[derive(Debug)]
+pub(super) struct RateLimiter {
+    rate: f64,
+    max_tokens: f64,
+    tokens: f64,
+    last_refill: Instant,
+    window_start: Instant,
+    window_allowed: u64,
+    window_total: u64,
+    previous_window_rate: Option<f64>,
+}
+
+impl RateLimiter {
+    /// Return a new RateLimiter allowing `rate` traces per second.
+    pub(super) fn new(rate: f64, now: Instant) -> Self {
+        let rate = rate.max(0.0);
+        // Rates lower than one trace per second still allow a trace once the bucket is full
+        let max_tokens = if rate > 0.0 { rate.max(1.0) } else { 0.0 };
+        Self {
+            rate,
+            max_tokens,
+            tokens: max_tokens,
+            last_refill: now,
+            window_start: now,
+            window_allowed: 0,
+            window_total: 0,
+            previous_window_rate: None,
+        }
+    }
+
+    /// Take a token from the bucket, return false if the bucket is empty.
+    pub(super) fn is_allowed(&mut self, now: Instant) -> bool {
+        self.update_window(now);
+        self.refill(now);
+        self.window_total += 1;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.window_allowed += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Return the ratio of allowed traces, averaged with the previous window if there is one.
+    pub(super) fn effective_rate(&self) -> f64 {
+        let current_rate = self.effective_rate_of_window();
+        match self.previous_window_rate {
+            Some(previous_rate) => (current_rate + previous_rate) / 2.0,
+            None => current_rate,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.max_tokens);
+        self.last_refill = now;
+    }
+
+    fn update_window(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < WINDOW {
+            return;
+        }
+        // The previous window is only relevant if it directly precedes the current one
+        self.previous_window_rate = if elapsed < 2 * WINDOW {
+            Some(self.effective_rate_of_window())
+        } else {
+            None
+        };
+        self.window_start = now;
+        self.window_allowed = 0;
+        self.window_total = 0;
+    }
+
+    fn effective_rate_of_window(&self) -> f64 {
+        if self.window_total == 0 {
+            1.0
+        } else {
+            self.window_allowed as f64 / self.window_total as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(2.0, now);
+        assert!(limiter.is_allowed(now));
+        assert!(limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now));
+        assert_eq!(limiter.effective_rate(), 0.5);
+
+        // Half a second refills one token
+        let now = now + Duration::from_millis(500);
+        assert!(limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now));
+
+        // The bucket never holds more than `rate` tokens
+        let now = now + Duration::from_secs(10);
+        assert!(limiter.is_allowed(now));
+        assert!(limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now));
+    }
+
+    #[test]
+    fn test_effective_rate_uses_previous_window() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(1.0, now);
+        assert!(limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now));
+
+        let now = now + Duration::from_millis(1500);
+        assert!(limiter.is_allowed(now));
+        // 1/2 on the previous window and 1/1 on the current one
+        assert_eq!(limiter.effective_rate(), 0.75);
+    }
+
+    #[test]
+    fn test_rate_lower_than_one() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(0.5, now);
+        assert!(limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now + Duration::from_secs(1)));
+        assert!(limiter.is_allowed(now + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_zero_rate() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(0.0, now);
+        assert!(!limiter.is_allowed(now));
+        assert!(!limiter.is_allowed(now + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! User-defined trace sampling rules.
+use datadog_trace_protobuf::span_bytes::SpanBytes;
+use std::collections::HashMap;
+
+/// A trace sampling rule.
+///
+/// A rule matches a trace if all its patterns match the root span of the trace. Patterns are
+/// case-insensitive globs where `*` matches any sequence of characters and `?` matches a single
+/// character. A missing pattern matches any value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SamplingRule {
+    /// Pattern matched against the service of the root span
+    pub service: Option<String>,
+    /// Pattern matched against the operation name of the root span
+    pub name: Option<String>,
+    /// Pattern matched against the resource of the root span
+    pub resource: Option<String>,
+    /// Patterns matched against the tags of the root span, the tag must be present to match.
+    /// Metrics are matched if they are integers.
+    pub tags: HashMap<String, String>,
+    /// Ratio of the matching traces which are kept, between 0 and 1
+    pub sample_rate: f64,
+    /// Maximum number of traces kept per second by this rule, unlimited if not set
+    pub rate_limit: Option<f64>,
+}
+
+impl SamplingRule {
+    /// Return true if the rule matches the given root span
+    pub(super) fn matches(&self, root_span: &SpanBytes) -> bool {
+        let pattern_matches = |pattern: &Option<String>, value: &str| {
+            pattern
+                .as_deref()
+                .map_or(true, |pattern| glob_match(pattern, value))
+        };
+        pattern_matches(&self.service, &root_span.service)
+            && pattern_matches(&self.name, &root_span.name)
+            && pattern_matches(&self.resource, &root_span.resource)
+            && self
+                .tags
+                .iter()
+                .all(|(key, pattern)| match get_tag_value(root_span, key) {
+                    Some(value) => glob_match(pattern, &value),
+                    None => false,
+                })
+    }
+}
+
+/// Return the value of a tag of the span from its meta or its metrics.
+///
+/// Metrics are only returned if they are integers since patterns can't match float values
+/// reliably.
+fn get_tag_value(span: &SpanBytes, key: &str) -> Option<String> {
+    if let Some(value) = span.meta.get(key) {
+        return Some(value.to_string());
+    }
+    span.metrics
+        .get(key)
+        .filter(|value| value.fract() == 0.0)
+        .map(|value| format!("{}", *value as i64))
+}
+
+/// Match `subject` against a case-insensitive glob `pattern` supporting `*` and `?`.
+fn glob_match(pattern: &str, subject: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let subject: Vec<char> = subject.to_lowercase().chars().collect();
+
+    let (mut p, mut s) = (0, 0);
+    // Position of the last `*` in the pattern and of the subject when it was reached
+    let mut backtrack: Option<(usize, usize)> = None;
+    while s < subject.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == subject[s]) {
+            p += 1;
+            s += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, s));
+            p += 1;
+        } else if let Some((star_p, star_s)) = backtrack {
+            // Let the last `*` match one more character
+            p = star_p + 1;
+            s = star_s + 1;
+            backtrack = Some((star_p, star_s + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+        assert!(glob_match("web-*", "web-server"));
+        assert!(glob_match("WEB-*", "web-server"));
+        assert!(glob_match("*-server", "web-server"));
+        assert!(glob_match("w?b*r", "web-server"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("web-?", "web-server"));
+        assert!(!glob_match("*-client", "web-server"));
+        assert!(glob_match("a**", "a"));
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let mut span = SpanBytes {
+            service: "web-server".into(),
+            name: "http.request".into(),
+            resource: "GET /users".into(),
+            ..Default::default()
+        };
+        span.meta.insert("env".into(), "prod".into());
+        span.metrics.insert("http.status_code".into(), 200.0);
+        span.metrics.insert("ratio".into(), 0.5);
+
+        assert!(SamplingRule::default().matches(&span));
+
+        let rule = SamplingRule {
+            service: Some("web-*".to_string()),
+            name: Some("http.*".to_string()),
+            resource: Some("GET *".to_string()),
+            tags: HashMap::from([
+                ("env".to_string(), "prod".to_string()),
+                ("http.status_code".to_string(), "2??".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert!(rule.matches(&span));
+
+        let rule = SamplingRule {
+            resource: Some("POST *".to_string()),
+            ..Default::default()
+        };
+        assert!(!rule.matches(&span));
+
+        // Missing tags and float metrics never match
+        for tag in ["missing", "ratio"] {
+            let rule = SamplingRule {
+                tags: HashMap::from([(tag.to_string(), "*".to_string())]),
+                ..Default::default()
+            };
+            assert!(!rule.matches(&span));
+        }
+    }
+}
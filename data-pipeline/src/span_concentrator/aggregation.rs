@@ -0,0 +1,291 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! This includes the aggregation key used to group spans together and the computation of the
+//! stats of each group.
+use datadog_ddsketch::DDSketch;
+use datadog_trace_protobuf::pb;
+use datadog_trace_protobuf::span_bytes::SpanBytes;
+use std::collections::HashMap;
+
+const TAG_STATUS_CODE: &str = "http.status_code";
+const TAG_SPANKIND: &str = "span.kind";
+const TAG_ORIGIN: &str = "_dd.origin";
+const TAG_SYNTHETICS: &str = "synthetics";
+
+/// Span kinds for which the peer tags are used in the aggregation key
+const PEER_TAGS_SPAN_KINDS: [&str; 3] = ["client", "producer", "consumer"];
+
+/// Return the http status code of the span, 0 if it is missing or invalid.
+///
+/// The status code can be stored either as a metric or as a meta.
+fn get_status_code(span: &SpanBytes) -> u32 {
+    if let Some(status_code) = span.metrics.get(TAG_STATUS_CODE) {
+        *status_code as u32
+    } else if let Some(status_code) = span.meta.get(TAG_STATUS_CODE) {
+        status_code.parse().unwrap_or(0)
+    } else {
+        0
+    }
+}
+
+/// Return true if the span has been generated by synthetics traffic
+fn is_synthetics_request(span: &SpanBytes) -> bool {
+    span.meta
+        .get(TAG_ORIGIN)
+        .is_some_and(|origin| origin.starts_with(TAG_SYNTHETICS))
+}
+
+/// Return the peer tags of the span to be used in the aggregation key.
+///
+/// Peer tags are only used for spans describing an outgoing request (client, producer or
+/// consumer). Only tags present on the span are returned.
+fn get_peer_tags(
+    span: &SpanBytes,
+    span_kind: &str,
+    peer_tag_keys: &[String],
+) -> Vec<(String, String)> {
+    if !PEER_TAGS_SPAN_KINDS.contains(&span_kind.to_lowercase().as_str()) {
+        return vec![];
+    }
+    peer_tag_keys
+        .iter()
+        .filter_map(|key| Some((key.clone(), span.meta.get(key.as_str())?.to_string())))
+        .collect()
+}
+
+/// The key used to group spans together to compute stats.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(super) struct AggregationKey {
+    resource_name: String,
+    service_name: String,
+    operation_name: String,
+    span_type: String,
+    span_kind: String,
+    http_status_code: u32,
+    is_synthetics_request: bool,
+    peer_tags: Vec<(String, String)>,
+    is_trace_root: bool,
+}
+
+impl AggregationKey {
+    /// Return an AggregationKey matching the given span.
+    ///
+    /// If `peer_tag_keys` is not empty then the peer tags of the span will be included in the key.
+    pub(super) fn from_span(span: &SpanBytes, peer_tag_keys: &[String]) -> Self {
+        let span_kind = span
+            .meta
+            .get(TAG_SPANKIND)
+            .map(|span_kind| span_kind.as_str())
+            .unwrap_or_default();
+        Self {
+            resource_name: span.resource.to_string(),
+            service_name: span.service.to_string(),
+            operation_name: span.name.to_string(),
+            span_type: span.r#type.to_string(),
+            span_kind: span_kind.to_owned(),
+            http_status_code: get_status_code(span),
+            is_synthetics_request: is_synthetics_request(span),
+            peer_tags: get_peer_tags(span, span_kind, peer_tag_keys),
+            is_trace_root: span.parent_id == 0,
+        }
+    }
+}
+
+/// The stats computed from a group of spans with the same AggregationKey
+#[derive(Debug, Default, Clone)]
+pub(super) struct GroupedStats {
+    hits: u64,
+    errors: u64,
+    duration: u64,
+    top_level_hits: u64,
+    ok_summary: DDSketch,
+    error_summary: DDSketch,
+}
+
+impl GroupedStats {
+    /// Update the stats of a GroupedStats by inserting a span.
+    fn insert(&mut self, span: &SpanBytes, is_top_level: bool) {
+        // Negative durations are invalid and would corrupt the sketches
+        let duration = span.duration.max(0) as u64;
+        self.hits += 1;
+        self.duration += duration;
+        if is_top_level {
+            self.top_level_hits += 1;
+        }
+        // DDSketch only rejects negative or non-finite values, which can't happen here
+        if span.error != 0 {
+            self.errors += 1;
+            let _ = self.error_summary.add(duration as f64);
+        } else {
+            let _ = self.ok_summary.add(duration as f64);
+        }
+    }
+}
+
+/// A time bucket used for stats aggregation. It stores a map of GroupedStats storing the stats of
+/// spans aggregated on their AggregationKey.
+#[derive(Debug, Clone)]
+pub(super) struct StatsBucket {
+    data: HashMap<AggregationKey, GroupedStats>,
+    start: u64,
+}
+
+impl StatsBucket {
+    /// Return a new StatsBucket starting at the given timestamp
+    pub(super) fn new(start_timestamp: u64) -> Self {
+        Self {
+            data: HashMap::new(),
+            start: start_timestamp,
+        }
+    }
+
+    /// Insert a span into the bucket and update the GroupedStats of its AggregationKey.
+    pub(super) fn insert(&mut self, key: AggregationKey, span: &SpanBytes, is_top_level: bool) {
+        self.data.entry(key).or_default().insert(span, is_top_level);
+    }
+
+    /// Consume the bucket and return a ClientStatsBucket containing the bucket stats.
+    /// `bucket_duration` is the size of buckets for the concentrator containing the bucket.
+    pub(super) fn flush(self, bucket_duration: u64) -> pb::ClientStatsBucket {
+        pb::ClientStatsBucket {
+            start: self.start,
+            duration: bucket_duration,
+            stats: self
+                .data
+                .into_iter()
+                .map(|(key, stats)| encode_grouped_stats(key, stats))
+                .collect(),
+            agent_time_shift: 0,
+        }
+    }
+}
+
+/// Create a ClientGroupedStats struct based on the given AggregationKey and GroupedStats
+fn encode_grouped_stats(key: AggregationKey, group: GroupedStats) -> pb::ClientGroupedStats {
+    pb::ClientGroupedStats {
+        service: key.service_name,
+        name: key.operation_name,
+        resource: key.resource_name,
+        http_status_code: key.http_status_code,
+        r#type: key.span_type,
+        db_type: String::new(),
+        hits: group.hits,
+        errors: group.errors,
+        duration: group.duration,
+        ok_summary: group.ok_summary.encode_to_vec(),
+        error_summary: group.error_summary.encode_to_vec(),
+        synthetics: key.is_synthetics_request,
+        top_level_hits: group.top_level_hits,
+        span_kind: key.span_kind,
+        peer_tags: key
+            .peer_tags
+            .into_iter()
+            .map(|(key, value)| format!("{key}:{value}"))
+            .collect(),
+        is_trace_root: if key.is_trace_root {
+            pb::Trilean::True.into()
+        } else {
+            pb::Trilean::False.into()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_span() -> SpanBytes {
+        SpanBytes {
+            service: "service".into(),
+            name: "op".into(),
+            resource: "res".into(),
+            span_id: 1,
+            parent_id: 0,
+            duration: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_aggregation_key_from_span() {
+        let mut span = test_span();
+        span.r#type = "web".into();
+        span.meta.insert(TAG_STATUS_CODE.into(), "500".into());
+        span.meta
+            .insert(TAG_ORIGIN.into(), "synthetics-browser".into());
+
+        let key = AggregationKey::from_span(&span, &[]);
+        assert_eq!(
+            key,
+            AggregationKey {
+                resource_name: "res".to_string(),
+                service_name: "service".to_string(),
+                operation_name: "op".to_string(),
+                span_type: "web".to_string(),
+                span_kind: "".to_string(),
+                http_status_code: 500,
+                is_synthetics_request: true,
+                peer_tags: vec![],
+                is_trace_root: true,
+            }
+        );
+
+        // Metric status code takes precedence over the meta
+        span.metrics.insert(TAG_STATUS_CODE.into(), 404.0);
+        assert_eq!(AggregationKey::from_span(&span, &[]).http_status_code, 404);
+    }
+
+    #[test]
+    fn test_aggregation_key_peer_tags() {
+        let peer_tag_keys = vec!["db.instance".to_string(), "peer.hostname".to_string()];
+        let mut span = test_span();
+        span.parent_id = 2;
+        span.meta.insert("db.instance".into(), "i-1234".into());
+        span.meta.insert("db.system".into(), "pg".into());
+
+        // Peer tags are ignored for server spans
+        span.meta.insert(TAG_SPANKIND.into(), "server".into());
+        let key = AggregationKey::from_span(&span, &peer_tag_keys);
+        assert!(key.peer_tags.is_empty());
+        assert!(!key.is_trace_root);
+
+        span.meta.insert(TAG_SPANKIND.into(), "client".into());
+        let key = AggregationKey::from_span(&span, &peer_tag_keys);
+        assert_eq!(
+            key.peer_tags,
+            vec![("db.instance".to_string(), "i-1234".to_string())]
+        );
+        assert_eq!(key.span_kind, "client");
+    }
+
+    #[test]
+    fn test_stats_bucket_flush() {
+        let mut bucket = StatsBucket::new(10);
+        let span = test_span();
+        let mut error_span = test_span();
+        error_span.error = 1;
+        error_span.duration = 300;
+
+        bucket.insert(AggregationKey::from_span(&span, &[]), &span, true);
+        bucket.insert(
+            AggregationKey::from_span(&error_span, &[]),
+            &error_span,
+            false,
+        );
+
+        let flushed = bucket.flush(5);
+        assert_eq!(flushed.start, 10);
+        assert_eq!(flushed.duration, 5);
+        assert_eq!(flushed.stats.len(), 1);
+
+        let stats = &flushed.stats[0];
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.duration, 400);
+        assert_eq!(stats.top_level_hits, 1);
+        assert_eq!(stats.is_trace_root, pb::Trilean::True as i32);
+        assert!(!stats.ok_summary.is_empty());
+        assert!(!stats.error_summary.is_empty());
+    }
+}
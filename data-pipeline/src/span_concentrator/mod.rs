@@ -0,0 +1,356 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module implements the SpanConcentrator used to aggregate spans into stats
+use std::collections::HashMap;
+use std::time::{self, Duration, SystemTime};
+
+use datadog_trace_protobuf::pb;
+use datadog_trace_protobuf::span_bytes::SpanBytes;
+
+use aggregation::{AggregationKey, StatsBucket};
+
+mod aggregation;
+
+/// Span metric set by the agent or the exporter to mark top level spans
+const TAG_TOP_LEVEL: &str = "_top_level";
+/// Span metric set by the tracer to mark top level spans
+const TAG_TRACER_TOP_LEVEL: &str = "_dd.top_level";
+/// Span metric set by the tracer to mark spans which should be measured
+const TAG_MEASURED: &str = "_dd.measured";
+/// Span metric set on partial snapshots of long running spans
+const TAG_PARTIAL_VERSION: &str = "_dd.partial_version";
+const TAG_SPANKIND: &str = "span.kind";
+
+/// Span kinds for which stats are computed by default even if the span is not top level or
+/// measured
+pub const DEFAULT_SPAN_KINDS_STATS_COMPUTED: [&str; 4] =
+    ["client", "server", "producer", "consumer"];
+
+/// Number of buckets kept in the concentrator before being flushed, this allows late spans to be
+/// aggregated in the right bucket.
+const BUFFER_LEN: u64 = 2;
+
+/// Return the unix timestamp in nanoseconds of a SystemTime, 0 if it is before the epoch
+fn system_time_to_unix_nanos(t: SystemTime) -> u64 {
+    t.duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+/// Return the start of the bucket containing the timestamp
+fn align_timestamp(t: u64, bucket_size: u64) -> u64 {
+    t - (t % bucket_size)
+}
+
+fn has_metric_flag(span: &SpanBytes, key: &str) -> bool {
+    span.metrics.get(key).is_some_and(|v| *v == 1.0)
+}
+
+/// Return true if the span is top level, either computed by the tracer or by the exporter
+fn is_top_level(span: &SpanBytes) -> bool {
+    has_metric_flag(span, TAG_TOP_LEVEL) || has_metric_flag(span, TAG_TRACER_TOP_LEVEL)
+}
+
+/// Return true if the span has been marked as measured by the tracer
+fn is_measured(span: &SpanBytes) -> bool {
+    has_metric_flag(span, TAG_MEASURED)
+}
+
+/// Return true if the span is a partial snapshot of a long running span, these spans are reported
+/// multiple times and must not be counted in the stats.
+fn is_partial_snapshot(span: &SpanBytes) -> bool {
+    span.metrics
+        .get(TAG_PARTIAL_VERSION)
+        .is_some_and(|v| *v >= 0.0)
+}
+
+/// SpanConcentrator compute stats on span aggregated by time and span attributes
+///
+/// # Aggregation
+/// Spans are aggregated into time buckets based on their end_time. Within each time bucket there
+/// is another level of aggregation based on the spans fields (e.g. resource_name, service_name)
+/// and the peer tags if `peer_tag_keys` is not empty.
+///
+/// # Span eligibility
+/// The ingested spans are only aggregated if they are top-level, measured or if their span.kind
+/// is one of `span_kinds_stats_computed`. Partial snapshots are never aggregated.
+///
+/// # Flushing
+/// When the SpanConcentrator is flushed it keeps the `BUFFER_LEN` most recent buckets and remove
+/// all other buckets returning their stats. This allows late spans to have up to `BUFFER_LEN`
+/// buckets of delay to be aggregated. If the flush is forced all the buckets are flushed
+/// regardless of their age.
+#[derive(Debug, Clone)]
+pub struct SpanConcentrator {
+    /// Size of the time buckets used for aggregation in nanos
+    bucket_size: u64,
+    buckets: HashMap<u64, StatsBucket>,
+    /// Timestamp of the oldest time bucket for which we allow data.
+    /// Any ingested stats older than it get added to this bucket.
+    oldest_timestamp: u64,
+    /// bufferLen is the number stats bucket we keep when flushing.
+    buffer_len: u64,
+    /// span.kind fields eligible for stats computation
+    span_kinds_stats_computed: Vec<String>,
+    /// keys for supplementary tags that describe peer.service entities
+    peer_tag_keys: Vec<String>,
+}
+
+impl SpanConcentrator {
+    /// Return a new concentrator with the given parameters
+    /// - `bucket_size` is the size of the time buckets
+    /// - `now` the current system time, used to define the oldest bucket
+    /// - `span_kinds_stats_computed` list of span kinds eligible for stats computation
+    /// - `peer_tags_keys` list of keys considered as peer tags for aggregation
+    ///
+    /// # Panics
+    /// Panics if `bucket_size` is smaller than one nanosecond
+    pub fn new(
+        bucket_size: Duration,
+        now: SystemTime,
+        span_kinds_stats_computed: Vec<String>,
+        peer_tag_keys: Vec<String>,
+    ) -> SpanConcentrator {
+        let bucket_size = bucket_size.as_nanos() as u64;
+        assert!(bucket_size > 0, "bucket_size must be at least 1ns");
+        SpanConcentrator {
+            bucket_size,
+            buckets: HashMap::new(),
+            oldest_timestamp: align_timestamp(system_time_to_unix_nanos(now), bucket_size),
+            buffer_len: BUFFER_LEN,
+            span_kinds_stats_computed,
+            peer_tag_keys,
+        }
+    }
+
+    /// Return the bucket size used for aggregation
+    pub fn get_bucket_size(&self) -> Duration {
+        Duration::from_nanos(self.bucket_size)
+    }
+
+    /// Return true if the span should be aggregated
+    fn should_compute_stats(&self, span: &SpanBytes) -> bool {
+        (is_top_level(span)
+            || is_measured(span)
+            || span.meta.get(TAG_SPANKIND).is_some_and(|span_kind| {
+                self.span_kinds_stats_computed
+                    .contains(&span_kind.to_lowercase())
+            }))
+            && !is_partial_snapshot(span)
+    }
+
+    /// Add a span into the concentrator, by computing stats if the span is eligible for stats
+    /// computation.
+    pub fn add_span(&mut self, span: &SpanBytes) {
+        if !self.should_compute_stats(span) {
+            return;
+        }
+        let end_time = span.start.saturating_add(span.duration).max(0) as u64;
+        let mut bucket_timestamp = align_timestamp(end_time, self.bucket_size);
+        // If the span is too old we aggregate it in the latest bucket instead of
+        // creating a new one
+        if bucket_timestamp < self.oldest_timestamp {
+            bucket_timestamp = self.oldest_timestamp;
+        }
+
+        let agg_key = AggregationKey::from_span(span, &self.peer_tag_keys);
+
+        self.buckets
+            .entry(bucket_timestamp)
+            .or_insert_with(|| StatsBucket::new(bucket_timestamp))
+            .insert(agg_key, span, is_top_level(span));
+    }
+
+    /// Flush all stats in the concentrator older than `now` - `buffer_len` * `bucket_size`.
+    /// If `force` is true, flush all stats regardless of their age.
+    pub fn flush(&mut self, now: SystemTime, force: bool) -> Vec<pb::ClientStatsBucket> {
+        let now_timestamp = system_time_to_unix_nanos(now);
+        let aligned_now = align_timestamp(now_timestamp, self.bucket_size);
+        // The oldest allowed bucket is the most recent one kept in the buffer
+        self.oldest_timestamp = if force {
+            aligned_now
+        } else {
+            aligned_now.saturating_sub((self.buffer_len - 1) * self.bucket_size)
+        };
+        let flush_threshold = now_timestamp.saturating_sub(self.buffer_len * self.bucket_size);
+
+        let buckets: Vec<(u64, StatsBucket)> = self.buckets.drain().collect();
+        buckets
+            .into_iter()
+            .filter_map(|(timestamp, bucket)| {
+                // Keep the recent buckets
+                if !force && timestamp > flush_threshold {
+                    self.buckets.insert(timestamp, bucket);
+                    return None;
+                }
+                Some(bucket.flush(self.bucket_size))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUCKET_SIZE: u64 = Duration::from_secs(10).as_nanos() as u64;
+
+    fn get_span(
+        start: u64,
+        duration: u64,
+        service: &str,
+        resource: &str,
+        parent_id: u64,
+        error: i32,
+    ) -> SpanBytes {
+        let mut span = SpanBytes {
+            trace_id: 1,
+            span_id: 1,
+            parent_id,
+            service: service.to_string().into(),
+            name: "query".into(),
+            resource: resource.to_string().into(),
+            start: start as i64,
+            duration: duration as i64,
+            error,
+            ..Default::default()
+        };
+        if parent_id == 0 {
+            span.metrics.insert(TAG_TOP_LEVEL.into(), 1.0);
+        }
+        span
+    }
+
+    fn new_concentrator(now: SystemTime) -> SpanConcentrator {
+        SpanConcentrator::new(
+            Duration::from_nanos(BUCKET_SIZE),
+            now,
+            DEFAULT_SPAN_KINDS_STATS_COMPUTED
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            vec![],
+        )
+    }
+
+    fn total_hits(buckets: &[pb::ClientStatsBucket]) -> u64 {
+        buckets
+            .iter()
+            .flat_map(|b| b.stats.iter())
+            .map(|s| s.hits)
+            .sum()
+    }
+
+    #[test]
+    fn test_span_eligibility() {
+        let now = SystemTime::now();
+        let now_ns = system_time_to_unix_nanos(now);
+        let mut concentrator = new_concentrator(now);
+
+        // Top level
+        concentrator.add_span(&get_span(now_ns, 10, "A1", "resource1", 0, 0));
+        // Measured
+        let mut measured = get_span(now_ns, 10, "A1", "resource2", 1, 0);
+        measured.metrics.insert(TAG_MEASURED.into(), 1.0);
+        concentrator.add_span(&measured);
+        // Eligible span kind
+        let mut client = get_span(now_ns, 10, "A1", "resource3", 1, 0);
+        client.meta.insert(TAG_SPANKIND.into(), "client".into());
+        concentrator.add_span(&client);
+        // Not eligible
+        let mut internal = get_span(now_ns, 10, "A1", "resource4", 1, 0);
+        internal.meta.insert(TAG_SPANKIND.into(), "internal".into());
+        concentrator.add_span(&internal);
+        // Partial snapshot
+        let mut partial = get_span(now_ns, 10, "A1", "resource5", 0, 0);
+        partial.metrics.insert(TAG_PARTIAL_VERSION.into(), 1.0);
+        concentrator.add_span(&partial);
+
+        let buckets = concentrator.flush(now, true);
+        assert_eq!(total_hits(&buckets), 3);
+        let mut resources: Vec<_> = buckets
+            .iter()
+            .flat_map(|b| b.stats.iter())
+            .map(|s| s.resource.as_str())
+            .collect();
+        resources.sort();
+        assert_eq!(resources, vec!["resource1", "resource2", "resource3"]);
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let now = SystemTime::now();
+        let now_ns = system_time_to_unix_nanos(now);
+        let mut concentrator = new_concentrator(now);
+
+        concentrator.add_span(&get_span(now_ns, 10, "A1", "resource1", 0, 0));
+        concentrator.add_span(&get_span(now_ns, 20, "A1", "resource1", 0, 1));
+        concentrator.add_span(&get_span(now_ns, 30, "A1", "resource2", 0, 0));
+        concentrator.add_span(&get_span(now_ns, 40, "A2", "resource1", 0, 0));
+
+        let buckets = concentrator.flush(now, true);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].duration, BUCKET_SIZE);
+        assert_eq!(buckets[0].stats.len(), 3);
+        let grouped = buckets[0]
+            .stats
+            .iter()
+            .find(|s| s.service == "A1" && s.resource == "resource1")
+            .unwrap();
+        assert_eq!(grouped.hits, 2);
+        assert_eq!(grouped.errors, 1);
+        assert_eq!(grouped.top_level_hits, 2);
+        assert_eq!(grouped.duration, 30);
+    }
+
+    #[test]
+    fn test_flush_keeps_recent_buckets() {
+        let now = SystemTime::now();
+        let now_ns = system_time_to_unix_nanos(now);
+        let mut concentrator = new_concentrator(now);
+
+        // Span in the current bucket
+        concentrator.add_span(&get_span(now_ns, 10, "A1", "resource1", 0, 0));
+
+        // Flushing now shouldn't return anything as the bucket is still in the buffer
+        assert!(concentrator.flush(now, false).is_empty());
+
+        // After buffer_len buckets, the bucket is flushed
+        let later = now + Duration::from_nanos(BUFFER_LEN * BUCKET_SIZE);
+        let buckets = concentrator.flush(later, false);
+        assert_eq!(total_hits(&buckets), 1);
+        assert_eq!(buckets[0].start, align_timestamp(now_ns, BUCKET_SIZE));
+        assert!(concentrator.flush(later, true).is_empty());
+    }
+
+    #[test]
+    fn test_late_span_goes_to_oldest_bucket() {
+        let now = SystemTime::now();
+        let now_ns = system_time_to_unix_nanos(now);
+        let mut concentrator = new_concentrator(now);
+
+        // Span ending long before the oldest bucket
+        let old = now_ns - 10 * BUCKET_SIZE;
+        concentrator.add_span(&get_span(old, 10, "A1", "resource1", 0, 0));
+
+        let buckets = concentrator.flush(now, true);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].start, align_timestamp(now_ns, BUCKET_SIZE));
+    }
+
+    #[test]
+    fn test_force_flush() {
+        let now = SystemTime::now();
+        let now_ns = system_time_to_unix_nanos(now);
+        let mut concentrator = new_concentrator(now);
+
+        concentrator.add_span(&get_span(now_ns, 10, "A1", "resource1", 0, 0));
+        concentrator.add_span(&get_span(now_ns + BUCKET_SIZE, 10, "A1", "resource1", 0, 0));
+
+        let buckets = concentrator.flush(now, true);
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(total_hits(&buckets), 2);
+        assert!(concentrator.flush(now, true).is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time,
+};
+
+use crate::span_concentrator::SpanConcentrator;
+use datadog_trace_protobuf::pb;
+use datadog_trace_utils::trace_utils::TracerHeaderTags;
+use ddcommon::{connector, Endpoint, HttpClient};
+use hyper::{Body, Client, Method};
+use log::error;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+const STATS_ENDPOINT_PATH: &str = "/v0.6/stats";
+
+/// Metadata about the library, sent alongside the stats payloads
+#[derive(Clone, Debug, Default)]
+pub struct LibraryMetadata {
+    pub hostname: String,
+    pub env: String,
+    pub version: String,
+    pub lang: String,
+    pub tracer_version: String,
+    pub runtime_id: String,
+    pub service: String,
+    pub container_id: String,
+    pub git_commit_sha: String,
+}
+
+impl<'a> From<&'a LibraryMetadata> for TracerHeaderTags<'a> {
+    fn from(meta: &'a LibraryMetadata) -> TracerHeaderTags<'a> {
+        TracerHeaderTags::<'_> {
+            lang: &meta.lang,
+            tracer_version: &meta.tracer_version,
+            container_id: &meta.container_id,
+            ..Default::default()
+        }
+    }
+}
+
+/// An exporter that periodically flushes the stats of a SpanConcentrator to the agent
+/// `/v0.6/stats` endpoint.
+///
+/// The agent expects a msgpack encoded `ClientStatsPayload` on this endpoint, the
+/// `StatsPayload` envelope is built by the agent itself.
+#[derive(Debug)]
+pub struct StatsExporter {
+    flush_interval: time::Duration,
+    concentrator: Arc<Mutex<SpanConcentrator>>,
+    endpoint: Endpoint,
+    meta: LibraryMetadata,
+    sequence_id: AtomicU64,
+    client: HttpClient,
+    cancellation_token: CancellationToken,
+}
+
+impl StatsExporter {
+    /// Return a new StatsExporter
+    ///
+    /// - `flush_interval` the interval on which the concentrator is flushed
+    /// - `concentrator` SpanConcentrator storing the stats to be sent to the agent
+    /// - `meta` metadata used in ClientStatsPayload and as headers to send stats to the agent
+    /// - `endpoint` the Endpoint used to send stats to the agent, see [`stats_url_from_agent_url`]
+    /// - `cancellation_token` Token used to safely shutdown the exporter by forcing a flush
+    pub fn new(
+        flush_interval: time::Duration,
+        concentrator: Arc<Mutex<SpanConcentrator>>,
+        meta: LibraryMetadata,
+        endpoint: Endpoint,
+        cancellation_token: CancellationToken,
+    ) -> Self {
+        Self {
+            flush_interval,
+            concentrator,
+            endpoint,
+            meta,
+            sequence_id: AtomicU64::new(0),
+            client: Client::builder().build(connector::Connector::default()),
+            cancellation_token,
+        }
+    }
+
+    /// Flush the stats of the concentrator and send them to the agent.
+    ///
+    /// If `force_flush` is true all the buckets are flushed, including the most recent ones.
+    /// Nothing is sent if there are no stats to flush.
+    pub async fn send(&self, force_flush: bool) -> anyhow::Result<()> {
+        let payload = self.flush(force_flush);
+        if payload.stats.is_empty() {
+            return Ok(());
+        }
+        let body = rmp_serde::encode::to_vec_named(&payload)?;
+
+        let mut req_builder = self
+            .endpoint
+            .into_request_builder(concat!("Tracer/", env!("CARGO_PKG_VERSION")))?
+            .method(Method::POST)
+            .header("Content-type", "application/msgpack");
+
+        let headers: HashMap<&'static str, String> =
+            TracerHeaderTags::from(self.meta.borrow()).into();
+        for (key, value) in &headers {
+            req_builder = req_builder.header(*key, value);
+        }
+
+        let response = self
+            .client
+            .request(req_builder.body(Body::from(body))?)
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body_bytes = hyper::body::to_bytes(response.into_body()).await?;
+            let response_body = String::from_utf8_lossy(&body_bytes);
+            anyhow::bail!("Agent did not accept stats ({status}): {response_body}");
+        }
+        Ok(())
+    }
+
+    /// Flush stats from the concentrator into a payload
+    fn flush(&self, force_flush: bool) -> pb::ClientStatsPayload {
+        let sequence = self.sequence_id.fetch_add(1, Ordering::Relaxed);
+        let buckets = self
+            .concentrator
+            .lock()
+            .unwrap()
+            .flush(time::SystemTime::now(), force_flush);
+        encode_stats_payload(self.meta.borrow(), sequence, buckets)
+    }
+
+    /// Run the exporter, flushing the concentrator every `flush_interval` until the cancellation
+    /// token is cancelled. A last forced flush is made before returning.
+    pub async fn run(&self) {
+        loop {
+            select! {
+                _ = self.cancellation_token.cancelled() => {
+                    if let Err(err) = self.send(true).await {
+                        error!("Error sending stats: {err}");
+                    }
+                    break;
+                },
+                _ = tokio::time::sleep(self.flush_interval) => {
+                    if let Err(err) = self.send(false).await {
+                        error!("Error sending stats: {err}");
+                    }
+                },
+            };
+        }
+    }
+}
+
+/// Build a ClientStatsPayload from the library metadata and the flushed buckets
+fn encode_stats_payload(
+    meta: &LibraryMetadata,
+    sequence: u64,
+    buckets: Vec<pb::ClientStatsBucket>,
+) -> pb::ClientStatsPayload {
+    pb::ClientStatsPayload {
+        hostname: meta.hostname.clone(),
+        env: meta.env.clone(),
+        lang: meta.lang.clone(),
+        version: meta.version.clone(),
+        runtime_id: meta.runtime_id.clone(),
+        tracer_version: meta.tracer_version.clone(),
+        sequence,
+        stats: buckets,
+        git_commit_sha: meta.git_commit_sha.clone(),
+        service: meta.service.clone(),
+        container_id: meta.container_id.clone(),
+        // These fields are unused or will be set by the Agent
+        tags: vec![],
+        agent_aggregation: String::new(),
+        image_tag: String::new(),
+    }
+}
+
+/// Return the stats endpoint url to send stats to the agent at `agent_url`
+pub fn stats_url_from_agent_url(agent_url: &str) -> anyhow::Result<hyper::Uri> {
+    let mut parts = agent_url.parse::<hyper::Uri>()?.into_parts();
+    parts.path_and_query = Some(hyper::http::uri::PathAndQuery::from_static(
+        STATS_ENDPOINT_PATH,
+    ));
+    Ok(hyper::Uri::from_parts(parts)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span_concentrator::DEFAULT_SPAN_KINDS_STATS_COMPUTED;
+    use datadog_trace_protobuf::span_bytes::SpanBytes;
+    use httpmock::prelude::*;
+    use httpmock::MockServer;
+    use std::time::Duration;
+
+    fn get_test_metadata() -> LibraryMetadata {
+        LibraryMetadata {
+            hostname: "libdatadog-test".into(),
+            env: "test".into(),
+            version: "0.0.0".into(),
+            lang: "rust".into(),
+            tracer_version: "0.0.0".into(),
+            runtime_id: "e39d6d12-0752-489f-b488-cf80006c0378".into(),
+            service: "stats_exporter_test".into(),
+            ..Default::default()
+        }
+    }
+
+    fn get_test_concentrator() -> SpanConcentrator {
+        let mut concentrator = SpanConcentrator::new(
+            Duration::from_secs(10),
+            // Make sure the oldest bucket will be flushed on next send
+            time::SystemTime::now() - Duration::from_secs(15),
+            DEFAULT_SPAN_KINDS_STATS_COMPUTED
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            vec![],
+        );
+        let mut trace = vec![];
+
+        for i in 1..100 {
+            trace.push(SpanBytes {
+                service: "libdatadog-test".into(),
+                duration: i,
+                ..Default::default()
+            })
+        }
+
+        trace.first_mut().unwrap().metrics = HashMap::from([("_dd.top_level".into(), 1.0)]);
+        trace.get_mut(2).unwrap().meta = HashMap::from([("span.kind".into(), "client".into())]);
+
+        for span in trace.iter() {
+            concentrator.add_span(span);
+        }
+        concentrator
+    }
+
+    #[test]
+    fn test_stats_url_from_agent_url() {
+        assert_eq!(
+            stats_url_from_agent_url("http://127.0.0.1:8126/")
+                .unwrap()
+                .to_string(),
+            "http://127.0.0.1:8126/v0.6/stats"
+        );
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn test_send_stats() {
+        let server = MockServer::start_async().await;
+
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .header("Content-type", "application/msgpack")
+                    .path("/v0.6/stats")
+                    .body_contains("libdatadog-test");
+                then.status(200).body("");
+            })
+            .await;
+
+        let stats_exporter = StatsExporter::new(
+            Duration::from_secs(1),
+            Arc::new(Mutex::new(get_test_concentrator())),
+            get_test_metadata(),
+            Endpoint {
+                url: stats_url_from_agent_url(&server.url("/")).unwrap(),
+                ..Default::default()
+            },
+            CancellationToken::new(),
+        );
+
+        let send_status = stats_exporter.send(true).await;
+        send_status.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn test_send_stats_fail() {
+        let server = MockServer::start_async().await;
+
+        let mock = server
+            .mock_async(|_when, then| {
+                then.status(503)
+                    .header("content-type", "application/json")
+                    .body(r#"{"status":"error"}"#);
+            })
+            .await;
+
+        let stats_exporter = StatsExporter::new(
+            Duration::from_secs(1),
+            Arc::new(Mutex::new(get_test_concentrator())),
+            get_test_metadata(),
+            Endpoint {
+                url: stats_url_from_agent_url(&server.url("/")).unwrap(),
+                ..Default::default()
+            },
+            CancellationToken::new(),
+        );
+
+        let send_status = stats_exporter.send(true).await;
+        send_status.unwrap_err();
+
+        mock.assert_async().await;
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn test_run_flushes_on_cancellation() {
+        let server = MockServer::start_async().await;
+
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST).path("/v0.6/stats");
+                then.status(200).body("");
+            })
+            .await;
+
+        let cancellation_token = CancellationToken::new();
+        let stats_exporter = StatsExporter::new(
+            Duration::from_secs(60),
+            Arc::new(Mutex::new(get_test_concentrator())),
+            get_test_metadata(),
+            Endpoint {
+                url: stats_url_from_agent_url(&server.url("/")).unwrap(),
+                ..Default::default()
+            },
+            cancellation_token.clone(),
+        );
+
+        cancellation_token.cancel();
+        stats_exporter.run().await;
+
+        mock.assert_async().await;
+    }
+}
@@ -0,0 +1,329 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! Buffering of trace payloads used by the asynchronous mode of the TraceExporter.
+//!
+//! Payloads are pushed into a bounded queue without waiting on the agent. A background task
+//! batches them and flushes the batch when it exceeds a size threshold, when the flush interval
+//! elapses or when a flush is explicitly requested. Batches are coalesced with
+//! [`trace_utils::coalesce_send_data`] before being sent with a shared client.
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::trace_exporter::agent_response::ResponseHandler;
+use crate::trace_exporter::TraceExporterError;
+use datadog_trace_utils::trace_utils::{self, SendData};
+use ddcommon::HttpClient;
+use log::{debug, error};
+use tokio::runtime::Runtime;
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+/// Configuration of the trace buffer used in the asynchronous mode of the TraceExporter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceBufferConfig {
+    /// Maximum number of payloads waiting to be batched. When the buffer is full, new payloads
+    /// are dropped.
+    pub max_buffered_payloads: usize,
+    /// Approximate size in bytes of buffered payloads triggering a flush.
+    pub flush_threshold_bytes: usize,
+    /// Maximum time a payload is buffered before being flushed.
+    pub flush_interval: Duration,
+}
+
+impl Default for TraceBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_buffered_payloads: 1000,
+            flush_threshold_bytes: 10 * 1024 * 1024,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+enum Message {
+    Payload(Box<SendData>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Handle on the queue and the background task flushing the buffered payloads.
+pub(crate) struct TraceBuffer {
+    sender: mpsc::Sender<Message>,
+    worker: JoinHandle<()>,
+}
+
+impl TraceBuffer {
+    /// Start the background task on `runtime` and return a handle to push payloads to it. The
+    /// successful agent responses are passed to `response_handler`.
+    pub(crate) fn start(
+        config: TraceBufferConfig,
+        runtime: &Runtime,
+        client: HttpClient,
+        response_handler: Arc<ResponseHandler>,
+    ) -> Self {
+        // A zero sized channel isn't allowed, a buffer of 1 payload is the closest behavior
+        let (sender, receiver) = mpsc::channel(config.max_buffered_payloads.max(1));
+        let worker = runtime.spawn(run(config, receiver, client, response_handler));
+        Self { sender, worker }
+    }
+
+    /// Enqueue a payload without waiting.
+    ///
+    /// Returns an error if the buffer is full or if the background task has stopped, in which
+    /// case the payload is dropped.
+    pub(crate) fn push(&self, data: SendData) -> Result<(), TraceExporterError> {
+        self.sender
+            .try_send(Message::Payload(Box::new(data)))
+            .map_err(|err| match err {
+                mpsc::error::TrySendError::Full(_) => TraceExporterError::BufferFull,
+                mpsc::error::TrySendError::Closed(_) => TraceExporterError::BufferClosed,
+            })
+    }
+
+    /// Send all the payloads pushed before this call and wait for the requests to complete.
+    pub(crate) async fn flush(&self) -> anyhow::Result<()> {
+        let (ack_sender, ack_receiver) = oneshot::channel();
+        self.sender
+            .send(Message::Flush(ack_sender))
+            .await
+            .map_err(|_| anyhow::anyhow!("Trace buffer is closed"))?;
+        ack_receiver
+            .await
+            .map_err(|_| anyhow::anyhow!("Trace buffer stopped before flushing"))
+    }
+
+    /// Close the buffer and wait for the background task to send the remaining payloads.
+    pub(crate) async fn shutdown(self) -> anyhow::Result<()> {
+        drop(self.sender);
+        Ok(self.worker.await?)
+    }
+}
+
+/// Receive messages until the channel is closed, flushing the batch when needed. Remaining
+/// payloads are flushed once all the senders have been dropped.
+async fn run(
+    config: TraceBufferConfig,
+    mut receiver: mpsc::Receiver<Message>,
+    client: HttpClient,
+    response_handler: Arc<ResponseHandler>,
+) {
+    let mut batch = Batch::default();
+    let mut interval = tokio::time::interval(config.flush_interval);
+    // The first tick completes immediately
+    interval.tick().await;
+
+    loop {
+        select! {
+            message = receiver.recv() => match message {
+                Some(Message::Payload(data)) => {
+                    batch.push(*data);
+                    if batch.size >= config.flush_threshold_bytes {
+                        batch.flush(&client, &response_handler).await;
+                        interval.reset();
+                    }
+                }
+                Some(Message::Flush(ack)) => {
+                    batch.flush(&client, &response_handler).await;
+                    let _ = ack.send(());
+                }
+                None => {
+                    batch.flush(&client, &response_handler).await;
+                    break;
+                }
+            },
+            _ = interval.tick() => batch.flush(&client, &response_handler).await,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Batch {
+    payloads: Vec<SendData>,
+    size: usize,
+}
+
+impl Batch {
+    fn push(&mut self, data: SendData) {
+        self.size += data.len();
+        self.payloads.push(data);
+    }
+
+    /// Coalesce and send the buffered payloads, errors are logged and the payloads dropped.
+    async fn flush(&mut self, client: &HttpClient, response_handler: &ResponseHandler) {
+        if self.payloads.is_empty() {
+            return;
+        }
+        let payloads = std::mem::take(&mut self.payloads);
+        self.size = 0;
+        debug!("Flushing {} buffered trace payloads", payloads.len());
+        for data in trace_utils::coalesce_send_data(payloads) {
+            match data.send_with_client(client).await.last_result {
+                Ok(response) => match hyper::body::to_bytes(response.into_body()).await {
+                    Ok(body) => response_handler.handle(&String::from_utf8_lossy(&body)),
+                    Err(err) => error!("Error reading agent response body: {err}"),
+                },
+                Err(err) => error!("Error sending buffered traces: {err}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datadog_trace_protobuf::pb;
+    use datadog_trace_utils::trace_utils::TracerHeaderTags;
+    use datadog_trace_utils::tracer_payload::TracerPayloadCollection;
+    use ddcommon::{connector, Endpoint};
+    use httpmock::prelude::*;
+    use httpmock::MockServer;
+
+    fn test_send_data(url: &str, size: usize) -> SendData {
+        let span = pb::Span {
+            service: "test-service".to_string(),
+            name: "test".to_string(),
+            span_id: 1,
+            trace_id: 1,
+            ..Default::default()
+        };
+        SendData::new(
+            size,
+            TracerPayloadCollection::V04(vec![vec![span]]),
+            TracerHeaderTags::default(),
+            &Endpoint {
+                url: url.parse().unwrap(),
+                api_key: None,
+            },
+        )
+    }
+
+    fn test_runtime() -> Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn test_client() -> HttpClient {
+        hyper::Client::builder().build(connector::Connector::default())
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_flush_coalesces_payloads() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(200).body("{}");
+        });
+
+        let runtime = test_runtime();
+        let config = TraceBufferConfig {
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let buffer = TraceBuffer::start(
+            config,
+            &runtime,
+            test_client(),
+            Arc::new(ResponseHandler::default()),
+        );
+        let url = server.url("/v0.4/traces");
+        buffer.push(test_send_data(&url, 100)).unwrap();
+        buffer.push(test_send_data(&url, 100)).unwrap();
+
+        runtime.block_on(buffer.flush()).unwrap();
+        // Both payloads have been coalesced in a single request
+        mock.assert_hits(1);
+        runtime.block_on(buffer.shutdown()).unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_flush_on_size_threshold() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(200).body("{}");
+        });
+
+        let runtime = test_runtime();
+        let config = TraceBufferConfig {
+            flush_threshold_bytes: 100,
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let buffer = TraceBuffer::start(
+            config,
+            &runtime,
+            test_client(),
+            Arc::new(ResponseHandler::default()),
+        );
+        buffer
+            .push(test_send_data(&server.url("/v0.4/traces"), 200))
+            .unwrap();
+
+        runtime.block_on(async {
+            for _ in 0..100 {
+                if mock.hits_async().await == 1 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+        mock.assert_hits(1);
+        runtime.block_on(buffer.shutdown()).unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_shutdown_sends_remaining_payloads() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(200).body("{}");
+        });
+
+        let runtime = test_runtime();
+        let config = TraceBufferConfig {
+            flush_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let buffer = TraceBuffer::start(
+            config,
+            &runtime,
+            test_client(),
+            Arc::new(ResponseHandler::default()),
+        );
+        buffer
+            .push(test_send_data(&server.url("/v0.4/traces"), 100))
+            .unwrap();
+
+        runtime.block_on(buffer.shutdown()).unwrap();
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_push_full_buffer() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let config = TraceBufferConfig {
+            max_buffered_payloads: 1,
+            ..Default::default()
+        };
+        // The current thread runtime is never driven so the worker never consumes the queue
+        let buffer = TraceBuffer::start(
+            config,
+            &runtime,
+            test_client(),
+            Arc::new(ResponseHandler::default()),
+        );
+        let url = "http://127.0.0.1:1/v0.4/traces";
+        buffer.push(test_send_data(url, 100)).unwrap();
+        assert!(buffer.push(test_send_data(url, 100)).is_err());
+    }
+}
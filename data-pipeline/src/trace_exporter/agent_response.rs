@@ -0,0 +1,113 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! Handling of the agent responses to trace payloads.
+use crate::sampler::PrioritySampler;
+use log::error;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Callback called with the raw body of each successful agent response.
+pub trait ResponseCallback: Send + Sync {
+    fn call(&self, response: &str);
+}
+
+/// Callback called with the sampling rates sent by the agent.
+///
+/// The rates are keyed by `service:<service>,env:<env>`, the key `service:,env:` holds the
+/// default rate.
+pub trait SamplingRatesCallback: Send + Sync {
+    fn call(&self, rates: &HashMap<String, f64>);
+}
+
+/// Body of the agent response to a trace payload
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct AgentResponse {
+    #[serde(default)]
+    pub rate_by_service: HashMap<String, f64>,
+}
+
+/// Dispatch the agent responses to the callbacks registered on the exporter.
+#[derive(Default)]
+pub(crate) struct ResponseHandler {
+    pub(crate) response_callback: Option<Box<dyn ResponseCallback>>,
+    pub(crate) rates_callback: Option<Box<dyn SamplingRatesCallback>>,
+    pub(crate) sampler: Option<Arc<PrioritySampler>>,
+}
+
+impl ResponseHandler {
+    /// Handle the body of a successful agent response.
+    ///
+    /// The sampling rates are only delivered to the callback and the sampler if the body is a
+    /// valid agent response, parsing errors are logged as the traces have been accepted anyway.
+    pub(crate) fn handle(&self, body: &str) {
+        if let Some(callback) = &self.response_callback {
+            callback.call(body);
+        }
+        if self.rates_callback.is_none() && self.sampler.is_none() {
+            return;
+        }
+        let rates = match serde_json::from_str::<AgentResponse>(body) {
+            Ok(response) if !response.rate_by_service.is_empty() => response.rate_by_service,
+            Ok(_) => return,
+            Err(err) => {
+                error!("Error parsing agent response: {err}");
+                return;
+            }
+        };
+        if let Some(sampler) = &self.sampler {
+            sampler.update_agent_rates(&rates);
+        }
+        if let Some(callback) = &self.rates_callback {
+            callback.call(&rates);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct TestRatesCallback(Arc<Mutex<Vec<HashMap<String, f64>>>>);
+
+    impl SamplingRatesCallback for TestRatesCallback {
+        fn call(&self, rates: &HashMap<String, f64>) {
+            self.0.lock().unwrap().push(rates.clone());
+        }
+    }
+
+    #[test]
+    fn test_parse_agent_response() {
+        let response: AgentResponse = serde_json::from_str(
+            r#"{"rate_by_service":{"service:,env:":1.0,"service:test,env:prod":0.5}}"#,
+        )
+        .unwrap();
+        assert_eq!(response.rate_by_service.len(), 2);
+        assert_eq!(response.rate_by_service["service:,env:"], 1.0);
+        assert_eq!(response.rate_by_service["service:test,env:prod"], 0.5);
+
+        let response: AgentResponse = serde_json::from_str("{}").unwrap();
+        assert!(response.rate_by_service.is_empty());
+    }
+
+    #[test]
+    fn test_handler_delivers_rates() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let handler = ResponseHandler {
+            response_callback: None,
+            rates_callback: Some(Box::new(TestRatesCallback(received.clone()))),
+            sampler: None,
+        };
+
+        handler.handle(r#"{"rate_by_service":{"service:test,env:prod":0.5}}"#);
+        // Invalid and empty responses are ignored
+        handler.handle("OK");
+        handler.handle("{}");
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["service:test,env:prod"], 0.5);
+    }
+}
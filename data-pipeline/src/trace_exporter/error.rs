@@ -0,0 +1,123 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use hyper::StatusCode;
+use std::error::Error;
+use std::fmt;
+
+/// Errors returned by the TraceExporter when traces couldn't be sent to the agent.
+#[derive(Debug)]
+pub enum TraceExporterError {
+    /// The traces couldn't be deserialized from the input payload.
+    Deserialization(rmp_serde::decode::Error),
+    /// The traces couldn't be serialized in the output format.
+    Serialization(String),
+    /// The request couldn't be sent to the agent or the response couldn't be read.
+    Network(String),
+    /// The request to the agent timed out.
+    Timeout,
+    /// The agent answered with an unexpected HTTP status, the request may succeed if retried.
+    HttpStatus { status: StatusCode, body: String },
+    /// The agent rejected the payload, retrying the same payload won't succeed.
+    AgentRejected { status: StatusCode, body: String },
+    /// The trace buffer is full, the traces have been dropped. Only in asynchronous mode.
+    BufferFull,
+    /// The trace buffer has been closed, the traces have been dropped. Only in asynchronous mode.
+    BufferClosed,
+}
+
+impl TraceExporterError {
+    /// Return the error matching an unsuccessful response from the agent.
+    ///
+    /// Client errors mean the agent refused the payload itself, except for timeouts and rate
+    /// limiting which are transient.
+    pub(crate) fn from_status(status: StatusCode, body: String) -> Self {
+        if status.is_client_error()
+            && status != StatusCode::REQUEST_TIMEOUT
+            && status != StatusCode::TOO_MANY_REQUESTS
+        {
+            TraceExporterError::AgentRejected { status, body }
+        } else {
+            TraceExporterError::HttpStatus { status, body }
+        }
+    }
+}
+
+impl fmt::Display for TraceExporterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceExporterError::Deserialization(err) => {
+                write!(f, "Error deserializing traces: {err}")
+            }
+            TraceExporterError::Serialization(err) => write!(f, "Error serializing traces: {err}"),
+            TraceExporterError::Network(err) => write!(f, "Error sending traces: {err}"),
+            TraceExporterError::Timeout => write!(f, "Request to the agent timed out"),
+            TraceExporterError::HttpStatus { status, body } => {
+                write!(f, "Agent responded with status {status}: {body}")
+            }
+            TraceExporterError::AgentRejected { status, body } => {
+                write!(f, "Agent rejected traces with status {status}: {body}")
+            }
+            TraceExporterError::BufferFull => write!(f, "Trace buffer is full, traces dropped"),
+            TraceExporterError::BufferClosed => {
+                write!(f, "Trace buffer is closed, traces dropped")
+            }
+        }
+    }
+}
+
+impl Error for TraceExporterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TraceExporterError::Deserialization(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rmp_serde::decode::Error> for TraceExporterError {
+    fn from(err: rmp_serde::decode::Error) -> Self {
+        TraceExporterError::Deserialization(err)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for TraceExporterError {
+    fn from(err: rmp_serde::encode::Error) -> Self {
+        TraceExporterError::Serialization(err.to_string())
+    }
+}
+
+impl From<hyper::Error> for TraceExporterError {
+    fn from(err: hyper::Error) -> Self {
+        if err.is_timeout() {
+            TraceExporterError::Timeout
+        } else {
+            TraceExporterError::Network(err.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        assert!(matches!(
+            TraceExporterError::from_status(StatusCode::BAD_REQUEST, String::new()),
+            TraceExporterError::AgentRejected { .. }
+        ));
+        assert!(matches!(
+            TraceExporterError::from_status(StatusCode::PAYLOAD_TOO_LARGE, String::new()),
+            TraceExporterError::AgentRejected { .. }
+        ));
+        assert!(matches!(
+            TraceExporterError::from_status(StatusCode::TOO_MANY_REQUESTS, String::new()),
+            TraceExporterError::HttpStatus { .. }
+        ));
+        assert!(matches!(
+            TraceExporterError::from_status(StatusCode::SERVICE_UNAVAILABLE, String::new()),
+            TraceExporterError::HttpStatus { .. }
+        ));
+    }
+}
@@ -0,0 +1,1107 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+pub(crate) mod agent_response;
+mod error;
+
+pub use agent_response::{AgentResponse, ResponseCallback, SamplingRatesCallback};
+pub use error::TraceExporterError;
+
+use crate::sampler::{PrioritySampler, SamplingRule};
+use crate::span_concentrator::{SpanConcentrator, DEFAULT_SPAN_KINDS_STATS_COMPUTED};
+use crate::stats_exporter::{self, LibraryMetadata, StatsExporter};
+use crate::trace_buffer::{TraceBuffer, TraceBufferConfig};
+use agent_response::ResponseHandler;
+use bytes::Bytes;
+use datadog_trace_normalization::normalizer;
+use datadog_trace_protobuf::pb;
+use datadog_trace_protobuf::span_bytes::SpanBytes;
+use datadog_trace_utils::send_data::send_data_result::ResponseStatusError;
+use datadog_trace_utils::trace_utils::{self, SendData, SendDataResult, TracerHeaderTags};
+use datadog_trace_utils::tracer_payload::TraceEncoding;
+use datadog_trace_utils::{msgpack_v04, msgpack_v05};
+use ddcommon::{connector, Endpoint, HttpClient};
+use hyper::http::uri::PathAndQuery;
+use hyper::{Body, Client, Method, StatusCode, Uri};
+use log::error;
+use std::mem::ManuallyDrop;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use std::{borrow::Borrow, collections::HashMap, str::FromStr};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Header set on trace payloads when the stats have been computed by the exporter
+const DATADOG_CLIENT_COMPUTED_STATS: &str = "Datadog-Client-Computed-Stats";
+/// Maximum time spent flushing the remaining stats and traces when the exporter is dropped
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// TraceExporterInputFormat represents the format of the input traces.
+/// The input format can be either Proxy, V0.4 or V0.5, where V0.4 is the default.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub enum TraceExporterInputFormat {
+    /// Proxy format is used when the traces are to be sent to the agent without processing them.
+    /// The whole payload is sent as is to the agent.
+    Proxy,
+    #[default]
+    V04,
+    V05,
+}
+
+/// TraceExporterOutputFormat represents the format of the output traces.
+/// The output format can be either V0.4, V0.5 or v0.7, where V0.4 is the default.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C)]
+pub enum TraceExporterOutputFormat {
+    #[default]
+    V04,
+    V07,
+    V05,
+}
+
+impl TraceExporterOutputFormat {
+    /// Add the agent trace endpoint path to the URL.
+    fn add_path(&self, url: &Uri) -> Uri {
+        add_path(
+            url,
+            match self {
+                TraceExporterOutputFormat::V04 => "/v0.4/traces",
+                TraceExporterOutputFormat::V07 => "/v0.7/traces",
+                TraceExporterOutputFormat::V05 => "/v0.5/traces",
+            },
+        )
+    }
+}
+
+/// Add a path to the URL.
+///
+/// # Arguments
+///
+/// * `url` - The URL to which the path is to be added.
+/// * `path` - The path to be added to the URL.
+fn add_path(url: &Uri, path: &str) -> Uri {
+    let p_and_q = url.path_and_query();
+    let new_p_and_q = match p_and_q {
+        Some(pq) => {
+            let mut p = pq.path().to_string();
+            if p.ends_with('/') {
+                p.pop();
+            }
+            p.push_str(path);
+            PathAndQuery::from_str(p.as_str())
+        }
+        None => PathAndQuery::from_str(path),
+    }
+    .unwrap();
+    let mut parts = url.clone().into_parts();
+    parts.path_and_query = Some(new_p_and_q);
+    Uri::from_parts(parts).unwrap()
+}
+
+struct TracerTags {
+    tracer_version: String,
+    language: String,
+    language_version: String,
+    language_interpreter: String,
+}
+
+impl<'a> From<&'a TracerTags> for TracerHeaderTags<'a> {
+    fn from(tags: &'a TracerTags) -> TracerHeaderTags<'a> {
+        TracerHeaderTags::<'_> {
+            lang: &tags.language,
+            lang_version: &tags.language_version,
+            tracer_version: &tags.tracer_version,
+            lang_interpreter: &tags.language_interpreter,
+            ..Default::default()
+        }
+    }
+}
+
+impl<'a> From<&'a TracerTags> for HashMap<&'static str, String> {
+    fn from(tags: &'a TracerTags) -> HashMap<&'static str, String> {
+        TracerHeaderTags::<'_> {
+            lang: &tags.language,
+            lang_version: &tags.language_version,
+            tracer_version: &tags.tracer_version,
+            lang_interpreter: &tags.language_interpreter,
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// Handle on the stats computation running alongside the exporter
+struct StatsComputation {
+    concentrator: Arc<Mutex<SpanConcentrator>>,
+    cancellation_token: CancellationToken,
+    exporter_handle: JoinHandle<()>,
+}
+
+pub struct TraceExporter {
+    endpoint: Endpoint,
+    tags: TracerTags,
+    input_format: TraceExporterInputFormat,
+    output_format: TraceExporterOutputFormat,
+    response_handler: Arc<ResponseHandler>,
+    /// Taken when the exporter is dropped, to shut it down without blocking in async contexts.
+    runtime: ManuallyDrop<Runtime>,
+    client: HttpClient,
+    stats: Option<StatsComputation>,
+    buffer: Option<TraceBuffer>,
+    sampler: Option<Arc<PrioritySampler>>,
+}
+
+impl TraceExporter {
+    pub fn builder() -> TraceExporterBuilder {
+        TraceExporterBuilder::default()
+    }
+
+    /// Send traces to the agent and return the body of the agent response.
+    ///
+    /// In asynchronous mode the traces are only buffered and an empty JSON object is returned,
+    /// the agent responses are only delivered to the registered callbacks.
+    pub fn send(&self, data: &[u8], trace_count: usize) -> Result<String, TraceExporterError> {
+        match self.input_format {
+            TraceExporterInputFormat::Proxy => self.send_proxy(data, trace_count),
+            TraceExporterInputFormat::V04 | TraceExporterInputFormat::V05 => {
+                self.send_deser_ser(data)
+            }
+        }
+    }
+
+    /// Send all the traces buffered by the asynchronous mode and wait for the requests to
+    /// complete. Does nothing if the asynchronous mode is not enabled.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        match &self.buffer {
+            Some(buffer) => self.runtime.block_on(buffer.flush()),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the exporter, sending the buffered traces and the remaining stats.
+    ///
+    /// Returns an error if everything couldn't be sent within `timeout`.
+    pub fn shutdown(mut self, timeout: Duration) -> anyhow::Result<()> {
+        let buffer = self.buffer.take();
+        let stats = self.stats.take();
+        self.runtime.block_on(async {
+            tokio::time::timeout(timeout, async {
+                if let Some(stats) = stats {
+                    stats.cancellation_token.cancel();
+                    stats.exporter_handle.await?;
+                }
+                if let Some(buffer) = buffer {
+                    buffer.shutdown().await?;
+                }
+                anyhow::Ok(())
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("Shutdown timed out after {timeout:?}"))?
+        })
+    }
+
+    fn send_proxy(&self, data: &[u8], trace_count: usize) -> Result<String, TraceExporterError> {
+        self.send_data_to_url(
+            data,
+            trace_count,
+            self.output_format.add_path(&self.endpoint.url),
+        )
+    }
+
+    fn send_data_to_url(
+        &self,
+        data: &[u8],
+        trace_count: usize,
+        uri: Uri,
+    ) -> Result<String, TraceExporterError> {
+        self.runtime.block_on(async {
+            let mut req_builder = hyper::Request::builder()
+                .uri(uri)
+                .header(
+                    hyper::header::USER_AGENT,
+                    concat!("Tracer/", env!("CARGO_PKG_VERSION")),
+                )
+                .method(Method::POST);
+
+            let headers: HashMap<&'static str, String> = self.tags.borrow().into();
+
+            for (key, value) in &headers {
+                req_builder = req_builder.header(*key, value);
+            }
+            req_builder = req_builder
+                .header("Content-type", "application/msgpack")
+                .header("X-Datadog-Trace-Count", trace_count.to_string().as_str());
+            if self.stats.is_some() {
+                req_builder = req_builder.header(DATADOG_CLIENT_COMPUTED_STATS, "true");
+            }
+            let req = req_builder
+                .body(Body::from(Bytes::copy_from_slice(data)))
+                .unwrap();
+
+            let response = self.client.request(req).await?;
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let body = String::from_utf8_lossy(&body).to_string();
+            if !status.is_success() {
+                return Err(TraceExporterError::from_status(status, body));
+            }
+            self.response_handler.handle(&body);
+            Ok(body)
+        })
+    }
+
+    /// Return true if the payload is sent as is, in which case the traces are only decoded to be
+    /// validated and counted.
+    fn forwards_payload(&self) -> bool {
+        self.input_format == TraceExporterInputFormat::V04
+            && self.sampler.is_none()
+            && self.stats.is_none()
+            && self.buffer.is_none()
+            && self.output_format == TraceExporterOutputFormat::V04
+    }
+
+    fn send_deser_ser(&self, data: &[u8]) -> Result<String, TraceExporterError> {
+        let size = data.len();
+        let mut traces: Vec<Vec<SpanBytes>> = match self.input_format {
+            TraceExporterInputFormat::V05 => msgpack_v05::decode(data)?
+                .into_iter()
+                .map(|trace| trace.into_iter().map(SpanBytes::from).collect())
+                .collect(),
+            _ => {
+                // SAFETY: the decoded spans don't outlive this call. They are re-encoded or
+                // converted to owned spans before being sent or buffered, and the request bodies
+                // are copied.
+                let payload: &'static [u8] = unsafe { std::mem::transmute(data) };
+                msgpack_v04::decode(Bytes::from_static(payload))?
+            }
+        };
+
+        if traces.is_empty() {
+            error!("No traces deserialized from the request body.");
+            return Ok(String::from("{}"));
+        }
+
+        if self.forwards_payload() {
+            return self.send_data_to_url(
+                data,
+                traces.len(),
+                self.output_format.add_path(&self.endpoint.url),
+            );
+        }
+
+        for trace in traces.iter_mut() {
+            if let Err(err) = normalizer::normalize_trace_bytes(trace) {
+                error!("Error normalizing trace: {err}");
+            }
+        }
+
+        if let Some(sampler) = &self.sampler {
+            for trace in traces.iter_mut() {
+                sampler.sample(trace);
+            }
+        }
+
+        if let Some(stats) = &self.stats {
+            add_spans_to_stats(&stats.concentrator, &mut traces);
+        }
+
+        let mut header_tags: TracerHeaderTags<'_> = (&self.tags).into();
+        header_tags.client_computed_stats = self.stats.is_some();
+
+        if let Some(buffer) = &self.buffer {
+            let tracer_payload = trace_utils::collect_trace_chunks(
+                into_owned_traces(traces),
+                &header_tags,
+                |_chunk, _root_span_index| {},
+                self.endpoint.api_key.is_some(),
+                match self.output_format {
+                    TraceExporterOutputFormat::V04 => TraceEncoding::V04,
+                    TraceExporterOutputFormat::V07 => TraceEncoding::V07,
+                    TraceExporterOutputFormat::V05 => TraceEncoding::V05,
+                },
+            );
+            let endpoint = Endpoint {
+                url: self.output_format.add_path(&self.endpoint.url),
+                ..self.endpoint.clone()
+            };
+            let send_data = SendData::new(size, tracer_payload, header_tags, &endpoint);
+            buffer.push(send_data)?;
+            return Ok(String::from("{}"));
+        }
+
+        let trace_count = traces.len();
+        match self.output_format {
+            TraceExporterOutputFormat::V04 => self.send_data_to_url(
+                &msgpack_v04::encode(&traces)?,
+                trace_count,
+                self.output_format.add_path(&self.endpoint.url),
+            ),
+            TraceExporterOutputFormat::V05 => self.send_data_to_url(
+                &msgpack_v05::encode(&into_owned_traces(traces))?,
+                trace_count,
+                self.output_format.add_path(&self.endpoint.url),
+            ),
+            TraceExporterOutputFormat::V07 => {
+                let tracer_payload = trace_utils::collect_trace_chunks(
+                    into_owned_traces(traces),
+                    &header_tags,
+                    |_chunk, _root_span_index| {},
+                    self.endpoint.api_key.is_some(),
+                    TraceEncoding::V07,
+                );
+
+                let endpoint = Endpoint {
+                    url: self.output_format.add_path(&self.endpoint.url),
+                    ..self.endpoint.clone()
+                };
+                let send_data = SendData::new(size, tracer_payload, header_tags, &endpoint);
+                self.runtime.block_on(async {
+                    let result = send_data.send_with_client(&self.client).await;
+                    let response = response_from_send_data_result(result)?;
+                    let body = hyper::body::to_bytes(response.into_body()).await?;
+                    let body = String::from_utf8_lossy(&body).to_string();
+                    self.response_handler.handle(&body);
+                    Ok(body)
+                })
+            }
+        }
+    }
+}
+
+impl Drop for TraceExporter {
+    /// Stop the stats computation and the trace buffer, giving them a chance to send the remaining
+    /// stats and traces. Use [`TraceExporter::shutdown`] to control the timeout.
+    ///
+    /// Waiting for them would panic when the exporter is dropped from an async context, in which
+    /// case the remaining stats and traces are dropped. Call [`TraceExporter::shutdown`] from a
+    /// blocking context before dropping the exporter to send them.
+    fn drop(&mut self) {
+        // SAFETY: the runtime is not used after this point.
+        let runtime = unsafe { ManuallyDrop::take(&mut self.runtime) };
+        if tokio::runtime::Handle::try_current().is_ok() {
+            if let Some(stats) = self.stats.take() {
+                stats.cancellation_token.cancel();
+            }
+            if self.buffer.take().is_some() {
+                error!("TraceExporter dropped from an async context, buffered traces are dropped");
+            }
+            // Dropping the runtime would block on its worker threads.
+            runtime.shutdown_background();
+            return;
+        }
+        if let Some(stats) = self.stats.take() {
+            stats.cancellation_token.cancel();
+            let _ = runtime.block_on(async {
+                tokio::time::timeout(SHUTDOWN_TIMEOUT, stats.exporter_handle).await
+            });
+        }
+        if let Some(buffer) = self.buffer.take() {
+            let _ = runtime.block_on(async {
+                tokio::time::timeout(SHUTDOWN_TIMEOUT, buffer.shutdown()).await
+            });
+        }
+    }
+}
+
+/// Return the response of a SendDataResult, or the error matching its failure.
+///
+/// Unsuccessful responses are reported as a [`ResponseStatusError`] holding the status and body
+/// of the agent response. Other errors are only reported as a message, their kind is inferred
+/// from the request counters.
+fn response_from_send_data_result(
+    result: SendDataResult,
+) -> Result<hyper::Response<Body>, TraceExporterError> {
+    let SendDataResult {
+        last_result,
+        requests_count,
+        errors_timeout,
+        ..
+    } = result;
+    last_result.map_err(|err| match err.downcast::<ResponseStatusError>() {
+        Ok(ResponseStatusError { status, body }) => TraceExporterError::from_status(
+            StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            body,
+        ),
+        // The payload failed to be serialized before any request was made
+        Err(err) if requests_count == 0 => TraceExporterError::Serialization(err.to_string()),
+        Err(_) if errors_timeout > 0 => TraceExporterError::Timeout,
+        Err(err) => TraceExporterError::Network(err.to_string()),
+    })
+}
+
+/// Copy the strings of the spans, for the traces outliving the payload or encoded from protobuf
+/// spans.
+fn into_owned_traces(traces: Vec<Vec<SpanBytes>>) -> Vec<Vec<pb::Span>> {
+    traces
+        .into_iter()
+        .map(|trace| trace.into_iter().map(pb::Span::from).collect())
+        .collect()
+}
+
+/// Add all the spans of the traces to the concentrator.
+///
+/// The top level spans are computed before aggregation since tracers are not required to mark
+/// them.
+fn add_spans_to_stats(concentrator: &Mutex<SpanConcentrator>, traces: &mut [Vec<SpanBytes>]) {
+    let mut concentrator = concentrator.lock().unwrap();
+    for trace in traces.iter_mut() {
+        trace_utils::compute_top_level_span_bytes(trace);
+        for span in trace.iter() {
+            concentrator.add_span(span);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TraceExporterBuilder {
+    url: Option<String>,
+    tracer_version: String,
+    language: String,
+    language_version: String,
+    language_interpreter: String,
+    hostname: String,
+    env: String,
+    app_version: String,
+    service: String,
+    input_format: TraceExporterInputFormat,
+    output_format: TraceExporterOutputFormat,
+    response_callback: Option<Box<dyn ResponseCallback>>,
+    rates_callback: Option<Box<dyn SamplingRatesCallback>>,
+    stats_bucket_size: Option<Duration>,
+    peer_tag_keys: Vec<String>,
+    buffer_config: Option<TraceBufferConfig>,
+    sampling_rules: Option<Vec<SamplingRule>>,
+}
+
+impl TraceExporterBuilder {
+    pub fn set_url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_owned());
+        self
+    }
+
+    pub fn set_tracer_version(mut self, tracer_version: &str) -> Self {
+        tracer_version.clone_into(&mut self.tracer_version);
+        self
+    }
+
+    pub fn set_language(mut self, lang: &str) -> Self {
+        lang.clone_into(&mut self.language);
+        self
+    }
+
+    pub fn set_language_version(mut self, lang_version: &str) -> Self {
+        lang_version.clone_into(&mut self.language_version);
+        self
+    }
+
+    pub fn set_language_interpreter(mut self, lang_interpreter: &str) -> Self {
+        lang_interpreter.clone_into(&mut self.language_interpreter);
+        self
+    }
+
+    pub fn set_hostname(mut self, hostname: &str) -> Self {
+        hostname.clone_into(&mut self.hostname);
+        self
+    }
+
+    pub fn set_env(mut self, env: &str) -> Self {
+        env.clone_into(&mut self.env);
+        self
+    }
+
+    pub fn set_app_version(mut self, app_version: &str) -> Self {
+        app_version.clone_into(&mut self.app_version);
+        self
+    }
+
+    pub fn set_service(mut self, service: &str) -> Self {
+        service.clone_into(&mut self.service);
+        self
+    }
+
+    pub fn set_input_format(mut self, input_format: TraceExporterInputFormat) -> Self {
+        self.input_format = input_format;
+        self
+    }
+
+    pub fn set_output_format(mut self, output_format: TraceExporterOutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Set the callback called with the body of each successful agent response
+    pub fn set_response_callback(mut self, response_callback: Box<dyn ResponseCallback>) -> Self {
+        self.response_callback = Some(response_callback);
+        self
+    }
+
+    /// Set the callback called with the `rate_by_service` sampling rates of the agent responses
+    pub fn set_sampling_rates_callback(
+        mut self,
+        rates_callback: Box<dyn SamplingRatesCallback>,
+    ) -> Self {
+        self.rates_callback = Some(rates_callback);
+        self
+    }
+
+    /// Enable the computation of stats by the exporter.
+    ///
+    /// Spans are aggregated in buckets of `bucket_size` and the stats are flushed to the agent
+    /// every `bucket_size`. The traces are then sent with the `Datadog-Client-Computed-Stats`
+    /// header so the agent doesn't compute the stats again. Stats computation requires the traces
+    /// to be deserialized and is not available with the Proxy input format.
+    pub fn enable_stats(mut self, bucket_size: Duration) -> Self {
+        self.stats_bucket_size = Some(bucket_size);
+        self
+    }
+
+    /// Set the keys of the span tags used as peer tags in the stats aggregation
+    pub fn set_peer_tag_keys(mut self, peer_tag_keys: Vec<String>) -> Self {
+        self.peer_tag_keys = peer_tag_keys;
+        self
+    }
+
+    /// Enable the asynchronous mode of the exporter.
+    ///
+    /// In asynchronous mode `send` doesn't wait for the agent: traces are pushed into a bounded
+    /// buffer and sent in batches by a background task according to `config`. Traces are dropped
+    /// when the buffer is full. The agent response is not returned to the caller. Use
+    /// [`TraceExporter::flush`] and [`TraceExporter::shutdown`] to make sure the buffered traces
+    /// are sent. Not available with the Proxy input format.
+    pub fn enable_async_mode(mut self, config: TraceBufferConfig) -> Self {
+        self.buffer_config = Some(config);
+        self
+    }
+
+    /// Enable the priority sampling of traces by the exporter.
+    ///
+    /// The sampling priority of traces without one is set by the first matching rule of
+    /// `sampling_rules`, or by the rates sent by the agent if none matches. Sampling requires the
+    /// traces to be deserialized and is not available with the Proxy input format.
+    pub fn enable_sampling(mut self, sampling_rules: Vec<SamplingRule>) -> Self {
+        self.sampling_rules = Some(sampling_rules);
+        self
+    }
+
+    pub fn build(mut self) -> anyhow::Result<TraceExporter> {
+        let agent_url = self
+            .url
+            .to_owned()
+            .unwrap_or(String::from("http://127.0.0.1:8126/"));
+        let endpoint = Endpoint {
+            url: hyper::Uri::from_str(agent_url.as_str())?,
+            api_key: None,
+        };
+        if self.buffer_config.is_some() && self.input_format == TraceExporterInputFormat::Proxy {
+            anyhow::bail!("Asynchronous mode is not supported with the Proxy input format");
+        }
+        if self.sampling_rules.is_some() && self.input_format == TraceExporterInputFormat::Proxy {
+            anyhow::bail!("Sampling is not supported with the Proxy input format");
+        }
+        // Stats and buffered traces are flushed in the background so the runtime needs its own
+        // worker thread
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        // The client is shared by all the requests so connections to the agent can be reused
+        let client: HttpClient = Client::builder().build(connector::Connector::default());
+        let sampler = self
+            .sampling_rules
+            .take()
+            .map(|rules| Arc::new(PrioritySampler::new(rules)));
+        let response_handler = Arc::new(ResponseHandler {
+            response_callback: self.response_callback.take(),
+            rates_callback: self.rates_callback.take(),
+            sampler: sampler.clone(),
+        });
+        let buffer = self.buffer_config.take().map(|config| {
+            TraceBuffer::start(config, &runtime, client.clone(), response_handler.clone())
+        });
+
+        let stats = match self.stats_bucket_size {
+            Some(bucket_size) => {
+                if self.input_format == TraceExporterInputFormat::Proxy {
+                    anyhow::bail!("Stats computation is not supported with the Proxy input format");
+                }
+                let concentrator = Arc::new(Mutex::new(SpanConcentrator::new(
+                    bucket_size,
+                    SystemTime::now(),
+                    DEFAULT_SPAN_KINDS_STATS_COMPUTED
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                    std::mem::take(&mut self.peer_tag_keys),
+                )));
+                let cancellation_token = CancellationToken::new();
+                let stats_exporter = StatsExporter::new(
+                    bucket_size,
+                    concentrator.clone(),
+                    LibraryMetadata {
+                        hostname: self.hostname.clone(),
+                        env: self.env.clone(),
+                        version: self.app_version.clone(),
+                        lang: self.language.clone(),
+                        tracer_version: self.tracer_version.clone(),
+                        service: self.service.clone(),
+                        ..Default::default()
+                    },
+                    Endpoint {
+                        url: stats_exporter::stats_url_from_agent_url(&agent_url)?,
+                        api_key: None,
+                    },
+                    cancellation_token.clone(),
+                );
+                let exporter_handle = runtime.spawn(async move { stats_exporter.run().await });
+                Some(StatsComputation {
+                    concentrator,
+                    cancellation_token,
+                    exporter_handle,
+                })
+            }
+            None => None,
+        };
+
+        Ok(TraceExporter {
+            endpoint,
+            tags: TracerTags {
+                tracer_version: std::mem::take(&mut self.tracer_version),
+                language_version: std::mem::take(&mut self.language_version),
+                language_interpreter: std::mem::take(&mut self.language_interpreter),
+                language: std::mem::take(&mut self.language),
+            },
+            input_format: self.input_format,
+            output_format: self.output_format,
+            response_handler,
+            runtime: ManuallyDrop::new(runtime),
+            client,
+            stats,
+            buffer,
+            sampler,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use httpmock::MockServer;
+    use std::collections::HashMap;
+
+    #[test]
+    fn new() {
+        let builder = TraceExporterBuilder::default();
+        let exporter = builder
+            .set_url("http://192.168.1.1:8127/")
+            .set_tracer_version("v0.1")
+            .set_language("nodejs")
+            .set_language_version("1.0")
+            .set_language_interpreter("v8")
+            .set_input_format(TraceExporterInputFormat::Proxy)
+            .set_output_format(TraceExporterOutputFormat::V07)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            exporter
+                .output_format
+                .add_path(&exporter.endpoint.url)
+                .to_string(),
+            "http://192.168.1.1:8127/v0.7/traces"
+        );
+        assert_eq!(exporter.input_format, TraceExporterInputFormat::Proxy);
+        assert_eq!(exporter.tags.tracer_version, "v0.1");
+        assert_eq!(exporter.tags.language, "nodejs");
+        assert_eq!(exporter.tags.language_version, "1.0");
+        assert_eq!(exporter.tags.language_interpreter, "v8");
+    }
+
+    #[test]
+    fn new_defaults() {
+        let builder = TraceExporterBuilder::default();
+        let exporter = builder
+            .set_tracer_version("v0.1")
+            .set_language("nodejs")
+            .set_language_version("1.0")
+            .set_language_interpreter("v8")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            exporter
+                .output_format
+                .add_path(&exporter.endpoint.url)
+                .to_string(),
+            "http://127.0.0.1:8126/v0.4/traces"
+        );
+        assert_eq!(exporter.input_format, TraceExporterInputFormat::V04);
+        assert_eq!(exporter.tags.tracer_version, "v0.1");
+        assert_eq!(exporter.tags.language, "nodejs");
+        assert_eq!(exporter.tags.language_version, "1.0");
+        assert_eq!(exporter.tags.language_interpreter, "v8");
+    }
+
+    #[test]
+    fn new_with_stats_proxy_input() {
+        let result = TraceExporterBuilder::default()
+            .set_input_format(TraceExporterInputFormat::Proxy)
+            .enable_stats(Duration::from_secs(10))
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn new_with_sampling_proxy_input() {
+        let result = TraceExporterBuilder::default()
+            .set_input_format(TraceExporterInputFormat::Proxy)
+            .enable_sampling(vec![])
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_sampler_uses_agent_rates() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(200)
+                .body(r#"{"rate_by_service":{"service:,env:":0.0}}"#);
+        });
+
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .enable_sampling(vec![])
+            .build()
+            .unwrap();
+        exporter.send(&test_traces_payload(), 1).unwrap();
+
+        // The default rate sent by the agent drops all the traces
+        let mut trace = vec![SpanBytes {
+            service: "test".into(),
+            trace_id: 1,
+            span_id: 1,
+            ..Default::default()
+        }];
+        assert_eq!(
+            exporter.sampler.as_ref().unwrap().sample(&mut trace),
+            Some(crate::sampler::SamplingPriority::AutoReject)
+        );
+    }
+
+    #[test]
+    fn new_with_async_mode_proxy_input() {
+        let result = TraceExporterBuilder::default()
+            .set_input_format(TraceExporterInputFormat::Proxy)
+            .enable_async_mode(TraceBufferConfig::default())
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_async_mode_send_and_shutdown() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .header("Content-type", "application/msgpack")
+                .path("/v0.4/traces");
+            then.status(200).body("{}");
+        });
+
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .set_language("rust")
+            .enable_async_mode(TraceBufferConfig {
+                flush_interval: Duration::from_secs(3600),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+
+        let traces = vec![vec![pb::Span {
+            service: "test".to_string(),
+            name: "test".to_string(),
+            span_id: 1,
+            trace_id: 1,
+            ..Default::default()
+        }]];
+        let data = rmp_serde::to_vec_named(&traces).unwrap();
+
+        exporter.send(&data, 1).unwrap();
+        exporter.send(&data, 1).unwrap();
+        exporter.flush().unwrap();
+        // Both payloads are coalesced in a single request
+        mock.assert_hits(1);
+
+        exporter.send(&data, 1).unwrap();
+        exporter.shutdown(Duration::from_secs(5)).unwrap();
+        mock.assert_hits(2);
+    }
+
+    #[test]
+    fn test_flush_sync_mode() {
+        let exporter = TraceExporterBuilder::default().build().unwrap();
+        assert!(exporter.flush().is_ok());
+        assert!(exporter.shutdown(Duration::from_secs(1)).is_ok());
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn test_drop_in_async_context() {
+        let exporter = TraceExporterBuilder::default()
+            .enable_stats(Duration::from_secs(10))
+            .enable_async_mode(TraceBufferConfig::default())
+            .build()
+            .unwrap();
+        drop(exporter);
+    }
+
+    struct TestRatesCallback(Arc<Mutex<Vec<HashMap<String, f64>>>>);
+
+    impl SamplingRatesCallback for TestRatesCallback {
+        fn call(&self, rates: &HashMap<String, f64>) {
+            self.0.lock().unwrap().push(rates.clone());
+        }
+    }
+
+    fn test_traces_payload() -> Vec<u8> {
+        let traces = vec![vec![pb::Span {
+            service: "test".to_string(),
+            name: "test".to_string(),
+            span_id: 1,
+            trace_id: 1,
+            ..Default::default()
+        }]];
+        rmp_serde::to_vec_named(&traces).unwrap()
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_send_delivers_sampling_rates() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(200)
+                .body(r#"{"rate_by_service":{"service:test,env:prod":0.5}}"#);
+        });
+
+        let rates = Arc::new(Mutex::new(vec![]));
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .set_sampling_rates_callback(Box::new(TestRatesCallback(rates.clone())))
+            .build()
+            .unwrap();
+
+        let response = exporter.send(&test_traces_payload(), 1).unwrap();
+        assert_eq!(
+            response,
+            r#"{"rate_by_service":{"service:test,env:prod":0.5}}"#
+        );
+        let rates = rates.lock().unwrap();
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0]["service:test,env:prod"], 0.5);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_send_errors() {
+        let server = MockServer::start();
+        let mut mock = server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(413).body("payload too large");
+        });
+
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .build()
+            .unwrap();
+
+        match exporter.send(&test_traces_payload(), 1) {
+            Err(TraceExporterError::AgentRejected { status, body }) => {
+                assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+                assert_eq!(body, "payload too large");
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+
+        mock.delete();
+        server.mock(|when, then| {
+            when.method(POST).path("/v0.4/traces");
+            then.status(503);
+        });
+        assert!(matches!(
+            exporter.send(&test_traces_payload(), 1),
+            Err(TraceExporterError::HttpStatus { .. })
+        ));
+
+        assert!(matches!(
+            exporter.send(b"invalid", 1),
+            Err(TraceExporterError::Deserialization(_))
+        ));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_send_v05_input() {
+        let server = MockServer::start();
+        // The traces are re-encoded in v0.4 where span fields are named
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v0.4/traces")
+                .body_contains("resource");
+            then.status(200).body("{}");
+        });
+
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .set_input_format(TraceExporterInputFormat::V05)
+            .build()
+            .unwrap();
+
+        let traces: Vec<Vec<pb::Span>> = rmp_serde::from_slice(&test_traces_payload()).unwrap();
+        let data = msgpack_v05::encode(&traces).unwrap();
+        exporter.send(&data, 1).unwrap();
+        mock.assert();
+
+        // v0.4 payloads are rejected
+        assert!(matches!(
+            exporter.send(&test_traces_payload(), 1),
+            Err(TraceExporterError::Deserialization(_))
+        ));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_send_v05_output() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .header("X-Datadog-Trace-Count", "1")
+                .path("/v0.5/traces");
+            then.status(200).body("{}");
+        });
+
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .set_output_format(TraceExporterOutputFormat::V05)
+            .build()
+            .unwrap();
+
+        exporter.send(&test_traces_payload(), 1).unwrap();
+        mock.assert();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_send_v07_error_keeps_agent_response() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/v0.7/traces");
+            then.status(400).body("invalid payload");
+        });
+
+        let exporter = TraceExporterBuilder::default()
+            .set_url(&server.url("/"))
+            .set_output_format(TraceExporterOutputFormat::V07)
+            .build()
+            .unwrap();
+
+        match exporter.send(&test_traces_payload(), 1) {
+            Err(TraceExporterError::AgentRejected { status, body }) => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(body, "invalid payload");
+            }
+            res => panic!("Unexpected result {res:?}"),
+        }
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn test_send_network_error() {
+        // Nothing listens on the discard port
+        let exporter = TraceExporterBuilder::default()
+            .set_url("http://127.0.0.1:9/")
+            .build()
+            .unwrap();
+        assert!(matches!(
+            exporter.send(&test_traces_payload(), 1),
+            Err(TraceExporterError::Network(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_spans_to_stats() {
+        let concentrator = Mutex::new(SpanConcentrator::new(
+            Duration::from_secs(10),
+            SystemTime::now(),
+            vec![],
+            vec![],
+        ));
+        let mut traces = vec![vec![
+            SpanBytes {
+                service: "test".into(),
+                name: "root".into(),
+                span_id: 1,
+                parent_id: 0,
+                ..Default::default()
+            },
+            SpanBytes {
+                service: "test".into(),
+                name: "child".into(),
+                span_id: 2,
+                parent_id: 1,
+                ..Default::default()
+            },
+        ]];
+
+        add_spans_to_stats(&concentrator, &mut traces);
+
+        // Only the root span is top level
+        let buckets = concentrator.lock().unwrap().flush(SystemTime::now(), true);
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].stats.len(), 1);
+        assert_eq!(buckets[0].stats[0].name, "root");
+        assert_eq!(buckets[0].stats[0].top_level_hits, 1);
+    }
+
+    #[test]
+    fn test_from_tracer_tags_to_tracer_header_tags() {
+        let tracer_tags = TracerTags {
+            tracer_version: "v0.1".to_string(),
+            language: "rust".to_string(),
+            language_version: "1.52.1".to_string(),
+            language_interpreter: "rustc".to_string(),
+        };
+
+        let tracer_header_tags: TracerHeaderTags = (&tracer_tags).into();
+
+        assert_eq!(tracer_header_tags.tracer_version, "v0.1");
+        assert_eq!(tracer_header_tags.lang, "rust");
+        assert_eq!(tracer_header_tags.lang_version, "1.52.1");
+        assert_eq!(tracer_header_tags.lang_interpreter, "rustc");
+    }
+
+    #[test]
+    fn test_from_tracer_tags_to_hashmap() {
+        let tracer_tags = TracerTags {
+            tracer_version: "v0.1".to_string(),
+            language: "rust".to_string(),
+            language_version: "1.52.1".to_string(),
+            language_interpreter: "rustc".to_string(),
+        };
+
+        let hashmap: HashMap<&'static str, String> = (&tracer_tags).into();
+
+        assert_eq!(hashmap.get("datadog-meta-tracer-version").unwrap(), "v0.1");
+        assert_eq!(hashmap.get("datadog-meta-lang").unwrap(), "rust");
+        assert_eq!(hashmap.get("datadog-meta-lang-version").unwrap(), "1.52.1");
+        assert_eq!(
+            hashmap.get("datadog-meta-lang-interpreter").unwrap(),
+            "rustc"
+        );
+    }
+}
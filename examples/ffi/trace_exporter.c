@@ -48,7 +48,16 @@ int main(int argc, char** argv)
     }
 
     ddog_ByteSlice buffer = { .ptr = NULL, .len=0 };
-    TRY(ddog_trace_exporter_send(trace_exporter, buffer, 0));
+    ddog_TraceExporterErrorCode error_code;
+    // Failing to send traces, for instance when no agent is running, is not fatal: the error code
+    // tells whether the traces may be sent again.
+    ddog_MaybeError send_error = ddog_trace_exporter_send(trace_exporter, buffer, 0, &error_code);
+    if (send_error.tag == DDOG_OPTION_ERROR_SOME_ERROR) {
+        ddog_CharSlice message = ddog_Error_message(&send_error.some);
+        fprintf(stderr, "Failed to send traces (error code %d): %.*s\n", (int)error_code,
+                (int)message.len, (char *)message.ptr);
+        ddog_MaybeError_drop(send_error);
+    }
 
     ddog_trace_exporter_free(trace_exporter);
 
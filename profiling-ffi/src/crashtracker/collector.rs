@@ -33,10 +33,9 @@ pub unsafe extern "C" fn ddog_prof_Crashtracker_shutdown() -> CrashtrackerResult
 /// This should be one of the first things done after a fork, to minimize the
 /// chance that a crash occurs between the fork, and this call.
 /// In particular, reset the counters that track the profiler state machine,
-/// and start a new receiver to collect data from this fork.
-/// NOTE: An alternative design would be to have a 1:many sidecar listening on a
-/// socket instead of 1:1 receiver listening on a pipe, but the only real
-/// advantage would be to have fewer processes in `ps -a`.
+/// and start a new receiver to collect data from this fork, unless the crash-tracker was
+/// initialized with a unix socket, in which case the receiver listening on it, e.g. the
+/// sidecar's, also receives the reports of this fork.
 ///
 /// # Preconditions
 ///     This function assumes that the crash-tracker has previously been
@@ -131,3 +130,32 @@ pub unsafe extern "C" fn ddog_prof_Crashtracker_init_with_receiver(
     .context("ddog_prof_Crashtracker_init failed")
     .into()
 }
+
+#[no_mangle]
+#[must_use]
+/// Initialize the crash-tracking infrastructure, sending the crash reports to the receiver
+/// listening on `socket_path`, e.g. the sidecar's, instead of spawning a receiver process.
+/// On Linux, a path which doesn't start with `/` or `.` is the name of an abstract socket.
+///
+/// # Preconditions
+///     None.
+/// # Safety
+///     Crash-tracking functions are not reentrant.
+///     No other crash-handler functions should be called concurrently.
+/// # Atomicity
+///     This function is not atomic. A crash during its execution may lead to
+///     unexpected crash-handling behaviour.
+pub unsafe extern "C" fn ddog_prof_Crashtracker_init_with_unix_socket(
+    config: CrashtrackerConfiguration,
+    socket_path: CharSlice,
+    metadata: CrashtrackerMetadata,
+) -> CrashtrackerResult {
+    (|| {
+        let config = config.try_into()?;
+        let socket_path = socket_path.try_to_utf8()?;
+        let metadata = metadata.try_into()?;
+        datadog_crashtracker::init_with_unix_socket(config, socket_path, metadata)
+    })()
+    .context("ddog_prof_Crashtracker_init_with_unix_socket failed")
+    .into()
+}
@@ -30,12 +30,37 @@ pub struct CrashtrackerReceiverConfig<'a> {
 pub struct CrashtrackerConfiguration<'a> {
     pub additional_files: Slice<'a, CharSlice<'a>>,
     pub create_alt_stack: bool,
+    /// Directories searched by the receiver for debug files, by build id. If empty,
+    /// /usr/lib/debug is searched.
+    pub debug_dirs: Slice<'a, CharSlice<'a>>,
+    /// Optional directory where the debug files downloaded from debuginfod are cached. Defaults
+    /// to dd-crashtracker-debuginfod in $XDG_CACHE_HOME or ~/.cache.
+    pub optional_debuginfod_cache_dir: CharSlice<'a>,
+    /// Optional debuginfod server queried for the debug files which aren't found locally.
+    pub optional_debuginfod_url: CharSlice<'a>,
     /// The endpoint to send the crash report to (can be a file://)
     ///
     /// If ProfilingEndpoint is left to a zero value (enum value for Agent + empty charslice),
     /// the crashtracker will infer the agent host from env variables.
     pub endpoint: ProfilingEndpoint<'a>,
+    /// Names of the environment variables of the crashed process to include in the report.
+    pub environment_variables: Slice<'a, CharSlice<'a>>,
+    /// Upper bound on the reports of the same crash uploaded per hour by the receivers of this
+    /// user on this host. 0 for no limit.
+    pub max_reports_per_hour: u32,
+    /// Whether to also attach a minidump of the crashed process to the report, on Linux. For
+    /// file endpoints, it is also written next to the report.
+    pub minidump: bool,
+    /// Whether to also report Rust panics, through a panic hook which calls the previous one.
+    /// Only the first panic of the process is reported.
+    pub report_panics: bool,
     pub resolve_frames: StacktraceCollection,
+    /// The signals to handle. If empty, SIGSEGV, SIGBUS, SIGABRT, SIGILL, SIGFPE and SIGSYS are
+    /// handled.
+    pub signals: Slice<'a, libc::c_int>,
+    /// Optional directory where crash reports are kept until they are uploaded. Reports which
+    /// couldn't be uploaded are retried when the crashtracker is next initialized.
+    pub optional_spool_dir: CharSlice<'a>,
     pub timeout_secs: u64,
     pub wait_for_receiver: bool,
 }
@@ -93,18 +118,50 @@ impl<'a> TryFrom<CrashtrackerConfiguration<'a>>
             vec
         };
         let create_alt_stack = value.create_alt_stack;
+        let debug_dirs = {
+            let mut vec = Vec::with_capacity(value.debug_dirs.len());
+            for x in value.debug_dirs.iter() {
+                vec.push(x.try_to_utf8()?.to_string());
+            }
+            vec
+        };
+        let debuginfod_cache_dir = option_from_char_slice(value.optional_debuginfod_cache_dir)?;
+        let debuginfod_url = option_from_char_slice(value.optional_debuginfod_url)?;
         let endpoint = unsafe { exporter::try_to_endpoint(value.endpoint).ok() };
+        let environment_variables = {
+            let mut vec = Vec::with_capacity(value.environment_variables.len());
+            for x in value.environment_variables.iter() {
+                vec.push(x.try_to_utf8()?.to_string());
+            }
+            vec
+        };
+        let max_reports_per_hour =
+            (value.max_reports_per_hour > 0).then_some(value.max_reports_per_hour);
+        let minidump = value.minidump;
+        let report_panics = value.report_panics;
         let resolve_frames = value.resolve_frames;
+        let signals = value.signals.iter().copied().collect();
+        let spool_dir = option_from_char_slice(value.optional_spool_dir)?;
         let timeout = Duration::from_secs(value.timeout_secs);
         let wait_for_receiver = value.wait_for_receiver;
-        Self::new(
+        Self {
             additional_files,
             create_alt_stack,
+            debug_dirs,
+            debuginfod_cache_dir,
+            debuginfod_url,
             endpoint,
+            environment_variables,
+            max_reports_per_hour,
+            minidump,
+            report_panics,
             resolve_frames,
+            signals,
+            spool_dir,
             timeout,
             wait_for_receiver,
-        )
+        }
+        .validate()
     }
 }
 
@@ -422,6 +479,10 @@ impl<'a> TryFrom<SigInfo<'a>> for datadog_crashtracker::SigInfo {
     fn try_from(value: SigInfo<'a>) -> Result<Self, Self::Error> {
         let signum = value.signum;
         let signame = option_from_char_slice(value.signame)?;
-        Ok(Self { signum, signame })
+        Ok(Self {
+            signum,
+            signame,
+            ..Default::default()
+        })
     }
 }
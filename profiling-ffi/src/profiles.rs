@@ -9,6 +9,7 @@ use datadog_profiling::internal::ProfiledEndpointsStats;
 use ddcommon_ffi::slice::{AsBytes, CharSlice, Slice};
 use ddcommon_ffi::Error;
 use std::num::NonZeroI64;
+use std::ptr::NonNull;
 use std::str::Utf8Error;
 use std::time::{Duration, SystemTime};
 
@@ -441,6 +442,80 @@ pub unsafe extern "C" fn ddog_prof_Profile_add(
     .into()
 }
 
+/// Enables live heap profiling, tracking up to `max_tracked` allocations at
+/// once with `ddog_prof_Profile_track_allocation`. Tracked allocations are
+/// kept across resets.
+///
+/// # Safety
+/// The `profile` ptr must point to a valid Profile object created by this
+/// module.
+/// This call is _NOT_ thread-safe.
+#[must_use]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_Profile_enable_live_heap(
+    profile: *mut Profile,
+    max_tracked: usize,
+) -> ProfileResult {
+    (|| {
+        let profile = profile_ptr_to_inner(profile)?;
+        profile.enable_live_heap(max_tracked);
+        anyhow::Ok(())
+    })()
+    .context("ddog_prof_Profile_enable_live_heap failed")
+    .into()
+}
+
+/// Tracks an allocation, keyed by `id` which is usually its address, until
+/// it's removed with `ddog_prof_Profile_untrack_allocation`. The `sample` is
+/// added to the profile each time it's serialized until then, upscaled by
+/// the upscaling rules of the profile. Allocations over the limit given to
+/// `ddog_prof_Profile_enable_live_heap` aren't tracked, and their number is
+/// reported in the comments of the serialized profile.
+///
+/// # Safety
+/// The `profile` ptr must point to a valid Profile object created by this
+/// module. All pointers inside the `sample` need to be valid for the duration
+/// of this call.
+/// This call is _NOT_ thread-safe.
+#[must_use]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_Profile_track_allocation(
+    profile: *mut Profile,
+    id: u64,
+    sample: Sample,
+) -> ProfileResult {
+    (|| {
+        let profile = profile_ptr_to_inner(profile)?;
+        let sample = sample.try_into()?;
+        profile.track_allocation(id, sample)
+    })()
+    .context("ddog_prof_Profile_track_allocation failed")
+    .into()
+}
+
+/// Stops tracking the allocation with the `id` given to
+/// `ddog_prof_Profile_track_allocation`, usually because it was freed.
+/// Untracking an allocation which isn't tracked does nothing.
+///
+/// # Safety
+/// The `profile` ptr must point to a valid Profile object created by this
+/// module.
+/// This call is _NOT_ thread-safe.
+#[must_use]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_Profile_untrack_allocation(
+    profile: *mut Profile,
+    id: u64,
+) -> ProfileResult {
+    (|| {
+        let profile = profile_ptr_to_inner(profile)?;
+        profile.untrack_allocation(id);
+        anyhow::Ok(())
+    })()
+    .context("ddog_prof_Profile_untrack_allocation failed")
+    .into()
+}
+
 unsafe fn profile_ptr_to_inner<'a>(
     profile_ptr: *mut Profile,
 ) -> anyhow::Result<&'a mut internal::Profile> {
@@ -609,6 +684,113 @@ pub unsafe extern "C" fn ddog_prof_Profile_add_upscaling_rule_proportional(
     .into()
 }
 
+/// What a profile does with new samples once it used up its memory budget,
+/// see `ddog_prof_Profile_set_memory_budget`. From then on, nothing new is
+/// interned, and the policy applies to the samples which would add interned
+/// data.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub enum BudgetPolicy {
+    /// Labels which aren't in the profile already are dropped from samples,
+    /// and samples with a new stack are dropped.
+    DropNewLabels,
+    /// Stacks which aren't in the profile already are collapsed into a
+    /// "[truncated]" frame, and samples with new labels are dropped.
+    TruncateStacks,
+    /// Samples which would add interned data are dropped.
+    DropSamples,
+}
+
+/// Limits the memory used by the functions, locations, mappings, stacks,
+/// labels and strings of the profile. Once it estimates to use `max_bytes`,
+/// the `policy` applies to new samples. The budget is kept across resets, and
+/// the number of dropped items is reported in the comments of the serialized
+/// profile.
+///
+/// # Arguments
+/// * `profile` - a reference to the profile that will contain the samples.
+/// * `max_bytes` - the budget in bytes.
+/// * `policy` - what to do with new samples once the budget is used up.
+///
+/// # Safety
+/// The `profile` ptr must point to a valid Profile object created by this
+/// module.
+/// This call is _NOT_ thread-safe.
+#[must_use]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_Profile_set_memory_budget(
+    profile: *mut Profile,
+    max_bytes: usize,
+    policy: BudgetPolicy,
+) -> ProfileResult {
+    (|| {
+        let profile = profile_ptr_to_inner(profile)?;
+        let policy = match policy {
+            BudgetPolicy::DropNewLabels => internal::BudgetPolicy::DropNewLabels,
+            BudgetPolicy::TruncateStacks => internal::BudgetPolicy::TruncateStacks,
+            BudgetPolicy::DropSamples => internal::BudgetPolicy::DropSamples,
+        };
+        profile.set_memory_budget(Some(internal::MemoryBudget { max_bytes, policy }));
+        anyhow::Ok(())
+    })()
+    .context("ddog_prof_Profile_set_memory_budget failed")
+    .into()
+}
+
+/// Creates a symbolizer for `ddog_prof_Profile_symbolize`. It caches the
+/// symbolized frames, so keep it and use it for each profile. Must call
+/// `ddog_prof_NativeSymbolizer_drop` when you are done with it.
+#[cfg(unix)]
+#[no_mangle]
+#[must_use]
+pub extern "C" fn ddog_prof_NativeSymbolizer_new() -> NonNull<internal::NativeSymbolizer> {
+    let symbolizer = Box::new(internal::NativeSymbolizer::new());
+    // Safety: Box::into_raw will always be non-null.
+    unsafe { NonNull::new_unchecked(Box::into_raw(symbolizer)) }
+}
+
+/// # Safety
+/// The `symbolizer` may be null, but if non-null the pointer must point to a
+/// valid symbolizer made by `ddog_prof_NativeSymbolizer_new` that has not
+/// already been dropped.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_NativeSymbolizer_drop(
+    symbolizer: Option<&mut internal::NativeSymbolizer>,
+) {
+    if let Some(reference) = symbolizer {
+        // Safety: symbolizers are opaque and therefore Boxed.
+        drop(Box::from_raw(reference as *mut _))
+    }
+}
+
+/// Symbolizes the native frames which were added with an address and a
+/// mapping but no function name, from the ELF files of their mappings. Call
+/// it right before serializing the profile, as the symbolized functions
+/// aren't kept when the profile is reset. Frames which can't be symbolized
+/// are serialized with their address only.
+///
+/// # Safety
+/// The `profile` ptr must point to a valid Profile object created by this
+/// module, and the `symbolizer` to a symbolizer made by
+/// `ddog_prof_NativeSymbolizer_new`.
+/// This call is _NOT_ thread-safe.
+#[cfg(unix)]
+#[must_use]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_Profile_symbolize(
+    profile: *mut Profile,
+    symbolizer: Option<&mut internal::NativeSymbolizer>,
+) -> ProfileResult {
+    (|| {
+        let profile = profile_ptr_to_inner(profile)?;
+        let symbolizer = symbolizer.context("symbolizer pointer was null")?;
+        profile.symbolize(symbolizer)
+    })()
+    .context("ddog_prof_Profile_symbolize failed")
+    .into()
+}
+
 unsafe fn add_upscaling_rule(
     profile: &mut internal::Profile,
     offset_values: Slice<usize>,
@@ -743,6 +925,106 @@ pub unsafe extern "C" fn ddog_prof_Profile_reset(
     .into()
 }
 
+/// Merges the samples and endpoints of `other` into `profile`. Samples with
+/// the same stack and labels in both profiles are aggregated. The upscaling
+/// rules and tracked allocations of `other` are not carried over.
+///
+/// `other` is consumed, even on failure: it must still be dropped with
+/// `ddog_prof_Profile_drop`, but can't be used for anything else.
+///
+/// # Safety
+/// The `profile` and `other` ptrs must point to distinct valid Profile
+/// objects created by this module.
+/// This call is _NOT_ thread-safe.
+#[must_use]
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_Profile_merge(
+    profile: *mut Profile,
+    other: *mut Profile,
+) -> ProfileResult {
+    (|| {
+        anyhow::ensure!(profile != other, "cannot merge a profile into itself");
+        let profile = profile_ptr_to_inner(profile)?;
+        let other = other
+            .as_mut()
+            .and_then(Profile::take)
+            .context("other profile pointer was null or already dropped")?;
+        profile.merge(*other)
+    })()
+    .context("ddog_prof_Profile_merge failed")
+    .into()
+}
+
+/// Creates the state of the delta computation of
+/// `ddog_prof_Profile_reset_and_return_delta`, where the values at the
+/// offsets of `cumulative_value_offsets` in the sample types are cumulative.
+/// Keep it and use it for each reset of the profile. Must call
+/// `ddog_prof_ProfileDelta_drop` when you are done with it.
+///
+/// # Safety
+/// The `cumulative_value_offsets` slice must have a pointer that is suitably
+/// aligned and the correct number of elements.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn ddog_prof_ProfileDelta_new(
+    cumulative_value_offsets: Slice<usize>,
+) -> NonNull<internal::ProfileDelta> {
+    let delta = Box::new(internal::ProfileDelta::new(
+        cumulative_value_offsets.as_slice(),
+    ));
+    // Safety: Box::into_raw will always be non-null.
+    NonNull::new_unchecked(Box::into_raw(delta))
+}
+
+/// # Safety
+/// The `delta` may be null, but if non-null the pointer must point to a valid
+/// delta made by `ddog_prof_ProfileDelta_new` that has not already been
+/// dropped.
+#[no_mangle]
+pub unsafe extern "C" fn ddog_prof_ProfileDelta_drop(delta: Option<&mut internal::ProfileDelta>) {
+    if let Some(reference) = delta {
+        // Safety: deltas are opaque and therefore Boxed.
+        drop(Box::from_raw(reference as *mut _))
+    }
+}
+
+/// Resets all data in `profile` except the sample types and period, like
+/// `ddog_prof_Profile_reset`, and returns the previous profile, where the
+/// cumulative values are replaced by their difference since the previous
+/// call with the same `delta`. The first call returns the values as-is.
+///
+/// Serialize the returned profile with `ddog_prof_Profile_serialize`, and
+/// drop it with `ddog_prof_Profile_drop` when you are done with it.
+///
+/// # Arguments
+/// * `profile` - A mutable reference to the profile to be reset.
+/// * `delta` - The delta computation made by `ddog_prof_ProfileDelta_new`.
+/// * `start_time` - The time of the profile (after reset). Pass None/null to use the current time.
+///
+/// # Safety
+/// The `profile` ptr must point to a valid Profile object created by this
+/// module, and the `delta` to a delta made by `ddog_prof_ProfileDelta_new`.
+/// If `start_time` is not null, it must point to a valid Timespec object.
+/// This call is _NOT_ thread-safe.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn ddog_prof_Profile_reset_and_return_delta(
+    profile: *mut Profile,
+    delta: Option<&mut internal::ProfileDelta>,
+    start_time: Option<&Timespec>,
+) -> ProfileNewResult {
+    match (|| {
+        let profile = profile_ptr_to_inner(profile)?;
+        let delta = delta.context("delta pointer was null")?;
+        profile.reset_and_return_delta(delta, start_time.map(SystemTime::from))
+    })()
+    .context("ddog_prof_Profile_reset_and_return_delta failed")
+    {
+        Ok(profile) => ProfileNewResult::Ok(Profile::new(profile)),
+        Err(err) => ProfileNewResult::Err(err.into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -761,6 +1043,46 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn symbolize() -> Result<(), Error> {
+        unsafe {
+            let sample_type: *const ValueType = &ValueType::new("samples", "count");
+            let mut profile = Result::from(ddog_prof_Profile_new(
+                Slice::from_raw_parts(sample_type, 1),
+                None,
+                None,
+            ))?;
+            let locations = vec![Location {
+                mapping: Mapping {
+                    memory_start: 0x1000,
+                    memory_limit: 0x2000,
+                    filename: "/nonexistent/libapp.so".into(),
+                    ..Default::default()
+                },
+                address: 0x1100,
+                ..Default::default()
+            }];
+            let values: Vec<i64> = vec![1];
+            let sample = Sample {
+                locations: Slice::from(&locations),
+                values: Slice::from(&values),
+                labels: Slice::empty(),
+            };
+            Result::from(ddog_prof_Profile_add(&mut profile, sample, None))?;
+
+            // The file of the mapping can't be read, so the frame keeps its
+            // address only.
+            let mut symbolizer = ddog_prof_NativeSymbolizer_new();
+            let result = ddog_prof_Profile_symbolize(&mut profile, Some(symbolizer.as_mut()));
+            ddog_prof_NativeSymbolizer_drop(Some(symbolizer.as_mut()));
+            Result::from(result)?;
+            Result::from(ddog_prof_Profile_symbolize(&mut profile, None)).unwrap_err();
+            ddog_prof_Profile_drop(&mut profile);
+            Ok(())
+        }
+    }
+
     #[test]
     fn add_failure() -> Result<(), Error> {
         unsafe {
@@ -937,4 +1259,46 @@ mod tests {
             ddog_prof_Profile_drop(&mut provide_distinct_locations_ffi());
         }
     }
+
+    #[test]
+    fn merge_and_delta() -> Result<(), Error> {
+        unsafe {
+            let mut profile = provide_distinct_locations_ffi();
+            let mut other = provide_distinct_locations_ffi();
+            Result::from(ddog_prof_Profile_merge(&mut profile, &mut other))?;
+            assert!(other.inner.is_null());
+            assert_eq!(
+                profile
+                    .inner
+                    .as_ref()
+                    .unwrap()
+                    .only_for_testing_num_aggregated_samples(),
+                2
+            );
+            Result::from(ddog_prof_Profile_merge(&mut profile, &mut other)).unwrap_err();
+            let profile_ptr: *mut Profile = &mut profile;
+            Result::from(ddog_prof_Profile_merge(profile_ptr, profile_ptr)).unwrap_err();
+            ddog_prof_Profile_drop(&mut other);
+
+            let offsets: Vec<usize> = vec![0];
+            let mut delta = ddog_prof_ProfileDelta_new(Slice::from(&offsets));
+            let mut previous = Result::from(ddog_prof_Profile_reset_and_return_delta(
+                &mut profile,
+                Some(delta.as_mut()),
+                None,
+            ))?;
+            assert_eq!(
+                previous
+                    .inner
+                    .as_ref()
+                    .unwrap()
+                    .only_for_testing_num_aggregated_samples(),
+                2
+            );
+            ddog_prof_Profile_drop(&mut previous);
+            ddog_prof_ProfileDelta_drop(Some(delta.as_mut()));
+            ddog_prof_Profile_drop(&mut profile);
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,105 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use criterion::*;
+use datadog_profiling::api;
+use datadog_profiling::internal::{Profile, ShardedProfile};
+use std::sync::{Barrier, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+const SAMPLES_PER_THREAD: usize = 1_000;
+const FUNCTIONS: [&str; 8] = [
+    "main", "run", "dispatch", "handle", "parse", "render", "write", "flush",
+];
+
+fn add_sample(thread: usize, i: usize, add: impl FnOnce(api::Sample)) {
+    // Vary the stacks and labels a little so samples aren't all aggregated
+    // into the same one.
+    let locations = (0..FUNCTIONS.len() - i % 4)
+        .map(|depth| api::Location {
+            function: api::Function {
+                name: FUNCTIONS[(depth + thread) % FUNCTIONS.len()],
+                filename: "app.rb",
+                ..Default::default()
+            },
+            line: depth as i64,
+            ..Default::default()
+        })
+        .collect();
+    add(api::Sample {
+        locations,
+        values: vec![1, 10_000],
+        labels: vec![api::Label {
+            key: "thread id",
+            num: thread as i64,
+            ..Default::default()
+        }],
+    });
+}
+
+/// Runs `add` for [SAMPLES_PER_THREAD] samples on each of `threads` threads
+/// at once, and returns the time it took.
+fn run_threads(threads: usize, add: impl Fn(api::Sample) + Sync) -> Duration {
+    let barrier = Barrier::new(threads + 1);
+    std::thread::scope(|scope| {
+        for thread in 0..threads {
+            let (barrier, add) = (&barrier, &add);
+            scope.spawn(move || {
+                barrier.wait();
+                for i in 0..SAMPLES_PER_THREAD {
+                    add_sample(thread, i, add);
+                }
+                barrier.wait();
+            });
+        }
+        barrier.wait();
+        let start = Instant::now();
+        barrier.wait();
+        start.elapsed()
+    })
+}
+
+pub fn add_samples_from_threads(c: &mut Criterion) {
+    let sample_types = [
+        api::ValueType::new("samples", "count"),
+        api::ValueType::new("wall-time", "nanoseconds"),
+    ];
+    let mut group = c.benchmark_group("add samples from threads");
+    for threads in [1, 4, 8] {
+        group.throughput(Throughput::Elements((threads * SAMPLES_PER_THREAD) as u64));
+        group.bench_with_input(
+            BenchmarkId::new("mutex", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    let profile = Mutex::new(Profile::new(SystemTime::now(), &sample_types, None));
+                    (0..iters)
+                        .map(|_| {
+                            run_threads(threads, |sample| {
+                                profile.lock().unwrap().add_sample(sample, None).unwrap()
+                            })
+                        })
+                        .sum()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("sharded", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    let profile =
+                        ShardedProfile::new(SystemTime::now(), &sample_types, None, threads);
+                    (0..iters)
+                        .map(|_| {
+                            run_threads(threads, |sample| profile.add_sample(sample, None).unwrap())
+                        })
+                        .sum()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, add_samples_from_threads);
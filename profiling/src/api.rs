@@ -74,6 +74,9 @@ pub struct Line<'a> {
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 pub struct Location<'a> {
     pub mapping: Mapping<'a>,
+    /// Native frames may leave the function empty and only set the address
+    /// and the mapping, to be symbolized later, see
+    /// [crate::internal::Profile::symbolize].
     pub function: Function<'a>,
 
     /// The instruction address for this location, if available.  It
@@ -195,7 +198,7 @@ pub struct Profile<'a> {
     pub start_time: SystemTime,
 }
 
-fn string_table_fetch(pprof: &pprof::Profile, id: i64) -> anyhow::Result<&String> {
+pub(crate) fn string_table_fetch(pprof: &pprof::Profile, id: i64) -> anyhow::Result<&String> {
     pprof
         .string_table
         .get(id as u64 as usize)
@@ -11,6 +11,13 @@ pub type FxIndexSet<K> = indexmap::IndexSet<K, BuildHasherDefault<rustc_hash::Fx
 
 pub use string_id::*;
 
+/// Returns an estimate of the bytes used by the `set` besides what its items
+/// point to. Each entry of the set holds the item and its hash, and its hash
+/// table holds an index and a control byte per entry.
+pub(crate) fn index_set_used_bytes<T, S>(set: &indexmap::IndexSet<T, S>) -> usize {
+    set.capacity() * (std::mem::size_of::<T>() + 2 * std::mem::size_of::<usize>() + 1)
+}
+
 pub trait Id: Copy + Eq + Hash {
     type RawId;
 
@@ -4,7 +4,7 @@
 #[allow(unused)]
 pub mod wordpress_test_data;
 
-use crate::collections::identifiable::{Id, StringId};
+use crate::collections::identifiable::{index_set_used_bytes, Id, StringId};
 use crate::iter::{IntoLendingIterator, LendingIterator};
 use datadog_alloc::{AllocError, Allocator, ChainAllocator, VirtualAllocator};
 use std::alloc::Layout;
@@ -117,6 +117,25 @@ impl StringTable {
         self.strings.len()
     }
 
+    /// Returns the [StringId] of the string, if the string table holds it.
+    #[inline]
+    pub fn find(&self, str: &str) -> Option<StringId> {
+        self.strings.get_index_of(str).map(StringId::from_offset)
+    }
+
+    /// Returns an estimate of the bytes used by the strings and the set
+    /// which indexes them.
+    pub fn used_bytes(&self) -> usize {
+        self.bytes.used_bytes() + index_set_used_bytes(&self.strings)
+    }
+
+    /// Returns the string with the given [StringId], if the string table
+    /// holds it.
+    #[inline]
+    pub fn get(&self, id: StringId) -> Option<&str> {
+        self.strings.get_index(id.to_offset()).copied()
+    }
+
     /// Adds the string to the string table if it isn't present already, and
     /// returns a [StringId] that corresponds to the order that this string
     /// was originally inserted.
@@ -69,6 +69,26 @@ pub fn agentless<AsStrRef: AsRef<str>, IntoCow: Into<Cow<'static, str>>>(
     })
 }
 
+/// Creates an Endpoint for sending profiles to an OTLP/HTTP receiver, such
+/// as an OpenTelemetry collector. Use it with [crate::exporter::ProfileExporter::build_otlp].
+///
+/// # Arguments
+/// * `base_url` - has protocol, host, and port e.g. http://localhost:4318/
+pub fn otlp(base_url: Uri) -> anyhow::Result<Endpoint> {
+    let mut parts = base_url.into_parts();
+    let p_q = match parts.path_and_query {
+        None => None,
+        Some(pq) => {
+            let path = pq.path();
+            let path = path.strip_suffix('/').unwrap_or(path);
+            Some(format!("{path}/v1development/profiles").parse()?)
+        }
+    };
+    parts.path_and_query = p_q;
+    let url = Uri::from_parts(parts)?;
+    Ok(Endpoint { url, api_key: None })
+}
+
 pub fn file(path: impl AsRef<str>) -> anyhow::Result<Endpoint> {
     let url: String = format!("file://{}", path.as_ref());
     Ok(Endpoint {
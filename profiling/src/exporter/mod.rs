@@ -8,9 +8,12 @@ use std::io::{Cursor, Write};
 use bytes::Bytes;
 pub use chrono::{DateTime, Utc};
 pub use ddcommon::tag::Tag;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 pub use hyper::Uri;
 use hyper_multipart_rfc7578::client::multipart;
 use lz4_flex::frame::FrameEncoder;
+use prost::Message;
 use serde_json::json;
 use tokio::runtime::Runtime;
 use tokio_util::sync::CancellationToken;
@@ -27,6 +30,7 @@ pub use connector::uds::{socket_path_from_uri, socket_path_to_uri};
 pub use connector::named_pipe::{named_pipe_path_from_uri, named_pipe_path_to_uri};
 
 use crate::internal::ProfiledEndpointsStats;
+use crate::otlp;
 
 const DURATION_ZERO: std::time::Duration = std::time::Duration::from_millis(0);
 
@@ -292,6 +296,57 @@ impl ProfileExporter {
         )
     }
 
+    /// Build a Request that posts the profiles to an OTLP/HTTP endpoint, see
+    /// [config::otlp], instead of using the Datadog intake format.
+    ///
+    /// The tags of the exporter and `additional_tags` become the attributes
+    /// of the resource, with the `service`, `env` and `version` tags renamed
+    /// to their OpenTelemetry semantic conventions. The profiling library is
+    /// the instrumentation scope.
+    pub fn build_otlp(
+        &self,
+        profiles: Vec<otlp::Profile>,
+        additional_tags: Option<&Vec<Tag>>,
+        timeout: std::time::Duration,
+    ) -> anyhow::Result<Request> {
+        let resource_attributes = self
+            .tags
+            .iter()
+            .chain(additional_tags)
+            .flatten()
+            .map(|tag| {
+                let (key, value) = tag.as_ref().split_once(':').unwrap_or((tag.as_ref(), ""));
+                let key = match key {
+                    "service" => "service.name",
+                    "env" => "deployment.environment",
+                    "version" => "service.version",
+                    key => key,
+                };
+                otlp::KeyValue::string(key, value)
+            })
+            .collect();
+        let scope = otlp::InstrumentationScope {
+            name: self.profiling_library_name.to_string(),
+            version: self.profiling_library_version.to_string(),
+            ..Default::default()
+        };
+        let data = otlp::ProfilesData::new(resource_attributes, scope, profiles);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data.encode_to_vec())?;
+        let body = encoder.finish()?;
+
+        let builder = self
+            .endpoint
+            .into_request_builder(concat!("DDProf/", env!("CARGO_PKG_VERSION")))?
+            .method(http::Method::POST)
+            .header("Connection", "close")
+            .header(http::header::CONTENT_TYPE, "application/x-protobuf")
+            .header(http::header::CONTENT_ENCODING, "gzip");
+
+        Ok(Request::from(builder.body(hyper::Body::from(body))?).with_timeout(timeout))
+    }
+
     pub fn send(
         &self,
         request: Request,
@@ -23,6 +23,13 @@ impl ProfiledEndpointsStats {
         *entry = entry.saturating_add(value);
     }
 
+    /// Adds the counts of `other` to the counts of the same endpoints.
+    pub fn merge(&mut self, other: ProfiledEndpointsStats) {
+        for (endpoint_name, value) in other.count {
+            self.add_endpoint_count(endpoint_name, value);
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.count.is_empty()
     }
@@ -47,3 +47,11 @@ impl Id for FunctionId {
         self.0.get().into()
     }
 }
+
+impl FunctionId {
+    #[inline]
+    pub fn to_offset(&self) -> usize {
+        // The id is the offset + 1, see [small_non_zero_pprof_id].
+        self.0.get() as usize - 1
+    }
+}
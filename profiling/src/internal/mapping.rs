@@ -61,3 +61,11 @@ impl Id for MappingId {
         self.0.get().into()
     }
 }
+
+impl MappingId {
+    #[inline]
+    pub fn to_offset(&self) -> usize {
+        // The id is the offset + 1, see [small_non_zero_pprof_id].
+        self.0.get() as usize - 1
+    }
+}
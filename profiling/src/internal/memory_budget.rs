@@ -0,0 +1,40 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+/// What a profile does with new samples once its interned data (functions,
+/// locations, mappings, stacks, labels and strings) uses up its budget. From
+/// then on, nothing new is interned: samples made of the stacks and labels
+/// the profile holds already are still added, and the policy applies to the
+/// ones which would add interned data.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BudgetPolicy {
+    /// Samples are still added, but their labels which aren't in the profile
+    /// already are dropped, as unique values like request ids are the usual
+    /// cause of unbounded growth. If the remaining labels don't make up a
+    /// label set of the profile, they are all dropped. Samples with a new
+    /// stack are dropped.
+    DropNewLabels,
+    /// Samples are still added, but stacks which aren't in the profile
+    /// already are collapsed into a single "[truncated]" frame. Samples with
+    /// new labels are dropped.
+    TruncateStacks,
+    /// Samples which would add interned data are dropped.
+    DropSamples,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MemoryBudget {
+    /// The estimated bytes the interned data of a profile may use before the
+    /// `policy` applies, see [crate::internal::Profile::used_bytes].
+    pub max_bytes: usize,
+    pub policy: BudgetPolicy,
+}
+
+/// The number of items dropped since the profile was created or reset
+/// because of its [MemoryBudget].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DroppedItems {
+    pub labels: u64,
+    pub samples: u64,
+    pub truncated_stacks: u64,
+}
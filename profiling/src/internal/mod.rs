@@ -7,11 +7,14 @@ mod function;
 mod label;
 mod location;
 mod mapping;
+mod memory_budget;
 mod observation;
 mod owned_types;
 mod profile;
 mod sample;
 mod stack_trace;
+#[cfg(unix)]
+mod symbolizer;
 mod timestamp;
 mod upscaling;
 mod value_type;
@@ -22,10 +25,13 @@ pub use function::*;
 pub use label::*;
 pub use location::*;
 pub use mapping::*;
+pub use memory_budget::*;
 pub use observation::*;
 pub use profile::*;
 pub use sample::*;
 pub use stack_trace::*;
+#[cfg(unix)]
+pub use symbolizer::*;
 pub use timestamp::*;
 pub use upscaling::*;
 pub use value_type::*;
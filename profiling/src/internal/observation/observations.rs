@@ -86,6 +86,29 @@ impl Observations {
             .map(|o| o.timestamped_samples_count)
             .unwrap_or(0)
     }
+
+    /// Returns the values aggregated for the sample, if there are any.
+    /// Timestamped samples are not considered.
+    pub fn get_aggregated(&self, sample: &Sample) -> Option<&[i64]> {
+        let observations = self.inner.as_ref()?;
+        let observation = observations.aggregated_data.data.get(sample)?;
+        // SAFETY: The only way to build one of these is through
+        // [AggregatedObservations::add], which already checked the length.
+        Some(unsafe { observation.as_slice(observations.obs_len) })
+    }
+
+    /// Calls `f` with every aggregated sample and its values, which `f` may
+    /// modify, and removes the samples for which `f` returns false.
+    /// Timestamped samples are left as they are. Stops at the first error.
+    pub fn retain_aggregated<F>(&mut self, f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(Sample, &mut [i64]) -> anyhow::Result<bool>,
+    {
+        match self.inner.as_mut() {
+            Some(observations) => observations.aggregated_data.retain(f),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -143,6 +166,38 @@ impl AggregatedObservations {
     fn remove(&mut self, sample: &Sample) -> Option<TrimmedObservation> {
         self.data.remove(sample)
     }
+
+    fn retain<F>(&mut self, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(Sample, &mut [i64]) -> anyhow::Result<bool>,
+    {
+        let obs_len = self.obs_len;
+        let mut removed = Vec::new();
+        let mut result = Ok(());
+        for (sample, observation) in self.data.iter_mut() {
+            // SAFETY: The only way to build one of these is through
+            // [Self::add], which already checked that the length was correct.
+            let values = unsafe { observation.as_mut_slice(obs_len) };
+            match f(*sample, values) {
+                Ok(true) => {}
+                Ok(false) => removed.push(*sample),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+
+        // Removed observations still own their data, so they must be
+        // consumed rather than dropped.
+        for sample in removed {
+            if let Some(observation) = self.data.remove(&sample) {
+                // SAFETY: see above, the length was checked by [Self::add].
+                unsafe { observation.consume(obs_len) };
+            }
+        }
+        result
+    }
 }
 
 impl Drop for AggregatedObservations {
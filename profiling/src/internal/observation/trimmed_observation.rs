@@ -48,6 +48,11 @@ pub(super) struct TrimmedObservation {
 unsafe impl Send for TrimmedObservation {}
 
 impl TrimmedObservation {
+    /// Safety: the ObservationLength must have come from the same profile as the Observation
+    pub unsafe fn as_slice(&self, len: ObservationLength) -> &[i64] {
+        unsafe { std::slice::from_raw_parts(self.data, len.0) }
+    }
+
     /// Safety: the ObservationLength must have come from the same profile as the Observation
     pub unsafe fn as_mut_slice(&mut self, len: ObservationLength) -> &mut [i64] {
         unsafe { std::slice::from_raw_parts_mut(self.data, len.0) }
@@ -0,0 +1,90 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+/// Turns profiles of cumulative values, such as the bytes allocated since the
+/// process started, into profiles of the difference since the previous one.
+///
+/// Runtimes like Go and .NET report their allocation and heap counters as
+/// totals. Each time [ProfileDelta::compute] is called, the values at the
+/// cumulative offsets are replaced with the difference from the values of the
+/// same stack and labels in the previous profile, and samples where every
+/// value ends up zero are removed. Values at other offsets are left as-is.
+///
+/// When a value is lower than before, the counter is assumed to have been
+/// reset and the value is kept as-is. Only aggregated samples are considered;
+/// timestamped samples are individual events and are left untouched.
+pub struct ProfileDelta {
+    cumulative_value_offsets: Box<[usize]>,
+    /// Holds the cumulative values of the last computed profile, keyed by the
+    /// stack and labels re-interned into this profile.
+    previous: Option<Profile>,
+}
+
+impl ProfileDelta {
+    /// Creates a delta computation where the values at the offsets of
+    /// `cumulative_value_offsets` in the sample types are cumulative.
+    pub fn new(cumulative_value_offsets: &[usize]) -> Self {
+        Self {
+            cumulative_value_offsets: cumulative_value_offsets.into(),
+            previous: None,
+        }
+    }
+
+    /// Replaces the cumulative values of the aggregated samples of `profile`
+    /// with their difference from the previously computed profile, and
+    /// remembers the cumulative values for the next call. The first call
+    /// leaves the values as-is.
+    pub fn compute(&mut self, profile: &mut Profile) -> anyhow::Result<()> {
+        let sample_types = profile.api_sample_types()?;
+        let max_offset = sample_types.len();
+        anyhow::ensure!(
+            self.cumulative_value_offsets
+                .iter()
+                .all(|offset| *offset < max_offset),
+            "Invalid offset. Highest expected offset: {max_offset}",
+        );
+        if let Some(previous) = &self.previous {
+            let previous_sample_types = previous.api_sample_types()?;
+            anyhow::ensure!(
+                sample_types == previous_sample_types,
+                "cannot compute the delta of profiles with different sample types: {sample_types:?} and {previous_sample_types:?}"
+            );
+        }
+
+        let mut current = Profile::new(profile.start_time, &sample_types, None);
+        let mut current_cache = ImportCache::default();
+        let mut previous_cache = ImportCache::default();
+
+        // The observations are taken out so the rest of the profile can be
+        // borrowed while the values are rewritten.
+        let mut observations = std::mem::take(&mut profile.observations);
+        let result = observations.retain_aggregated(|sample, values| {
+            let current_sample = current.import_sample(profile, sample, &mut current_cache)?;
+            current
+                .observations
+                .add(current_sample, None, values.to_vec())?;
+
+            if let Some(previous) = self.previous.as_mut() {
+                let previous_sample =
+                    previous.import_sample(profile, sample, &mut previous_cache)?;
+                if let Some(previous_values) =
+                    previous.observations.get_aggregated(&previous_sample)
+                {
+                    for offset in self.cumulative_value_offsets.iter() {
+                        if values[*offset] >= previous_values[*offset] {
+                            values[*offset] -= previous_values[*offset];
+                        }
+                    }
+                }
+            }
+            Ok(values.iter().any(|value| *value != 0))
+        });
+        profile.observations = observations;
+        result?;
+
+        self.previous = Some(current);
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::api::string_table_fetch;
+use std::io::Read;
+use std::time::UNIX_EPOCH;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const LZ4_FRAME_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// The maximum size of a decompressed pprof, so that a small compressed
+/// payload can't make the importer allocate without bounds.
+const MAX_DECOMPRESSED_SIZE: u64 = 512 * 1024 * 1024;
+
+impl Profile {
+    /// Decodes a pprof into a profile that can be added to, merged and
+    /// serialized like any other. The pprof may be gzip compressed, like the
+    /// ones of Go's runtime/pprof, lz4 compressed, like the ones serialized
+    /// by [Profile::serialize_into_compressed_pprof], or not compressed.
+    /// Compressed pprofs larger than 512 MiB once decompressed are rejected.
+    /// The imported profile has no memory budget: it holds all the samples
+    /// of the pprof, and only the samples added or merged after a call to
+    /// [Profile::set_memory_budget] are subject to it.
+    ///
+    /// See [Profile::try_from_pprof] for how the pprof is converted.
+    pub fn import_pprof(encoded: &[u8]) -> anyhow::Result<Profile> {
+        use prost::Message;
+
+        let mut buffer = Vec::new();
+        let decoded = if encoded.starts_with(&GZIP_MAGIC) {
+            decompress(
+                flate2::read::MultiGzDecoder::new(encoded),
+                MAX_DECOMPRESSED_SIZE,
+                &mut buffer,
+            )
+            .context("failed to decompress gzip pprof")?;
+            buffer.as_slice()
+        } else if encoded.starts_with(&LZ4_FRAME_MAGIC) {
+            decompress(
+                lz4_flex::frame::FrameDecoder::new(encoded),
+                MAX_DECOMPRESSED_SIZE,
+                &mut buffer,
+            )
+            .context("failed to decompress lz4 pprof")?;
+            buffer.as_slice()
+        } else {
+            encoded
+        };
+
+        let pprof = pprof::Profile::decode(decoded).context("failed to decode pprof")?;
+        Profile::try_from_pprof(&pprof)
+    }
+
+    /// Converts a decoded pprof into a profile, interning its strings and
+    /// deduplicating its functions, locations and mappings.
+    ///
+    /// Locations with several lines, which is how pprof represents inlined
+    /// functions, are expanded into one location per line. Samples with an
+    /// "end_timestamp_ns" label become timestamped samples. The duration,
+    /// comments, and frame filters of the pprof are not kept.
+    pub fn try_from_pprof(pprof: &pprof::Profile) -> anyhow::Result<Profile> {
+        let start_time = if pprof.time_nanos.is_negative() {
+            UNIX_EPOCH - Duration::from_nanos(pprof.time_nanos.unsigned_abs())
+        } else {
+            UNIX_EPOCH + Duration::from_nanos(pprof.time_nanos as u64)
+        };
+
+        let sample_types = pprof
+            .sample_types
+            .iter()
+            .map(|t| {
+                Ok(api::ValueType::new(
+                    string_table_fetch(pprof, t.r#type)?,
+                    string_table_fetch(pprof, t.unit)?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let period = match pprof.period_type {
+            Some(t) => Some(api::Period {
+                r#type: api::ValueType::new(
+                    string_table_fetch(pprof, t.r#type)?,
+                    string_table_fetch(pprof, t.unit)?,
+                ),
+                value: pprof.period,
+            }),
+            None => None,
+        };
+
+        let mut profile = Profile::new(start_time, &sample_types, period);
+
+        let mappings: HashMap<u64, &pprof::Mapping> =
+            pprof.mappings.iter().map(|m| (m.id, m)).collect();
+        let functions: HashMap<u64, &pprof::Function> =
+            pprof.functions.iter().map(|f| (f.id, f)).collect();
+
+        let mut locations: HashMap<u64, Vec<LocationId>> =
+            HashMap::with_capacity(pprof.locations.len());
+        for location in pprof.locations.iter() {
+            let mapping = match location.mapping_id {
+                0 => api::Mapping::default(),
+                id => {
+                    let mapping = mappings
+                        .get(&id)
+                        .with_context(|| format!("Mapping {id} was not found."))?;
+                    api::Mapping {
+                        memory_start: mapping.memory_start,
+                        memory_limit: mapping.memory_limit,
+                        file_offset: mapping.file_offset,
+                        filename: string_table_fetch(pprof, mapping.filename)?,
+                        build_id: string_table_fetch(pprof, mapping.build_id)?,
+                    }
+                }
+            };
+
+            // Locations which weren't symbolized don't have lines at all.
+            let default_line = [pprof::Line::default()];
+            let lines = if location.lines.is_empty() {
+                &default_line[..]
+            } else {
+                &location.lines[..]
+            };
+
+            let mut ids = Vec::with_capacity(lines.len());
+            for line in lines {
+                let function = match line.function_id {
+                    0 => api::Function::default(),
+                    id => {
+                        let function = functions
+                            .get(&id)
+                            .with_context(|| format!("Function {id} was not found."))?;
+                        api::Function {
+                            name: string_table_fetch(pprof, function.name)?,
+                            system_name: string_table_fetch(pprof, function.system_name)?,
+                            filename: string_table_fetch(pprof, function.filename)?,
+                            start_line: function.start_line,
+                        }
+                    }
+                };
+                ids.push(profile.add_location(&api::Location {
+                    mapping,
+                    function,
+                    address: location.address,
+                    line: line.line,
+                }));
+            }
+            locations.insert(location.id, ids);
+        }
+
+        for sample in pprof.samples.iter() {
+            anyhow::ensure!(
+                sample.values.len() == sample_types.len(),
+                "expected {} sample types, but sample had {} sample types",
+                sample_types.len(),
+                sample.values.len(),
+            );
+
+            let mut timestamp = None;
+            let mut labels = Vec::with_capacity(sample.labels.len());
+            for label in sample.labels.iter() {
+                let key = string_table_fetch(pprof, label.key)?;
+                if key == "end_timestamp_ns" {
+                    timestamp = Timestamp::new(label.num);
+                    continue;
+                }
+                labels.push(api::Label {
+                    key,
+                    str: match label.str {
+                        0 => None,
+                        str => Some(string_table_fetch(pprof, str)?.as_str()),
+                    },
+                    num: label.num,
+                    num_unit: match label.num_unit {
+                        0 => None,
+                        num_unit => Some(string_table_fetch(pprof, num_unit)?.as_str()),
+                    },
+                });
+            }
+            profile.validate_sample_labels(&labels)?;
+            let labels = profile.add_label_set(&labels);
+
+            let mut stacktrace = Vec::with_capacity(sample.location_ids.len());
+            for id in sample.location_ids.iter() {
+                let ids = locations
+                    .get(id)
+                    .with_context(|| format!("Location {id} was not found."))?;
+                stacktrace.extend_from_slice(ids);
+            }
+            let stacktrace = profile.add_stacktrace(stacktrace);
+
+            profile.observations.add(
+                Sample::new(labels, stacktrace),
+                timestamp,
+                sample.values.clone(),
+            )?;
+        }
+
+        Ok(profile)
+    }
+}
+
+/// Reads `decoder` to the end into `buffer`, failing if more than `limit`
+/// bytes are decompressed.
+fn decompress(decoder: impl Read, limit: u64, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    decoder.take(limit + 1).read_to_end(buffer)?;
+    anyhow::ensure!(
+        buffer.len() as u64 <= limit,
+        "decompressed pprof is larger than {limit} bytes"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Resolves the ids of the samples of the pprof into strings, so that
+    /// pprofs with different string tables and ids can be compared.
+    fn resolved_samples(pprof: &pprof::Profile) -> Vec<(Vec<String>, Vec<i64>, Vec<String>)> {
+        let mut samples: Vec<_> = pprof
+            .samples
+            .iter()
+            .map(|sample| {
+                let locations = sample
+                    .location_ids
+                    .iter()
+                    .map(|id| {
+                        let location = &pprof.locations[*id as usize - 1];
+                        let mapping = &pprof.mappings[location.mapping_id as usize - 1];
+                        let line = location.lines[0];
+                        let function = &pprof.functions[line.function_id as usize - 1];
+                        format!(
+                            "{} {}:{} {:#x}",
+                            pprof.string_table_fetch(function.name),
+                            pprof.string_table_fetch(mapping.filename),
+                            line.line,
+                            location.address,
+                        )
+                    })
+                    .collect();
+                let mut labels: Vec<_> = sample
+                    .labels
+                    .iter()
+                    .map(|label| {
+                        format!(
+                            "{}={}{} {}",
+                            pprof.string_table_fetch(label.key),
+                            pprof.string_table_fetch(label.str),
+                            label.num,
+                            pprof.string_table_fetch(label.num_unit),
+                        )
+                    })
+                    .collect();
+                labels.sort_unstable();
+                (locations, sample.values.clone(), labels)
+            })
+            .collect();
+        samples.sort_unstable();
+        samples
+    }
+
+    fn create_profile() -> Profile {
+        let sample_types = [
+            api::ValueType::new("samples", "count"),
+            api::ValueType::new("wall-time", "nanoseconds"),
+        ];
+        let period = api::Period {
+            r#type: sample_types[1],
+            value: 10_000_000,
+        };
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, Some(period));
+
+        let mapping = api::Mapping {
+            memory_start: 0x1000,
+            memory_limit: 0x2000,
+            filename: "php",
+            build_id: "abc",
+            ..Default::default()
+        };
+        let main = api::Location {
+            mapping,
+            function: api::Function {
+                name: "{main}",
+                system_name: "{main}",
+                filename: "index.php",
+                start_line: 1,
+            },
+            address: 0x1010,
+            line: 3,
+        };
+        let test = api::Location {
+            mapping,
+            function: api::Function {
+                name: "test",
+                system_name: "test",
+                filename: "index.php",
+                start_line: 5,
+            },
+            address: 0x1020,
+            line: 6,
+        };
+        let labels = vec![
+            api::Label {
+                key: "thread name",
+                str: Some("main"),
+                ..Default::default()
+            },
+            api::Label {
+                key: "allocation size",
+                num: 64,
+                num_unit: Some("bytes"),
+                ..Default::default()
+            },
+        ];
+
+        profile
+            .add_sample(
+                api::Sample {
+                    locations: vec![test, main],
+                    values: vec![1, 100],
+                    labels: labels.clone(),
+                },
+                None,
+            )
+            .unwrap();
+        profile
+            .add_sample(
+                api::Sample {
+                    locations: vec![main],
+                    values: vec![2, 200],
+                    labels: labels.clone(),
+                },
+                Timestamp::new(42),
+            )
+            .unwrap();
+        profile
+            .add_sample(
+                api::Sample {
+                    locations: vec![main],
+                    values: vec![3, 300],
+                    labels: vec![],
+                },
+                None,
+            )
+            .unwrap();
+        profile
+    }
+
+    #[test]
+    fn import_lz4_pprof() -> anyhow::Result<()> {
+        let encoded = create_profile().serialize_into_compressed_pprof(None, None)?;
+        let expected = pprof::deserialize_compressed_pprof(&encoded.buffer)?;
+
+        let profile = Profile::import_pprof(&encoded.buffer)?;
+        assert_eq!(profile.only_for_testing_num_aggregated_samples(), 2);
+        assert_eq!(profile.only_for_testing_num_timestamped_samples(), 1);
+
+        let actual = pprof::roundtrip_to_pprof(profile)?;
+        assert_eq!(resolved_samples(&actual), resolved_samples(&expected));
+        assert_eq!(actual.time_nanos, expected.time_nanos);
+        assert_eq!(actual.period, expected.period);
+        assert_eq!(actual.functions.len(), 2);
+        assert_eq!(actual.mappings.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn import_gzip_and_uncompressed_pprof() -> anyhow::Result<()> {
+        let expected = pprof::roundtrip_to_pprof(create_profile())?;
+        let mut uncompressed = Vec::new();
+        expected.write_to_vec(&mut uncompressed)?;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&uncompressed)?;
+        let gzipped = encoder.finish()?;
+
+        for encoded in [gzipped, uncompressed] {
+            let actual = pprof::roundtrip_to_pprof(Profile::import_pprof(&encoded)?)?;
+            assert_eq!(resolved_samples(&actual), resolved_samples(&expected));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decompress_limit() -> anyhow::Result<()> {
+        let encoded = create_profile().serialize_into_compressed_pprof(None, None)?;
+        let mut buffer = Vec::new();
+        decompress(
+            lz4_flex::frame::FrameDecoder::new(encoded.buffer.as_slice()),
+            MAX_DECOMPRESSED_SIZE,
+            &mut buffer,
+        )?;
+        let size = buffer.len() as u64;
+
+        buffer.clear();
+        decompress(
+            lz4_flex::frame::FrameDecoder::new(encoded.buffer.as_slice()),
+            size,
+            &mut buffer,
+        )?;
+        buffer.clear();
+        assert!(decompress(
+            lz4_flex::frame::FrameDecoder::new(encoded.buffer.as_slice()),
+            size - 1,
+            &mut buffer,
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn import_inlined_functions() -> anyhow::Result<()> {
+        let pprof = pprof::Profile {
+            sample_types: vec![pprof::ValueType { r#type: 1, unit: 2 }],
+            samples: vec![pprof::Sample {
+                location_ids: vec![7],
+                values: vec![5],
+                labels: vec![],
+            }],
+            mappings: vec![pprof::Mapping {
+                id: 3,
+                filename: 3,
+                ..Default::default()
+            }],
+            locations: vec![pprof::Location {
+                id: 7,
+                mapping_id: 3,
+                address: 0x42,
+                lines: vec![
+                    pprof::Line {
+                        function_id: 9,
+                        line: 10,
+                    },
+                    pprof::Line {
+                        function_id: 8,
+                        line: 20,
+                    },
+                ],
+                is_folded: false,
+            }],
+            functions: vec![
+                pprof::Function {
+                    id: 8,
+                    name: 4,
+                    ..Default::default()
+                },
+                pprof::Function {
+                    id: 9,
+                    name: 5,
+                    ..Default::default()
+                },
+            ],
+            string_table: ["", "samples", "count", "app", "caller", "inlined"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            ..Default::default()
+        };
+
+        let actual = pprof::roundtrip_to_pprof(Profile::try_from_pprof(&pprof)?)?;
+        let expected = vec![(
+            vec![
+                "inlined app:10 0x42".to_string(),
+                "caller app:20 0x42".to_string(),
+            ],
+            vec![5],
+            vec![],
+        )];
+        assert_eq!(resolved_samples(&actual), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn import_unknown_location() {
+        let pprof = pprof::Profile {
+            sample_types: vec![pprof::ValueType { r#type: 1, unit: 2 }],
+            samples: vec![pprof::Sample {
+                location_ids: vec![1],
+                values: vec![1],
+                labels: vec![],
+            }],
+            string_table: ["", "samples", "count"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            ..Default::default()
+        };
+        assert!(Profile::try_from_pprof(&pprof).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+/// The allocations tracked by a profile, which make up its live heap.
+pub(super) struct LiveHeap {
+    allocations: HashMap<u64, (Sample, Box<[i64]>)>,
+    max_tracked: usize,
+    /// The number of allocations which weren't tracked because the limit was
+    /// reached, since the profile was created or reset.
+    untracked: u64,
+}
+
+impl LiveHeap {
+    fn new(max_tracked: usize) -> Self {
+        Self {
+            allocations: HashMap::new(),
+            max_tracked,
+            untracked: 0,
+        }
+    }
+}
+
+impl Profile {
+    /// Enables tracking allocations with [Profile::track_allocation], up to
+    /// `max_tracked` allocations at once. Tracked allocations are kept when
+    /// the profile is reset, as they are still live.
+    pub fn enable_live_heap(&mut self, max_tracked: usize) {
+        match self.live_heap.as_mut() {
+            Some(live_heap) => live_heap.max_tracked = max_tracked,
+            None => self.live_heap = Some(LiveHeap::new(max_tracked)),
+        }
+    }
+
+    /// Tracks an allocation until it's removed with
+    /// [Profile::untrack_allocation], where `id` is usually its address.
+    /// Tracking the id of an allocation which is already tracked replaces it.
+    ///
+    /// Until then, the allocation is added to the profile each time it's
+    /// serialized, with the stack, labels and values of the `sample`, which
+    /// usually only fill the values of live heap sample types. Like other
+    /// samples, the values are upscaled by the upscaling rules of the profile,
+    /// such as a [UpscalingInfo::Poisson] rule for the offsets of the live
+    /// heap sample types when allocations are sampled every few bytes.
+    ///
+    /// Once the limit given to [Profile::enable_live_heap] is reached, new
+    /// allocations are not tracked and their number is reported in the
+    /// comments of the serialized profile.
+    pub fn track_allocation(&mut self, id: u64, sample: api::Sample) -> anyhow::Result<()> {
+        let live_heap = self
+            .live_heap
+            .as_ref()
+            .context("live heap profiling is not enabled")?;
+        if live_heap.allocations.len() >= live_heap.max_tracked
+            && !live_heap.allocations.contains_key(&id)
+        {
+            if let Some(live_heap) = self.live_heap.as_mut() {
+                live_heap.untracked += 1;
+            }
+            return Ok(());
+        }
+
+        if let Some((sample, values)) = self.intern_sample(sample)? {
+            if let Some(live_heap) = self.live_heap.as_mut() {
+                live_heap
+                    .allocations
+                    .insert(id, (sample, values.into_boxed_slice()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops tracking the allocation, usually because it was freed. Returns
+    /// whether it was tracked.
+    pub fn untrack_allocation(&mut self, id: u64) -> bool {
+        self.live_heap
+            .as_mut()
+            .is_some_and(|live_heap| live_heap.allocations.remove(&id).is_some())
+    }
+
+    /// Moves the tracked allocations of `previous` into this profile,
+    /// re-interning their stacks and labels. The memory budget doesn't apply
+    /// to them, as they went through it when they were tracked.
+    pub(super) fn carry_over_live_heap(&mut self, previous: &Profile) -> anyhow::Result<()> {
+        let Some(previous_live_heap) = previous.live_heap.as_ref() else {
+            return Ok(());
+        };
+        let mut live_heap = LiveHeap::new(previous_live_heap.max_tracked);
+        let mut cache = ImportCache::default();
+        for (id, (sample, values)) in previous_live_heap.allocations.iter() {
+            let sample = self.import_sample(previous, *sample, &mut cache)?;
+            live_heap.allocations.insert(*id, (sample, values.clone()));
+        }
+        self.live_heap = Some(live_heap);
+        Ok(())
+    }
+
+    /// Adds the tracked allocations to the aggregated samples, before the
+    /// profile is serialized.
+    pub(super) fn add_live_heap_samples(&mut self) -> anyhow::Result<()> {
+        if let Some(live_heap) = self.live_heap.as_mut() {
+            for (_, (sample, values)) in std::mem::take(&mut live_heap.allocations) {
+                self.observations.add(sample, None, values.into_vec())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn live_heap_comments(&self) -> Option<String> {
+        let live_heap = self.live_heap.as_ref()?;
+        (live_heap.untracked > 0).then(|| {
+            format!(
+                "live heap: {} allocations were not tracked over the limit of {}",
+                live_heap.untracked, live_heap.max_tracked
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocation(function: &'static str, size: i64) -> api::Sample<'static> {
+        api_tests::create_function_sample(function, vec![1, size])
+    }
+
+    fn live_values(profile: Profile) -> anyhow::Result<Vec<(String, Vec<i64>)>> {
+        let pprof = crate::pprof::roundtrip_to_pprof(profile)?;
+        let mut values: Vec<_> = pprof
+            .samples
+            .iter()
+            .map(|sample| {
+                let location = &pprof.locations[sample.location_ids[0] as usize - 1];
+                let function = &pprof.functions[location.lines[0].function_id as usize - 1];
+                (
+                    pprof.string_table[function.name as usize].clone(),
+                    sample.values.clone(),
+                )
+            })
+            .collect();
+        values.sort();
+        Ok(values)
+    }
+
+    #[test]
+    fn live_heap() -> anyhow::Result<()> {
+        let sample_types = [
+            api::ValueType::new("heap-live-samples", "count"),
+            api::ValueType::new("heap-live-size", "bytes"),
+        ];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        assert!(profile.track_allocation(1, allocation("foo", 8)).is_err());
+
+        profile.enable_live_heap(3);
+        profile.track_allocation(1, allocation("foo", 8))?;
+        profile.track_allocation(2, allocation("foo", 16))?;
+        profile.track_allocation(3, allocation("bar", 32))?;
+        // Over the limit.
+        profile.track_allocation(4, allocation("bar", 64))?;
+        assert!(profile.untrack_allocation(2));
+        assert!(!profile.untrack_allocation(2));
+        assert!(!profile.untrack_allocation(4));
+
+        let previous = profile.reset_and_return_previous(None)?;
+        assert_eq!(
+            live_values(previous)?,
+            [
+                ("bar".to_string(), vec![1, 32]),
+                ("foo".to_string(), vec![1, 8])
+            ]
+        );
+
+        // The allocations are still live after the reset.
+        profile.track_allocation(2, allocation("baz", 16))?;
+        assert!(profile.untrack_allocation(3));
+        let previous = profile.reset_and_return_previous(None)?;
+        assert_eq!(
+            live_values(previous)?,
+            [
+                ("baz".to_string(), vec![1, 16]),
+                ("foo".to_string(), vec![1, 8])
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn live_heap_delta() -> anyhow::Result<()> {
+        let sample_types = [
+            api::ValueType::new("alloc-size", "bytes"),
+            api::ValueType::new("heap-live-size", "bytes"),
+        ];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        let mut delta = ProfileDelta::new(&[0]);
+        profile.enable_live_heap(10);
+        let sample = |values| api_tests::create_function_sample("foo", values);
+        profile.track_allocation(1, sample(vec![0, 32]))?;
+        profile.add_sample(sample(vec![64, 0]), None)?;
+        let first = profile.reset_and_return_delta(&mut delta, None)?;
+        assert_eq!(live_values(first)?, [("foo".to_string(), vec![64, 32])]);
+
+        // Nothing was allocated since, so the delta of the cumulative sample
+        // is zero, but the allocation is still live.
+        profile.add_sample(sample(vec![64, 0]), None)?;
+        let second = profile.reset_and_return_delta(&mut delta, None)?;
+        assert_eq!(live_values(second)?, [("foo".to_string(), vec![0, 32])]);
+        Ok(())
+    }
+
+    #[test]
+    fn live_heap_upscaling() -> anyhow::Result<()> {
+        let sample_types = [
+            api::ValueType::new("heap-live-samples", "count"),
+            api::ValueType::new("heap-live-size", "bytes"),
+        ];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        let sampling_distance = 512;
+        profile.add_upscaling_rule(
+            &[0, 1],
+            "",
+            "",
+            UpscalingInfo::Poisson {
+                sum_value_offset: 1,
+                count_value_offset: 0,
+                sampling_distance,
+            },
+        )?;
+        profile.enable_live_heap(10);
+        profile.track_allocation(1, allocation("foo", 128))?;
+
+        let scale = 1.0 / (1.0 - (-128.0 / sampling_distance as f64).exp());
+        assert_eq!(
+            live_values(profile)?,
+            [(
+                "foo".to_string(),
+                vec![scale.round() as i64, (128.0 * scale).round() as i64]
+            )]
+        );
+        Ok(())
+    }
+}
@@ -1,8 +1,20 @@
 // Copyright 2021-Present Datadog, Inc. https://www.datadoghq.com/
 // SPDX-License-Identifier: Apache-2.0
 
+mod delta;
 #[cfg(test)]
 mod fuzz_tests;
+mod import;
+mod live_heap;
+mod otlp;
+mod sharded;
+#[cfg(unix)]
+mod symbolize;
+
+pub use delta::*;
+pub use sharded::*;
+
+use self::live_heap::LiveHeap;
 
 use self::api::UpscalingInfo;
 use super::*;
@@ -26,18 +38,28 @@ pub struct Profile {
     /// When profiles are reset, the period needs to be preserved. This
     /// stores it in a way that does not depend on the string table.
     owned_period: Option<owned_types::Period>,
+    dropped: DroppedItems,
     endpoints: Endpoints,
     functions: FxIndexSet<Function>,
+    /// The bytes used by the stack traces and label sets on the heap, which
+    /// the capacity of their sets doesn't account for.
+    interned_heap_bytes: usize,
     labels: FxIndexSet<Label>,
     label_sets: FxIndexSet<LabelSet>,
+    live_heap: Option<LiveHeap>,
     locations: FxIndexSet<Location>,
     mappings: FxIndexSet<Mapping>,
+    memory_budget: Option<MemoryBudget>,
     observations: Observations,
     period: Option<(i64, ValueType)>,
     sample_types: Box<[ValueType]>,
     stack_traces: FxIndexSet<StackTrace>,
     start_time: SystemTime,
     strings: StringTable,
+    /// The functions and lines of the locations symbolized by
+    /// [Profile::symbolize], which replace the function and line of the
+    /// location when serialized.
+    symbolized_lines: HashMap<LocationId, Box<[(FunctionId, i64)]>>,
     timestamp_key: StringId,
     upscaling_rules: UpscalingRules,
 }
@@ -49,6 +71,14 @@ pub struct EncodedProfile {
     pub endpoints_stats: ProfiledEndpointsStats,
 }
 
+/// Maps the ids of a profile to the ids of another profile that its samples
+/// are imported into, see [Profile::import_sample].
+#[derive(Default)]
+struct ImportCache {
+    label_sets: HashMap<LabelSetId, LabelSetId>,
+    stack_traces: HashMap<StackTraceId, StackTraceId>,
+}
+
 /// Public API
 impl Profile {
     /// Add the endpoint data to the endpoint mappings.
@@ -78,42 +108,9 @@ impl Profile {
         sample: api::Sample,
         timestamp: Option<Timestamp>,
     ) -> anyhow::Result<()> {
-        anyhow::ensure!(
-            sample.values.len() == self.sample_types.len(),
-            "expected {} sample types, but sample had {} sample types",
-            self.sample_types.len(),
-            sample.values.len(),
-        );
-
-        self.validate_sample_labels(&sample)?;
-        let labels: Vec<_> = sample
-            .labels
-            .iter()
-            .map(|label| {
-                let key = self.intern(label.key);
-                let internal_label = if let Some(s) = label.str {
-                    let str = self.intern(s);
-                    Label::str(key, str)
-                } else {
-                    let num = label.num;
-                    let num_unit = label.num_unit.map(|s| self.intern(s));
-                    Label::num(key, num, num_unit)
-                };
-
-                self.labels.dedup(internal_label)
-            })
-            .collect();
-        let labels = self.label_sets.dedup(LabelSet::new(labels));
-
-        let locations = sample
-            .locations
-            .iter()
-            .map(|l| self.add_location(l))
-            .collect();
-
-        let stacktrace = self.add_stacktrace(locations);
-        self.observations
-            .add(Sample::new(labels, stacktrace), timestamp, sample.values)?;
+        if let Some((sample, values)) = self.intern_sample(sample)? {
+            self.observations.add(sample, timestamp, values)?;
+        }
         Ok(())
     }
 
@@ -156,6 +153,114 @@ impl Profile {
         )
     }
 
+    /// Merges the samples and endpoints of `other` into this profile. Samples
+    /// with the same stack and labels in both profiles are aggregated, and
+    /// timestamped samples keep their timestamps. The start time becomes the
+    /// earlier of the two.
+    ///
+    /// `other` is consumed because its timestamped samples are held in a
+    /// compressed buffer that can only be read once. Its upscaling rules are
+    /// not carried over: the merged samples are upscaled with the rules of
+    /// this profile when it gets serialized, and neither are the allocations
+    /// tracked for its live heap. Once this profile is over its memory
+    /// budget, the policy of the budget applies to the merged samples like
+    /// it does to added ones, and the items `other` dropped because of its
+    /// own budget are added to the ones of this profile.
+    pub fn merge(&mut self, mut other: Profile) -> anyhow::Result<()> {
+        let sample_types = self.api_sample_types()?;
+        let other_sample_types = other.api_sample_types()?;
+        anyhow::ensure!(
+            sample_types == other_sample_types,
+            "cannot merge profiles with different sample types: {sample_types:?} and {other_sample_types:?}"
+        );
+
+        let mut cache = ImportCache::default();
+        for (sample, timestamp, values) in std::mem::take(&mut other.observations) {
+            let sample = if let Some(policy) = self.over_budget_policy() {
+                let locations = other.get_api_locations(sample.stacktrace)?;
+                let labels = other.get_api_labels(sample.labels)?;
+                match self.find_sample_within_budget(policy, &locations, &labels) {
+                    Some(sample) => sample,
+                    None => continue,
+                }
+            } else {
+                self.import_sample(&other, sample, &mut cache)?
+            };
+            self.observations.add(sample, timestamp, values)?;
+        }
+
+        for (local_root_span_id, endpoint) in other.endpoints.mappings.iter() {
+            let endpoint = self.intern(other.get_string(*endpoint)?);
+            self.endpoints
+                .mappings
+                .insert(*local_root_span_id, endpoint);
+        }
+        self.endpoints
+            .stats
+            .merge(std::mem::take(&mut other.endpoints.stats));
+
+        self.dropped.labels += other.dropped.labels;
+        self.dropped.samples += other.dropped.samples;
+        self.dropped.truncated_stacks += other.dropped.truncated_stacks;
+
+        self.start_time = self.start_time.min(other.start_time);
+        Ok(())
+    }
+
+    /// Returns the number of items dropped because of the memory budget since
+    /// the profile was created or reset.
+    pub fn dropped_items(&self) -> DroppedItems {
+        self.dropped
+    }
+
+    /// Limits the memory used by the functions, locations, mappings, stacks,
+    /// labels and strings of the profile, which otherwise grow until the
+    /// profile is reset. Once [Profile::used_bytes] reaches the budget, the
+    /// policy of the budget applies to new samples. The budget is kept when
+    /// the profile is reset. The number of dropped items is reported in the
+    /// comments of the serialized profile.
+    pub fn set_memory_budget(&mut self, budget: Option<MemoryBudget>) {
+        self.memory_budget = budget;
+    }
+
+    /// Returns the policy of the memory budget if the profile is over it.
+    fn over_budget_policy(&self) -> Option<BudgetPolicy> {
+        self.memory_budget
+            .filter(|budget| self.used_bytes() >= budget.max_bytes)
+            .map(|budget| budget.policy)
+    }
+
+    /// Returns an estimate of the bytes used by the functions, locations,
+    /// mappings, stacks, labels and strings of the profile. The samples
+    /// themselves aren't included.
+    pub fn used_bytes(&self) -> usize {
+        index_set_used_bytes(&self.functions)
+            + index_set_used_bytes(&self.labels)
+            + index_set_used_bytes(&self.label_sets)
+            + index_set_used_bytes(&self.locations)
+            + index_set_used_bytes(&self.mappings)
+            + index_set_used_bytes(&self.stack_traces)
+            + self.interned_heap_bytes
+            + self.strings.used_bytes()
+    }
+
+    /// Resets all data except the sample types and period, like
+    /// [Profile::reset_and_return_previous], but the returned Profile only
+    /// holds the difference of the cumulative values since the previous
+    /// reset. See [ProfileDelta] for details. The allocations tracked for the
+    /// live heap are left out of the delta, as live heap values aren't
+    /// cumulative: they are added as-is when the returned profile is
+    /// serialized.
+    pub fn reset_and_return_delta(
+        &mut self,
+        delta: &mut ProfileDelta,
+        start_time: Option<SystemTime>,
+    ) -> anyhow::Result<Profile> {
+        let mut profile = self.reset_and_return_previous(start_time)?;
+        delta.compute(&mut profile)?;
+        Ok(profile)
+    }
+
     /// Resets all data except the sample types and period.
     /// Returns the previous Profile on success.
     #[inline]
@@ -168,8 +273,13 @@ impl Profile {
             self.owned_sample_types.take(),
             start_time.unwrap_or_else(SystemTime::now),
         );
+        profile.memory_budget = self.memory_budget;
 
         std::mem::swap(&mut *self, &mut profile);
+        // The tracked allocations are carried over once the sample types and
+        // period are owned by the new profile, so this profile can still be
+        // used if it fails.
+        self.carry_over_live_heap(&profile)?;
         Ok(profile)
     }
 
@@ -188,16 +298,8 @@ impl Profile {
         let end = end_time.unwrap_or_else(SystemTime::now);
         let start = self.start_time;
         let endpoints_stats = std::mem::take(&mut self.endpoints.stats);
-        let duration_nanos = duration
-            .unwrap_or_else(|| {
-                end.duration_since(start).unwrap_or({
-                    // Let's not throw away the whole profile just because the clocks were wrong.
-                    // todo: log that the clock went backward (or programmer mistake).
-                    Duration::ZERO
-                })
-            })
-            .as_nanos()
-            .min(i64::MAX as u128) as i64;
+        let duration_nanos = Self::duration_nanos(start, end, duration);
+        let comment = self.dropped_items_comments();
         let (period, period_type) = match self.period {
             Some(tuple) => (tuple.0, Some(tuple.1.into())),
             None => (0, None),
@@ -216,6 +318,7 @@ impl Profile {
         const INITIAL_PPROF_BUFFER_SIZE: usize = 32 * 1024;
         let mut encoder = CompressedProtobufSerializer::with_capacity(INITIAL_PPROF_BUFFER_SIZE);
 
+        self.add_live_heap_samples()?;
         for (sample, timestamp, mut values) in std::mem::take(&mut self.observations).into_iter() {
             let labels = self.enrich_sample_labels(sample, timestamp)?;
             let location_ids: Vec<_> = self
@@ -255,7 +358,18 @@ impl Profile {
             encoder.encode(ProfileMappingsEntry::from(item))?;
         }
 
-        for item in into_pprof_iter(self.locations) {
+        for (offset, location) in self.locations.into_iter().enumerate() {
+            let id = LocationId::from_offset(offset);
+            let mut item = location.to_pprof(id);
+            if let Some(lines) = self.symbolized_lines.remove(&id) {
+                item.lines = lines
+                    .iter()
+                    .map(|(function_id, line)| pprof::Line {
+                        function_id: function_id.to_raw_id(),
+                        line: *line,
+                    })
+                    .collect();
+            }
             encoder.encode(ProfileLocationsEntry::from(item))?;
         }
 
@@ -269,15 +383,11 @@ impl Profile {
         }
 
         encoder.encode(ProfileSimpler {
-            time_nanos: self
-                .start_time
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map_or(0, |duration| {
-                    duration.as_nanos().min(i64::MAX as u128) as i64
-                }),
+            time_nanos: Self::unix_nanos(self.start_time),
             duration_nanos,
             period_type,
             period,
+            comment,
         })?;
 
         Ok(EncodedProfile {
@@ -305,6 +415,36 @@ impl Profile {
         })
     }
 
+    fn add_label_set(&mut self, labels: &[api::Label]) -> LabelSetId {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|label| {
+                let key = self.intern(label.key);
+                let internal_label = if let Some(s) = label.str {
+                    let str = self.intern(s);
+                    Label::str(key, str)
+                } else {
+                    let num = label.num;
+                    let num_unit = label.num_unit.map(|s| self.intern(s));
+                    Label::num(key, num, num_unit)
+                };
+
+                self.labels.dedup(internal_label)
+            })
+            .collect();
+        self.add_label_set_ids(labels)
+    }
+
+    fn add_label_set_ids(&mut self, labels: Vec<LabelId>) -> LabelSetId {
+        let len = self.label_sets.len();
+        let heap_bytes = labels.len() * std::mem::size_of::<LabelId>();
+        let id = self.label_sets.dedup(LabelSet::new(labels));
+        if self.label_sets.len() > len {
+            self.interned_heap_bytes += heap_bytes;
+        }
+        id
+    }
+
     fn add_location(&mut self, location: &api::Location) -> LocationId {
         let mapping_id = self.add_mapping(&location.mapping);
         let function_id = self.add_function(&location.function);
@@ -330,7 +470,49 @@ impl Profile {
     }
 
     fn add_stacktrace(&mut self, locations: Vec<LocationId>) -> StackTraceId {
-        self.stack_traces.dedup(StackTrace { locations })
+        let len = self.stack_traces.len();
+        let heap_bytes = locations.len() * std::mem::size_of::<LocationId>();
+        let id = self.stack_traces.dedup(StackTrace { locations });
+        if self.stack_traces.len() > len {
+            self.interned_heap_bytes += heap_bytes;
+        }
+        id
+    }
+
+    /// Returns the sample types with their strings, which unlike the
+    /// [ValueType]s don't depend on the string table of the profile.
+    fn api_sample_types(&self) -> anyhow::Result<Vec<api::ValueType<'_>>> {
+        self.sample_types
+            .iter()
+            .map(|sample_type| {
+                Ok(api::ValueType::new(
+                    self.get_string(sample_type.r#type)?,
+                    self.get_string(sample_type.unit)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns the duration, or the time between the start and end when there
+    /// is none, in nanoseconds.
+    fn duration_nanos(start: SystemTime, end: SystemTime, duration: Option<Duration>) -> i64 {
+        duration
+            .unwrap_or_else(|| {
+                end.duration_since(start).unwrap_or({
+                    // Let's not throw away the whole profile just because the clocks were wrong.
+                    // todo: log that the clock went backward (or programmer mistake).
+                    Duration::ZERO
+                })
+            })
+            .as_nanos()
+            .min(i64::MAX as u128) as i64
+    }
+
+    fn unix_nanos(time: SystemTime) -> i64 {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| {
+                duration.as_nanos().min(i64::MAX as u128) as i64
+            })
     }
 
     #[inline]
@@ -390,6 +572,92 @@ impl Profile {
         }
     }
 
+    /// Returns the comments reporting the items dropped because of the memory
+    /// budget, interning them as needed.
+    fn dropped_items_comments(&mut self) -> Vec<i64> {
+        let DroppedItems {
+            labels,
+            samples,
+            truncated_stacks,
+        } = self.dropped;
+        let mut comments: Vec<String> = self.live_heap_comments().into_iter().collect();
+        if samples > 0 {
+            comments.push(format!("memory budget: dropped {samples} samples"));
+        }
+        if labels > 0 {
+            comments.push(format!("memory budget: dropped {labels} labels"));
+        }
+        if truncated_stacks > 0 {
+            comments.push(format!(
+                "memory budget: truncated {truncated_stacks} stacks"
+            ));
+        }
+        comments
+            .iter()
+            .map(|comment| self.intern(comment).to_raw_id())
+            .collect()
+    }
+
+    /// Returns the id of the label if the profile holds it already, without
+    /// interning anything.
+    fn find_label(&self, label: &api::Label) -> Option<LabelId> {
+        let key = self.strings.find(label.key)?;
+        let label = if let Some(s) = label.str {
+            Label::str(key, self.strings.find(s)?)
+        } else {
+            let num_unit = match label.num_unit {
+                Some(num_unit) => Some(self.strings.find(num_unit)?),
+                None => None,
+            };
+            Label::num(key, label.num, num_unit)
+        };
+        self.labels.get_index_of(&label).map(LabelId::from_offset)
+    }
+
+    /// Returns the id of the label set if the profile holds it already,
+    /// without interning anything.
+    fn find_label_set(&self, labels: Vec<LabelId>) -> Option<LabelSetId> {
+        self.label_sets
+            .get_index_of(&LabelSet::new(labels))
+            .map(LabelSetId::from_offset)
+    }
+
+    /// Returns the id of the location if the profile holds it already, along
+    /// with its mapping and function, without interning anything.
+    fn find_location(&self, location: &api::Location) -> Option<LocationId> {
+        let mapping = &location.mapping;
+        let mapping_id = self.mappings.get_index_of(&Mapping {
+            memory_start: mapping.memory_start,
+            memory_limit: mapping.memory_limit,
+            file_offset: mapping.file_offset,
+            filename: self.strings.find(mapping.filename)?,
+            build_id: self.strings.find(mapping.build_id)?,
+        })?;
+        let function = &location.function;
+        let function_id = self.functions.get_index_of(&Function {
+            name: self.strings.find(function.name)?,
+            system_name: self.strings.find(function.system_name)?,
+            filename: self.strings.find(function.filename)?,
+            start_line: function.start_line,
+        })?;
+        self.locations
+            .get_index_of(&Location {
+                mapping_id: MappingId::from_offset(mapping_id),
+                function_id: FunctionId::from_offset(function_id),
+                address: location.address,
+                line: location.line,
+            })
+            .map(LocationId::from_offset)
+    }
+
+    /// Returns the id of the stack if the profile holds it already, without
+    /// interning anything.
+    fn find_stacktrace(&self, locations: Vec<LocationId>) -> Option<StackTraceId> {
+        self.stack_traces
+            .get_index_of(&StackTrace { locations })
+            .map(StackTraceId::from_offset)
+    }
+
     fn get_label(&self, id: LabelId) -> anyhow::Result<&Label> {
         self.labels
             .get_index(id.to_offset())
@@ -402,12 +670,253 @@ impl Profile {
             .context("LabelSetId to have a valid interned index")
     }
 
+    /// Rebuilds the [api::Label]s of the label set, borrowing the strings
+    /// from the string table.
+    fn get_api_labels(&self, id: LabelSetId) -> anyhow::Result<Vec<api::Label<'_>>> {
+        self.get_label_set(id)?
+            .iter()
+            .map(|id| {
+                let label = self.get_label(*id)?;
+                let key = self.get_string(label.get_key())?;
+                Ok(match label.get_value() {
+                    LabelValue::Str(str) => api::Label {
+                        key,
+                        str: Some(self.get_string(*str)?),
+                        ..Default::default()
+                    },
+                    LabelValue::Num { num, num_unit } => api::Label {
+                        key,
+                        num: *num,
+                        num_unit: match num_unit {
+                            Some(num_unit) => Some(self.get_string(*num_unit)?),
+                            None => None,
+                        },
+                        ..Default::default()
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Rebuilds the [api::Location]s of the stack trace, leaf first.
+    fn get_api_locations(&self, id: StackTraceId) -> anyhow::Result<Vec<api::Location<'_>>> {
+        self.get_stacktrace(id)?
+            .locations
+            .iter()
+            .map(|id| self.get_location(*id))
+            .collect()
+    }
+
+    /// Returns the interned function with the given id.
+    fn get_function(&self, id: FunctionId) -> anyhow::Result<&Function> {
+        self.functions
+            .get_index(id.to_offset())
+            .context("FunctionId to have a valid interned index")
+    }
+
+    /// Rebuilds the [api::Location] of the location, borrowing the strings
+    /// from the string table.
+    fn get_location(&self, id: LocationId) -> anyhow::Result<api::Location<'_>> {
+        let location = self
+            .locations
+            .get_index(id.to_offset())
+            .context("LocationId to have a valid interned index")?;
+        let mapping = self.get_mapping(location.mapping_id)?;
+        let function = self.get_function(location.function_id)?;
+
+        Ok(api::Location {
+            mapping: api::Mapping {
+                memory_start: mapping.memory_start,
+                memory_limit: mapping.memory_limit,
+                file_offset: mapping.file_offset,
+                filename: self.get_string(mapping.filename)?,
+                build_id: self.get_string(mapping.build_id)?,
+            },
+            function: api::Function {
+                name: self.get_string(function.name)?,
+                system_name: self.get_string(function.system_name)?,
+                filename: self.get_string(function.filename)?,
+                start_line: function.start_line,
+            },
+            address: location.address,
+            line: location.line,
+        })
+    }
+
+    /// Returns the interned mapping with the given id.
+    fn get_mapping(&self, id: MappingId) -> anyhow::Result<&Mapping> {
+        self.mappings
+            .get_index(id.to_offset())
+            .context("MappingId to have a valid interned index")
+    }
+
     fn get_stacktrace(&self, st: StackTraceId) -> anyhow::Result<&StackTrace> {
         self.stack_traces
             .get_index(st.to_raw_id())
             .with_context(|| format!("StackTraceId {:?} to exist in profile", st))
     }
 
+    fn get_string(&self, id: StringId) -> anyhow::Result<&str> {
+        self.strings
+            .get(id)
+            .with_context(|| format!("StringId {:?} to exist in profile", id))
+    }
+
+    /// Adds the stack trace and labels of a `sample` of the `other` profile
+    /// to this profile, returning the equivalent sample of this profile.
+    /// The `cache` must only be used with the same pair of profiles.
+    fn import_sample(
+        &mut self,
+        other: &Profile,
+        sample: Sample,
+        cache: &mut ImportCache,
+    ) -> anyhow::Result<Sample> {
+        let stacktrace = match cache.stack_traces.get(&sample.stacktrace) {
+            Some(stacktrace) => *stacktrace,
+            None => {
+                let locations = other
+                    .get_stacktrace(sample.stacktrace)?
+                    .locations
+                    .iter()
+                    .map(|id| Ok(self.add_location(&other.get_location(*id)?)))
+                    .collect::<anyhow::Result<_>>()?;
+                let stacktrace = self.add_stacktrace(locations);
+                cache.stack_traces.insert(sample.stacktrace, stacktrace);
+                stacktrace
+            }
+        };
+
+        let labels = match cache.label_sets.get(&sample.labels) {
+            Some(labels) => *labels,
+            None => {
+                let labels = other
+                    .get_label_set(sample.labels)?
+                    .iter()
+                    .map(|id| {
+                        let label = other.get_label(*id)?;
+                        let key = self.intern(other.get_string(label.get_key())?);
+                        let label = match label.get_value() {
+                            LabelValue::Str(str) => {
+                                Label::str(key, self.intern(other.get_string(*str)?))
+                            }
+                            LabelValue::Num { num, num_unit } => {
+                                let num_unit = match num_unit {
+                                    Some(num_unit) => {
+                                        Some(self.intern(other.get_string(*num_unit)?))
+                                    }
+                                    None => None,
+                                };
+                                Label::num(key, *num, num_unit)
+                            }
+                        };
+                        Ok(self.labels.dedup(label))
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                let labels = self.add_label_set_ids(labels);
+                cache.label_sets.insert(sample.labels, labels);
+                labels
+            }
+        };
+
+        Ok(Sample::new(labels, stacktrace))
+    }
+
+    /// Validates the sample and interns its stack and labels, returning the
+    /// sample and its values, or None if the memory budget dropped it.
+    fn intern_sample(&mut self, sample: api::Sample) -> anyhow::Result<Option<(Sample, Vec<i64>)>> {
+        anyhow::ensure!(
+            sample.values.len() == self.sample_types.len(),
+            "expected {} sample types, but sample had {} sample types",
+            self.sample_types.len(),
+            sample.values.len(),
+        );
+
+        self.validate_sample_labels(&sample.labels)?;
+        if let Some(policy) = self.over_budget_policy() {
+            let sample_id =
+                self.find_sample_within_budget(policy, &sample.locations, &sample.labels);
+            return Ok(sample_id.map(|sample_id| (sample_id, sample.values)));
+        }
+        let sample_id = self.add_stack_and_labels(&sample.locations, &sample.labels);
+        Ok(Some((sample_id, sample.values)))
+    }
+
+    /// Resolves the stack and labels of a new sample to the ones the profile
+    /// holds already, once it is over its memory budget, as nothing new is
+    /// interned anymore. The policy of the budget applies if the sample would
+    /// add interned data. Returns None if the sample must be dropped.
+    fn find_sample_within_budget(
+        &mut self,
+        policy: BudgetPolicy,
+        locations: &[api::Location],
+        labels: &[api::Label],
+    ) -> Option<Sample> {
+        let stacktrace = locations
+            .iter()
+            .map(|location| self.find_location(location))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|locations| self.find_stacktrace(locations));
+        if stacktrace.is_none() && policy != BudgetPolicy::TruncateStacks {
+            self.dropped.samples += 1;
+            return None;
+        }
+
+        let label_ids: Vec<_> = labels
+            .iter()
+            .filter_map(|label| self.find_label(label))
+            .collect();
+        let new_labels = labels.len() - label_ids.len();
+        let label_set = self.find_label_set(label_ids);
+        let labels = match (label_set, policy) {
+            (Some(labels), _) if new_labels == 0 => labels,
+            (Some(labels), BudgetPolicy::DropNewLabels) => {
+                self.dropped.labels += new_labels as u64;
+                labels
+            }
+            // The labels the profile holds don't make up a label set it
+            // holds, so they are all dropped.
+            (None, BudgetPolicy::DropNewLabels) => {
+                self.dropped.labels += labels.len() as u64;
+                self.add_label_set_ids(vec![])
+            }
+            _ => {
+                self.dropped.samples += 1;
+                return None;
+            }
+        };
+
+        let stacktrace = stacktrace.unwrap_or_else(|| {
+            self.dropped.truncated_stacks += 1;
+            self.truncated_stacktrace()
+        });
+        Some(Sample::new(labels, stacktrace))
+    }
+
+    /// The stack of a single "[truncated]" frame, which replaces the stacks
+    /// dropped by [BudgetPolicy::TruncateStacks]. It is interned the first
+    /// time it's needed only, and doesn't grow the profile afterwards.
+    fn truncated_stacktrace(&mut self) -> StackTraceId {
+        let location = self.add_location(&api::Location {
+            function: api::Function {
+                name: "[truncated]",
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        self.add_stacktrace(vec![location])
+    }
+
+    fn add_stack_and_labels(
+        &mut self,
+        locations: &[api::Location],
+        labels: &[api::Label],
+    ) -> Sample {
+        let labels = self.add_label_set(labels);
+        let locations = locations.iter().map(|l| self.add_location(l)).collect();
+        let stacktrace = self.add_stacktrace(locations);
+        Sample::new(labels, stacktrace)
+    }
+
     /// Interns the `str` as a string, returning the id in the string table.
     /// The empty string is guaranteed to have an id of [StringId::ZERO].
     #[inline]
@@ -426,18 +935,23 @@ impl Profile {
         let mut profile = Self {
             owned_period,
             owned_sample_types,
+            dropped: Default::default(),
             endpoints: Default::default(),
             functions: Default::default(),
+            interned_heap_bytes: 0,
             labels: Default::default(),
             label_sets: Default::default(),
+            live_heap: None,
             locations: Default::default(),
             mappings: Default::default(),
+            memory_budget: None,
             observations: Default::default(),
             period: None,
             sample_types: Box::new([]),
             stack_traces: Default::default(),
             start_time,
             strings: Default::default(),
+            symbolized_lines: Default::default(),
             timestamp_key: Default::default(),
             upscaling_rules: Default::default(),
         };
@@ -496,10 +1010,10 @@ impl Profile {
     }
 
     /// Validates labels
-    fn validate_sample_labels(&mut self, sample: &api::Sample) -> anyhow::Result<()> {
+    fn validate_sample_labels(&mut self, labels: &[api::Label]) -> anyhow::Result<()> {
         let mut seen: HashMap<&str, &api::Label> = HashMap::new();
 
-        for label in sample.labels.iter() {
+        for label in labels.iter() {
             if let Some(duplicate) = seen.insert(label.key, label) {
                 anyhow::bail!("Duplicate label on sample: {:?} {:?}", duplicate, label);
             }
@@ -2109,4 +2623,329 @@ mod api_tests {
         }
         Ok(())
     }
+
+    pub(super) fn create_function_sample(
+        name: &'static str,
+        values: Vec<i64>,
+    ) -> api::Sample<'static> {
+        api::Sample {
+            locations: vec![api::Location {
+                function: api::Function {
+                    name,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            values,
+            labels: vec![create_label("thread", Some("main"))],
+        }
+    }
+
+    /// Returns the values of the samples of the pprof by the name of their
+    /// leaf function, which are expected to be distinct.
+    fn values_by_function(profile: &pprof::Profile) -> HashMap<&str, Vec<i64>> {
+        profile
+            .samples
+            .iter()
+            .map(|sample| {
+                let location = &profile.locations[sample.location_ids[0] as usize - 1];
+                let function = &profile.functions[location.lines[0].function_id as usize - 1];
+                (
+                    profile.string_table_fetch(function.name).as_str(),
+                    sample.values.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge() -> anyhow::Result<()> {
+        let sample_types = create_samples_types();
+        let start_time = SystemTime::now();
+        let mut profile = Profile::new(start_time, &sample_types, None);
+        profile.add_sample(create_function_sample("foo", vec![1, 10, 100]), None)?;
+        profile.add_endpoint_count(Cow::from("endpoint"), 1)?;
+
+        let mut other = Profile::new(start_time - Duration::from_secs(1), &sample_types, None);
+        other.add_sample(create_function_sample("bar", vec![2, 20, 200]), None)?;
+        other.add_sample(create_function_sample("foo", vec![3, 30, 300]), None)?;
+        let timestamp = Timestamp::new(42).unwrap();
+        other.add_sample(
+            create_function_sample("foo", vec![5, 50, 500]),
+            Some(timestamp),
+        )?;
+        other.add_endpoint(10, Cow::from("endpoint 10"))?;
+        other.add_endpoint_count(Cow::from("endpoint"), 2)?;
+
+        profile.merge(other)?;
+        assert_eq!(profile.only_for_testing_num_aggregated_samples(), 2);
+        assert_eq!(profile.only_for_testing_num_timestamped_samples(), 1);
+        assert_eq!(profile.start_time, start_time - Duration::from_secs(1));
+
+        let encoded = profile.serialize_into_compressed_pprof(None, None)?;
+        let mut expected_stats = ProfiledEndpointsStats::default();
+        expected_stats.add_endpoint_count("endpoint".to_string(), 3);
+        assert_eq!(encoded.endpoints_stats, expected_stats);
+
+        let serialized_profile = pprof::deserialize_compressed_pprof(&encoded.buffer)?;
+        assert_eq!(serialized_profile.samples.len(), 3);
+        assert_eq!(serialized_profile.functions.len(), 2);
+        let aggregated: Vec<_> = serialized_profile
+            .samples
+            .iter()
+            .filter(|sample| sample.labels.len() == 1)
+            .collect();
+        assert_eq!(aggregated.len(), 2);
+        assert_eq!(serialized_profile.samples.len() - aggregated.len(), 1);
+        let values: HashMap<_, _> = values_by_function(&serialized_profile)
+            .into_iter()
+            .filter(|(_, values)| values[0] != 5)
+            .collect();
+        assert_eq!(values["foo"], vec![4, 40, 400]);
+        assert_eq!(values["bar"], vec![2, 20, 200]);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_with_different_sample_types() {
+        let mut profile = Profile::new(SystemTime::now(), &create_samples_types(), None);
+        let other = Profile::new(
+            SystemTime::now(),
+            &[api::ValueType::new("samples", "count")],
+            None,
+        );
+        profile.merge(other).unwrap_err();
+    }
+
+    #[test]
+    fn delta_of_cumulative_values() -> anyhow::Result<()> {
+        let sample_types = [
+            api::ValueType::new("alloc-samples", "count"),
+            api::ValueType::new("alloc-space", "bytes"),
+            api::ValueType::new("inuse-space", "bytes"),
+        ];
+        let mut delta = ProfileDelta::new(&[0, 1]);
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+
+        profile.add_sample(create_function_sample("foo", vec![1, 100, 50]), None)?;
+        profile.add_sample(create_function_sample("bar", vec![2, 200, 0]), None)?;
+        let first = profile.reset_and_return_delta(&mut delta, None)?;
+        let serialized_profile = pprof::roundtrip_to_pprof(first)?;
+        let values = values_by_function(&serialized_profile);
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["foo"], vec![1, 100, 50]);
+        assert_eq!(values["bar"], vec![2, 200, 0]);
+
+        // bar didn't change, so it's dropped, and baz is new.
+        profile.add_sample(create_function_sample("foo", vec![3, 300, 60]), None)?;
+        profile.add_sample(create_function_sample("bar", vec![2, 200, 0]), None)?;
+        profile.add_sample(create_function_sample("baz", vec![1, 10, 10]), None)?;
+        let second = profile.reset_and_return_delta(&mut delta, None)?;
+        let serialized_profile = pprof::roundtrip_to_pprof(second)?;
+        let values = values_by_function(&serialized_profile);
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["foo"], vec![2, 200, 60]);
+        assert_eq!(values["baz"], vec![1, 10, 10]);
+
+        // The counters of foo went down, as if they were reset, so they're
+        // kept as-is.
+        profile.add_sample(create_function_sample("foo", vec![1, 50, 0]), None)?;
+        let third = profile.reset_and_return_delta(&mut delta, None)?;
+        let serialized_profile = pprof::roundtrip_to_pprof(third)?;
+        let values = values_by_function(&serialized_profile);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["foo"], vec![1, 50, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn delta_with_invalid_offset() {
+        let mut delta = ProfileDelta::new(&[3]);
+        let mut profile = Profile::new(SystemTime::now(), &create_samples_types(), None);
+        delta.compute(&mut profile).unwrap_err();
+    }
+
+    fn create_request_sample(request_id: i64, depth: usize) -> api::Sample<'static> {
+        const NAMES: [&str; 4] = ["a", "b", "c", "d"];
+        api::Sample {
+            locations: NAMES[..depth]
+                .iter()
+                .map(|name| api::Location {
+                    function: api::Function {
+                        name,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .collect(),
+            values: vec![1],
+            labels: vec![
+                create_label("thread", Some("main")),
+                api::Label {
+                    key: "request id",
+                    num: request_id,
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn memory_budget_drop_samples() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        profile.add_sample(create_request_sample(0, 2), None)?;
+        let max_bytes = profile.used_bytes();
+        profile.set_memory_budget(Some(MemoryBudget {
+            max_bytes,
+            policy: BudgetPolicy::DropSamples,
+        }));
+
+        // Only the samples which would add a label or a stack are dropped.
+        profile.add_sample(create_request_sample(1, 2), None)?;
+        profile.add_sample(create_request_sample(0, 3), None)?;
+        profile.add_sample(create_request_sample(0, 2), None)?;
+        assert_eq!(profile.used_bytes(), max_bytes);
+        assert_eq!(profile.only_for_testing_num_aggregated_samples(), 1);
+        assert_eq!(
+            profile.dropped_items(),
+            DroppedItems {
+                samples: 2,
+                ..Default::default()
+            }
+        );
+
+        let serialized_profile =
+            pprof::roundtrip_to_pprof(profile.reset_and_return_previous(None)?)?;
+        assert_eq!(serialized_profile.samples.len(), 1);
+        assert_eq!(serialized_profile.samples[0].values, [2]);
+        let comments: Vec<_> = serialized_profile
+            .comment
+            .iter()
+            .map(|id| serialized_profile.string_table_fetch(*id).as_str())
+            .collect();
+        assert_eq!(comments, ["memory budget: dropped 2 samples"]);
+
+        // The budget is kept across resets, but the counts start over.
+        assert_eq!(profile.dropped_items(), DroppedItems::default());
+        assert!(profile.memory_budget.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn memory_budget_drop_new_labels() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        profile.add_sample(create_request_sample(0, 2), None)?;
+        let mut thread_sample = create_request_sample(0, 2);
+        thread_sample.labels.truncate(1);
+        profile.add_sample(thread_sample, None)?;
+        let max_bytes = profile.used_bytes();
+        profile.set_memory_budget(Some(MemoryBudget {
+            max_bytes,
+            policy: BudgetPolicy::DropNewLabels,
+        }));
+
+        // The known "request id" 0 is kept, while 1 is dropped, and the
+        // sample with a new stack is dropped.
+        profile.add_sample(create_request_sample(0, 2), None)?;
+        profile.add_sample(create_request_sample(1, 2), None)?;
+        profile.add_sample(create_request_sample(0, 3), None)?;
+        assert_eq!(profile.used_bytes(), max_bytes);
+        assert_eq!(
+            profile.dropped_items(),
+            DroppedItems {
+                labels: 1,
+                samples: 1,
+                ..Default::default()
+            }
+        );
+
+        let serialized_profile = pprof::roundtrip_to_pprof(profile)?;
+        let mut samples: Vec<_> = serialized_profile
+            .samples
+            .iter()
+            .map(|sample| (sample.labels.len(), sample.values[0]))
+            .collect();
+        samples.sort_unstable();
+        assert_eq!(samples, [(1, 2), (2, 2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_budget_truncate_stacks() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        profile.add_sample(create_request_sample(0, 3), None)?;
+        profile.set_memory_budget(Some(MemoryBudget {
+            max_bytes: profile.used_bytes(),
+            policy: BudgetPolicy::TruncateStacks,
+        }));
+
+        // The new stack is collapsed, and the sample with a new label is
+        // dropped.
+        profile.add_sample(create_request_sample(0, 3), None)?;
+        profile.add_sample(create_request_sample(0, 4), None)?;
+        profile.add_sample(create_request_sample(1, 3), None)?;
+        assert_eq!(
+            profile.dropped_items(),
+            DroppedItems {
+                samples: 1,
+                truncated_stacks: 1,
+                ..Default::default()
+            }
+        );
+
+        let serialized_profile = pprof::roundtrip_to_pprof(profile)?;
+        let mut stacks: Vec<(Vec<&str>, i64)> = serialized_profile
+            .samples
+            .iter()
+            .map(|sample| {
+                let stack = sample
+                    .location_ids
+                    .iter()
+                    .map(|id| {
+                        let location = &serialized_profile.locations[*id as usize - 1];
+                        let function = &serialized_profile.functions
+                            [location.lines[0].function_id as usize - 1];
+                        serialized_profile
+                            .string_table_fetch(function.name)
+                            .as_str()
+                    })
+                    .collect();
+                (stack, sample.values[0])
+            })
+            .collect();
+        stacks.sort_unstable();
+        assert_eq!(stacks, [(vec!["[truncated]"], 1), (vec!["a", "b", "c"], 2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_budget_merge() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        profile.add_sample(create_request_sample(0, 2), None)?;
+        profile.set_memory_budget(Some(MemoryBudget {
+            max_bytes: profile.used_bytes(),
+            policy: BudgetPolicy::DropNewLabels,
+        }));
+
+        let mut other = Profile::new(SystemTime::now(), &sample_types, None);
+        other.add_sample(create_request_sample(0, 2), None)?;
+        other.add_sample(create_request_sample(1, 2), None)?;
+        profile.merge(other)?;
+
+        // The sample of request 1 is kept without its labels, as its "thread"
+        // label alone isn't a label set of the profile.
+        assert_eq!(profile.only_for_testing_num_aggregated_samples(), 2);
+        assert_eq!(
+            profile.dropped_items(),
+            DroppedItems {
+                labels: 2,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,481 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::otlp;
+use std::collections::hash_map::Entry;
+
+impl Profile {
+    /// Converts the profile into an OpenTelemetry profile, the alternative to
+    /// [Profile::serialize_into_compressed_pprof] for OTLP pipelines. Wrap it
+    /// into [otlp::ProfilesData] to encode it, or see
+    /// [crate::exporter::ProfileExporter::build_otlp] to export it.
+    ///
+    /// The labels of the samples become attributes, with the units of the
+    /// numeric ones in the attribute units. Samples with a "trace id" label,
+    /// holding the trace id as a hex string, are linked to their span, from
+    /// their "span id" label or else their "local root span id" one, and these
+    /// labels become the link rather than attributes. Without a trace id, the
+    /// span ids are only kept as attributes. The build ids of mappings
+    /// become their [otlp::BUILD_ID_ATTRIBUTE] attribute. Timestamped samples keep
+    /// their timestamp in `timestamps_unix_nano` rather than in a label.
+    ///
+    /// # Arguments
+    /// * `end_time` - Optional end time of the profile. Passing None will use the current time.
+    /// * `duration` - Optional duration of the profile, see
+    ///   [Profile::serialize_into_compressed_pprof] for how it's computed otherwise.
+    pub fn into_otlp(
+        mut self,
+        end_time: Option<SystemTime>,
+        duration: Option<Duration>,
+    ) -> anyhow::Result<otlp::Profile> {
+        let end = end_time.unwrap_or_else(SystemTime::now);
+        let start = self.start_time;
+        let mut profile = otlp::Profile {
+            sample_type: self.sample_types.iter().map(Into::into).collect(),
+            time_nanos: Self::unix_nanos(start),
+            duration_nanos: Self::duration_nanos(start, end, duration),
+            profile_id: uuid::Uuid::new_v4().into_bytes().to_vec(),
+            ..Default::default()
+        };
+        profile.comment_strindices = self
+            .dropped_items_comments()
+            .into_iter()
+            .map(|id| id as i32)
+            .collect();
+        if let Some((period, period_type)) = self.period {
+            profile.period = period;
+            profile.period_type = Some(period_type.into());
+        }
+
+        let mut attributes: FxIndexSet<Label> = Default::default();
+        let mut links: FxIndexSet<([u8; 16], [u8; 8])> = Default::default();
+        let mut stacks: HashMap<StackTraceId, (i32, i32)> = HashMap::new();
+        self.add_live_heap_samples()?;
+        for (sample, timestamp, mut values) in std::mem::take(&mut self.observations).into_iter() {
+            let mut labels = self.enrich_sample_labels(sample, None)?;
+            self.upscaling_rules.upscale_values(&mut values, &labels)?;
+            let link_index = self
+                .take_link(&mut labels)?
+                .map(|link| links.insert_full(link).0 as i32);
+
+            let (locations_start_index, locations_length) = match stacks.entry(sample.stacktrace) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let locations = &self.get_stacktrace(sample.stacktrace)?.locations;
+                    let start_index = profile.location_indices.len() as i32;
+                    profile
+                        .location_indices
+                        .extend(locations.iter().map(|id| id.to_offset() as i32));
+                    *entry.insert((start_index, locations.len() as i32))
+                }
+            };
+
+            profile.sample.push(otlp::Sample {
+                locations_start_index,
+                locations_length,
+                value: values,
+                attribute_indices: labels
+                    .into_iter()
+                    .map(|label| attributes.insert_full(label).0 as i32)
+                    .collect(),
+                link_index,
+                timestamps_unix_nano: timestamp
+                    .map(|ts| vec![ts.get() as u64])
+                    .unwrap_or_default(),
+            });
+        }
+
+        let mut units: FxIndexSet<otlp::AttributeUnit> = Default::default();
+        for label in attributes.iter() {
+            let key = self.get_string(label.get_key())?;
+            let attribute = match *label.get_value() {
+                LabelValue::Str(str) => otlp::KeyValue::string(key, self.get_string(str)?),
+                LabelValue::Num { num, num_unit } => {
+                    if let Some(unit) = num_unit {
+                        units.insert(otlp::AttributeUnit {
+                            attribute_key_strindex: strindex(label.get_key()),
+                            unit_strindex: strindex(unit),
+                        });
+                    }
+                    otlp::KeyValue::int(key, num)
+                }
+            };
+            profile.attribute_table.push(attribute);
+        }
+        profile.attribute_units = units.into_iter().collect();
+        profile.link_table = links
+            .into_iter()
+            .map(|(trace_id, span_id)| otlp::Link {
+                trace_id: trace_id.to_vec(),
+                span_id: span_id.to_vec(),
+            })
+            .collect();
+
+        profile.mapping_table = Vec::with_capacity(self.mappings.len());
+        for mapping in self.mappings.iter() {
+            let mut attribute_indices = Vec::new();
+            if !mapping.build_id.is_zero() {
+                attribute_indices.push(profile.attribute_table.len() as i32);
+                profile.attribute_table.push(otlp::KeyValue::string(
+                    otlp::BUILD_ID_ATTRIBUTE,
+                    self.get_string(mapping.build_id)?,
+                ));
+            }
+            profile.mapping_table.push(otlp::Mapping {
+                memory_start: mapping.memory_start,
+                memory_limit: mapping.memory_limit,
+                file_offset: mapping.file_offset,
+                filename_strindex: strindex(mapping.filename),
+                attribute_indices,
+                ..Default::default()
+            });
+        }
+
+        profile.location_table = self
+            .locations
+            .iter()
+            .enumerate()
+            .map(|(offset, location)| {
+                let line = |function_id: FunctionId, line: i64| otlp::Line {
+                    function_index: function_id.to_offset() as i32,
+                    line,
+                    column: 0,
+                };
+                let lines = match self.symbolized_lines.get(&LocationId::from_offset(offset)) {
+                    Some(lines) => lines.iter().map(|(f, l)| line(*f, *l)).collect(),
+                    None => vec![line(location.function_id, location.line)],
+                };
+                otlp::Location {
+                    mapping_index: Some(location.mapping_id.to_offset() as i32),
+                    address: location.address,
+                    line: lines,
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        profile.function_table = self
+            .functions
+            .iter()
+            .map(|function| otlp::Function {
+                name_strindex: strindex(function.name),
+                system_name_strindex: strindex(function.system_name),
+                filename_strindex: strindex(function.filename),
+                start_line: function.start_line,
+            })
+            .collect();
+
+        let mut lender = self.strings.into_lending_iter();
+        while let Some(item) = lender.next() {
+            profile.string_table.push(item.to_string());
+        }
+
+        Ok(profile)
+    }
+
+    /// Removes the trace id and span id labels from `labels` and returns them
+    /// as the trace id and span id of a link, if the labels have a valid trace
+    /// id and a span id. The labels are left as-is otherwise.
+    fn take_link(&self, labels: &mut Vec<Label>) -> anyhow::Result<Option<([u8; 16], [u8; 8])>> {
+        let mut trace_id = None;
+        let mut span_id = None;
+        let mut local_root_span_id = None;
+        for (index, label) in labels.iter().enumerate() {
+            match (self.get_string(label.get_key())?, *label.get_value()) {
+                ("trace id", LabelValue::Str(str)) => {
+                    trace_id = u128::from_str_radix(self.get_string(str)?, 16)
+                        .ok()
+                        .filter(|id| *id != 0)
+                        .map(|id| (index, id));
+                }
+                ("span id", LabelValue::Num { num, .. }) if num != 0 => {
+                    span_id = Some((index, num));
+                }
+                ("local root span id", LabelValue::Num { num, .. }) if num != 0 => {
+                    local_root_span_id = Some((index, num));
+                }
+                _ => {}
+            }
+        }
+        let (Some((trace_id_index, trace_id)), Some((span_id_index, span_id))) =
+            (trace_id, span_id.or(local_root_span_id))
+        else {
+            return Ok(None);
+        };
+        // Remove the later label first, so the index of the other one stays valid.
+        labels.remove(trace_id_index.max(span_id_index));
+        labels.remove(trace_id_index.min(span_id_index));
+        Ok(Some((
+            trace_id.to_be_bytes(),
+            (span_id as u64).to_be_bytes(),
+        )))
+    }
+}
+
+/// The index of the string in the string table, which OTLP profiles encode
+/// as an int32.
+fn strindex(id: StringId) -> i32 {
+    id.to_raw_id() as i32
+}
+
+impl From<ValueType> for otlp::ValueType {
+    fn from(vt: ValueType) -> Self {
+        Self::from(&vt)
+    }
+}
+
+impl From<&ValueType> for otlp::ValueType {
+    fn from(vt: &ValueType) -> Self {
+        Self {
+            type_strindex: strindex(vt.r#type),
+            unit_strindex: strindex(vt.unit),
+            aggregation_temporality: otlp::AggregationTemporality::Unspecified as i32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn into_otlp() -> anyhow::Result<()> {
+        let sample_types = [
+            api::ValueType::new("samples", "count"),
+            api::ValueType::new("wall-time", "nanoseconds"),
+        ];
+        let period = api::Period {
+            r#type: sample_types[1],
+            value: 10_000_000,
+        };
+        let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut profile = Profile::new(start_time, &sample_types, Some(period));
+
+        let mapping = api::Mapping {
+            memory_start: 0x1000,
+            memory_limit: 0x2000,
+            filename: "/usr/bin/app",
+            build_id: "0123abcd",
+            ..Default::default()
+        };
+        let main = api::Location {
+            mapping,
+            function: api::Function {
+                name: "main",
+                filename: "main.c",
+                ..Default::default()
+            },
+            address: 0x1010,
+            line: 3,
+        };
+        let work = api::Location {
+            mapping,
+            function: api::Function {
+                name: "work",
+                filename: "main.c",
+                ..Default::default()
+            },
+            address: 0x1020,
+            line: 7,
+        };
+        let span_id = u64::MAX - 1;
+        let labels = vec![
+            api::Label {
+                key: "local root span id",
+                num: span_id as i64,
+                ..Default::default()
+            },
+            api::Label {
+                key: "allocation size",
+                num: 64,
+                num_unit: Some("bytes"),
+                ..Default::default()
+            },
+        ];
+        profile.add_sample(
+            api::Sample {
+                locations: vec![work, main],
+                values: vec![1, 100],
+                labels: labels.clone(),
+            },
+            None,
+        )?;
+        profile.add_sample(
+            api::Sample {
+                locations: vec![work, main],
+                values: vec![2, 200],
+                labels: vec![],
+            },
+            Timestamp::new(42),
+        )?;
+        profile.add_endpoint(span_id, Cow::from("GET /"))?;
+
+        let end_time = start_time + Duration::from_secs(60);
+        let profile = profile.into_otlp(Some(end_time), None)?;
+
+        // Make sure the message survives encoding, like it would on export.
+        let data = otlp::ProfilesData::new(
+            vec![otlp::KeyValue::string("service.name", "app")],
+            otlp::InstrumentationScope::default(),
+            vec![profile],
+        );
+        let data = otlp::ProfilesData::decode(data.encode_to_vec().as_slice())?;
+        let profile = &data.resource_profiles[0].scope_profiles[0].profiles[0];
+        let string = |id: i32| profile.string_table[id as usize].as_str();
+        assert_eq!(profile.profile_id.len(), 16);
+        assert_eq!(profile.time_nanos, 1_700_000_000_000_000_000);
+
+        let sample_types: Vec<_> = profile
+            .sample_type
+            .iter()
+            .map(|vt| (string(vt.type_strindex), string(vt.unit_strindex)))
+            .collect();
+        assert_eq!(
+            sample_types,
+            [("samples", "count"), ("wall-time", "nanoseconds")]
+        );
+        assert_eq!(profile.period, 10_000_000);
+        assert_eq!(profile.duration_nanos, 60_000_000_000);
+
+        assert_eq!(profile.mapping_table.len(), 1);
+        let mapping = &profile.mapping_table[0];
+        assert_eq!(string(mapping.filename_strindex), "/usr/bin/app");
+        assert_eq!(mapping.attribute_indices.len(), 1);
+        assert_eq!(
+            profile.attribute_table[mapping.attribute_indices[0] as usize],
+            otlp::KeyValue::string(otlp::BUILD_ID_ATTRIBUTE, "0123abcd")
+        );
+
+        assert_eq!(profile.sample.len(), 2);
+        for sample in profile.sample.iter() {
+            let start = sample.locations_start_index as usize;
+            let end = start + sample.locations_length as usize;
+            let functions: Vec<_> = profile.location_indices[start..end]
+                .iter()
+                .map(|index| {
+                    let location = &profile.location_table[*index as usize];
+                    assert_eq!(location.mapping_index, Some(0));
+                    let function =
+                        &profile.function_table[location.line[0].function_index as usize];
+                    string(function.name_strindex)
+                })
+                .collect();
+            assert_eq!(functions, ["work", "main"]);
+        }
+        // Both samples share the same stack.
+        assert_eq!(profile.location_indices.len(), 2);
+
+        let aggregated = profile
+            .sample
+            .iter()
+            .find(|sample| sample.timestamps_unix_nano.is_empty())
+            .unwrap();
+        assert_eq!(aggregated.value, [1, 100]);
+        let mut attributes: Vec<_> = aggregated
+            .attribute_indices
+            .iter()
+            .map(|index| profile.attribute_table[*index as usize].clone())
+            .collect();
+        attributes.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            attributes,
+            [
+                otlp::KeyValue::int("allocation size", 64),
+                otlp::KeyValue::int("local root span id", span_id as i64),
+                otlp::KeyValue::string("trace endpoint", "GET /"),
+            ]
+        );
+        assert_eq!(profile.attribute_units.len(), 1);
+        assert_eq!(
+            string(profile.attribute_units[0].attribute_key_strindex),
+            "allocation size"
+        );
+        assert_eq!(string(profile.attribute_units[0].unit_strindex), "bytes");
+        // Without a trace id, samples aren't linked to their span.
+        assert!(profile.link_table.is_empty());
+        assert_eq!(aggregated.link_index, None);
+
+        let timestamped = profile
+            .sample
+            .iter()
+            .find(|sample| !sample.timestamps_unix_nano.is_empty())
+            .unwrap();
+        assert_eq!(timestamped.value, [2, 200]);
+        assert_eq!(timestamped.timestamps_unix_nano, [42]);
+        assert!(timestamped.attribute_indices.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn into_otlp_links() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        let trace_id = "0123456789abcdef0011223344556677";
+        let sample = |labels| api::Sample {
+            locations: vec![],
+            values: vec![1],
+            labels,
+        };
+        let trace_id_label = api::Label {
+            key: "trace id",
+            str: Some(trace_id),
+            ..Default::default()
+        };
+        let local_root_span_id_label = api::Label {
+            key: "local root span id",
+            num: 1,
+            ..Default::default()
+        };
+        let span_id_label = api::Label {
+            key: "span id",
+            num: 2,
+            ..Default::default()
+        };
+        // Linked to the span, rather than to the local root span.
+        profile.add_sample(
+            sample(vec![
+                trace_id_label,
+                local_root_span_id_label,
+                span_id_label,
+            ]),
+            None,
+        )?;
+        // Linked to the local root span.
+        profile.add_sample(sample(vec![trace_id_label, local_root_span_id_label]), None)?;
+        // Not linked without a trace id.
+        profile.add_sample(sample(vec![span_id_label]), None)?;
+
+        let profile = profile.into_otlp(None, None)?;
+        let link = |span_id: u64| otlp::Link {
+            trace_id: 0x0123456789abcdef0011223344556677u128
+                .to_be_bytes()
+                .to_vec(),
+            span_id: span_id.to_be_bytes().to_vec(),
+        };
+        assert_eq!(profile.link_table.len(), 2);
+
+        let attributes = |sample: &otlp::Sample| -> Vec<_> {
+            sample
+                .attribute_indices
+                .iter()
+                .map(|index| profile.attribute_table[*index as usize].key.as_str())
+                .collect()
+        };
+        assert_eq!(profile.sample.len(), 3);
+        for sample in profile.sample.iter() {
+            match sample.link_index {
+                Some(index) if profile.link_table[index as usize] == link(2) => {
+                    assert_eq!(attributes(sample), ["local root span id"])
+                }
+                Some(index) => {
+                    assert_eq!(profile.link_table[index as usize], link(1));
+                    assert!(attributes(sample).is_empty());
+                }
+                None => assert_eq!(attributes(sample), ["span id"]),
+            }
+        }
+        Ok(())
+    }
+}
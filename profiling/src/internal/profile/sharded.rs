@@ -0,0 +1,251 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// A [Profile] that samples can be added to from many threads at once.
+///
+/// [Profile::add_sample] takes `&mut self`, so sharing a profile between
+/// threads means wrapping it in a mutex that every sampler contends on.
+/// Instead, each thread adds its samples to one of a fixed number of shards,
+/// each a profile of its own behind its own mutex, which is only contended by
+/// the threads mapped to the same shard. The shards are merged into the main
+/// profile with [ShardedProfile::flush], which can be called on a background
+/// cadence, and always before the profile is reset.
+///
+/// Merging keeps the semantics of [Profile::add_sample]: samples with the
+/// same stack and labels are aggregated no matter which shard they were added
+/// to, and timestamped samples keep their timestamps. Upscaling rules,
+/// endpoints and tracked allocations are added to the main profile directly.
+pub struct ShardedProfile {
+    main: Mutex<Profile>,
+    shards: Box<[Mutex<Profile>]>,
+}
+
+impl ShardedProfile {
+    /// Creates a profile with `num_shards` shards, at least one. A number of
+    /// shards around the number of threads adding samples keeps contention
+    /// low, at the cost of interning their strings, stacks and labels once
+    /// per shard until the next flush.
+    pub fn new(
+        start_time: SystemTime,
+        sample_types: &[api::ValueType],
+        period: Option<api::Period>,
+        num_shards: usize,
+    ) -> Self {
+        let shards = (0..num_shards.max(1))
+            .map(|_| Mutex::new(Profile::new(start_time, sample_types, period)))
+            .collect();
+        Self {
+            main: Mutex::new(Profile::new(start_time, sample_types, period)),
+            shards,
+        }
+    }
+
+    /// Adds the sample to the shard of the current thread. See
+    /// [Profile::add_sample].
+    pub fn add_sample(
+        &self,
+        sample: api::Sample,
+        timestamp: Option<Timestamp>,
+    ) -> anyhow::Result<()> {
+        lock(&self.shards[Self::shard_index(self.shards.len())])?.add_sample(sample, timestamp)
+    }
+
+    /// See [Profile::add_endpoint].
+    pub fn add_endpoint(&self, local_root_span_id: u64, endpoint: Cow<str>) -> anyhow::Result<()> {
+        lock(&self.main)?.add_endpoint(local_root_span_id, endpoint)
+    }
+
+    /// See [Profile::add_endpoint_count].
+    pub fn add_endpoint_count(&self, endpoint: Cow<str>, value: i64) -> anyhow::Result<()> {
+        lock(&self.main)?.add_endpoint_count(endpoint, value)
+    }
+
+    /// See [Profile::add_upscaling_rule].
+    pub fn add_upscaling_rule(
+        &self,
+        offset_values: &[usize],
+        label_name: &str,
+        label_value: &str,
+        upscaling_info: UpscalingInfo,
+    ) -> anyhow::Result<()> {
+        lock(&self.main)?.add_upscaling_rule(offset_values, label_name, label_value, upscaling_info)
+    }
+
+    /// Sets the memory budget of the main profile and of each shard, see
+    /// [Profile::set_memory_budget]. Each shard is held to the whole budget
+    /// between flushes, so the shards may use up to `num_shards` times the
+    /// budget together. The items dropped by the shards are reported by the
+    /// main profile once they are flushed.
+    pub fn set_memory_budget(&self, budget: Option<MemoryBudget>) -> anyhow::Result<()> {
+        lock(&self.main)?.set_memory_budget(budget);
+        for shard in self.shards.iter() {
+            lock(shard)?.set_memory_budget(budget);
+        }
+        Ok(())
+    }
+
+    /// See [Profile::enable_live_heap]. The allocations are tracked by the
+    /// main profile, so that they can be untracked from any thread.
+    pub fn enable_live_heap(&self, max_tracked: usize) -> anyhow::Result<()> {
+        lock(&self.main)?.enable_live_heap(max_tracked);
+        Ok(())
+    }
+
+    /// See [Profile::track_allocation].
+    pub fn track_allocation(&self, id: u64, sample: api::Sample) -> anyhow::Result<()> {
+        lock(&self.main)?.track_allocation(id, sample)
+    }
+
+    /// See [Profile::untrack_allocation].
+    pub fn untrack_allocation(&self, id: u64) -> anyhow::Result<bool> {
+        Ok(lock(&self.main)?.untrack_allocation(id))
+    }
+
+    /// Merges the samples of every shard into the main profile and empties
+    /// the shards. Samples keep being added to the other shards while one is
+    /// being merged.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let mut main = lock(&self.main)?;
+        self.flush_into(&mut main, None)
+    }
+
+    /// Flushes the shards, then resets the profile and returns the previous
+    /// one, ready to be serialized. See [Profile::reset_and_return_previous].
+    pub fn reset_and_return_previous(
+        &self,
+        start_time: Option<SystemTime>,
+    ) -> anyhow::Result<Profile> {
+        let start_time = start_time.unwrap_or_else(SystemTime::now);
+        let mut main = lock(&self.main)?;
+        self.flush_into(&mut main, Some(start_time))?;
+        main.reset_and_return_previous(Some(start_time))
+    }
+
+    /// Merges the shards into `main`. The shards restart at `start_time` if
+    /// given, otherwise they keep their start time, which is merged into the
+    /// main profile with the samples.
+    fn flush_into(&self, main: &mut Profile, start_time: Option<SystemTime>) -> anyhow::Result<()> {
+        for shard in self.shards.iter() {
+            // Only hold the lock of the shard while swapping it out, so the
+            // threads adding to it don't wait on the merge.
+            let samples = {
+                let mut shard = lock(shard)?;
+                let start_time = start_time.unwrap_or(shard.start_time);
+                shard.reset_and_return_previous(Some(start_time))?
+            };
+            main.merge(samples)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the shard of the current thread. Threads are assigned shards
+    /// in a round-robin fashion the first time they add a sample.
+    fn shard_index(num_shards: usize) -> usize {
+        static NEXT_THREAD_INDEX: AtomicUsize = AtomicUsize::new(0);
+        thread_local! {
+            static THREAD_INDEX: usize = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+        }
+        THREAD_INDEX.with(|index| *index % num_shards)
+    }
+}
+
+fn lock(profile: &Mutex<Profile>) -> anyhow::Result<MutexGuard<'_, Profile>> {
+    profile
+        .lock()
+        .map_err(|_| anyhow::anyhow!("profile lock was poisoned"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api_tests::create_function_sample as sample;
+    use std::sync::Arc;
+
+    #[test]
+    fn samples_from_threads_are_aggregated() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let profile = Arc::new(ShardedProfile::new(
+            SystemTime::now(),
+            &sample_types,
+            None,
+            4,
+        ));
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let profile = profile.clone();
+                std::thread::spawn(move || -> anyhow::Result<()> {
+                    for i in 0..100 {
+                        profile.add_sample(sample("foo", vec![1]), None)?;
+                        if i % 10 == 0 {
+                            profile.flush()?;
+                        }
+                        profile.add_sample(sample("bar", vec![2]), Timestamp::new(i + 1))?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap()?;
+        }
+
+        let profile = profile.reset_and_return_previous(None)?;
+        assert_eq!(profile.only_for_testing_num_aggregated_samples(), 1);
+        assert_eq!(profile.only_for_testing_num_timestamped_samples(), 800);
+        let (_, _, values) = profile
+            .observations
+            .into_iter()
+            .find(|(_, timestamp, _)| timestamp.is_none())
+            .unwrap();
+        assert_eq!(values, [800]);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_budget_of_shards() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let profile = ShardedProfile::new(SystemTime::now(), &sample_types, None, 2);
+        profile.set_memory_budget(Some(MemoryBudget {
+            max_bytes: 0,
+            policy: BudgetPolicy::DropSamples,
+        }))?;
+        profile.add_sample(sample("foo", vec![1]), None)?;
+        profile.add_sample(sample("bar", vec![1]), None)?;
+
+        let profile = profile.reset_and_return_previous(None)?;
+        assert_eq!(profile.only_for_testing_num_aggregated_samples(), 0);
+        assert_eq!(profile.dropped_items().samples, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn live_heap_across_threads() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("heap-live-size", "bytes")];
+        let profile = Arc::new(ShardedProfile::new(
+            SystemTime::now(),
+            &sample_types,
+            None,
+            2,
+        ));
+        profile.enable_live_heap(10)?;
+        profile.track_allocation(1, sample("foo", vec![8]))?;
+        profile.track_allocation(2, sample("bar", vec![16]))?;
+
+        let untracker = profile.clone();
+        assert!(std::thread::spawn(move || untracker.untrack_allocation(1))
+            .join()
+            .unwrap()?);
+
+        let previous = profile.reset_and_return_previous(None)?;
+        assert_eq!(previous.only_for_testing_num_aggregated_samples(), 0);
+        let pprof = crate::pprof::roundtrip_to_pprof(previous)?;
+        assert_eq!(pprof.samples.len(), 1);
+        assert_eq!(pprof.samples[0].values, [16]);
+        Ok(())
+    }
+}
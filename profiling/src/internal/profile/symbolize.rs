@@ -0,0 +1,167 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+impl Profile {
+    /// Symbolizes the native frames that were added with an address and a
+    /// mapping but no function, so that samplers of native stacks don't pay
+    /// for symbolization on the sampling path. The address of the location is
+    /// translated to an offset in the file of its mapping, which must be an
+    /// ELF file on the local filesystem.
+    ///
+    /// The functions of a location, including the ones inlined at the
+    /// address, are serialized as the lines of the location. Locations which
+    /// can't be symbolized are serialized with their address only.
+    ///
+    /// Call this right before serializing the profile, as the symbolized
+    /// functions aren't kept when the profile is reset or merged. A profile
+    /// imported with [Profile::import_pprof] can be symbolized too, to
+    /// symbolize profiles outside of the profiled process.
+    pub fn symbolize(&mut self, symbolizer: &mut NativeSymbolizer) -> anyhow::Result<()> {
+        // Group the file offsets by mapping, to symbolize each file at once.
+        let mut offsets_by_mapping: HashMap<MappingId, (Vec<LocationId>, Vec<u64>)> =
+            HashMap::new();
+        for (offset, location) in self.locations.iter().enumerate() {
+            let function = self.get_function(location.function_id)?;
+            if function.name != StringId::ZERO || location.address == 0 {
+                continue;
+            }
+            let mapping = self.get_mapping(location.mapping_id)?;
+            if mapping.filename == StringId::ZERO {
+                continue;
+            }
+            let Some(file_offset) = location
+                .address
+                .checked_sub(mapping.memory_start)
+                .and_then(|offset| offset.checked_add(mapping.file_offset))
+            else {
+                continue;
+            };
+            let (locations, file_offsets) =
+                offsets_by_mapping.entry(location.mapping_id).or_default();
+            locations.push(LocationId::from_offset(offset));
+            file_offsets.push(file_offset);
+        }
+
+        for (mapping_id, (locations, file_offsets)) in offsets_by_mapping {
+            let mapping = self.get_mapping(mapping_id)?;
+            let path = self.get_string(mapping.filename)?;
+            let build_id = self.get_string(mapping.build_id)?;
+            let symbolized: Vec<Vec<SymbolizedFrame>> = symbolizer
+                .symbolize(path, build_id, &file_offsets)
+                .into_iter()
+                .map(<[SymbolizedFrame]>::to_vec)
+                .collect();
+            for (location_id, frames) in locations.into_iter().zip(symbolized) {
+                if frames.is_empty() {
+                    continue;
+                }
+                let lines = frames
+                    .iter()
+                    .map(|frame| {
+                        let function = self.add_function(&api::Function {
+                            name: &frame.name,
+                            filename: &frame.filename,
+                            ..Default::default()
+                        });
+                        (function, frame.line)
+                    })
+                    .collect();
+                self.symbolized_lines.insert(location_id, lines);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbolize() -> anyhow::Result<()> {
+        let sample_types = [api::ValueType::new("samples", "count")];
+        let mut profile = Profile::new(SystemTime::now(), &sample_types, None);
+        let mapping = api::Mapping {
+            memory_start: 0x7f00_0000_0000,
+            memory_limit: 0x7f00_0001_0000,
+            file_offset: 0x1000,
+            filename: "/usr/lib/libapp.so",
+            build_id: "0123abcd",
+        };
+        let native = |address| api::Location {
+            mapping,
+            address,
+            ..Default::default()
+        };
+        let symbolized = api::Location {
+            function: api::Function {
+                name: "main",
+                ..Default::default()
+            },
+            ..native(0x7f00_0000_0300)
+        };
+        profile.add_sample(
+            api::Sample {
+                locations: vec![
+                    native(0x7f00_0000_0100),
+                    native(0x7f00_0000_0200),
+                    symbolized,
+                ],
+                values: vec![1],
+                labels: vec![],
+            },
+            None,
+        )?;
+
+        let mut symbolizer = NativeSymbolizer::new();
+        symbolizer.insert_into_cache(
+            "0123abcd",
+            0x1100,
+            vec![
+                SymbolizedFrame {
+                    name: "inlined".to_string(),
+                    filename: "app.h".to_string(),
+                    line: 12,
+                },
+                SymbolizedFrame {
+                    name: "work".to_string(),
+                    filename: "app.c".to_string(),
+                    line: 34,
+                },
+            ],
+        );
+        // Not symbolized, like the address of a stripped function.
+        symbolizer.insert_into_cache("0123abcd", 0x1200, vec![]);
+        profile.symbolize(&mut symbolizer)?;
+
+        let pprof = crate::pprof::roundtrip_to_pprof(profile)?;
+        let string = |id: i64| pprof.string_table[id as usize].as_str();
+        let lines: Vec<Vec<_>> = pprof.samples[0]
+            .location_ids
+            .iter()
+            .map(|id| {
+                let location = &pprof.locations[*id as usize - 1];
+                location
+                    .lines
+                    .iter()
+                    .map(|line| {
+                        let function = &pprof.functions[line.function_id as usize - 1];
+                        (string(function.name), string(function.filename), line.line)
+                    })
+                    .filter(|(name, _, _)| !name.is_empty())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                vec![("inlined", "app.h", 12), ("work", "app.c", 34)],
+                vec![],
+                vec![("main", "", 0)],
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use blazesym::symbolize::{CodeInfo, Elf, Input, Source, Sym, Symbolized, Symbolizer};
+use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+
+/// The number of symbolized offsets a [NativeSymbolizer] caches by default.
+const DEFAULT_MAX_CACHED_OFFSETS: usize = 64 * 1024;
+
+/// A function of the source code of a native frame.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SymbolizedFrame {
+    pub name: String,
+    pub filename: String,
+    pub line: i64,
+}
+
+impl SymbolizedFrame {
+    fn new(name: &str, code_info: Option<&CodeInfo>) -> Self {
+        let (filename, line) = match code_info {
+            Some(code_info) => (
+                code_info.to_path().display().to_string(),
+                code_info.line.unwrap_or_default().into(),
+            ),
+            None => (String::new(), 0),
+        };
+        Self {
+            name: name.to_string(),
+            filename,
+            line,
+        }
+    }
+}
+
+/// Symbolizes the addresses of native frames from the ELF files they were
+/// loaded from, so that samplers can record addresses and leave the
+/// symbolization off the sampling path, see [crate::internal::Profile::symbolize].
+///
+/// The symbolized frames are cached by the build id of the file and the
+/// offset in the file, or by the path of the file for the files without a
+/// build id. The build id of the file is checked before it is symbolized, so
+/// that a file replaced in place, e.g. by an upgrade, isn't cached under the
+/// build id of the file it replaced. A symbolizer is meant to be kept for the lifetime of the process
+/// and used for each profile, as the same addresses show up in each of them.
+/// Once the cache holds its maximum number of offsets, it is emptied and
+/// refilled with the offsets that keep showing up.
+pub struct NativeSymbolizer {
+    cache: HashMap<Box<str>, HashMap<u64, Box<[SymbolizedFrame]>>>,
+    cached_offsets: usize,
+    max_cached_offsets: usize,
+    symbolizer: Symbolizer,
+}
+
+impl Default for NativeSymbolizer {
+    fn default() -> Self {
+        Self::with_max_cached_offsets(DEFAULT_MAX_CACHED_OFFSETS)
+    }
+}
+
+impl NativeSymbolizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a symbolizer which caches up to about `max_cached_offsets`
+    /// symbolized offsets, rather than the default of 65536.
+    pub fn with_max_cached_offsets(max_cached_offsets: usize) -> Self {
+        Self {
+            cache: HashMap::new(),
+            cached_offsets: 0,
+            max_cached_offsets,
+            symbolizer: Symbolizer::new(),
+        }
+    }
+
+    /// Returns the frames of each of the `file_offsets` of the file at `path`,
+    /// innermost first: the function the offset is in comes last, after the
+    /// functions inlined into it. Offsets which couldn't be symbolized have
+    /// no frames, as do the offsets which aren't cached yet when the file
+    /// doesn't have the hex encoded `build_id`.
+    pub fn symbolize(
+        &mut self,
+        path: &str,
+        build_id: &str,
+        file_offsets: &[u64],
+    ) -> Vec<&[SymbolizedFrame]> {
+        let key = if build_id.is_empty() { path } else { build_id };
+        let cached = |cache: &HashMap<_, HashMap<u64, _>>, offset: &u64| {
+            cache
+                .get(key)
+                .is_some_and(|frames: &HashMap<u64, _>| frames.contains_key(offset))
+        };
+
+        let mut missing: Vec<u64> = file_offsets
+            .iter()
+            .filter(|offset| !cached(&self.cache, offset))
+            .copied()
+            .collect();
+        if !missing.is_empty() && self.cached_offsets + missing.len() > self.max_cached_offsets {
+            self.cache.clear();
+            self.cached_offsets = 0;
+            missing = file_offsets.to_vec();
+        }
+        missing.sort_unstable();
+        missing.dedup();
+        if !missing.is_empty() && (build_id.is_empty() || Self::has_build_id(path, build_id)) {
+            let source = Source::from(Elf::new(path));
+            // When the file can't be read, its offsets aren't cached, so that
+            // they are symbolized once it can be, like after a transient
+            // error.
+            if let Ok(symbolized) = self
+                .symbolizer
+                .symbolize(&source, Input::FileOffset(missing.as_slice()))
+            {
+                let cache = self.cache.entry(key.into()).or_default();
+                for (offset, symbolized) in missing.into_iter().zip(symbolized) {
+                    let frames = match symbolized {
+                        Symbolized::Sym(sym) => Self::frames(sym),
+                        Symbolized::Unknown(_) => Box::new([]),
+                    };
+                    if cache.insert(offset, frames).is_none() {
+                        self.cached_offsets += 1;
+                    }
+                }
+            }
+        }
+
+        let cache = self.cache.get(key);
+        file_offsets
+            .iter()
+            .map(|offset| {
+                cache
+                    .and_then(|cache| cache.get(offset))
+                    .map_or(&[][..], |frames| frames)
+            })
+            .collect()
+    }
+
+    /// Whether the ELF file at `path` has the hex encoded `build_id`.
+    fn has_build_id(path: &str, build_id: &str) -> bool {
+        match read_build_id(path) {
+            Ok(Some(file_build_id)) => {
+                let file_build_id: String =
+                    file_build_id.iter().map(|b| format!("{b:02x}")).collect();
+                file_build_id.eq_ignore_ascii_case(build_id)
+            }
+            _ => false,
+        }
+    }
+
+    fn frames(sym: Sym) -> Box<[SymbolizedFrame]> {
+        // The code info of the symbol and of each inlined function is the
+        // line being run in that function, like the lines of a location.
+        sym.inlined
+            .iter()
+            .rev()
+            .map(|inlined| SymbolizedFrame::new(&inlined.name, inlined.code_info.as_ref()))
+            .chain(std::iter::once(SymbolizedFrame::new(
+                &sym.name,
+                sym.code_info.as_ref(),
+            )))
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn insert_into_cache(
+        &mut self,
+        build_id: &str,
+        file_offset: u64,
+        frames: Vec<SymbolizedFrame>,
+    ) {
+        let cache = self.cache.entry(build_id.into()).or_default();
+        if cache
+            .insert(file_offset, frames.into_boxed_slice())
+            .is_none()
+        {
+            self.cached_offsets += 1;
+        }
+    }
+}
+
+/// Reads the GNU build id of the 64-bit little-endian ELF file at `path`, from
+/// its note segments.
+fn read_build_id(path: &str) -> std::io::Result<Option<Vec<u8>>> {
+    const PT_NOTE: u32 = 4;
+    const NT_GNU_BUILD_ID: u32 = 3;
+
+    let file = File::open(path)?;
+    let mut header = [0u8; 64];
+    file.read_exact_at(&mut header, 0)?;
+    if &header[..6] != b"\x7fELF\x02\x01" {
+        return Ok(None);
+    }
+    let u16_at = |bytes: &[u8], at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at =
+        |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    let u64_at =
+        |bytes: &[u8], at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let align4 = |size: usize| (size + 3) & !3;
+
+    let phoff = u64_at(&header, 0x20);
+    let phentsize = u16_at(&header, 0x36) as u64;
+    let phnum = u16_at(&header, 0x38) as u64;
+    if phentsize < 0x38 {
+        return Ok(None);
+    }
+    for i in 0..phnum {
+        let mut phdr = [0u8; 0x38];
+        file.read_exact_at(&mut phdr, phoff + i * phentsize)?;
+        if u32_at(&phdr, 0) != PT_NOTE {
+            continue;
+        }
+        let size = u64_at(&phdr, 0x20).min(1 << 16) as usize;
+        let mut notes = vec![0u8; size];
+        file.read_exact_at(&mut notes, u64_at(&phdr, 0x8))?;
+        let mut at = 0;
+        while at + 12 <= notes.len() {
+            let name_size = u32_at(&notes, at) as usize;
+            let desc_size = u32_at(&notes, at + 4) as usize;
+            let note_type = u32_at(&notes, at + 8);
+            let name_start = at + 12;
+            let desc_start = name_start + align4(name_size);
+            let desc_end = desc_start + desc_size;
+            if desc_end > notes.len() {
+                break;
+            }
+            if note_type == NT_GNU_BUILD_ID
+                && &notes[name_start..name_start + name_size] == b"GNU\0"
+            {
+                return Ok(Some(notes[desc_start..desc_end].to_vec()));
+            }
+            at = desc_start + align4(desc_size);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    fn symbolized_function() -> usize {
+        std::hint::black_box(42)
+    }
+
+    /// Returns the path of the test binary and the offset of `address` in
+    /// it, from the mappings of the process.
+    fn file_offset(address: usize) -> (String, u64) {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        maps.lines()
+            .find_map(|line| {
+                let mut fields = line.split_whitespace();
+                let (start, end) = fields.next()?.split_once('-')?;
+                let start = usize::from_str_radix(start, 16).ok()?;
+                let end = usize::from_str_radix(end, 16).ok()?;
+                let offset = u64::from_str_radix(fields.nth(1)?, 16).ok()?;
+                let path = fields.nth(2)?;
+                (start..end)
+                    .contains(&address)
+                    .then(|| (path.to_string(), (address - start) as u64 + offset))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)]
+    fn symbolize_test_binary() {
+        assert_eq!(symbolized_function(), 42);
+        let (path, offset) = file_offset(symbolized_function as *const () as usize);
+        let mut symbolizer = NativeSymbolizer::new();
+        let frames = symbolizer.symbolize(&path, "", &[offset]);
+        let function = frames[0].last().expect("the function to be symbolized");
+        assert!(
+            function.name.contains("symbolized_function"),
+            "unexpected function {function:?}"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)]
+    fn cache_is_bounded() {
+        let path = std::env::current_exe().unwrap();
+        let path = path.to_str().unwrap();
+        let mut symbolizer = NativeSymbolizer::with_max_cached_offsets(2);
+        symbolizer.symbolize(path, "", &[0, 1, 1]);
+        assert_eq!(symbolizer.cached_offsets, 2);
+        symbolizer.symbolize(path, "", &[0]);
+        assert_eq!(symbolizer.cached_offsets, 2);
+
+        // Over the limit, the cache starts over.
+        symbolizer.symbolize(path, "", &[2]);
+        assert_eq!(symbolizer.cached_offsets, 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)]
+    fn other_build_id_isnt_cached() {
+        let path = std::env::current_exe().unwrap();
+        let path = path.to_str().unwrap();
+        let build_id = read_build_id(path).unwrap();
+        let mut symbolizer = NativeSymbolizer::new();
+        let frames = symbolizer.symbolize(path, "0123abcd", &[0]);
+        assert!(frames[0].is_empty());
+        assert_eq!(symbolizer.cached_offsets, 0);
+
+        if let Some(build_id) = build_id {
+            let build_id: String = build_id.iter().map(|b| format!("{b:02X}")).collect();
+            symbolizer.symbolize(path, &build_id, &[0]);
+            assert_eq!(symbolizer.cached_offsets, 1);
+        }
+    }
+}
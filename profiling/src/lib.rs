@@ -6,5 +6,6 @@ pub mod collections;
 pub mod exporter;
 pub mod internal;
 pub mod iter;
+pub mod otlp;
 pub mod pprof;
 pub mod serializer;
@@ -0,0 +1,59 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! The messages of the OpenTelemetry profiles signal, as defined by the
+//! `opentelemetry.proto.profiles.v1development` package of
+//! opentelemetry-proto v1.5. The signal is still in development, so these may
+//! change along with it.
+
+mod proto;
+
+pub use proto::*;
+
+/// The attribute of a mapping holding its build id, from the OpenTelemetry
+/// semantic conventions of processes.
+pub const BUILD_ID_ATTRIBUTE: &str = "process.executable.build_id.gnu";
+
+impl KeyValue {
+    pub fn int(key: impl Into<String>, value: i64) -> Self {
+        Self {
+            key: key.into(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::IntValue(value)),
+            }),
+        }
+    }
+
+    pub fn string(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(value.into())),
+            }),
+        }
+    }
+}
+
+impl ProfilesData {
+    /// Creates the data of a single resource and instrumentation scope.
+    pub fn new(
+        resource_attributes: Vec<KeyValue>,
+        scope: InstrumentationScope,
+        profiles: Vec<Profile>,
+    ) -> Self {
+        Self {
+            resource_profiles: vec![ResourceProfiles {
+                resource: Some(Resource {
+                    attributes: resource_attributes,
+                    dropped_attributes_count: 0,
+                }),
+                scope_profiles: vec![ScopeProfiles {
+                    scope: Some(scope),
+                    profiles,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        }
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+// opentelemetry/proto/common/v1/common.proto
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AnyValue {
+    #[prost(oneof = "any_value::Value", tags = "1, 2, 3, 4, 5, 6, 7")]
+    pub value: Option<any_value::Value>,
+}
+
+pub mod any_value {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Value {
+        #[prost(string, tag = "1")]
+        StringValue(String),
+        #[prost(bool, tag = "2")]
+        BoolValue(bool),
+        #[prost(int64, tag = "3")]
+        IntValue(i64),
+        #[prost(double, tag = "4")]
+        DoubleValue(f64),
+        #[prost(message, tag = "5")]
+        ArrayValue(super::ArrayValue),
+        #[prost(message, tag = "6")]
+        KvlistValue(super::KeyValueList),
+        #[prost(bytes = "vec", tag = "7")]
+        BytesValue(Vec<u8>),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ArrayValue {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyValueList {
+    #[prost(message, repeated, tag = "1")]
+    pub values: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+    #[prost(message, repeated, tag = "3")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(uint32, tag = "4")]
+    pub dropped_attributes_count: u32,
+}
+
+// opentelemetry/proto/resource/v1/resource.proto
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(uint32, tag = "2")]
+    pub dropped_attributes_count: u32,
+}
+
+// opentelemetry/proto/profiles/v1development/profiles.proto
+
+/// The top-level message of the signal. It has the same encoding as the
+/// `ExportProfilesServiceRequest` sent to OTLP endpoints.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProfilesData {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_profiles: Vec<ResourceProfiles>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResourceProfiles {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_profiles: Vec<ScopeProfiles>,
+    #[prost(string, tag = "3")]
+    pub schema_url: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScopeProfiles {
+    #[prost(message, optional, tag = "1")]
+    pub scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    pub profiles: Vec<Profile>,
+    #[prost(string, tag = "3")]
+    pub schema_url: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Profile {
+    #[prost(message, repeated, tag = "1")]
+    pub sample_type: Vec<ValueType>,
+    #[prost(message, repeated, tag = "2")]
+    pub sample: Vec<Sample>,
+    #[prost(message, repeated, tag = "3")]
+    pub mapping_table: Vec<Mapping>,
+    #[prost(message, repeated, tag = "4")]
+    pub location_table: Vec<Location>,
+    /// The stacks of the samples, as slices of indices into `location_table`.
+    #[prost(int32, repeated, tag = "5")]
+    pub location_indices: Vec<i32>,
+    #[prost(message, repeated, tag = "6")]
+    pub function_table: Vec<Function>,
+    #[prost(message, repeated, tag = "7")]
+    pub attribute_table: Vec<KeyValue>,
+    #[prost(message, repeated, tag = "8")]
+    pub attribute_units: Vec<AttributeUnit>,
+    #[prost(message, repeated, tag = "9")]
+    pub link_table: Vec<Link>,
+    #[prost(string, repeated, tag = "10")]
+    pub string_table: Vec<String>,
+    #[prost(int64, tag = "11")]
+    pub time_nanos: i64,
+    #[prost(int64, tag = "12")]
+    pub duration_nanos: i64,
+    #[prost(message, optional, tag = "13")]
+    pub period_type: Option<ValueType>,
+    #[prost(int64, tag = "14")]
+    pub period: i64,
+    #[prost(int32, repeated, tag = "15")]
+    pub comment_strindices: Vec<i32>, // Indices into string table
+    #[prost(int32, tag = "16")]
+    pub default_sample_type_strindex: i32, // Index into string table
+    /// A 16 byte, globally unique id. All zeroes is invalid.
+    #[prost(bytes = "vec", tag = "17")]
+    pub profile_id: Vec<u8>,
+    /// Indices into `attribute_table`.
+    #[prost(int32, repeated, tag = "18")]
+    pub attribute_indices: Vec<i32>,
+    #[prost(uint32, tag = "19")]
+    pub dropped_attributes_count: u32,
+    #[prost(string, tag = "20")]
+    pub original_payload_format: String,
+    #[prost(bytes = "vec", tag = "21")]
+    pub original_payload: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct AttributeUnit {
+    #[prost(int32, tag = "1")]
+    pub attribute_key_strindex: i32, // Index into string table
+    #[prost(int32, tag = "2")]
+    pub unit_strindex: i32, // Index into string table
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct Link {
+    /// A 16 byte trace id.
+    #[prost(bytes = "vec", tag = "1")]
+    pub trace_id: Vec<u8>,
+    /// An 8 byte span id.
+    #[prost(bytes = "vec", tag = "2")]
+    pub span_id: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum AggregationTemporality {
+    Unspecified = 0,
+    Delta = 1,
+    Cumulative = 2,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct ValueType {
+    #[prost(int32, tag = "1")]
+    pub type_strindex: i32, // Index into string table
+    #[prost(int32, tag = "2")]
+    pub unit_strindex: i32, // Index into string table
+    #[prost(enumeration = "AggregationTemporality", tag = "3")]
+    pub aggregation_temporality: i32,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct Sample {
+    /// The stack of the sample, as a slice of `Profile.location_indices`.
+    #[prost(int32, tag = "1")]
+    pub locations_start_index: i32,
+    #[prost(int32, tag = "2")]
+    pub locations_length: i32,
+    #[prost(int64, repeated, tag = "3")]
+    pub value: Vec<i64>,
+    /// Indices into `Profile.attribute_table`.
+    #[prost(int32, repeated, tag = "4")]
+    pub attribute_indices: Vec<i32>,
+    /// Index into `Profile.link_table`.
+    #[prost(int32, optional, tag = "5")]
+    pub link_index: Option<i32>,
+    #[prost(uint64, repeated, tag = "6")]
+    pub timestamps_unix_nano: Vec<u64>,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct Mapping {
+    #[prost(uint64, tag = "1")]
+    pub memory_start: u64,
+    #[prost(uint64, tag = "2")]
+    pub memory_limit: u64,
+    #[prost(uint64, tag = "3")]
+    pub file_offset: u64,
+    #[prost(int32, tag = "4")]
+    pub filename_strindex: i32, // Index into string table
+    /// Indices into `Profile.attribute_table`.
+    #[prost(int32, repeated, tag = "5")]
+    pub attribute_indices: Vec<i32>,
+    #[prost(bool, tag = "6")]
+    pub has_functions: bool,
+    #[prost(bool, tag = "7")]
+    pub has_filenames: bool,
+    #[prost(bool, tag = "8")]
+    pub has_line_numbers: bool,
+    #[prost(bool, tag = "9")]
+    pub has_inline_frames: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct Location {
+    /// Index into `Profile.mapping_table`.
+    #[prost(int32, optional, tag = "1")]
+    pub mapping_index: Option<i32>,
+    #[prost(uint64, tag = "2")]
+    pub address: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub line: Vec<Line>,
+    #[prost(bool, tag = "4")]
+    pub is_folded: bool,
+    /// Indices into `Profile.attribute_table`.
+    #[prost(int32, repeated, tag = "5")]
+    pub attribute_indices: Vec<i32>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct Line {
+    /// Index into `Profile.function_table`.
+    #[prost(int32, tag = "1")]
+    pub function_index: i32,
+    #[prost(int64, tag = "2")]
+    pub line: i64,
+    #[prost(int64, tag = "3")]
+    pub column: i64,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, ::prost::Message)]
+pub struct Function {
+    #[prost(int32, tag = "1")]
+    pub name_strindex: i32, // Index into string table
+    #[prost(int32, tag = "2")]
+    pub system_name_strindex: i32, // Index into string table
+    #[prost(int32, tag = "3")]
+    pub filename_strindex: i32, // Index into string table
+    #[prost(int64, tag = "4")]
+    pub start_line: i64,
+}
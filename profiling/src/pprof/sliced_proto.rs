@@ -94,6 +94,8 @@ pub struct ProfileSimpler {
     pub period_type: Option<ValueType>,
     #[prost(int64, tag = "12")]
     pub period: i64,
+    #[prost(int64, repeated, tag = "13")]
+    pub comment: Vec<i64>,
 }
 
 impl From<ValueType> for ProfileSampleTypesEntry {
@@ -0,0 +1,78 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+use datadog_profiling::exporter::{config, ProfileExporter, Tag};
+use datadog_profiling::{api, internal, otlp};
+use ddcommon::tag;
+use prost::Message;
+use std::io::Read;
+use std::time::SystemTime;
+
+#[test]
+// This test invokes an external function SecTrustSettingsCopyCertificates
+// which Miri cannot evaluate.
+#[cfg_attr(miri, ignore)]
+fn otlp_agent() {
+    let base_url = "http://localhost:4318/".parse().expect("url to parse");
+    let endpoint = config::otlp(base_url).expect("endpoint to construct");
+    let tags: Vec<Tag> = vec![tag!("service", "php"), tag!("host", "bits")];
+    let exporter = ProfileExporter::new("dd-trace-foo", "1.2.3", "php", Some(tags), endpoint)
+        .expect("exporter to construct");
+
+    let sample_types = [api::ValueType::new("samples", "count")];
+    let mut profile = internal::Profile::new(SystemTime::now(), &sample_types, None);
+    profile
+        .add_sample(
+            api::Sample {
+                locations: vec![],
+                values: vec![1],
+                labels: vec![],
+            },
+            None,
+        )
+        .expect("sample to be added");
+    let profile = profile.into_otlp(None, None).expect("otlp conversion");
+
+    let timeout = std::time::Duration::from_secs(10);
+    let additional_tags = vec![tag!("env", "prod")];
+    let request = exporter
+        .build_otlp(vec![profile], Some(&additional_tags), timeout)
+        .expect("request to be built");
+
+    assert_eq!(
+        request.uri().to_string(),
+        "http://localhost:4318/v1development/profiles"
+    );
+    assert_eq!(request.timeout().expect("timeout to exist"), timeout);
+    let headers = request.headers();
+    assert_eq!(
+        headers.get("Content-Type").unwrap(),
+        "application/x-protobuf"
+    );
+    assert_eq!(headers.get("Content-Encoding").unwrap(), "gzip");
+
+    let body = futures::executor::block_on(hyper::body::to_bytes(request.body())).unwrap();
+    let mut decoded = Vec::new();
+    flate2::read::GzDecoder::new(body.as_ref())
+        .read_to_end(&mut decoded)
+        .expect("body to be gzipped");
+    let data = otlp::ProfilesData::decode(decoded.as_slice()).expect("body to be ProfilesData");
+
+    let resource_profiles = &data.resource_profiles[0];
+    assert_eq!(
+        resource_profiles.resource.as_ref().unwrap().attributes,
+        [
+            otlp::KeyValue::string("service.name", "php"),
+            otlp::KeyValue::string("host", "bits"),
+            otlp::KeyValue::string("deployment.environment", "prod"),
+        ]
+    );
+    let scope_profiles = &resource_profiles.scope_profiles[0];
+    let scope = scope_profiles.scope.as_ref().unwrap();
+    assert_eq!(scope.name, "dd-trace-foo");
+    assert_eq!(scope.version, "1.2.3");
+    assert_eq!(scope_profiles.profiles.len(), 1);
+    let profile = &scope_profiles.profiles[0];
+    assert_eq!(profile.sample.len(), 1);
+    assert_eq!(profile.sample[0].value, [1]);
+}
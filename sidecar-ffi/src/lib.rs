@@ -536,6 +536,21 @@ pub unsafe extern "C" fn ddog_sidecar_send_trace_v04_bytes(
     MaybeError::None
 }
 
+/// The path of the socket of the crash receiver hosted by the sidecar, to initialize the
+/// crashtracker with, e.g. with `ddog_prof_Crashtracker_init_with_unix_socket`.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn ddog_sidecar_crashtracker_socket_path() -> ffi::CharSlice<'static> {
+    static PATH: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    PATH.get_or_init(|| {
+        datadog_sidecar::crashtracker::crashtracker_unix_socket_path()
+            .to_string_lossy()
+            .into_owned()
+    })
+    .as_str()
+    .into()
+}
+
 /// Dumps the current state of the sidecar.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
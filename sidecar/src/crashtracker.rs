@@ -0,0 +1,64 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! The crash receiver hosted by the sidecar.
+//!
+//! Processes initialize the crashtracker with [`crashtracker_unix_socket_path`] instead of
+//! spawning a receiver of their own. The sidecar then receives, completes and uploads the crash
+//! reports of all of them, including the forks of pre-fork worker pools, on its runtime.
+//!
+//! The reports don't go through the IPC channel of the sidecar, as the crash handler can only
+//! write the report to a plain socket from the signal handler, without allocating. Any process
+//! can connect to the socket, so the receiver only accepts the reports of the processes of the
+//! user running the sidecar, and trusts the pid of the peer of the socket rather than the one of
+//! the report.
+
+use crate::primary_sidecar_identifier;
+use datadog_crashtracker::{async_receiver_entry_point_unix_listener, get_unix_socket};
+use std::path::PathBuf;
+use tokio::net::UnixListener;
+use tracing::{error, info};
+
+/// The path of the socket the crash receiver of the sidecar listens on, shared by all the
+/// processes of the same user with the same version of the library, like the sidecar itself.
+/// On Linux it is an abstract socket name, as understood by the crashtracker.
+pub fn crashtracker_unix_socket_path() -> PathBuf {
+    let base_path = format!(
+        concat!(
+            "libdatadog/",
+            crate::sidecar_version!(),
+            "@{}.crashtracker.sock"
+        ),
+        primary_sidecar_identifier()
+    );
+    if cfg!(target_os = "linux") {
+        PathBuf::from(base_path)
+    } else {
+        std::env::temp_dir().join(base_path)
+    }
+}
+
+fn bind_crashtracker_socket() -> anyhow::Result<UnixListener> {
+    let socket_path = crashtracker_unix_socket_path();
+    if let Some(dir) = socket_path.parent().filter(|_| socket_path.is_absolute()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let listener = get_unix_socket(socket_path.to_string_lossy())?;
+    listener.set_nonblocking(true)?;
+    Ok(UnixListener::from_std(listener)?)
+}
+
+/// Receive crash reports until the sidecar shuts down. If another sidecar already listens on the
+/// socket, e.g. with per process sidecars, it keeps receiving the reports.
+pub(crate) async fn crashtracker_receiver() {
+    let listener = match bind_crashtracker_socket() {
+        Ok(listener) => listener,
+        Err(e) => {
+            info!("Not receiving crash reports, the crashtracker socket is unavailable: {e:?}");
+            return;
+        }
+    };
+    if let Err(e) = async_receiver_entry_point_unix_listener(&listener).await {
+        error!("The crash receiver stopped: {e:?}");
+    }
+}
@@ -65,6 +65,9 @@ where
         cancel();
     });
 
+    #[cfg(unix)]
+    tokio::spawn(crate::crashtracker::crashtracker_receiver());
+
     let server = SidecarServer::default();
     let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel::<()>(1);
 
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 pub mod agent_remote_config;
 pub mod config;
+#[cfg(unix)]
+pub mod crashtracker;
 pub mod dogstatsd;
 mod dump;
 pub mod entry;
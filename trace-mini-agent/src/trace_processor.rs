@@ -8,7 +8,8 @@ use hyper::{http, Body, Request, Response, StatusCode};
 use log::info;
 use tokio::sync::mpsc::Sender;
 
-use datadog_trace_obfuscation::obfuscate::obfuscate_span;
+use datadog_trace_obfuscation::obfuscate::obfuscate_span_bytes;
+use datadog_trace_protobuf::pb;
 use datadog_trace_utils::trace_utils::SendData;
 use datadog_trace_utils::trace_utils::{self};
 use datadog_trace_utils::tracer_payload::TraceEncoding;
@@ -56,9 +57,9 @@ impl TraceProcessor for ServerlessTraceProcessor {
 
         let tracer_header_tags = (&parts.headers).into();
 
-        // deserialize traces from the request body, convert to protobuf structs (see trace-protobuf
-        // crate)
-        let (body_size, traces) = match trace_utils::get_traces_from_request_body(body).await {
+        // deserialize traces from the request body, the spans borrow their strings from the body
+        // until they are obfuscated and converted to protobuf structs (see trace-protobuf crate)
+        let (body_size, traces) = match trace_utils::get_v04_traces_from_request_body(body).await {
             Ok(res) => res,
             Err(err) => {
                 return log_and_create_http_response(
@@ -67,6 +68,18 @@ impl TraceProcessor for ServerlessTraceProcessor {
                 );
             }
         };
+        let traces = traces
+            .into_iter()
+            .map(|trace| {
+                trace
+                    .into_iter()
+                    .map(|mut span| {
+                        obfuscate_span_bytes(&mut span, &config.obfuscation_config);
+                        pb::Span::from(span)
+                    })
+                    .collect()
+            })
+            .collect();
 
         let payload = trace_utils::collect_trace_chunks(
             traces,
@@ -83,7 +96,6 @@ impl TraceProcessor for ServerlessTraceProcessor {
                         span,
                         config.mini_agent_version.as_str(),
                     );
-                    obfuscate_span(span, &config.obfuscation_config);
                 }
             },
             true, // In mini agent, we always send agentless
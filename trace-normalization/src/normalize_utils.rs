@@ -48,6 +48,11 @@ pub(crate) fn normalize_service(svc: &str) -> anyhow::Result<String> {
     normalize_tag(truncated_service)
 }
 
+// is_normalized_service returns true if normalize_service would return the service unchanged
+pub(crate) fn is_normalized_service(svc: &str) -> bool {
+    !svc.is_empty() && svc.len() <= MAX_SERVICE_LEN && is_normalized_ascii_tag(svc)
+}
+
 // normalize_tag applies some normalization to ensure the tags match the backend requirements.
 pub(crate) fn normalize_tag(tag: &str) -> anyhow::Result<String> {
     // Fast path: Check if the tag is valid and only contains ASCII characters,
@@ -171,6 +176,23 @@ pub(crate) fn normalize_name(name: &str) -> anyhow::Result<String> {
     normalize_metric_names(truncated_name)
 }
 
+// is_normalized_name returns true if normalize_name would return the name unchanged
+pub(crate) fn is_normalized_name(name: &str) -> bool {
+    if name.is_empty() || name.len() > MAX_NAME_LEN || name.ends_with('_') {
+        return false;
+    }
+    let bytes = name.as_bytes();
+    if !is_alpha(bytes[0] as char) {
+        return false;
+    }
+    bytes
+        .iter()
+        .all(|c| is_alpha_num(*c as char) || *c == b'.' || *c == b'_')
+        && !bytes
+            .windows(2)
+            .any(|w| matches!(w, [b'_', b'_'] | [b'_', b'.'] | [b'.', b'_']))
+}
+
 pub(crate) fn normalize_metric_names(name: &str) -> anyhow::Result<String> {
     let mut result = String::with_capacity(name.len());
 
@@ -232,6 +254,44 @@ mod tests {
     use crate::normalize_utils;
     use duplicate::duplicate_item;
 
+    #[test]
+    fn test_is_normalized_matches_normalize() {
+        let long = "a".repeat(150);
+        let inputs = [
+            "",
+            "good",
+            "bad-name",
+            "Upper.Case",
+            "a..b",
+            "a._b",
+            "a_.b",
+            "a__b",
+            "a_",
+            "a.",
+            "_a",
+            "1a",
+            "a1",
+            "é",
+            "svc:1",
+            long.as_str(),
+        ];
+        for input in inputs {
+            assert_eq!(
+                normalize_utils::is_normalized_name(input),
+                normalize_utils::normalize_name(input).is_ok_and(|name| name == input),
+                "{input}"
+            );
+            // Non-ASCII services are only accepted by the slow path
+            if normalize_utils::is_normalized_service(input) || input.is_ascii() {
+                assert_eq!(
+                    normalize_utils::is_normalized_service(input),
+                    normalize_utils::normalize_service(input).is_ok_and(|svc| svc == input),
+                    "{input}"
+                );
+            }
+        }
+    }
+
     #[duplicate_item(
         test_name                       input                               expected                    expected_err;
         [test_normalize_empty_string]   [""]                                [""]                        ["Normalizer Error: Empty span name."];
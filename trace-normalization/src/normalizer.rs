@@ -3,6 +3,7 @@
 
 use crate::normalize_utils;
 use datadog_trace_protobuf::pb;
+use datadog_trace_protobuf::span_bytes::SpanBytes;
 use std::time::SystemTime;
 
 const MAX_TYPE_LEN: usize = 100;
@@ -61,15 +62,39 @@ fn normalize_span(s: &mut pb::Span) -> anyhow::Result<()> {
         s.parent_id = 0;
     }
 
-    // Start & Duration as nanoseconds timestamps
-    // if s.Start is very little, less than year 2000 probably a unit issue so discard
-    if s.duration < 0 {
-        s.duration = 0;
+    normalize_timing(&mut s.start, &mut s.duration)?;
+
+    if s.r#type.len() > MAX_TYPE_LEN {
+        s.r#type = normalize_utils::truncate_utf8(&s.r#type, MAX_TYPE_LEN).to_string();
+    }
+
+    if s.meta.contains_key("env") {
+        if let Some(env_tag) = s.meta.get("env") {
+            if let Ok(normalized_tag) = normalize_utils::normalize_tag(env_tag) {
+                s.meta.insert("env".to_string(), normalized_tag);
+            }
+        }
+    };
+
+    if let Some(code) = s.meta.get("http.status_code") {
+        if !is_valid_status_code(code) {
+            s.meta.remove("http.status_code");
+        }
+    };
+
+    Ok(())
+}
+
+// Start & Duration as nanoseconds timestamps
+// if s.Start is very little, less than year 2000 probably a unit issue so discard
+fn normalize_timing(start: &mut i64, duration: &mut i64) -> anyhow::Result<()> {
+    if *duration < 0 {
+        *duration = 0;
     }
-    if s.duration > i64::MAX - s.start {
-        s.duration = 0;
+    if *duration > i64::MAX - *start {
+        *duration = 0;
     }
-    if s.start < YEAR_2000_NANOSEC_TS {
+    if *start < YEAR_2000_NANOSEC_TS {
         let now = match SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .map(|t| t.as_nanos() as i64)
@@ -79,20 +104,57 @@ fn normalize_span(s: &mut pb::Span) -> anyhow::Result<()> {
                 anyhow::bail!(format!("Normalizer Error: {err}"))
             }
         };
-        s.start = now - s.duration;
-        if s.start < 0 {
-            s.start = now;
+        *start = now - *duration;
+        if *start < 0 {
+            *start = now;
         }
     }
+    Ok(())
+}
+
+// normalize_span_bytes applies the same normalization as normalize_span to a span borrowing its
+// strings. Fields which are already normalized are left untouched so they keep referencing the
+// payload buffer, only the modified fields allocate.
+fn normalize_span_bytes(s: &mut SpanBytes) -> anyhow::Result<()> {
+    anyhow::ensure!(s.trace_id != 0, "TraceID is zero (reason:trace_id_zero)");
+    anyhow::ensure!(s.span_id != 0, "SpanID is zero (reason:span_id_zero)");
+
+    if !normalize_utils::is_normalized_service(&s.service) {
+        s.service = match normalize_utils::normalize_service(&s.service) {
+            Ok(service) => service,
+            Err(_) => normalize_utils::fallback_service(),
+        }
+        .into();
+    }
+
+    if !normalize_utils::is_normalized_name(&s.name) {
+        s.name = match normalize_utils::normalize_name(&s.name) {
+            Ok(name) => name,
+            Err(_) => DEFAULT_SPAN_NAME.to_string(),
+        }
+        .into();
+    }
+
+    if s.resource.is_empty() {
+        s.resource.clone_from(&s.name)
+    }
+
+    if s.parent_id == s.trace_id && s.parent_id == s.span_id {
+        s.parent_id = 0;
+    }
+
+    normalize_timing(&mut s.start, &mut s.duration)?;
 
     if s.r#type.len() > MAX_TYPE_LEN {
-        s.r#type = normalize_utils::truncate_utf8(&s.r#type, MAX_TYPE_LEN).to_string();
+        s.r#type = normalize_utils::truncate_utf8(&s.r#type, MAX_TYPE_LEN)
+            .to_string()
+            .into();
     }
 
-    if s.meta.contains_key("env") {
-        if let Some(env_tag) = s.meta.get("env") {
+    if let Some(env_tag) = s.meta.get("env") {
+        if !normalize_utils::is_normalized_ascii_tag(env_tag) {
             if let Ok(normalized_tag) = normalize_utils::normalize_tag(env_tag) {
-                s.meta.insert("env".to_string(), normalized_tag);
+                s.meta.insert("env".into(), normalized_tag.into());
             }
         }
     };
@@ -134,6 +196,26 @@ pub fn normalize_trace(trace: &mut [pb::Span]) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// normalize_trace_bytes is the counterpart of normalize_trace for spans borrowing their strings
+/// from a payload buffer, see [`SpanBytes`].
+pub fn normalize_trace_bytes(trace: &mut [SpanBytes]) -> anyhow::Result<()> {
+    let first_trace_id = match trace.first() {
+        Some(first_span) => first_span.trace_id,
+        None => anyhow::bail!("Normalize Trace Error: Trace is empty"),
+    };
+
+    for span in trace {
+        if span.trace_id != first_trace_id {
+            anyhow::bail!(format!(
+                "Normalize Trace Error: Trace has foreign span: {:?}",
+                span
+            ));
+        }
+        normalize_span_bytes(span)?;
+    }
+    Ok(())
+}
+
 /// normalize_chunk takes a trace chunk and
 /// * populates origin field if it wasn't populated
 /// * populates priority field if it wasn't populated
@@ -178,6 +260,7 @@ mod tests {
     use crate::normalizer;
     use crate::normalizer::DEFAULT_SPAN_NAME;
     use datadog_trace_protobuf::pb;
+    use datadog_trace_protobuf::span_bytes::SpanBytes;
     use rand::Rng;
     use std::collections::HashMap;
     use std::time::SystemTime;
@@ -626,4 +709,42 @@ mod tests {
         assert!(normalizer::normalize_chunk(&mut chunk, 0).is_ok());
         assert_eq!(normalizer::SamplerPriority::UserKeep as i32, chunk.priority);
     }
+
+    #[test]
+    fn test_normalize_trace_bytes_matches_normalize_trace() {
+        let mut unnormalized = new_test_span();
+        unnormalized.service = "Django Service".to_string();
+        unnormalized.name = "trace-api.request".to_string();
+        unnormalized.resource = "".to_string();
+        unnormalized.r#type = "t".repeat(200);
+        unnormalized
+            .meta
+            .insert("env".to_string(), "PROD".to_string());
+        unnormalized
+            .meta
+            .insert("http.status_code".to_string(), "999".to_string());
+        let mut trace = vec![new_test_span(), unnormalized];
+        trace[1].trace_id = trace[0].trace_id;
+
+        let mut trace_bytes: Vec<SpanBytes> = trace.iter().cloned().map(Into::into).collect();
+        let service_ptr = trace_bytes[0].service.as_ptr();
+
+        assert!(normalizer::normalize_trace(&mut trace).is_ok());
+        assert!(normalizer::normalize_trace_bytes(&mut trace_bytes).is_ok());
+
+        // Normalized strings are not copied
+        assert_eq!(trace_bytes[0].service.as_ptr(), service_ptr);
+        let trace_bytes: Vec<pb::Span> = trace_bytes.into_iter().map(Into::into).collect();
+        assert_eq!(trace_bytes, trace);
+        assert_eq!(trace[1].service, "django_service");
+        assert_eq!(trace[1].resource, "trace_api.request");
+    }
+
+    #[test]
+    fn test_normalize_trace_bytes_invalid() {
+        let mut span: SpanBytes = new_test_span().into();
+        span.trace_id = 0;
+        assert!(normalizer::normalize_trace_bytes(&mut [span]).is_err());
+        assert!(normalizer::normalize_trace_bytes(&mut []).is_err());
+    }
 }
@@ -2,13 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use datadog_trace_protobuf::pb;
+use datadog_trace_protobuf::span_bytes::SpanBytes;
 
 use crate::{
     http::obfuscate_url_string,
     memcached::obfuscate_memcached_string,
     obfuscation_config::ObfuscationConfig,
     redis::{obfuscate_redis_string, remove_all_redis_args},
-    replacer::replace_span_tags,
+    replacer::{replace_span_bytes_tags, replace_span_tags},
 };
 
 pub fn obfuscate_span(span: &mut pb::Span, config: &ObfuscationConfig) {
@@ -48,13 +49,56 @@ pub fn obfuscate_span(span: &mut pb::Span, config: &ObfuscationConfig) {
     }
 }
 
+/// obfuscate_span_bytes is the counterpart of obfuscate_span for spans borrowing their strings
+/// from a payload buffer, only the obfuscated values are copied.
+pub fn obfuscate_span_bytes(span: &mut SpanBytes, config: &ObfuscationConfig) {
+    match span.r#type.as_str() {
+        "web" | "http" => {
+            if span.meta.is_empty() {
+                return;
+            }
+            if let Some(url) = span.meta.get_mut("http.url") {
+                *url = obfuscate_url_string(
+                    url,
+                    config.http_remove_query_string,
+                    config.http_remove_path_digits,
+                )
+                .into()
+            }
+        }
+        "memcached" if config.obfuscate_memcached => {
+            if let Some(cmd) = span.meta.get_mut("memcached.command") {
+                *cmd = obfuscate_memcached_string(cmd).into()
+            }
+        }
+        "redis" => {
+            if !config.obfuscation_redis_enabled || span.meta.is_empty() {
+                return;
+            }
+            if let Some(redis_cmd) = span.meta.get_mut("redis.raw_command") {
+                let cmd = if config.obfuscation_redis_remove_all_args {
+                    obfuscate_redis_string(&remove_all_redis_args(redis_cmd))
+                } else {
+                    obfuscate_redis_string(redis_cmd)
+                };
+                *redis_cmd = cmd.into()
+            }
+        }
+        _ => {}
+    }
+    if let Some(tag_replace_rules) = &config.tag_replace_rules {
+        replace_span_bytes_tags(span, tag_replace_rules, &mut String::new());
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use datadog_trace_utils::test_utils;
 
     use crate::{obfuscation_config, replacer};
 
-    use super::obfuscate_span;
+    use super::{obfuscate_span, obfuscate_span_bytes};
+    use datadog_trace_protobuf::{pb, span_bytes::SpanBytes};
 
     #[test]
     fn test_obfuscates_span_url_strings() {
@@ -145,4 +189,41 @@ mod tests {
             "GEOADD key longitude latitude ?"
         )
     }
+
+    #[test]
+    fn test_obfuscate_span_bytes() {
+        let mut span = test_utils::create_test_span(111, 222, 0, 1, true);
+        span.r#type = "http".to_string();
+        span.meta.insert(
+            "http.url".to_string(),
+            "http://foo.com/id/123/page/q?search=bar&page=2".to_string(),
+        );
+        span.meta
+            .insert("custom.tag".to_string(), "/foo/bar/foo".to_string());
+        let parsed_rules = replacer::parse_rules_from_string(
+            r#"[{"name": "custom.tag", "pattern": "(/foo/bar/).*", "repl": "${1}extra"}]"#,
+        )
+        .unwrap();
+        let obf_config = obfuscation_config::ObfuscationConfig {
+            tag_replace_rules: Some(parsed_rules),
+            http_remove_query_string: true,
+            http_remove_path_digits: true,
+            obfuscate_memcached: false,
+            obfuscation_redis_enabled: false,
+            obfuscation_redis_remove_all_args: false,
+        };
+        let mut span_bytes: SpanBytes = span.clone().into();
+        let service_ptr = span_bytes.service.as_ptr();
+
+        obfuscate_span(&mut span, &obf_config);
+        obfuscate_span_bytes(&mut span_bytes, &obf_config);
+
+        assert_eq!(span_bytes.service.as_ptr(), service_ptr);
+        assert_eq!(pb::Span::from(span_bytes), span);
+        assert_eq!(
+            span.meta.get("http.url").unwrap(),
+            "http://foo.com/id/?/page/q?"
+        );
+        assert_eq!(span.meta.get("custom.tag").unwrap(), "/foo/bar/extra");
+    }
 }
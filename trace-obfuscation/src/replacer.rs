@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use datadog_trace_protobuf::pb;
+use datadog_trace_protobuf::span_bytes::{BytesString, SpanBytes};
 use regex::Regex;
 use serde::Deserialize;
 
@@ -40,6 +41,16 @@ impl ReplaceRule {
             scratch_space,
         )
     }
+
+    fn apply_bytes(&self, tag_value: &mut BytesString, scratch_space: &mut String) {
+        // Only copy the values which are modified
+        if !self.re.is_match(tag_value) {
+            return;
+        }
+        let mut value = tag_value.to_string();
+        self.apply(&mut value, scratch_space);
+        *tag_value = value.into();
+    }
 }
 
 /// replace_trace_tags replaces the tag values of all spans within a trace with a given set of
@@ -72,6 +83,32 @@ pub fn replace_span_tags(span: &mut pb::Span, rules: &[ReplaceRule], scratch_spa
     }
 }
 
+/// replace_span_bytes_tags is the counterpart of replace_span_tags for spans borrowing their
+/// strings from a payload buffer.
+pub fn replace_span_bytes_tags(
+    span: &mut SpanBytes,
+    rules: &[ReplaceRule],
+    scratch_space: &mut String,
+) {
+    for rule in rules {
+        match rule.name.as_ref() {
+            "*" => {
+                for (_, tag_value) in span.meta.iter_mut() {
+                    rule.apply_bytes(tag_value, scratch_space);
+                }
+            }
+            "resource.name" => {
+                rule.apply_bytes(&mut span.resource, scratch_space);
+            }
+            _ => {
+                if let Some(tag_value) = span.meta.get_mut(rule.name.as_str()) {
+                    rule.apply_bytes(tag_value, scratch_space);
+                }
+            }
+        }
+    }
+}
+
 /// parse_rules_from_string takes an array of rules, represented as an array of length 3 arrays
 /// holding the tag name, regex pattern, and replacement string as strings.
 /// * returns a vec of ReplaceRules
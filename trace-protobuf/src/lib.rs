@@ -3,6 +3,7 @@
 
 #[rustfmt::skip]
 pub mod pb;
+pub mod span_bytes;
 
 #[cfg(test)]
 mod pb_test;
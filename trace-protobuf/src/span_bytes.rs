@@ -0,0 +1,317 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! Span representation borrowing its strings from a shared [`Bytes`] buffer.
+//!
+//! [`SpanBytes`] mirrors [`pb::Span`] but stores every string as a [`BytesString`], a reference
+//! counted slice of the buffer the span was decoded from. Decoding, processing and re-encoding a
+//! payload with these types doesn't copy the strings of the spans, only the fields which are
+//! modified allocate.
+use crate::pb;
+use bytes::Bytes;
+use serde::{Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::str::Utf8Error;
+
+/// An immutable UTF-8 string backed by [`Bytes`].
+///
+/// Cloning a `BytesString` only increments the reference count of the underlying buffer.
+#[derive(Clone, Default)]
+pub struct BytesString {
+    bytes: Bytes,
+}
+
+impl BytesString {
+    /// Create a `BytesString` from a buffer, returns an error if it isn't valid UTF-8.
+    pub fn from_bytes(bytes: Bytes) -> Result<Self, Utf8Error> {
+        std::str::from_utf8(&bytes)?;
+        Ok(Self { bytes })
+    }
+
+    /// Create a `BytesString` from a static string without allocating.
+    pub const fn from_static(value: &'static str) -> Self {
+        Self {
+            bytes: Bytes::from_static(value.as_bytes()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: the bytes are valid UTF-8, it is checked when building the string
+        unsafe { std::str::from_utf8_unchecked(&self.bytes) }
+    }
+
+    /// Return the buffer holding the string.
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl Deref for BytesString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for BytesString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for BytesString {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for BytesString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for BytesString {}
+
+impl PartialEq<str> for BytesString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for BytesString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for BytesString {
+    // Must hash like `str` to be looked up through `Borrow<str>`
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for BytesString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for BytesString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl From<String> for BytesString {
+    fn from(value: String) -> Self {
+        Self {
+            bytes: Bytes::from(value),
+        }
+    }
+}
+
+impl From<&'static str> for BytesString {
+    fn from(value: &'static str) -> Self {
+        Self::from_static(value)
+    }
+}
+
+impl From<BytesString> for String {
+    fn from(value: BytesString) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+impl Serialize for BytesString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Counterpart of [`pb::SpanLink`] using [`BytesString`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SpanLinkBytes {
+    pub trace_id: u64,
+    pub trace_id_high: u64,
+    pub span_id: u64,
+    pub attributes: HashMap<BytesString, BytesString>,
+    pub tracestate: BytesString,
+    pub flags: u32,
+}
+
+/// Counterpart of [`pb::Span`] using [`BytesString`].
+///
+/// It serializes to the same v0.4 msgpack map as [`pb::Span`], except `meta_struct` values which
+/// are encoded as binaries.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SpanBytes {
+    pub service: BytesString,
+    pub name: BytesString,
+    pub resource: BytesString,
+    pub trace_id: u64,
+    pub span_id: u64,
+    pub parent_id: u64,
+    pub start: i64,
+    pub duration: i64,
+    #[serde(skip_serializing_if = "pb::is_default")]
+    pub error: i32,
+    pub meta: HashMap<BytesString, BytesString>,
+    pub metrics: HashMap<BytesString, f64>,
+    pub r#type: BytesString,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub meta_struct: HashMap<BytesString, Bytes>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub span_links: Vec<SpanLinkBytes>,
+}
+
+fn into_string_map(map: HashMap<BytesString, BytesString>) -> HashMap<String, String> {
+    map.into_iter().map(|(k, v)| (k.into(), v.into())).collect()
+}
+
+fn from_string_map(map: HashMap<String, String>) -> HashMap<BytesString, BytesString> {
+    map.into_iter().map(|(k, v)| (k.into(), v.into())).collect()
+}
+
+impl From<SpanLinkBytes> for pb::SpanLink {
+    fn from(link: SpanLinkBytes) -> Self {
+        pb::SpanLink {
+            trace_id: link.trace_id,
+            trace_id_high: link.trace_id_high,
+            span_id: link.span_id,
+            attributes: into_string_map(link.attributes),
+            tracestate: link.tracestate.into(),
+            flags: link.flags,
+        }
+    }
+}
+
+impl From<pb::SpanLink> for SpanLinkBytes {
+    fn from(link: pb::SpanLink) -> Self {
+        SpanLinkBytes {
+            trace_id: link.trace_id,
+            trace_id_high: link.trace_id_high,
+            span_id: link.span_id,
+            attributes: from_string_map(link.attributes),
+            tracestate: link.tracestate.into(),
+            flags: link.flags,
+        }
+    }
+}
+
+impl From<SpanBytes> for pb::Span {
+    /// Copy the strings of the span into owned strings.
+    fn from(span: SpanBytes) -> Self {
+        pb::Span {
+            service: span.service.into(),
+            name: span.name.into(),
+            resource: span.resource.into(),
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            parent_id: span.parent_id,
+            start: span.start,
+            duration: span.duration,
+            error: span.error,
+            meta: into_string_map(span.meta),
+            metrics: span
+                .metrics
+                .into_iter()
+                .map(|(k, v)| (k.into(), v))
+                .collect(),
+            r#type: span.r#type.into(),
+            meta_struct: span
+                .meta_struct
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.to_vec()))
+                .collect(),
+            span_links: span.span_links.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<pb::Span> for SpanBytes {
+    /// Take ownership of the strings of the span, they are not copied.
+    fn from(span: pb::Span) -> Self {
+        SpanBytes {
+            service: span.service.into(),
+            name: span.name.into(),
+            resource: span.resource.into(),
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            parent_id: span.parent_id,
+            start: span.start,
+            duration: span.duration,
+            error: span.error,
+            meta: from_string_map(span.meta),
+            metrics: span
+                .metrics
+                .into_iter()
+                .map(|(k, v)| (k.into(), v))
+                .collect(),
+            r#type: span.r#type.into(),
+            meta_struct: span
+                .meta_struct
+                .into_iter()
+                .map(|(k, v)| (k.into(), Bytes::from(v)))
+                .collect(),
+            span_links: span.span_links.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_string() {
+        let buffer = Bytes::from_static(b"servicename");
+        let service = BytesString::from_bytes(buffer.slice(0..7)).unwrap();
+        assert_eq!(service, "service");
+        assert_eq!(service.len(), 7);
+        // The string references the buffer
+        assert_eq!(service.as_bytes().as_ptr(), buffer.as_ptr());
+
+        assert!(BytesString::from_bytes(Bytes::from_static(&[0xff, 0xfe])).is_err());
+
+        let map = HashMap::from([(BytesString::from("key"), 1)]);
+        assert_eq!(map.get("key"), Some(&1));
+    }
+
+    #[test]
+    fn test_span_conversion() {
+        let span = pb::Span {
+            service: "service".to_string(),
+            name: "name".to_string(),
+            resource: "resource".to_string(),
+            trace_id: 1,
+            span_id: 2,
+            parent_id: 3,
+            start: 4,
+            duration: 5,
+            error: 1,
+            meta: HashMap::from([("key".to_string(), "value".to_string())]),
+            metrics: HashMap::from([("metric".to_string(), 1.5)]),
+            r#type: "web".to_string(),
+            meta_struct: HashMap::from([("appsec".to_string(), vec![1, 2, 3])]),
+            span_links: vec![pb::SpanLink {
+                trace_id: 10,
+                span_id: 11,
+                tracestate: "dd=s:1".to_string(),
+                ..Default::default()
+            }],
+        };
+        let span_bytes = SpanBytes::from(span.clone());
+        assert_eq!(
+            span_bytes.meta.get("key").map(|v| v.as_str()),
+            Some("value")
+        );
+        assert_eq!(pb::Span::from(span_bytes), span);
+    }
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod config_utils;
+pub mod msgpack_v04;
+pub mod msgpack_v05;
 pub mod send_data;
 pub mod stats_utils;
 #[cfg(any(test, feature = "test-utils"))]
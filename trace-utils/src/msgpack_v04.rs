@@ -0,0 +1,402 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! Zero-copy decoding of traces in the v0.4 msgpack format.
+//!
+//! A v0.4 payload is an array of traces, each trace being an array of spans encoded as maps. The
+//! decoded [`SpanBytes`] reference the strings of the payload buffer instead of copying them, so
+//! the spans can be processed and re-encoded with [`encode`] while only allocating for the fields
+//! which are modified.
+//!
+//! Like the serde implementation of [`pb::Span`](datadog_trace_protobuf::pb::Span), missing and
+//! nil fields are set to their default value and unknown fields are ignored.
+use bytes::Bytes;
+use datadog_trace_protobuf::span_bytes::{BytesString, SpanBytes, SpanLinkBytes};
+use rmp::Marker;
+use rmp_serde::decode::Error;
+use std::collections::HashMap;
+
+/// Maximum nesting of the values skipped by the decoder, matches the limit of rmp_serde
+const MAX_DEPTH: usize = 1024;
+
+/// Encode traces in the v0.4 format.
+pub fn encode(traces: &[Vec<SpanBytes>]) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(traces)
+}
+
+/// Decode traces from a v0.4 payload without copying their strings.
+///
+/// Returns an error if the payload is not valid msgpack, doesn't follow the v0.4 layout or
+/// contains invalid UTF-8 strings.
+pub fn decode(data: Bytes) -> Result<Vec<Vec<SpanBytes>>, Error> {
+    let mut decoder = Decoder {
+        data: &data,
+        buf: &data,
+    };
+    let trace_count = rmp::decode::read_array_len(&mut decoder.buf)?;
+    // Capacities are bounded by the payload size since each element is at least one byte
+    let mut traces = Vec::with_capacity((trace_count as usize).min(data.len()));
+    for _ in 0..trace_count {
+        let span_count = rmp::decode::read_array_len(&mut decoder.buf)?;
+        let mut trace = Vec::with_capacity((span_count as usize).min(decoder.buf.len()));
+        for _ in 0..span_count {
+            trace.push(decoder.read_span()?);
+        }
+        traces.push(trace);
+    }
+    Ok(traces)
+}
+
+struct Decoder<'a> {
+    /// The whole payload, strings are sliced from it
+    data: &'a Bytes,
+    /// The remaining bytes to decode
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn offset(&self) -> usize {
+        self.data.len() - self.buf.len()
+    }
+
+    /// Advance past `len` bytes and return their position in the payload.
+    fn take(&mut self, len: usize) -> Result<std::ops::Range<usize>, Error> {
+        if self.buf.len() < len {
+            return Err(Error::LengthMismatch(len as u32));
+        }
+        let start = self.offset();
+        self.buf = &self.buf[len..];
+        Ok(start..start + len)
+    }
+
+    /// Consume a nil value if it is the next one, nil fields are decoded as default values.
+    fn read_nil(&mut self) -> bool {
+        if self.buf.first() == Some(&Marker::Null.to_u8()) {
+            self.buf = &self.buf[1..];
+            return true;
+        }
+        false
+    }
+
+    fn read_str(&mut self) -> Result<&'a str, Error> {
+        let len = rmp::decode::read_str_len(&mut self.buf)?;
+        let range = self.take(len as usize)?;
+        let data: &'a Bytes = self.data;
+        Ok(std::str::from_utf8(&data[range])?)
+    }
+
+    fn read_string(&mut self) -> Result<BytesString, Error> {
+        if self.read_nil() {
+            return Ok(BytesString::default());
+        }
+        let len = rmp::decode::read_str_len(&mut self.buf)?;
+        let range = self.take(len as usize)?;
+        Ok(BytesString::from_bytes(self.data.slice(range))?)
+    }
+
+    fn read_int<T: Default + num_traits::FromPrimitive>(&mut self) -> Result<T, Error> {
+        if self.read_nil() {
+            return Ok(T::default());
+        }
+        Ok(rmp::decode::read_int(&mut self.buf)?)
+    }
+
+    fn read_f64(&mut self) -> Result<f64, Error> {
+        match self.buf.first().map(|b| Marker::from_u8(*b)) {
+            Some(Marker::Null) => {
+                self.read_nil();
+                Ok(0.0)
+            }
+            Some(Marker::F32) => Ok(rmp::decode::read_f32(&mut self.buf)? as f64),
+            Some(Marker::F64) => Ok(rmp::decode::read_f64(&mut self.buf)?),
+            _ => Ok(rmp::decode::read_int(&mut self.buf)?),
+        }
+    }
+
+    fn read_map_len(&mut self) -> Result<usize, Error> {
+        if self.read_nil() {
+            return Ok(0);
+        }
+        Ok(rmp::decode::read_map_len(&mut self.buf)? as usize)
+    }
+
+    fn read_string_map(&mut self) -> Result<HashMap<BytesString, BytesString>, Error> {
+        let len = self.read_map_len()?;
+        let mut map = HashMap::with_capacity(len.min(self.buf.len()));
+        for _ in 0..len {
+            map.insert(self.read_string()?, self.read_string()?);
+        }
+        Ok(map)
+    }
+
+    fn read_metrics(&mut self) -> Result<HashMap<BytesString, f64>, Error> {
+        let len = self.read_map_len()?;
+        let mut map = HashMap::with_capacity(len.min(self.buf.len()));
+        for _ in 0..len {
+            map.insert(self.read_string()?, self.read_f64()?);
+        }
+        Ok(map)
+    }
+
+    /// Read a binary value, arrays of integers are accepted as well since it is how serde encodes
+    /// `Vec<u8>`.
+    fn read_binary(&mut self) -> Result<Bytes, Error> {
+        match self.buf.first().map(|b| Marker::from_u8(*b)) {
+            Some(Marker::Null) => {
+                self.read_nil();
+                Ok(Bytes::new())
+            }
+            Some(Marker::FixArray(_) | Marker::Array16 | Marker::Array32) => {
+                let len = rmp::decode::read_array_len(&mut self.buf)? as usize;
+                let mut bytes = Vec::with_capacity(len.min(self.buf.len()));
+                for _ in 0..len {
+                    bytes.push(rmp::decode::read_int(&mut self.buf)?);
+                }
+                Ok(Bytes::from(bytes))
+            }
+            _ => {
+                let len = rmp::decode::read_bin_len(&mut self.buf)?;
+                let range = self.take(len as usize)?;
+                Ok(self.data.slice(range))
+            }
+        }
+    }
+
+    fn read_meta_struct(&mut self) -> Result<HashMap<BytesString, Bytes>, Error> {
+        let len = self.read_map_len()?;
+        let mut map = HashMap::with_capacity(len.min(self.buf.len()));
+        for _ in 0..len {
+            map.insert(self.read_string()?, self.read_binary()?);
+        }
+        Ok(map)
+    }
+
+    fn read_span_link(&mut self) -> Result<SpanLinkBytes, Error> {
+        let mut link = SpanLinkBytes::default();
+        let len = self.read_map_len()?;
+        for _ in 0..len {
+            match self.read_str()? {
+                "trace_id" => link.trace_id = self.read_int()?,
+                "trace_id_high" => link.trace_id_high = self.read_int()?,
+                "span_id" => link.span_id = self.read_int()?,
+                "attributes" => link.attributes = self.read_string_map()?,
+                "tracestate" => link.tracestate = self.read_string()?,
+                "flags" => link.flags = self.read_int()?,
+                _ => self.skip_value(0)?,
+            }
+        }
+        Ok(link)
+    }
+
+    fn read_span_links(&mut self) -> Result<Vec<SpanLinkBytes>, Error> {
+        if self.read_nil() {
+            return Ok(Vec::new());
+        }
+        let len = rmp::decode::read_array_len(&mut self.buf)? as usize;
+        let mut links = Vec::with_capacity(len.min(self.buf.len()));
+        for _ in 0..len {
+            links.push(self.read_span_link()?);
+        }
+        Ok(links)
+    }
+
+    fn read_span(&mut self) -> Result<SpanBytes, Error> {
+        let mut span = SpanBytes::default();
+        let len = rmp::decode::read_map_len(&mut self.buf)?;
+        for _ in 0..len {
+            match self.read_str()? {
+                "service" => span.service = self.read_string()?,
+                "name" => span.name = self.read_string()?,
+                "resource" => span.resource = self.read_string()?,
+                "trace_id" => span.trace_id = self.read_int()?,
+                "span_id" => span.span_id = self.read_int()?,
+                "parent_id" => span.parent_id = self.read_int()?,
+                "start" => span.start = self.read_int()?,
+                "duration" => span.duration = self.read_int()?,
+                "error" => span.error = self.read_int()?,
+                "meta" => span.meta = self.read_string_map()?,
+                "metrics" => span.metrics = self.read_metrics()?,
+                "type" => span.r#type = self.read_string()?,
+                "meta_struct" => span.meta_struct = self.read_meta_struct()?,
+                "span_links" => span.span_links = self.read_span_links()?,
+                _ => self.skip_value(0)?,
+            }
+        }
+        Ok(span)
+    }
+
+    /// Skip the next value of any type.
+    fn skip_value(&mut self, depth: usize) -> Result<(), Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::DepthLimitExceeded);
+        }
+        let marker = rmp::decode::read_marker(&mut self.buf)?;
+        let (data_len, elements) = match marker {
+            Marker::FixPos(_) | Marker::FixNeg(_) | Marker::Null | Marker::True | Marker::False => {
+                (0, 0)
+            }
+            Marker::U8 | Marker::I8 => (1, 0),
+            Marker::U16 | Marker::I16 => (2, 0),
+            Marker::U32 | Marker::I32 | Marker::F32 => (4, 0),
+            Marker::U64 | Marker::I64 | Marker::F64 => (8, 0),
+            Marker::FixStr(len) => (len as usize, 0),
+            Marker::Str8 | Marker::Bin8 => (self.read_len(1)?, 0),
+            Marker::Str16 | Marker::Bin16 => (self.read_len(2)?, 0),
+            Marker::Str32 | Marker::Bin32 => (self.read_len(4)?, 0),
+            Marker::FixArray(len) => (0, len as usize),
+            Marker::Array16 => (0, self.read_len(2)?),
+            Marker::Array32 => (0, self.read_len(4)?),
+            Marker::FixMap(len) => (0, 2 * len as usize),
+            Marker::Map16 => (0, 2 * self.read_len(2)?),
+            Marker::Map32 => (0, 2 * self.read_len(4)?),
+            // Extensions are followed by their type
+            Marker::FixExt1 => (2, 0),
+            Marker::FixExt2 => (3, 0),
+            Marker::FixExt4 => (5, 0),
+            Marker::FixExt8 => (9, 0),
+            Marker::FixExt16 => (17, 0),
+            Marker::Ext8 => (self.read_len(1)? + 1, 0),
+            Marker::Ext16 => (self.read_len(2)? + 1, 0),
+            Marker::Ext32 => (self.read_len(4)? + 1, 0),
+            Marker::Reserved => return Err(Error::TypeMismatch(Marker::Reserved)),
+        };
+        self.take(data_len)?;
+        for _ in 0..elements {
+            self.skip_value(depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Read a big endian length stored on `size` bytes.
+    fn read_len(&mut self, size: usize) -> Result<usize, Error> {
+        let range = self.take(size)?;
+        Ok(self.data[range]
+            .iter()
+            .fold(0, |len, byte| (len << 8) | *byte as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datadog_trace_protobuf::pb;
+    use serde_json::json;
+
+    fn test_traces() -> Vec<Vec<pb::Span>> {
+        let span = |trace_id, span_id, parent_id, name: &str| pb::Span {
+            service: "test-service".to_string(),
+            name: name.to_string(),
+            resource: "GET /".to_string(),
+            trace_id,
+            span_id,
+            parent_id,
+            start: 1_700_000_000_000_000_000,
+            duration: 5_000,
+            error: 0,
+            meta: HashMap::from([
+                ("env".to_string(), "test".to_string()),
+                ("http.method".to_string(), "GET".to_string()),
+            ]),
+            metrics: HashMap::from([("_sampling_priority_v1".to_string(), 1.0)]),
+            r#type: "web".to_string(),
+            ..Default::default()
+        };
+        vec![
+            vec![span(1, 1, 0, "root"), span(1, 2, 1, "child")],
+            vec![pb::Span {
+                error: 1,
+                span_links: vec![pb::SpanLink {
+                    trace_id: 1,
+                    span_id: 1,
+                    attributes: HashMap::from([("link".to_string(), "attr".to_string())]),
+                    ..Default::default()
+                }],
+                meta_struct: HashMap::from([("appsec".to_string(), vec![0x81, 0xa1, 0x61, 1])]),
+                ..span(2, 3, u64::MAX, "other")
+            }],
+        ]
+    }
+
+    fn into_pb(traces: Vec<Vec<SpanBytes>>) -> Vec<Vec<pb::Span>> {
+        traces
+            .into_iter()
+            .map(|trace| trace.into_iter().map(Into::into).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_serde_payload() {
+        let traces = test_traces();
+        let payload = Bytes::from(rmp_serde::to_vec_named(&traces).unwrap());
+        let decoded = decode(payload.clone()).unwrap();
+
+        // Strings reference the payload
+        let service = decoded[0][0].service.as_bytes();
+        assert!(payload.as_ptr_range().contains(&service.as_ptr()));
+        assert_eq!(into_pb(decoded), traces);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let traces = test_traces();
+        let decoded = decode(Bytes::from(rmp_serde::to_vec_named(&traces).unwrap())).unwrap();
+        let encoded = encode(&decoded).unwrap();
+
+        // meta_struct values are encoded as binaries which serde doesn't decode into `Vec<u8>`
+        let deserialized: Vec<Vec<pb::Span>> =
+            rmp_serde::from_slice(&encode(&decoded[..1]).unwrap()).unwrap();
+        assert_eq!(deserialized[0], traces[0]);
+        assert_eq!(into_pb(decode(Bytes::from(encoded)).unwrap()), traces);
+    }
+
+    #[test]
+    fn test_decode_missing_nil_and_unknown_fields() {
+        let payload = rmp_serde::to_vec_named(&json!([[{
+            "service": null,
+            "name": "name",
+            "trace_id": 1,
+            "span_id": 2,
+            "start": -1,
+            "meta": null,
+            "metrics": {"int": 2, "float": 0.5},
+            "unknown": {"nested": [1, "two", {"three": 3.0}]},
+        }], []]))
+        .unwrap();
+
+        let traces = decode(Bytes::from(payload)).unwrap();
+        assert_eq!(traces.len(), 2);
+        assert!(traces[1].is_empty());
+        let span = &traces[0][0];
+        assert_eq!(span.service, "");
+        assert_eq!(span.name, "name");
+        assert_eq!((span.trace_id, span.span_id, span.parent_id), (1, 2, 0));
+        assert_eq!(span.start, -1);
+        assert!(span.meta.is_empty());
+        assert_eq!(span.metrics.get("int"), Some(&2.0));
+        assert_eq!(span.metrics.get("float"), Some(&0.5));
+    }
+
+    #[test]
+    fn test_decode_invalid_payloads() {
+        // Not an array of traces
+        let payload = rmp_serde::to_vec_named(&json!({"service": "test"})).unwrap();
+        assert!(decode(Bytes::from(payload)).is_err());
+
+        // Wrong field type
+        let payload = rmp_serde::to_vec_named(&json!([[{"trace_id": "1"}]])).unwrap();
+        assert!(decode(Bytes::from(payload)).is_err());
+
+        // Truncated payload
+        let payload = rmp_serde::to_vec_named(&test_traces()).unwrap();
+        assert!(decode(Bytes::copy_from_slice(&payload[..payload.len() - 3])).is_err());
+
+        // Invalid UTF-8 string: fixarray(1) fixarray(1) fixmap(1) "name" str8(2)
+        let mut payload = vec![0x91, 0x91, 0x81, 0xa4];
+        payload.extend_from_slice(b"name");
+        payload.extend_from_slice(&[0xd9, 0x02, 0xff, 0xfe]);
+        assert!(decode(Bytes::from(payload)).is_err());
+
+        // Huge announced length
+        assert!(decode(Bytes::from_static(&[0xdd, 0xff, 0xff, 0xff, 0xff])).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright 2024-Present Datadog, Inc. https://www.datadoghq.com/
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encoding and decoding of traces in the v0.5 msgpack format.
+//!
+//! A v0.5 payload is an array of two elements: a string table and the traces. Each span is an
+//! array of 12 elements where every string is replaced by its index in the string table:
+//!
+//! ```text
+//! [service, name, resource, trace_id, span_id, parent_id, start, duration, error, meta, metrics,
+//!  type]
+//! ```
+//!
+//! The first string of the table is always the empty string. Fields which don't exist in the v0.5
+//! format, such as span links, are dropped when encoding.
+use datadog_trace_protobuf::pb::Span;
+use serde::de::Error as _;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+type StringIndex = u32;
+
+#[derive(Serialize, Deserialize)]
+struct SpanV05(
+    StringIndex,                       // service
+    StringIndex,                       // name
+    StringIndex,                       // resource
+    u64,                               // trace_id
+    u64,                               // span_id
+    u64,                               // parent_id
+    i64,                               // start
+    i64,                               // duration
+    i32,                               // error
+    HashMap<StringIndex, StringIndex>, // meta
+    HashMap<StringIndex, f64>,         // metrics
+    StringIndex,                       // type
+);
+
+#[derive(Serialize)]
+struct PayloadV05<'a>(Vec<&'a str>, Vec<Vec<SpanV05>>);
+
+#[derive(Deserialize)]
+struct OwnedPayloadV05(Vec<String>, Vec<Vec<SpanV05>>);
+
+/// Deduplicates the strings of a payload into a table
+struct StringTable<'a> {
+    strings: Vec<&'a str>,
+    indices: HashMap<&'a str, StringIndex>,
+}
+
+impl<'a> StringTable<'a> {
+    fn new() -> Self {
+        Self {
+            strings: vec![""],
+            indices: HashMap::from([("", 0)]),
+        }
+    }
+
+    fn add(&mut self, string: &'a str) -> StringIndex {
+        *self.indices.entry(string).or_insert_with(|| {
+            self.strings.push(string);
+            (self.strings.len() - 1) as StringIndex
+        })
+    }
+}
+
+/// Encode traces in the v0.5 format.
+pub fn encode(traces: &[Vec<Span>]) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let mut table = StringTable::new();
+    let traces = traces
+        .iter()
+        .map(|trace| {
+            trace
+                .iter()
+                .map(|span| {
+                    SpanV05(
+                        table.add(&span.service),
+                        table.add(&span.name),
+                        table.add(&span.resource),
+                        span.trace_id,
+                        span.span_id,
+                        span.parent_id,
+                        span.start,
+                        span.duration,
+                        span.error,
+                        span.meta
+                            .iter()
+                            .map(|(k, v)| (table.add(k), table.add(v)))
+                            .collect(),
+                        span.metrics
+                            .iter()
+                            .map(|(k, v)| (table.add(k), *v))
+                            .collect(),
+                        table.add(&span.r#type),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    rmp_serde::to_vec(&PayloadV05(table.strings, traces))
+}
+
+/// Decode traces from a v0.5 payload.
+///
+/// Returns an error if the payload is not valid msgpack, doesn't follow the v0.5 layout or
+/// references a string outside of the string table.
+pub fn decode(data: &[u8]) -> Result<Vec<Vec<Span>>, rmp_serde::decode::Error> {
+    let OwnedPayloadV05(strings, traces) = rmp_serde::from_slice(data)?;
+    let get = |index: StringIndex| {
+        strings.get(index as usize).cloned().ok_or_else(|| {
+            rmp_serde::decode::Error::custom(format!(
+                "String index {index} out of bounds of the string table of size {}",
+                strings.len()
+            ))
+        })
+    };
+    traces
+        .into_iter()
+        .map(|trace| {
+            trace
+                .into_iter()
+                .map(|span| {
+                    Ok(Span {
+                        service: get(span.0)?,
+                        name: get(span.1)?,
+                        resource: get(span.2)?,
+                        trace_id: span.3,
+                        span_id: span.4,
+                        parent_id: span.5,
+                        start: span.6,
+                        duration: span.7,
+                        error: span.8,
+                        meta: span
+                            .9
+                            .into_iter()
+                            .map(|(k, v)| Ok((get(k)?, get(v)?)))
+                            .collect::<Result<_, rmp_serde::decode::Error>>()?,
+                        metrics: span
+                            .10
+                            .into_iter()
+                            .map(|(k, v)| Ok((get(k)?, v)))
+                            .collect::<Result<_, rmp_serde::decode::Error>>()?,
+                        r#type: get(span.11)?,
+                        ..Default::default()
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_traces() -> Vec<Vec<Span>> {
+        let span = |trace_id, span_id, parent_id, name: &str| Span {
+            service: "test-service".to_string(),
+            name: name.to_string(),
+            resource: "GET /".to_string(),
+            trace_id,
+            span_id,
+            parent_id,
+            start: 1_700_000_000_000_000_000,
+            duration: 5_000,
+            error: 0,
+            meta: HashMap::from([
+                ("env".to_string(), "test".to_string()),
+                ("http.method".to_string(), "GET".to_string()),
+            ]),
+            metrics: HashMap::from([("_sampling_priority_v1".to_string(), 1.0)]),
+            r#type: "web".to_string(),
+            ..Default::default()
+        };
+        vec![
+            vec![span(1, 1, 0, "root"), span(1, 2, 1, "child")],
+            vec![Span {
+                error: 1,
+                ..span(2, 3, 0, "other")
+            }],
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let traces = test_traces();
+        let decoded = decode(&encode(&traces).unwrap()).unwrap();
+        assert_eq!(decoded, traces);
+    }
+
+    #[test]
+    fn test_round_trip_empty() {
+        let decoded = decode(&encode(&[]).unwrap()).unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn test_string_table() {
+        let encoded = encode(&test_traces()).unwrap();
+        let OwnedPayloadV05(strings, traces) = rmp_serde::from_slice(&encoded).unwrap();
+        assert_eq!(strings[0], "");
+        // Every string is stored once
+        let mut deduplicated = strings.clone();
+        deduplicated.sort();
+        deduplicated.dedup();
+        assert_eq!(deduplicated.len(), strings.len());
+        assert_eq!(traces.len(), 2);
+        assert_eq!(strings[traces[0][0].0 as usize], "test-service");
+    }
+
+    #[test]
+    fn test_smaller_than_v04() {
+        let trace = test_traces().swap_remove(0);
+        let traces = vec![(0..50).flat_map(|_| trace.clone()).collect::<Vec<_>>()];
+        let v04 = rmp_serde::to_vec_named(&traces).unwrap();
+        let v05 = encode(&traces).unwrap();
+        assert!(v05.len() < v04.len() / 2);
+    }
+
+    #[test]
+    fn test_decode_hand_encoded_payload() {
+        let strings = vec!["", "service", "name", "resource", "key", "value", "metric"];
+        let span = (
+            1u32,
+            2u32,
+            3u32,
+            10u64,
+            11u64,
+            0u64,
+            100i64,
+            20i64,
+            0i32,
+            HashMap::from([(4u32, 5u32)]),
+            HashMap::from([(6u32, 0.5f64)]),
+            0u32,
+        );
+        let payload = rmp_serde::to_vec(&(strings, vec![vec![span]])).unwrap();
+
+        let traces = decode(&payload).unwrap();
+        assert_eq!(
+            traces,
+            vec![vec![Span {
+                service: "service".to_string(),
+                name: "name".to_string(),
+                resource: "resource".to_string(),
+                trace_id: 10,
+                span_id: 11,
+                parent_id: 0,
+                start: 100,
+                duration: 20,
+                error: 0,
+                meta: HashMap::from([("key".to_string(), "value".to_string())]),
+                metrics: HashMap::from([("metric".to_string(), 0.5)]),
+                r#type: "".to_string(),
+                ..Default::default()
+            }]]
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_string_index() {
+        let span = (
+            1u32,
+            0u32,
+            0u32,
+            1u64,
+            1u64,
+            0u64,
+            0i64,
+            0i64,
+            0i32,
+            HashMap::<u32, u32>::new(),
+            HashMap::<u32, f64>::new(),
+            0u32,
+        );
+        let payload = rmp_serde::to_vec(&(vec![""], vec![vec![span]])).unwrap();
+        assert!(decode(&payload).is_err());
+    }
+
+    #[test]
+    fn test_decode_v04_payload_fails() {
+        let v04 = rmp_serde::to_vec_named(&test_traces()).unwrap();
+        assert!(decode(&v04).is_err());
+    }
+}
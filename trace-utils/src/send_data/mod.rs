@@ -6,12 +6,13 @@ pub mod send_data_result;
 
 pub use crate::send_data::retry_strategy::{RetryBackoffType, RetryStrategy};
 
+use crate::msgpack_v05;
 use crate::trace_utils::{SendDataResult, TracerHeaderTags};
 use crate::tracer_payload::TracerPayloadCollection;
 use anyhow::{anyhow, Context};
 use bytes::Bytes;
 use datadog_trace_protobuf::pb::{AgentPayload, TracerPayload};
-use ddcommon::{connector, Endpoint, HttpRequestBuilder};
+use ddcommon::{connector, Endpoint, HttpClient, HttpRequestBuilder};
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use hyper::header::HeaderValue;
@@ -193,15 +194,31 @@ impl SendData {
     ///
     /// A `SendDataResult` instance containing the result of the operation.
     pub async fn send(&self) -> SendDataResult {
+        self.send_with_client(&Client::builder().build(connector::Connector::default()))
+            .await
+    }
+
+    /// Sends the data to the target endpoint using the given client. Sharing a client between
+    /// calls allows the connections to the target to be reused.
+    ///
+    /// # Arguments
+    ///
+    /// * `client`: The client used to send the requests.
+    ///
+    /// # Returns
+    ///
+    /// A `SendDataResult` instance containing the result of the operation.
+    pub async fn send_with_client(&self, client: &HttpClient) -> SendDataResult {
         if self.use_protobuf() {
-            self.send_with_protobuf().await
+            self.send_with_protobuf(client).await
         } else {
-            self.send_with_msgpack().await
+            self.send_with_msgpack(client).await
         }
     }
 
     async fn send_request(
         &self,
+        client: &HttpClient,
         req: HttpRequestBuilder,
         payload: Bytes,
     ) -> Result<Response<Body>, RequestError> {
@@ -210,11 +227,7 @@ impl SendData {
             Err(_) => return Err(RequestError::Build),
         };
 
-        match Client::builder()
-            .build(connector::Connector::default())
-            .request(req)
-            .await
-        {
+        match client.request(req).await {
             Ok(resp) => Ok(resp),
             Err(e) => {
                 if e.is_timeout() {
@@ -236,6 +249,7 @@ impl SendData {
     // a deep clone.
     async fn send_payload(
         &self,
+        client: &HttpClient,
         content_type: &'static str,
         payload: Vec<u8>,
         payload_chunks: u64,
@@ -261,7 +275,7 @@ impl SendData {
                 .expect("HttpRequestBuilder unable to get headers for request")
                 .extend(headers.clone());
 
-            match self.send_request(req, payload.clone()).await {
+            match self.send_request(client, req, payload.clone()).await {
                 // An Ok response doesn't necessarily mean the request was successful, we need to
                 // check the status code and if it's not a 2xx or 3xx we treat it as an error
                 Ok(response) => {
@@ -340,7 +354,7 @@ impl SendData {
         req
     }
 
-    async fn send_with_protobuf(&self) -> SendDataResult {
+    async fn send_with_protobuf(&self, client: &HttpClient) -> SendDataResult {
         let mut result = SendDataResult::default();
         let chunks = u64::try_from(self.tracer_payloads.size()).unwrap();
 
@@ -357,6 +371,7 @@ impl SendData {
                 result
                     .update(
                         self.send_payload(
+                            client,
                             HEADER_CTYPE_PROTOBUF,
                             serialized_trace_payload,
                             chunks,
@@ -372,7 +387,7 @@ impl SendData {
         }
     }
 
-    async fn send_with_msgpack(&self) -> SendDataResult {
+    async fn send_with_msgpack(&self, client: &HttpClient) -> SendDataResult {
         let mut result = SendDataResult::default();
         let mut futures = FuturesUnordered::new();
 
@@ -388,6 +403,7 @@ impl SendData {
                         Err(e) => return result.error(anyhow!(e)),
                     };
                     futures.push(self.send_payload(
+                        client,
                         HEADER_CTYPE_MSGPACK,
                         payload,
                         chunks,
@@ -395,16 +411,26 @@ impl SendData {
                     ));
                 }
             }
-            TracerPayloadCollection::V04(payloads) => {
+            TracerPayloadCollection::V04(payloads) | TracerPayloadCollection::V05(payloads) => {
                 let chunks = u64::try_from(self.tracer_payloads.size()).unwrap();
                 let headers = Some(HashMap::from([(HEADER_DD_TRACE_COUNT, chunks.to_string())]));
 
-                let payload = match rmp_serde::to_vec_named(payloads) {
+                let encoded = match &self.tracer_payloads {
+                    TracerPayloadCollection::V05(_) => msgpack_v05::encode(payloads),
+                    _ => rmp_serde::to_vec_named(payloads),
+                };
+                let payload = match encoded {
                     Ok(p) => p,
                     Err(e) => return result.error(anyhow!(e)),
                 };
 
-                futures.push(self.send_payload(HEADER_CTYPE_MSGPACK, payload, chunks, headers));
+                futures.push(self.send_payload(
+                    client,
+                    HEADER_CTYPE_MSGPACK,
+                    payload,
+                    chunks,
+                    headers,
+                ));
             }
         }
 
@@ -520,6 +546,7 @@ mod tests {
             TracerPayloadCollection::V04(payloads) => {
                 rmp_serde::to_vec_named(payloads).unwrap().len()
             }
+            TracerPayloadCollection::V05(payloads) => msgpack_v05::encode(payloads).unwrap().len(),
         }
     }
 
@@ -760,6 +787,77 @@ mod tests {
         assert_eq!(*res.responses_count_per_code.get(&200).unwrap(), 1_u64);
     }
 
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn request_msgpack_v05() {
+        let server = MockServer::start_async().await;
+
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .header(HEADER_DD_TRACE_COUNT, "2")
+                    .header("Content-type", "application/msgpack")
+                    .path("/");
+                then.status(200).body("");
+            })
+            .await;
+
+        let trace = vec![create_test_span(1234, 12342, 12341, 1, false)];
+        let data = SendData::new(
+            100,
+            TracerPayloadCollection::V05(vec![trace.clone(), trace]),
+            HEADER_TAGS,
+            &Endpoint {
+                api_key: None,
+                url: server.url("/").parse::<hyper::Uri>().unwrap(),
+            },
+        );
+
+        let data_payload_len = rmp_compute_payload_len(&data.tracer_payloads);
+        let res = data.send().await;
+
+        mock.assert_async().await;
+
+        assert_eq!(res.last_result.unwrap().status(), 200);
+        assert_eq!(res.requests_count, 1);
+        assert_eq!(res.chunks_sent, 2);
+        assert_eq!(res.bytes_sent, data_payload_len as u64);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[tokio::test]
+    async fn request_with_shared_client() {
+        let server = MockServer::start_async().await;
+
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(POST)
+                    .header("Content-type", "application/msgpack")
+                    .path("/");
+                then.status(200).body("");
+            })
+            .await;
+
+        let client = Client::builder().build(connector::Connector::default());
+        let trace = vec![create_test_span(1234, 12342, 12341, 1, false)];
+        let data = SendData::new(
+            100,
+            TracerPayloadCollection::V04(vec![trace]),
+            HEADER_TAGS,
+            &Endpoint {
+                api_key: None,
+                url: server.url("/").parse::<hyper::Uri>().unwrap(),
+            },
+        );
+
+        let first = data.send_with_client(&client).await;
+        let second = data.send_with_client(&client).await;
+
+        mock.assert_hits_async(2).await;
+        assert_eq!(first.last_result.unwrap().status(), 200);
+        assert_eq!(second.last_result.unwrap().status(), 200);
+    }
+
     #[cfg_attr(miri, ignore)]
     #[tokio::test]
     async fn request_msgpack_several_payloads() {
@@ -5,6 +5,27 @@ use crate::send_data::RequestResult;
 use anyhow::anyhow;
 use hyper::{Body, Response};
 use std::collections::HashMap;
+use std::fmt;
+
+/// Error of the last request when the server answered with an unsuccessful status, which
+/// `SendDataResult::last_result` holds so that it can be downcast to get the response.
+#[derive(Debug)]
+pub struct ResponseStatusError {
+    pub status: u16,
+    pub body: String,
+}
+
+impl fmt::Display for ResponseStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - Server did not accept traces: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for ResponseStatusError {}
 
 #[derive(Debug)]
 pub struct SendDataResult {
@@ -76,11 +97,11 @@ impl SendDataResult {
                 let body_bytes = hyper::body::to_bytes(response.into_body()).await;
                 let response_body =
                     String::from_utf8(body_bytes.unwrap_or_default().to_vec()).unwrap_or_default();
-                self.last_result = Err(anyhow::format_err!(
-                    "{} - Server did not accept traces: {}",
-                    status_code,
-                    response_body,
-                ));
+                self.last_result = Err(ResponseStatusError {
+                    status: status_code,
+                    body: response_body,
+                }
+                .into());
             }
             RequestResult::TimeoutError((attempts, chunks)) => {
                 self.errors_timeout += 1;
@@ -1,17 +1,19 @@
 // Copyright 2023-Present Datadog, Inc. https://www.datadoghq.com/
 // SPDX-License-Identifier: Apache-2.0
 
-use hyper::{body::Buf, Body};
+use hyper::Body;
 use log::{error, info};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
+use crate::msgpack_v04;
 pub use crate::send_data::send_data_result::SendDataResult;
 pub use crate::send_data::SendData;
 pub use crate::tracer_header_tags::TracerHeaderTags;
 use crate::tracer_payload::{TraceEncoding, TracerPayloadCollection};
 use datadog_trace_normalization::normalizer;
 use datadog_trace_protobuf::pb::{self, Span, TraceChunk, TracerPayload};
+use datadog_trace_protobuf::span_bytes::{BytesString, SpanBytes};
 use ddcommon::azure_app_services;
 
 /// Span metric the mini agent must set for the backend to recognize top level span
@@ -21,12 +23,30 @@ const TRACER_TOP_LEVEL_KEY: &str = "_dd.top_level";
 
 const MAX_PAYLOAD_SIZE: usize = 50 * 1024 * 1024;
 
+/// Decode v0.4 traces from a request body to owned spans, see
+/// [`get_v04_traces_from_request_body`].
+///
 /// First value of returned tuple is the payload size
 pub async fn get_traces_from_request_body(body: Body) -> anyhow::Result<(usize, Vec<Vec<Span>>)> {
-    let buffer = hyper::body::aggregate(body).await?;
-    let size = buffer.remaining();
+    let (size, traces) = get_v04_traces_from_request_body(body).await?;
+    let traces = traces
+        .into_iter()
+        .map(|trace| trace.into_iter().map(Span::from).collect())
+        .collect();
+    Ok((size, traces))
+}
+
+/// Decode v0.4 traces from a request body without copying their strings, see
+/// [`msgpack_v04::decode`].
+///
+/// First value of returned tuple is the payload size
+pub async fn get_v04_traces_from_request_body(
+    body: Body,
+) -> anyhow::Result<(usize, Vec<Vec<SpanBytes>>)> {
+    let buffer = hyper::body::to_bytes(body).await?;
+    let size = buffer.len();
 
-    let traces: Vec<Vec<Span>> = match rmp_serde::from_read(buffer.reader()) {
+    let traces = match msgpack_v04::decode(buffer) {
         Ok(res) => res,
         Err(err) => {
             anyhow::bail!("Error deserializing trace from request body: {err}")
@@ -122,49 +142,68 @@ pub fn coalesce_send_data(mut data: Vec<SendData>) -> Vec<SendData> {
     data
 }
 
-fn get_root_span_index(trace: &Vec<Span>) -> anyhow::Result<usize> {
-    if trace.is_empty() {
+/// Return the index of the root span of the trace.
+///
+/// The root span is the last span without a parent, or the span whose parent isn't in the trace.
+pub fn get_root_span_index(trace: &[Span]) -> anyhow::Result<usize> {
+    find_root_span_index(trace.len(), |i| {
+        (trace[i].trace_id, trace[i].span_id, trace[i].parent_id)
+    })
+}
+
+/// get_root_span_index_bytes is the counterpart of get_root_span_index for spans borrowing their
+/// strings from a payload buffer, see [`SpanBytes`].
+pub fn get_root_span_index_bytes(trace: &[SpanBytes]) -> anyhow::Result<usize> {
+    find_root_span_index(trace.len(), |i| {
+        (trace[i].trace_id, trace[i].span_id, trace[i].parent_id)
+    })
+}
+
+/// Find the root span of a trace of `len` spans, `ids` returns the trace id, span id and parent id
+/// of the span at the given index.
+fn find_root_span_index(
+    len: usize,
+    ids: impl Fn(usize) -> (u64, u64, u64),
+) -> anyhow::Result<usize> {
+    if len == 0 {
         anyhow::bail!("Cannot find root span index in an empty trace.");
     }
 
-    // parent_id -> (child_span, index_of_child_span_in_trace)
-    let mut parent_id_to_child_map: HashMap<u64, (&Span, usize)> = HashMap::new();
+    // parent_id -> index_of_child_span_in_trace
+    let mut parent_id_to_child_map: HashMap<u64, usize> = HashMap::new();
 
     // look for the span with parent_id == 0 (starting from the end) since some clients put the root
     // span last.
-    for i in (0..trace.len()).rev() {
-        let cur_span = &trace[i];
-        if cur_span.parent_id == 0 {
+    for i in (0..len).rev() {
+        let (_, _, parent_id) = ids(i);
+        if parent_id == 0 {
             return Ok(i);
         }
-        parent_id_to_child_map.insert(cur_span.parent_id, (cur_span, i));
+        parent_id_to_child_map.insert(parent_id, i);
     }
 
-    for span in trace {
-        if parent_id_to_child_map.contains_key(&span.span_id) {
-            parent_id_to_child_map.remove(&span.span_id);
-        }
+    for i in 0..len {
+        let (_, span_id, _) = ids(i);
+        parent_id_to_child_map.remove(&span_id);
     }
 
     // if the trace is valid, parent_id_to_child_map should just have 1 entry at this point.
     if parent_id_to_child_map.len() != 1 {
         error!(
             "Could not find the root span for trace with trace_id: {}",
-            &trace[0].trace_id,
+            ids(0).0,
         );
     }
 
     // pick a span without a parent
-    let span_tuple = match parent_id_to_child_map.values().copied().next() {
-        Some(res) => res,
+    match parent_id_to_child_map.values().copied().next() {
+        Some(index) => Ok(index),
         None => {
             // just return the index of the last span in the trace.
             info!("Returning index of last span in trace as root span index.");
-            return Ok(trace.len() - 1);
+            Ok(len - 1)
         }
-    };
-
-    Ok(span_tuple.1)
+    }
 }
 
 /// Updates all the spans top-level attribute.
@@ -198,6 +237,26 @@ pub fn compute_top_level_span(trace: &mut [Span]) {
     }
 }
 
+/// compute_top_level_span_bytes is the counterpart of compute_top_level_span for spans borrowing
+/// their strings from a payload buffer, see [`SpanBytes`].
+pub fn compute_top_level_span_bytes(trace: &mut [SpanBytes]) {
+    let span_id_to_service: HashMap<u64, BytesString> = trace
+        .iter()
+        .map(|span| (span.span_id, span.service.clone()))
+        .collect();
+    for span in trace.iter_mut() {
+        let is_top_level = span.parent_id == 0
+            || span_id_to_service
+                .get(&span.parent_id)
+                .map_or(true, |parent_span_service| {
+                    *parent_span_service != span.service
+                });
+        if is_top_level {
+            span.metrics.insert(TOP_LEVEL_KEY.into(), 1.0);
+        }
+    }
+}
+
 fn set_top_level_span(span: &mut Span, is_top_level: bool) {
     if !is_top_level {
         if span.metrics.contains_key(TOP_LEVEL_KEY) {
@@ -309,6 +368,7 @@ pub fn collect_trace_chunks(
 ) -> TracerPayloadCollection {
     match encoding_type {
         TraceEncoding::V04 => TracerPayloadCollection::V04(traces),
+        TraceEncoding::V05 => TracerPayloadCollection::V05(traces),
         TraceEncoding::V07 => {
             let mut trace_chunks: Vec<TraceChunk> = Vec::new();
 
@@ -382,7 +442,9 @@ mod tests {
     use serde_json::json;
     use std::collections::HashMap;
 
-    use super::{get_root_span_index, set_serverless_root_span_tags};
+    use super::{
+        get_root_span_index, get_v04_traces_from_request_body, set_serverless_root_span_tags,
+    };
     use crate::trace_utils::{TracerHeaderTags, MAX_PAYLOAD_SIZE};
     use crate::tracer_payload::TracerPayloadCollection;
     use crate::{
@@ -525,6 +587,45 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_get_v04_traces_from_request_body() {
+        let trace_input = json!([{
+            "service": "test-service",
+            "name": "test-service-name",
+            "resource": "test-service-resource",
+            "trace_id": 111,
+            "span_id": 222,
+            "start": 1,
+            "duration": 5,
+            "meta": {"env": "test"},
+        }]);
+        let bytes = rmp_serde::to_vec_named(&vec![&trace_input]).unwrap();
+        let body_size = bytes.len();
+        let request = Request::builder()
+            .body(hyper::body::Body::from(bytes))
+            .unwrap();
+
+        let (size, traces) = get_v04_traces_from_request_body(request.into_body())
+            .await
+            .unwrap();
+        assert_eq!(size, body_size);
+        assert_eq!(traces.len(), 1);
+        let span = &traces[0][0];
+        assert_eq!(span.service, "test-service");
+        assert_eq!((span.trace_id, span.span_id), (111, 222));
+        assert_eq!(span.meta.get("env").map(|v| v.as_str()), Some("test"));
+
+        let request = Request::builder()
+            .body(hyper::body::Body::from(
+                rmp_serde::to_vec(&json!([])).unwrap(),
+            ))
+            .unwrap();
+        assert!(get_v04_traces_from_request_body(request.into_body())
+            .await
+            .is_err());
+    }
+
     #[test]
     fn test_get_root_span_index_from_complete_trace() {
         let trace = vec![
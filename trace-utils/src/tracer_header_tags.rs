@@ -53,6 +53,12 @@ impl<'a> From<TracerHeaderTags<'a>> for HashMap<&'static str, String> {
             ),
             ("datadog-container-id", tags.container_id.to_string()),
         ]);
+        if tags.client_computed_top_level {
+            headers.insert("datadog-client-computed-top-level", "true".to_string());
+        }
+        if tags.client_computed_stats {
+            headers.insert("datadog-client-computed-stats", "true".to_string());
+        }
         headers.retain(|_, v| !v.is_empty());
         headers
     }
@@ -140,6 +146,26 @@ mod tests {
         assert_eq!(map.get("datadog-container-id"), None);
     }
 
+    #[test]
+    fn tags_to_hashmap_client_computed() {
+        let header_tags = TracerHeaderTags {
+            lang: "test-lang",
+            client_computed_top_level: true,
+            client_computed_stats: true,
+            ..Default::default()
+        };
+
+        let map: HashMap<&'static str, String> = header_tags.into();
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get("datadog-meta-lang").unwrap(), "test-lang");
+        assert_eq!(
+            map.get("datadog-client-computed-top-level").unwrap(),
+            "true"
+        );
+        assert_eq!(map.get("datadog-client-computed-stats").unwrap(), "true");
+    }
+
     #[test]
     fn header_map_to_tags() {
         let mut header_map = HeaderMap::new();
@@ -15,6 +15,8 @@ pub enum TraceEncoding {
     V04,
     /// v0.7 encoding (TracerPayload).
     V07,
+    /// v0.5 encoding (TracerPayloadV04 serialized with a string table).
+    V05,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +26,8 @@ pub enum TracerPayloadCollection {
     V07(Vec<TracerPayload>),
     /// Collection of TracerPayloadsV04.
     V04(Vec<TracerPayloadV04>),
+    /// Collection of TracerPayloadsV04 to be sent with the v0.5 encoding.
+    V05(Vec<TracerPayloadV04>),
 }
 
 impl TracerPayloadCollection {
@@ -54,6 +58,11 @@ impl TracerPayloadCollection {
                     dest.append(src)
                 }
             }
+            TracerPayloadCollection::V05(dest) => {
+                if let TracerPayloadCollection::V05(src) = other {
+                    dest.append(src)
+                }
+            }
         }
     }
 
@@ -101,7 +110,9 @@ impl TracerPayloadCollection {
             TracerPayloadCollection::V07(collection) => {
                 collection.iter().map(|s| s.chunks.len()).sum()
             }
-            TracerPayloadCollection::V04(collection) => collection.len(),
+            TracerPayloadCollection::V04(collection) | TracerPayloadCollection::V05(collection) => {
+                collection.len()
+            }
         }
     }
 }
@@ -166,6 +177,19 @@ mod tests {
         assert_eq!(4, trace.size());
     }
 
+    #[test]
+    fn test_append_traces_v05() {
+        let mut trace =
+            TracerPayloadCollection::V05(vec![vec![create_test_span(0, 1, 0, 2, true)]]);
+
+        trace.append(&mut trace.clone());
+        assert_eq!(2, trace.size());
+
+        // Collections of different versions are not appended
+        trace.append(&mut TracerPayloadCollection::V04(vec![vec![]]));
+        assert_eq!(2, trace.size());
+    }
+
     #[test]
     fn test_merge_traces() {
         let mut trace = create_dummy_collection_v07();